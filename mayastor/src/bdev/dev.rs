@@ -39,6 +39,7 @@ pub(crate) mod uri {
     use crate::{
         bdev::{
             aio,
+            ftl,
             loopback,
             malloc,
             null,
@@ -63,10 +64,14 @@ pub(crate) mod uri {
         match url.scheme() {
             "aio" => Ok(Box::new(aio::Aio::try_from(&url)?)),
             "bdev" => Ok(Box::new(loopback::Loopback::try_from(&url)?)),
+            "ftl" => Ok(Box::new(ftl::Ftl::try_from(&url)?)),
             "loopback" => Ok(Box::new(loopback::Loopback::try_from(&url)?)),
             "malloc" => Ok(Box::new(malloc::Malloc::try_from(&url)?)),
             "null" => Ok(Box::new(null::Null::try_from(&url)?)),
             "nvmf" => Ok(Box::new(nvmx::NvmfDeviceTemplate::try_from(&url)?)),
+            "nvmf+discovery" => {
+                Ok(Box::new(nvmx::NvmfDeviceTemplate::try_from(&url)?))
+            }
             "pcie" => Ok(Box::new(nvme::NVMe::try_from(&url)?)),
             "uring" => Ok(Box::new(uring::Uring::try_from(&url)?)),
 
@@ -9,19 +9,40 @@ use std::{
 };
 
 use async_trait::async_trait;
+use futures::channel::oneshot;
 use nix::errno::Errno;
 use once_cell::sync::{Lazy, OnceCell};
 
 use spdk_rs::{
     libspdk::{
         iovec,
+        spdk_bdev_comparev_and_writev_blocks,
+        spdk_bdev_comparev_blocks,
         spdk_bdev_free_io,
+        spdk_bdev_get_max_active_zones,
+        spdk_bdev_get_max_open_zones,
+        spdk_bdev_get_max_zone_append_size,
+        spdk_bdev_get_zone_info,
+        spdk_bdev_get_zone_size,
         spdk_bdev_io,
+        spdk_bdev_io_get_append_location,
+        spdk_bdev_is_zoned,
         spdk_bdev_readv_blocks,
         spdk_bdev_reset,
         spdk_bdev_unmap_blocks,
         spdk_bdev_write_zeroes_blocks,
         spdk_bdev_writev_blocks,
+        spdk_bdev_zone_append,
+        spdk_bdev_zone_info,
+        spdk_bdev_zone_management,
+        SPDK_BDEV_IO_STATUS_MISCOMPARE,
+        SPDK_BDEV_ZONE_RESET,
+        SPDK_BDEV_ZONE_STATE_CLOSED,
+        SPDK_BDEV_ZONE_STATE_EMPTY,
+        SPDK_BDEV_ZONE_STATE_EXP_OPEN,
+        SPDK_BDEV_ZONE_STATE_FULL,
+        SPDK_BDEV_ZONE_STATE_IMP_OPEN,
+        SPDK_BDEV_ZONE_STATE_READ_ONLY,
     },
     nvme_admin_opc,
     DmaBuf,
@@ -29,26 +50,33 @@ use spdk_rs::{
     IoType,
 };
 
-use crate::core::{
-    mempool::MemoryPool,
-    Bdev,
-    BdevHandle,
-    BlockDevice,
-    BlockDeviceDescriptor,
-    BlockDeviceHandle,
-    BlockDeviceIoStats,
-    CoreError,
-    Descriptor,
-    DeviceEventDispatcher,
-    DeviceEventSink,
-    DeviceEventType,
-    DeviceIoController,
-    IoCompletionCallback,
-    IoCompletionCallbackArg,
-    IoCompletionStatus,
-    NvmeCommandStatus,
-    NvmeStatus,
-    UntypedBdev,
+use crate::{
+    core::{
+        mempool::MemoryPool,
+        resettable_zones,
+        Bdev,
+        BdevHandle,
+        BlockDevice,
+        BlockDeviceDescriptor,
+        BlockDeviceHandle,
+        BlockDeviceIoStats,
+        CoreError,
+        Descriptor,
+        DeviceEventDispatcher,
+        DeviceEventSink,
+        DeviceEventType,
+        DeviceIoController,
+        IoCompletionCallback,
+        IoCompletionCallbackArg,
+        IoCompletionStatus,
+        NvmeCommandStatus,
+        NvmeStatus,
+        UntypedBdev,
+        ZoneGeometry,
+        ZoneInfo,
+        ZoneState,
+    },
+    ffihelper::{cb_arg, done_cb},
 };
 
 /// TODO
@@ -218,6 +246,25 @@ impl BlockDevice for SpdkBlockDevice {
     fn io_type_supported(&self, io_type: IoType) -> bool {
         self.bdev.io_type_supported(io_type)
     }
+    /// returns the zone geometry of a zoned device
+    fn zone_geometry(&self) -> Option<ZoneGeometry> {
+        unsafe {
+            let bdev = self.bdev.unsafe_inner_ptr();
+            if !spdk_bdev_is_zoned(bdev) {
+                return None;
+            }
+
+            let zone_size = spdk_bdev_get_zone_size(bdev);
+            Some(ZoneGeometry {
+                zone_size,
+                num_zones: self.bdev.num_blocks() / zone_size,
+                max_open_zones: spdk_bdev_get_max_open_zones(bdev),
+                max_active_zones: spdk_bdev_get_max_active_zones(bdev),
+                max_append_size: spdk_bdev_get_max_zone_append_size(bdev)
+                    as u64,
+            })
+        }
+    }
     /// returns the IO statistics
     async fn io_stats(&self) -> Result<BlockDeviceIoStats, CoreError> {
         self.bdev.stats_async().await
@@ -295,6 +342,13 @@ fn io_type_to_err(
         IoType::Reset => CoreError::ResetDispatch {
             source,
         },
+        IoType::Compare | IoType::CompareAndWrite => {
+            CoreError::CompareDispatch {
+                source,
+                offset,
+                len,
+            }
+        }
         _ => {
             warn!("Unsupported I/O operation: {:?}", op);
             CoreError::NotSupported {
@@ -333,6 +387,42 @@ fn free_bdev_io_ctx(ctx: *mut IoCtx) {
     pool.put(ctx);
 }
 
+extern "C" fn bdev_zone_completion(
+    bdev_io: *mut spdk_bdev_io,
+    success: bool,
+    ctx: *mut c_void,
+) {
+    unsafe { spdk_bdev_free_io(bdev_io) };
+    done_cb(ctx, success);
+}
+
+extern "C" fn bdev_zone_append_completion(
+    bdev_io: *mut spdk_bdev_io,
+    success: bool,
+    ctx: *mut c_void,
+) {
+    let lba = if success {
+        Some(unsafe { spdk_bdev_io_get_append_location(bdev_io) })
+    } else {
+        None
+    };
+    unsafe { spdk_bdev_free_io(bdev_io) };
+    done_cb(ctx, lba);
+}
+
+/// Converts the state of an SPDK bdev zone.
+fn bdev_zone_state(info: &spdk_bdev_zone_info) -> ZoneState {
+    match info.state {
+        SPDK_BDEV_ZONE_STATE_EMPTY => ZoneState::Empty,
+        SPDK_BDEV_ZONE_STATE_IMP_OPEN => ZoneState::ImplicitOpen,
+        SPDK_BDEV_ZONE_STATE_EXP_OPEN => ZoneState::ExplicitOpen,
+        SPDK_BDEV_ZONE_STATE_CLOSED => ZoneState::Closed,
+        SPDK_BDEV_ZONE_STATE_READ_ONLY => ZoneState::ReadOnly,
+        SPDK_BDEV_ZONE_STATE_FULL => ZoneState::Full,
+        _ => ZoneState::Offline,
+    }
+}
+
 extern "C" fn bdev_io_completion(
     child_bio: *mut spdk_bdev_io,
     success: bool,
@@ -343,6 +433,10 @@ extern "C" fn bdev_io_completion(
     // Get extended NVMe error status from original bio in case of error.
     let status = if success {
         IoCompletionStatus::Success
+    } else if unsafe { (*child_bio).internal.status }
+        == SPDK_BDEV_IO_STATUS_MISCOMPARE as i8
+    {
+        IoCompletionStatus::Miscompare
     } else {
         let nvme_status = NvmeStatus::from(child_bio);
         let nvme_cmd_status = NvmeCommandStatus::from_command_status(
@@ -479,6 +573,106 @@ impl BlockDeviceHandle for SpdkBlockDeviceHandle {
         }
     }
 
+    fn comparev_blocks(
+        &self,
+        iov: *mut iovec,
+        iovcnt: i32,
+        offset_blocks: u64,
+        num_blocks: u64,
+        cb: IoCompletionCallback,
+        cb_arg: IoCompletionCallbackArg,
+    ) -> Result<(), CoreError> {
+        let ctx = alloc_bdev_io_ctx(
+            IoType::Compare,
+            IoCtx {
+                handle: self,
+                cb,
+                cb_arg,
+            },
+            offset_blocks,
+            num_blocks,
+        )?;
+
+        // The bdev layer reads and compares the blocks itself for devices
+        // which cannot compare them.
+        let (desc, chan) = self.handle.io_tuple();
+        let rc = unsafe {
+            spdk_bdev_comparev_blocks(
+                desc,
+                chan,
+                iov,
+                iovcnt,
+                offset_blocks,
+                num_blocks,
+                Some(bdev_io_completion),
+                ctx as *mut c_void,
+            )
+        };
+
+        if rc < 0 {
+            free_bdev_io_ctx(ctx);
+            Err(CoreError::CompareDispatch {
+                source: Errno::from_i32(-rc),
+                offset: offset_blocks,
+                len: num_blocks,
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    fn comparev_and_writev_blocks(
+        &self,
+        compare_iov: *mut iovec,
+        compare_iovcnt: i32,
+        write_iov: *mut iovec,
+        write_iovcnt: i32,
+        offset_blocks: u64,
+        num_blocks: u64,
+        cb: IoCompletionCallback,
+        cb_arg: IoCompletionCallbackArg,
+    ) -> Result<(), CoreError> {
+        let ctx = alloc_bdev_io_ctx(
+            IoType::CompareAndWrite,
+            IoCtx {
+                handle: self,
+                cb,
+                cb_arg,
+            },
+            offset_blocks,
+            num_blocks,
+        )?;
+
+        // For devices which cannot compare and write atomically, the bdev
+        // layer compares and writes the blocks with their range locked.
+        let (desc, chan) = self.handle.io_tuple();
+        let rc = unsafe {
+            spdk_bdev_comparev_and_writev_blocks(
+                desc,
+                chan,
+                compare_iov,
+                compare_iovcnt,
+                write_iov,
+                write_iovcnt,
+                offset_blocks,
+                num_blocks,
+                Some(bdev_io_completion),
+                ctx as *mut c_void,
+            )
+        };
+
+        if rc < 0 {
+            free_bdev_io_ctx(ctx);
+            Err(CoreError::CompareDispatch {
+                source: Errno::from_i32(-rc),
+                offset: offset_blocks,
+                len: num_blocks,
+            })
+        } else {
+            Ok(())
+        }
+    }
+
     fn reset(
         &self,
         cb: IoCompletionCallback,
@@ -596,6 +790,168 @@ impl BlockDeviceHandle for SpdkBlockDeviceHandle {
         }
     }
 
+    /// reports the zones of a zoned device
+    async fn zone_report(
+        &self,
+        zone_id: u64,
+        num_zones: u64,
+    ) -> Result<Vec<ZoneInfo>, CoreError> {
+        let geometry =
+            self.device.zone_geometry().ok_or(CoreError::NotSupported {
+                source: Errno::EOPNOTSUPP,
+            })?;
+
+        // the report must not run past the last zone
+        let first = zone_id / geometry.zone_size;
+        let num_zones = num_zones.min(geometry.num_zones.saturating_sub(first));
+        if num_zones == 0 {
+            return Ok(Vec::new());
+        }
+
+        let mut infos =
+            vec![spdk_bdev_zone_info::default(); num_zones as usize];
+        let (s, r) = oneshot::channel::<bool>();
+
+        let (desc, chan) = self.handle.io_tuple();
+        let rc = unsafe {
+            spdk_bdev_get_zone_info(
+                desc,
+                chan,
+                zone_id,
+                num_zones,
+                infos.as_mut_ptr(),
+                Some(bdev_zone_completion),
+                cb_arg(s),
+            )
+        };
+
+        if rc < 0 {
+            return Err(CoreError::ZoneReportDispatch {
+                source: Errno::from_i32(-rc),
+                zone_id,
+            });
+        }
+
+        if !r.await.expect("Failed awaiting at zone_report()") {
+            return Err(CoreError::ZoneReportFailed {
+                zone_id,
+            });
+        }
+
+        Ok(infos
+            .iter()
+            .map(|info| ZoneInfo {
+                zone_id: info.zone_id,
+                write_pointer: info.write_pointer,
+                capacity: info.capacity,
+                state: bdev_zone_state(info),
+            })
+            .collect())
+    }
+
+    /// appends the buffer to a zone of a zoned device
+    async fn zone_append(
+        &self,
+        zone_id: u64,
+        buffer: &DmaBuf,
+    ) -> Result<u64, CoreError> {
+        let geometry =
+            self.device.zone_geometry().ok_or(CoreError::NotSupported {
+                source: Errno::EOPNOTSUPP,
+            })?;
+
+        let block_len = self.device.block_len();
+        let num_blocks = buffer.len() / block_len;
+        if buffer.len() % block_len != 0
+            || num_blocks == 0
+            || num_blocks > geometry.max_append_size
+        {
+            return Err(CoreError::ZoneAppendDispatch {
+                source: Errno::EINVAL,
+                zone_id,
+            });
+        }
+
+        let (s, r) = oneshot::channel::<Option<u64>>();
+
+        let (desc, chan) = self.handle.io_tuple();
+        let rc = unsafe {
+            spdk_bdev_zone_append(
+                desc,
+                chan,
+                **buffer,
+                zone_id,
+                num_blocks,
+                Some(bdev_zone_append_completion),
+                cb_arg(s),
+            )
+        };
+
+        if rc < 0 {
+            return Err(CoreError::ZoneAppendDispatch {
+                source: Errno::from_i32(-rc),
+                zone_id,
+            });
+        }
+
+        r.await.expect("Failed awaiting at zone_append()").ok_or(
+            CoreError::ZoneAppendFailed {
+                zone_id,
+            },
+        )
+    }
+
+    /// resets the write pointer of one or all zones of a zoned device
+    async fn zone_reset(
+        &self,
+        zone_id: u64,
+        all: bool,
+    ) -> Result<(), CoreError> {
+        let geometry =
+            self.device.zone_geometry().ok_or(CoreError::NotSupported {
+                source: Errno::EOPNOTSUPP,
+            })?;
+
+        // bdevs reset a single zone at a time, so resetting all of them
+        // resets the ones that can be, as NVMe does for all zones
+        let zones = if all {
+            resettable_zones(&self.zone_report(0, geometry.num_zones).await?)
+        } else {
+            vec![zone_id]
+        };
+
+        for zone_id in zones {
+            let (s, r) = oneshot::channel::<bool>();
+
+            let (desc, chan) = self.handle.io_tuple();
+            let rc = unsafe {
+                spdk_bdev_zone_management(
+                    desc,
+                    chan,
+                    zone_id,
+                    SPDK_BDEV_ZONE_RESET,
+                    Some(bdev_zone_completion),
+                    cb_arg(s),
+                )
+            };
+
+            if rc < 0 {
+                return Err(CoreError::ZoneResetDispatch {
+                    source: Errno::from_i32(-rc),
+                    zone_id,
+                });
+            }
+
+            if !r.await.expect("Failed awaiting at zone_reset()") {
+                return Err(CoreError::ZoneResetFailed {
+                    zone_id,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
     /// NVMe commands are not applicable for non-NVMe devices.
     async fn nvme_admin_custom(&self, opcode: u8) -> Result<(), CoreError> {
         Err(CoreError::NvmeAdminDispatch {
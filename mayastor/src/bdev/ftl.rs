@@ -0,0 +1,343 @@
+//!
+//! The ftl bdev builds a flash translation layer on top of a zoned device,
+//! which turns the zoned device into a regular block device that can, for
+//! instance, hold a pool. The zoned device the layer is built on is given
+//! by its URI, with any '&' in it percent-encoded. A new translation layer
+//! is only created when asked for explicitly, as it discards what the zoned
+//! device holds:
+//! ```ignore
+//!     ftl:///ftl0?base=pcie:///0000:01:00.0&mode=create
+//! ```
+//! The layer is then known by the UUID it was created with, which replaces
+//! the mode in the URI the device is aliased with. Giving the UUID restores
+//! the existing translation layer instead, as when importing a pool:
+//! ```ignore
+//!     ftl:///ftl0?base=pcie:///0000:01:00.0&uuid=<uuid>
+//! ```
+//! Destroying the translation layer destroys the zoned device only when it
+//! was created along with the layer, not when it existed before.
+use std::{
+    collections::{HashMap, HashSet},
+    convert::TryFrom,
+    os::raw::{c_int, c_void},
+};
+
+use async_trait::async_trait;
+use futures::channel::oneshot;
+use nix::errno::Errno;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use snafu::ResultExt;
+use url::Url;
+
+use spdk_rs::libspdk::{
+    bdev_ftl_create_bdev,
+    bdev_ftl_delete_bdev,
+    ftl_bdev_info,
+    ftl_bdev_init_opts,
+    spdk_ftl_conf_init_defaults,
+    SPDK_FTL_MODE_CREATE,
+};
+
+use crate::{
+    bdev::{
+        dev::{self, reject_unknown_parameters},
+        util::uri,
+        CreateDestroy,
+        GetName,
+        SpdkBlockDevice,
+    },
+    core::UntypedBdev,
+    ffihelper::{
+        cb_arg,
+        done_errno_cb,
+        errno_result_from_i32,
+        ErrnoResult,
+        IntoCString,
+    },
+    nexus_uri::{self, NexusBdevError},
+};
+
+/// names of the translation layers which created their zoned device, and
+/// destroy it along with them
+static FTL_OWNED_BASES: Lazy<Mutex<HashSet<String>>> =
+    Lazy::new(|| Mutex::new(HashSet::new()));
+
+#[derive(Debug)]
+pub(super) struct Ftl {
+    /// the name of the bdev we created, this is equal to the URI path minus
+    /// the leading '/'
+    name: String,
+    /// the URI of the device
+    url: Url,
+    /// the URI of the zoned device the translation layer is built on
+    base: String,
+    /// uuid of the translation layer to restore, a new one is created when
+    /// not given
+    uuid: Option<uuid::Uuid>,
+}
+
+/// the mode which creates a new translation layer
+const MODE_CREATE: &str = "create";
+
+impl TryFrom<&Url> for Ftl {
+    type Error = NexusBdevError;
+
+    fn try_from(url: &Url) -> Result<Self, Self::Error> {
+        let segments = uri::segments(url);
+        if segments.is_empty() {
+            return Err(NexusBdevError::UriInvalid {
+                uri: url.to_string(),
+                message: "empty path".to_string(),
+            });
+        }
+
+        let mut parameters: HashMap<String, String> =
+            url.query_pairs().into_owned().collect();
+
+        let base = parameters.remove("base").ok_or_else(|| {
+            NexusBdevError::UriInvalid {
+                uri: url.to_string(),
+                message: "'base' must be specified".to_string(),
+            }
+        })?;
+
+        if base.starts_with("ftl:") {
+            return Err(NexusBdevError::UriInvalid {
+                uri: url.to_string(),
+                message: "'base' must not be an ftl device".to_string(),
+            });
+        }
+
+        let uuid = uri::uuid(parameters.remove("uuid")).context(
+            nexus_uri::UuidParamParseError {
+                uri: url.to_string(),
+            },
+        )?;
+
+        // formatting the zoned device must be asked for, so that a missing
+        // uuid does not wipe an existing translation layer
+        match (parameters.remove("mode").as_deref(), uuid) {
+            (Some(MODE_CREATE), None) | (None, Some(_)) => {}
+            (Some(MODE_CREATE), Some(_)) => {
+                return Err(NexusBdevError::UriInvalid {
+                    uri: url.to_string(),
+                    message: "'uuid' can not be given with 'mode=create'"
+                        .to_string(),
+                });
+            }
+            (Some(mode), _) => {
+                return Err(NexusBdevError::UriInvalid {
+                    uri: url.to_string(),
+                    message: format!("invalid value for 'mode': {}", mode),
+                });
+            }
+            (None, None) => {
+                return Err(NexusBdevError::UriInvalid {
+                    uri: url.to_string(),
+                    message: "either 'uuid' or 'mode=create' must be specified"
+                        .to_string(),
+                });
+            }
+        }
+
+        reject_unknown_parameters(url, parameters)?;
+
+        // make sure the base device can be created
+        dev::uri::parse(&base)?;
+
+        Ok(Self {
+            name: url.path()[1 ..].into(),
+            url: url.clone(),
+            base,
+            uuid,
+        })
+    }
+}
+
+impl GetName for Ftl {
+    fn get_name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+impl Ftl {
+    /// Returns the alias of the device, which is its URI with the UUID of
+    /// the translation layer in place of the mode, so that it restores the
+    /// layer rather than creating a new one.
+    fn alias(&self, uuid: uuid::Uuid) -> String {
+        if self.uuid.is_some() {
+            return self.url.to_string();
+        }
+
+        let mut url = self.url.clone();
+        url.query_pairs_mut()
+            .clear()
+            .extend_pairs(self.url.query_pairs().filter(|(k, _)| k != "mode"))
+            .append_pair("uuid", &uuid.to_hyphenated().to_string());
+        url.to_string()
+    }
+
+    /// Creates the translation layer on the zoned device of the given name.
+    async fn create_ftl(&self, base_name: &str) -> Result<uuid::Uuid, Errno> {
+        extern "C" fn ftl_create_cb(
+            info: *const ftl_bdev_info,
+            arg: *mut c_void,
+            errno: c_int,
+        ) {
+            let sender = unsafe {
+                Box::from_raw(
+                    arg as *mut oneshot::Sender<ErrnoResult<uuid::Uuid>>,
+                )
+            };
+
+            let uuid = if errno == 0 {
+                uuid::Uuid::from_bytes(unsafe { (*info).uuid.u.raw })
+            } else {
+                uuid::Uuid::nil()
+            };
+
+            sender
+                .send(errno_result_from_i32(uuid, errno))
+                .expect("done callback receiver side disappeared");
+        }
+
+        let cname = self.name.clone().into_cstring();
+        let cbase = base_name.into_cstring();
+
+        let mut opts = ftl_bdev_init_opts {
+            name: cname.as_ptr(),
+            base_bdev: cbase.as_ptr(),
+            cache_bdev: std::ptr::null(),
+            ..Default::default()
+        };
+        unsafe { spdk_ftl_conf_init_defaults(&mut opts.ftl_conf) };
+
+        match self.uuid {
+            Some(uuid) => opts.uuid.u.raw = *uuid.as_bytes(),
+            None => opts.mode = SPDK_FTL_MODE_CREATE,
+        }
+
+        let (sender, receiver) = oneshot::channel::<ErrnoResult<uuid::Uuid>>();
+
+        let errno = unsafe {
+            bdev_ftl_create_bdev(&opts, Some(ftl_create_cb), cb_arg(sender))
+        };
+        errno_result_from_i32((), errno)?;
+
+        receiver.await.expect("Cancellation is not supported")
+    }
+}
+
+#[async_trait(?Send)]
+impl CreateDestroy for Ftl {
+    type Error = NexusBdevError;
+
+    async fn create(&self) -> Result<String, Self::Error> {
+        if UntypedBdev::lookup_by_name(&self.name).is_some() {
+            return Err(NexusBdevError::BdevExists {
+                name: self.name.clone(),
+            });
+        }
+
+        let base = dev::uri::parse(&self.base)?;
+        let (base_name, base_created) = match base.create().await {
+            Ok(name) => (name, true),
+            Err(NexusBdevError::BdevExists {
+                ..
+            }) => (base.get_name(), false),
+            Err(e) => return Err(e),
+        };
+
+        let zoned = SpdkBlockDevice::lookup_by_name(&base_name)
+            .map_or(false, |device| device.zone_geometry().is_some());
+
+        let result = if zoned {
+            self.create_ftl(&base_name).await.map_err(|source| {
+                NexusBdevError::CreateBdev {
+                    source,
+                    name: self.name.clone(),
+                }
+            })
+        } else {
+            Err(NexusBdevError::UriInvalid {
+                uri: self.url.to_string(),
+                message: format!("base device {} is not zoned", base_name),
+            })
+        };
+
+        let uuid = match result {
+            Ok(uuid) => uuid,
+            Err(e) => {
+                if base_created {
+                    if let Err(error) = base.destroy().await {
+                        error!(
+                            "failed to destroy base device {} of {}: {}",
+                            base_name, self.name, error
+                        );
+                    }
+                }
+                return Err(e);
+            }
+        };
+
+        info!(
+            "created flash translation layer {} with uuid {} on {}",
+            self.name, uuid, base_name
+        );
+
+        if base_created {
+            FTL_OWNED_BASES.lock().insert(self.name.clone());
+        }
+
+        match UntypedBdev::lookup_by_name(&self.name) {
+            Some(mut bdev) => {
+                let alias = self.alias(uuid);
+                if !bdev.add_alias(&alias) {
+                    error!(
+                        "failed to add alias {} to device {}",
+                        alias,
+                        self.get_name()
+                    );
+                }
+                Ok(self.name.clone())
+            }
+            None => Err(NexusBdevError::BdevNotFound {
+                name: self.name.clone(),
+            }),
+        }
+    }
+
+    async fn destroy(self: Box<Self>) -> Result<(), Self::Error> {
+        match UntypedBdev::lookup_by_name(&self.name) {
+            Some(mut bdev) => {
+                bdev.remove_alias(&self.alias(bdev.uuid()));
+                let (sender, receiver) = oneshot::channel::<ErrnoResult<()>>();
+                unsafe {
+                    bdev_ftl_delete_bdev(
+                        self.name.clone().into_cstring().as_ptr(),
+                        Some(done_errno_cb),
+                        cb_arg(sender),
+                    );
+                }
+                receiver
+                    .await
+                    .context(nexus_uri::CancelBdev {
+                        name: self.get_name(),
+                    })?
+                    .context(nexus_uri::DestroyBdev {
+                        name: self.get_name(),
+                    })?;
+
+                if FTL_OWNED_BASES.lock().remove(&self.name) {
+                    dev::uri::parse(&self.base)?.destroy().await
+                } else {
+                    Ok(())
+                }
+            }
+            None => Err(NexusBdevError::BdevNotFound {
+                name: self.get_name(),
+            }),
+        }
+    }
+}
@@ -6,7 +6,9 @@ pub use nexus::{Nexus, NexusInfo, NexusState};
 pub use nvmx::{
     nvme_io_ctx_pool_init,
     NvmeController,
+    NvmeControllerHealthStats,
     NvmeControllerState,
+    NvmeQpairStats,
     NVME_CONTROLLERS,
 };
 
@@ -14,6 +16,7 @@ mod aio;
 pub(crate) mod dev;
 pub(crate) use dev::uri;
 pub(crate) mod device;
+mod ftl;
 mod loopback;
 mod malloc;
 pub mod nexus;
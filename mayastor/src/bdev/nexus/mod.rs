@@ -13,7 +13,9 @@ mod nexus_io;
 mod nexus_iter;
 mod nexus_module;
 mod nexus_nbd;
+mod nexus_passthru;
 mod nexus_persistence;
+mod nexus_reservation;
 mod nexus_share;
 
 pub use nexus_bdev::{
@@ -21,6 +23,7 @@ pub use nexus_bdev::{
     nexus_create_v2,
     Error,
     Nexus,
+    NexusAnaParams,
     NexusNvmeParams,
     NexusState,
     NexusStatus,
@@ -29,6 +32,7 @@ pub use nexus_bdev::{
     VerboseError,
 };
 pub(crate) use nexus_bdev::{
+    ChildReservation,
     CreateChild,
     CreateRebuild,
     OpenChild,
@@ -38,7 +42,9 @@ pub(crate) use nexus_bdev::{
     ShareNbdNexus,
     ShareNvmfNexus,
     UnshareNexus,
+    UpdateAllowedHostsNexus,
     NEXUS_PRODUCT_ID,
+    NVME_MAX_ANA_GROUP,
 };
 pub(crate) use nexus_channel::{
     fault_nexus_child,
@@ -64,6 +70,14 @@ pub use nexus_iter::{
 };
 pub(crate) use nexus_module::{NexusModule, NEXUS_MODULE_NAME};
 pub(crate) use nexus_nbd::{NbdDisk, NbdError};
+pub(crate) use nexus_passthru::lookup_nexus_cmd_handler;
+pub use nexus_passthru::{
+    register_nexus_cmd_handler,
+    NexusCmd,
+    NexusCmdFuture,
+    NexusCmdHandler,
+    NexusCmdKind,
+};
 pub(crate) use nexus_persistence::PersistOp;
 pub use nexus_persistence::{ChildInfo, NexusInfo};
 
@@ -90,9 +104,10 @@ struct NexusShareReply {
 /// public function which simply calls register module
 pub fn register_module() {
     nexus_module::register_module();
+    nexus_passthru::register_nexus_cmd_handlers();
 
     use crate::{
-        core::{Share, UntypedBdev},
+        core::{MayastorFeatures, NvmfShareProps, Share, UntypedBdev},
         jsonrpc::{jsonrpc_register, Code, JsonRpcError, Result},
     };
 
@@ -112,7 +127,15 @@ pub fn register_module() {
                     let mut bdev = Pin::new(&mut bdev);
                     match proto.as_str() {
                         "nvmf" => {
-                            bdev.as_mut().share_nvmf(Some((args.cntlid_min, args.cntlid_max)))
+                            let props = NvmfShareProps::new()
+                                .with_range(Some((args.cntlid_min, args.cntlid_max)))
+                                .with_allow_any_host(true)
+                                .with_ana(
+                                    MayastorFeatures::get_features()
+                                        .asymmetric_namespace_access,
+                                    None,
+                                );
+                            bdev.as_mut().share_nvmf(Some(props))
                                 .await
                                 .map_err(|e| {
                                     JsonRpcError {
@@ -8,7 +8,6 @@ use std::{
     fmt::{Display, Formatter},
     marker::PhantomPinned,
     mem::MaybeUninit,
-    os::raw::c_void,
     pin::Pin,
 };
 
@@ -37,6 +36,7 @@ use super::{
 use crate::{
     bdev::{device_destroy, nexus::nexus_persistence::PersistentNexusInfo},
     core::{
+        poller::Poller,
         Bdev,
         BdevHandle,
         Command,
@@ -44,6 +44,7 @@ use crate::{
         Cores,
         DeviceEventSink,
         IoType,
+        MayastorFeatures,
         Protocol,
         Reactor,
         Share,
@@ -66,6 +67,8 @@ use spdk_rs::{
 
 pub static NVME_MIN_CNTLID: u16 = 1;
 pub static NVME_MAX_CNTLID: u16 = 0xffef;
+/// highest ANA group ID, a subsystem tracks the state of each group up to it
+pub static NVME_MAX_ANA_GROUP: u32 = 32;
 
 /// Obtain the full error chain
 pub trait VerboseError {
@@ -129,6 +132,8 @@ pub enum Error {
     ShareNvmfNexus { source: CoreError, name: String },
     #[snafu(display("Failed to unshare nexus {}", name))]
     UnshareNexus { source: CoreError, name: String },
+    #[snafu(display("Failed to update the allowed hosts of nexus {}", name))]
+    UpdateAllowedHostsNexus { source: CoreError, name: String },
     #[snafu(display(
         "Failed to register IO device nexus {}: {}",
         name,
@@ -177,6 +182,16 @@ pub enum Error {
         child: String,
         name: String,
     },
+    #[snafu(display(
+        "Failed to manage reservations of child {} of nexus {}",
+        child,
+        name
+    ))]
+    ChildReservation {
+        source: ChildError,
+        child: String,
+        name: String,
+    },
     #[snafu(display("Failed to open child {} of nexus {}", child, name))]
     OpenChild {
         source: ChildError,
@@ -370,12 +385,12 @@ pub enum NexusPauseState {
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum NvmeAnaState {
-    InvalidState, // invalid, do not use
-    OptimizedState,
-    NonOptimizedState,
-    InaccessibleState,
-    PersistentLossState, // not yet supported
-    ChangeState,         // not yet supported
+    InvalidState = 0, // invalid, do not use
+    OptimizedState = 1,
+    NonOptimizedState = 2,
+    InaccessibleState = 3,
+    PersistentLossState = 4, // not supported by the target
+    ChangeState = 15,        // only passed through while changing state
 }
 
 impl NvmeAnaState {
@@ -436,6 +451,29 @@ impl NexusNvmeParams {
     }
 }
 
+/// Asymmetric Namespace Access (ANA) parameters for the Nexus. Nexuses of the
+/// same volume on different nodes can be published under a shared subsystem
+/// NQN, each with its own ANA group, so hosts can fail over between them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NexusAnaParams {
+    /// report the ANA state of the nexus to hosts
+    pub reporting: bool,
+    /// ANA group of the nexus namespace, derived from the namespace when None
+    pub group_id: Option<u32>,
+    /// NQN of the subsystem, derived from the nexus name when None
+    pub subsystem_nqn: Option<String>,
+}
+
+impl Default for NexusAnaParams {
+    fn default() -> Self {
+        NexusAnaParams {
+            reporting: false,
+            group_id: None,
+            subsystem_nqn: None,
+        }
+    }
+}
+
 /// The main nexus structure
 #[derive(Debug)]
 pub struct Nexus<'n> {
@@ -464,6 +502,15 @@ pub struct Nexus<'n> {
     pub(crate) share_handle: Option<String>,
     /// enum containing the protocol-specific target used to publish the nexus
     pub nexus_target: Option<NexusTarget>,
+    /// NQNs of the hosts allowed to connect when published over NVMe-oF
+    pub(crate) allowed_hosts: Vec<String>,
+    /// any host may connect when published over NVMe-oF, which is the case
+    /// until the allowed hosts are set
+    pub(crate) allow_any_host: bool,
+    /// ANA parameters used when published over NVMe-oF
+    pub(crate) ana_params: NexusAnaParams,
+    /// watches the reservations of hosts when published over NVMe-oF
+    pub(crate) reservation_poller: Option<Poller<'n>>,
     /// Indicates if the Nexus has an I/O device.
     has_io_device: bool,
     /// Nexus pause counter to allow concurrent pause/resume.
@@ -572,6 +619,15 @@ impl<'n> Nexus<'n> {
             share_handle: None,
             req_size: size,
             nexus_target: None,
+            allowed_hosts: Vec::new(),
+            allow_any_host: true,
+            // reported when enabled for all nexuses, otherwise on publish
+            ana_params: NexusAnaParams {
+                reporting: MayastorFeatures::get_features()
+                    .asymmetric_namespace_access,
+                ..Default::default()
+            },
+            reservation_poller: None,
             nvme_params,
             has_io_device: false,
             pause_state: AtomicCell::new(NexusPauseState::Unpaused),
@@ -1060,11 +1116,18 @@ impl<'n> Nexus<'n> {
         self.update_failfast(false, None).await
     }
 
+    /// ANA group of the nexus namespace, which is the only namespace of the
+    /// subsystem
+    fn ana_group(&self) -> u32 {
+        self.ana_params.group_id.unwrap_or(1)
+    }
+
     /// get ANA state of the NVMe subsystem
     pub async fn get_ana_state(&self) -> Result<NvmeAnaState, Error> {
         if let Some(Protocol::Nvmf) = self.shared() {
             if let Some(subsystem) = NvmfSubsystem::nqn_lookup(&self.name) {
-                let ana_state = subsystem.get_ana_state().await? as i32;
+                let ana_state =
+                    subsystem.get_ana_state(self.ana_group()).await? as i32;
                 return NvmeAnaState::from_i32(ana_state);
             }
         }
@@ -1082,7 +1145,9 @@ impl<'n> Nexus<'n> {
         if let Some(Protocol::Nvmf) = self.shared() {
             if let Some(subsystem) = NvmfSubsystem::nqn_lookup(&self.name) {
                 subsystem.pause().await?;
-                let res = subsystem.set_ana_state(ana_state as u32).await;
+                let res = subsystem
+                    .set_ana_state(ana_state as u32, self.ana_group())
+                    .await;
                 subsystem.resume().await?;
                 return Ok(res?);
             }
@@ -1104,11 +1169,6 @@ impl<'n> Nexus<'n> {
             .any(|b| !b.io_type_supported(io_type))
     }
 
-    /// IO completion for local replica
-    pub fn io_completion_local(_success: bool, _parent_io: *mut c_void) {
-        unimplemented!();
-    }
-
     /// Status of the nexus
     /// Online
     /// All children must also be online
@@ -1301,8 +1361,16 @@ impl<'n> BdevOps for Nexus<'n> {
     fn io_type_supported(&self, io_type: IoType) -> bool {
         match io_type {
             // we always assume the device supports read/write commands
-            // allow NVMe Admin as it is needed for local replicas
-            IoType::Read | IoType::Write | IoType::NvmeAdmin => true,
+            // NVMe passthrough commands are processed by the handlers
+            // registered with the nexus, and fail when there is none.
+            // Compares are done by the nexus itself, reading the data back
+            // from children which cannot compare.
+            IoType::Read
+            | IoType::Write
+            | IoType::NvmeAdmin
+            | IoType::NvmeIo
+            | IoType::Compare
+            | IoType::CompareAndWrite => true,
             IoType::Flush
             | IoType::Reset
             | IoType::Unmap
@@ -31,6 +31,7 @@ use snafu::ResultExt;
 use super::{
     fault_nexus_child,
     nexus_iter_mut,
+    ChildReservation,
     ChildState,
     CreateChild,
     DrEvent,
@@ -51,7 +52,13 @@ use crate::{
         device_lookup,
         nexus::nexus_persistence::PersistOp,
     },
-    core::{partition, DeviceEventListener, DeviceEventType, Reactors},
+    core::{
+        partition,
+        DeviceEventListener,
+        DeviceEventType,
+        Reactors,
+        ReservationReport,
+    },
     nexus_uri::NexusBdevError,
 };
 
@@ -713,6 +720,61 @@ impl<'n> Nexus<'n> {
             .find(|c| c.match_device_name(device_name))
     }
 
+    /// Looks up a child by its URL, without requiring mutable access.
+    fn child_by_name(&self, name: &str) -> Result<&NexusChild<'n>, Error> {
+        self.children
+            .iter()
+            .find(|c| c.get_name() == name)
+            .ok_or_else(|| Error::ChildNotFound {
+                child: name.to_owned(),
+                name: self.name.clone(),
+            })
+    }
+
+    /// Get the NVMe reservations held on the child with the given URL.
+    pub async fn child_reservations(
+        &self,
+        uri: &str,
+    ) -> Result<ReservationReport, Error> {
+        self.child_by_name(uri)?.reservations().await.context(
+            ChildReservation {
+                child: uri.to_owned(),
+                name: self.name.clone(),
+            },
+        )
+    }
+
+    /// Preempt a stale registration on the child with the given URL, the
+    /// nexus takes over the reservation using its reservation key.
+    pub async fn preempt_child_reservation(
+        &self,
+        uri: &str,
+        preempt_key: u64,
+    ) -> Result<ReservationReport, Error> {
+        self.child_by_name(uri)?
+            .preempt_reservation(self.nvme_params.resv_key, preempt_key)
+            .await
+            .context(ChildReservation {
+                child: uri.to_owned(),
+                name: self.name.clone(),
+            })
+    }
+
+    /// Clear all reservations and registrations on the child with the given
+    /// URL.
+    pub async fn clear_child_reservations(
+        &self,
+        uri: &str,
+    ) -> Result<ReservationReport, Error> {
+        self.child_by_name(uri)?
+            .clear_reservations(self.nvme_params.resv_key)
+            .await
+            .context(ChildReservation {
+                child: uri.to_owned(),
+                name: self.name.clone(),
+            })
+    }
+
     /// Looks up a child by its URL.
     pub fn get_child_by_name(
         self: Pin<&mut Self>,
@@ -786,6 +848,51 @@ impl<'n> DeviceEventListener for Nexus<'n> {
                     dev_name
                 );
             }
+            DeviceEventType::DeviceResized => {
+                let nexus_size = self.req_size;
+                let child_size = match self
+                    .lookup_child(dev_name)
+                    .and_then(|c| c.get_device().ok())
+                {
+                    Some(dev) => dev.size_in_bytes(),
+                    None => {
+                        warn!(
+                            "No nexus child exists for device {}, ignoring device resize event",
+                            dev_name
+                        );
+                        return;
+                    }
+                };
+
+                if child_size >= nexus_size {
+                    info!(
+                        "{}: child {} resized to {} bytes",
+                        self.name, dev_name, child_size
+                    );
+                    return;
+                }
+
+                error!(
+                    "{}: child {} shrunk to {} bytes, nexus size: {}",
+                    self.name, dev_name, child_size, nexus_size
+                );
+                for mut nexus in nexus_iter_mut() {
+                    if fault_nexus_child(nexus.as_mut(), dev_name) {
+                        let child_dev = dev_name.to_string();
+                        Reactors::master().send_future(async move {
+                            let child_dev2 = child_dev.clone();
+                            if let Err(e) = nexus.child_retire(child_dev).await
+                            {
+                                warn!(
+                                    "retiring child {} returned {}",
+                                    child_dev2, e
+                                );
+                            }
+                        });
+                        return;
+                    }
+                }
+            }
             _ => {
                 info!("Ignoring {:?} event for device {}", evt, dev_name);
             }
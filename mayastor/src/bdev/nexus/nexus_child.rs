@@ -15,6 +15,9 @@ use super::{nexus_iter_mut, nexus_lookup_mut, DrEvent, VerboseError};
 use crate::{
     bdev::{device_create, device_destroy, device_lookup},
     core::{
+        clear_reservations,
+        preempt_reservation,
+        reservation_report,
         BlockDevice,
         BlockDeviceDescriptor,
         BlockDeviceHandle,
@@ -22,6 +25,7 @@ use crate::{
         DeviceEventSink,
         Reactor,
         Reactors,
+        ReservationReport,
     },
     nexus_uri::NexusBdevError,
     persistent_store::PersistentStore,
@@ -29,15 +33,10 @@ use crate::{
 };
 
 use spdk_rs::{
-    libspdk::{
-        spdk_nvme_registered_ctrlr_extended_data,
-        spdk_nvme_reservation_status_extended_data,
-    },
     nvme_reservation_acquire_action,
     nvme_reservation_register_action,
     nvme_reservation_register_cptpl,
     nvme_reservation_type,
-    DmaError,
 };
 
 #[derive(Debug, Snafu)]
@@ -70,8 +69,6 @@ pub enum ChildError {
     HandleCreate { source: CoreError },
     #[snafu(display("Failed to open a BlockDeviceHandle for child"))]
     HandleOpen { source: CoreError },
-    #[snafu(display("Failed to allocate DmaBuffer for child"))]
-    HandleDmaMalloc { source: DmaError },
     #[snafu(display("Failed to register key for child: {}", source))]
     ResvRegisterKey { source: CoreError },
     #[snafu(display("Failed to acquire reservation for child: {}", source))]
@@ -81,6 +78,8 @@ pub enum ChildError {
         source
     ))]
     ResvReport { source: CoreError },
+    #[snafu(display("Failed to release reservation for child: {}", source))]
+    ResvRelease { source: CoreError },
     #[snafu(display("Failed to get NVMe host ID: {}", source))]
     NvmeHostId { source: CoreError },
     #[snafu(display("Failed to create a BlockDevice for child {}", child))]
@@ -343,64 +342,82 @@ impl<'c> NexusChild<'c> {
         &self,
         hdl: &dyn BlockDeviceHandle,
     ) -> Result<Option<(u64, [u8; 16])>, ChildError> {
-        let mut buffer = hdl.dma_malloc(4096).context(HandleDmaMalloc {})?;
-        if let Err(e) = hdl.nvme_resv_report(1, &mut buffer).await {
-            return Err(ChildError::ResvReport {
-                source: e,
-            });
-        }
+        let report = reservation_report(hdl).await.context(ResvReport {})?;
         trace!(
-            "{}: received reservation report for child {}",
+            "{}: received reservation report for child {}: rtype {}, \
+            regctl {}, ptpls {}",
             self.parent,
-            self.name
-        );
-        let (stext, sl) = buffer.as_slice().split_at(std::mem::size_of::<
-            spdk_nvme_reservation_status_extended_data,
-        >());
-        let (pre, resv_status_ext, post) = unsafe {
-            stext.align_to::<spdk_nvme_reservation_status_extended_data>()
-        };
-        assert!(pre.is_empty());
-        assert!(post.is_empty());
-        let regctl = resv_status_ext[0].data.regctl;
-        trace!(
-            "reservation status: rtype {}, regctl {}, ptpls {}",
-            resv_status_ext[0].data.rtype,
-            regctl,
-            resv_status_ext[0].data.ptpls,
+            self.name,
+            report.rtype,
+            report.registrants.len(),
+            report.ptpl,
         );
-        let (pre, reg_ctrlr_ext, _post) = unsafe {
-            sl.align_to::<spdk_nvme_registered_ctrlr_extended_data>()
-        };
-        if !pre.is_empty() {
-            return Ok(None);
-        }
-        let mut numctrlr: usize = regctl.into();
-        if numctrlr > reg_ctrlr_ext.len() {
-            numctrlr = reg_ctrlr_ext.len();
-            warn!(
-                "Expecting data for {} controllers, received {}",
-                regctl, numctrlr
-            );
-        }
-        for (i, c) in reg_ctrlr_ext.iter().enumerate().take(numctrlr) {
-            let cntlid = c.cntlid;
-            let rkey = c.rkey;
+        for (i, r) in report.registrants.iter().enumerate() {
             trace!(
-                "ctrlr {}: cntlid {:0x}h, status {}, hostid {:0x?}, rkey {:0x}h",
+                "ctrlr {}: cntlid {:0x}h, holder {}, hostid {:0x?}, rkey {:0x}h",
                 i,
-                cntlid,
-                c.rcsts.status(),
-                c.hostid,
-                rkey,
+                r.cntlid,
+                r.holder,
+                r.host_id,
+                r.key,
             );
-            if resv_status_ext[0].data.rtype == 1 && c.rcsts.status() == 1 {
-                return Ok(Some((rkey, c.hostid)));
+            if report.rtype == 1 && r.holder {
+                return Ok(Some((r.key, r.host_id)));
             }
         }
         Ok(None)
     }
 
+    /// Get the NVMe reservations held on the child.
+    pub(crate) async fn reservations(
+        &self,
+    ) -> Result<ReservationReport, ChildError> {
+        let hdl = self.get_io_handle().context(HandleOpen {})?;
+        reservation_report(&*hdl).await.context(ResvReport {})
+    }
+
+    /// Preempt the registration, and reservation, of the host registered with
+    /// preempt_key, which typically is a nexus which no longer exists. The
+    /// nexus takes over the write exclusive reservation with its own key.
+    pub(crate) async fn preempt_reservation(
+        &self,
+        key: u64,
+        preempt_key: u64,
+    ) -> Result<ReservationReport, ChildError> {
+        let hdl = self.get_io_handle().context(HandleOpen {})?;
+        preempt_reservation(
+            &*hdl,
+            key,
+            preempt_key,
+            nvme_reservation_type::WRITE_EXCLUSIVE_ALL_REGS,
+        )
+        .await
+        .context(ResvAcquire {})?;
+        info!(
+            "{}: preempted key {:0x}h on child {}",
+            self.parent, preempt_key, self.name
+        );
+        reservation_report(&*hdl).await.context(ResvReport {})
+    }
+
+    /// Release the reservation and remove all registrations on the child,
+    /// the nexus too only takes its reservation again when the child is
+    /// re-opened.
+    pub(crate) async fn clear_reservations(
+        &self,
+        key: u64,
+    ) -> Result<ReservationReport, ChildError> {
+        let hdl = self.get_io_handle().context(HandleOpen {})?;
+        clear_reservations(&*hdl, key)
+            .await
+            .context(ResvRelease {})?;
+        info!(
+            "{}: cleared reservations on child {}",
+            self.parent, self.name
+        );
+        reservation_report(&*hdl).await.context(ResvReport {})
+    }
+
     /// Register an NVMe reservation on the child then acquire a write
     /// exclusive reservation, preempting an existing reservation, if another
     /// host has it.
@@ -4,35 +4,59 @@ use std::{
     pin::Pin,
 };
 
+use futures::channel::oneshot;
 use libc::c_void;
 use nix::errno::Errno;
+use snafu::ResultExt;
 
 use spdk_rs::{
-    libspdk::{spdk_bdev_io, spdk_io_channel},
+    libspdk::{
+        spdk_bdev_io,
+        spdk_bdev_io_complete_nvme_status,
+        spdk_io_channel,
+        SPDK_NVME_SCT_GENERIC,
+        SPDK_NVME_SCT_MEDIA_ERROR,
+        SPDK_NVME_SC_COMPARE_FAILURE,
+        SPDK_NVME_SC_INVALID_OPCODE,
+    },
     BdevIo,
+    IoVec,
 };
 
 use super::{
+    lookup_nexus_cmd_handler,
     nexus_lookup_mut,
     Nexus,
     NexusChannel,
     NexusChannelInner,
+    NexusCmd,
+    NexusCmdKind,
     NexusStatus,
     NEXUS_PRODUCT_ID,
 };
 
-use crate::core::{
-    BlockDevice,
-    BlockDeviceHandle,
-    CoreError,
-    Cores,
-    GenericStatusCode,
-    IoCompletionStatus,
-    IoStatus,
-    IoType,
-    Mthread,
-    NvmeCommandStatus,
-    Reactors,
+use crate::{
+    bdev::device_lookup,
+    core::{
+        BlockDevice,
+        BlockDeviceHandle,
+        CoreError,
+        Cores,
+        GenericStatusCode,
+        IoCompletionCallback,
+        IoCompletionCallbackArg,
+        IoCompletionStatus,
+        IoStatus,
+        IoType,
+        LockLbaRange,
+        Mthread,
+        NvmeCommandStatus,
+        RangeContext,
+        Reactors,
+        UnlockLbaRange,
+        UntypedBdev,
+    },
+    ffihelper::cb_arg,
 };
 
 /// TODO
@@ -104,6 +128,7 @@ impl<'n> NexusBio<'n> {
     fn submit_request(mut self) {
         if let Err(_e) = match self.io_type() {
             IoType::Read => self.readv(),
+            IoType::Compare | IoType::CompareAndWrite => self.submit_compare(),
             // these IOs are submitted to all the underlying children
             IoType::Write
             | IoType::WriteZeros
@@ -113,12 +138,8 @@ impl<'n> NexusBio<'n> {
                 self.ok();
                 Ok(())
             }
-            IoType::NvmeAdmin => {
-                self.fail();
-                Err(CoreError::NotSupported {
-                    source: Errno::EINVAL,
-                })
-            }
+            // passthrough commands are processed by the registered handlers
+            IoType::NvmeAdmin | IoType::NvmeIo => self.submit_passthru(),
             _ => {
                 trace!(?self, "not supported");
                 self.fail();
@@ -209,6 +230,18 @@ impl<'n> NexusBio<'n> {
         }
     }
 
+    /// Complete the IO with a compare failure.
+    fn miscompare(&mut self) {
+        unsafe {
+            spdk_bdev_io_complete_nvme_status(
+                self.as_ptr(),
+                0,
+                SPDK_NVME_SCT_MEDIA_ERROR as i32,
+                SPDK_NVME_SC_COMPARE_FAILURE as i32,
+            );
+        }
+    }
+
     /// retry this IO when all other IOs have completed
     #[inline]
     fn retry_checked(&mut self) {
@@ -380,6 +413,227 @@ impl<'n> NexusBio<'n> {
         hdl.reset(Self::child_completion, self.as_ptr().cast())
     }
 
+    /// Compare the data of the IO with the data of the children, and for a
+    /// compare-and-write write it to all children when it matches. The IO
+    /// completes with the outcome once done.
+    fn submit_compare(&mut self) -> Result<(), CoreError> {
+        let io = self.as_ptr();
+        Reactors::current().send_future(async move {
+            let mut bio = NexusBio::from(io);
+            let result = if matches!(bio.io_type(), IoType::CompareAndWrite) {
+                bio.locked_compare_and_write().await
+            } else {
+                bio.compare().await
+            };
+            match result {
+                Ok(IoCompletionStatus::Success) => bio.ok(),
+                // A miscompare is the outcome of the compare rather than a
+                // failure of the child, which remains in service.
+                Ok(IoCompletionStatus::Miscompare) => bio.miscompare(),
+                Ok(status) => {
+                    error!(?bio, "compare failed: {:?}", status);
+                    bio.fail();
+                }
+                Err(e) => {
+                    error!(?bio, "compare failed: {}", e);
+                    bio.fail();
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Compare the data of the IO with the data on one child. Children which
+    /// cannot compare have the data read back, and compared here. The child
+    /// is opened for the compare as the handles of the channel may go away
+    /// while it is in flight.
+    async fn compare(&mut self) -> Result<IoCompletionStatus, CoreError> {
+        let i = self
+            .inner_channel_mut()
+            .child_select()
+            .ok_or(CoreError::NoDevicesAvailable {})?;
+        let name = self.read_channel_at_index(i).get_device().device_name();
+        let hdl = device_lookup(&name)
+            .ok_or(CoreError::NoDevicesAvailable {})?
+            .open(false)?
+            .into_handle()?;
+
+        let offset = self.offset() + self.data_ent_offset();
+        if hdl.get_device().io_type_supported(IoType::Compare) {
+            let (iovs, iovcnt) = (self.iovs(), self.iov_count());
+            let num_blocks = self.num_blocks();
+            return Ok(submit_child_io(|cb, arg| {
+                hdl.comparev_blocks(iovs, iovcnt, offset, num_blocks, cb, arg)
+            })?
+            .await
+            .expect("child I/O completion dropped"));
+        }
+
+        let block_len = self.nexus_as_ref().block_len();
+        let size = self.num_blocks() * block_len;
+        let mut buf = hdl.dma_malloc(size).map_err(|_| {
+            CoreError::DmaAllocationError {
+                size,
+            }
+        })?;
+        hdl.read_at(offset * block_len, &mut buf).await?;
+
+        let iovs = unsafe {
+            std::slice::from_raw_parts(self.iovs(), self.iov_count() as usize)
+        };
+        if iovs_match(iovs, buf.as_slice()) {
+            Ok(IoCompletionStatus::Success)
+        } else {
+            Ok(IoCompletionStatus::Miscompare)
+        }
+    }
+
+    /// Compare-and-write must be atomic across all children, which fused
+    /// commands to each child cannot guarantee. The LBA range of the nexus
+    /// is locked instead, while the data is compared on one child and, when
+    /// it matches, written to all children.
+    async fn locked_compare_and_write(
+        &mut self,
+    ) -> Result<IoCompletionStatus, CoreError> {
+        let name = self.nexus_as_ref().name.clone();
+        let desc = UntypedBdev::open_by_name(&name, false)?;
+        let ch = desc.get_channel().ok_or(CoreError::GetIoChannel {
+            name,
+        })?;
+
+        let (offset, len) = (self.offset(), self.num_blocks());
+        let mut ctx = RangeContext::new(offset, len);
+        desc.lock_lba_range(&mut ctx, &ch)
+            .await
+            .context(LockLbaRange {
+                offset,
+                len,
+            })?;
+
+        let result = match self.compare().await {
+            Ok(IoCompletionStatus::Success) => self.write_all_fused().await,
+            result => result,
+        };
+
+        desc.unlock_lba_range(&mut ctx, &ch)
+            .await
+            .context(UnlockLbaRange {
+                offset,
+                len,
+            })?;
+        result
+    }
+
+    /// Write the data of a compare-and-write to all children, the children
+    /// which fail the write are taken out as for a regular write.
+    async fn write_all_fused(
+        &mut self,
+    ) -> Result<IoCompletionStatus, CoreError> {
+        let (iovs, iovcnt) = unsafe {
+            let bdev = &(*self.as_ptr()).u.bdev;
+            (bdev.fused_iovs, bdev.fused_iovcnt)
+        };
+        let offset = self.offset() + self.data_ent_offset();
+        let num_blocks = self.num_blocks();
+
+        // all writes are submitted before any completes, so the handles of
+        // the channel are not kept while waiting
+        let mut pending = Vec::new();
+        let mut failed = Vec::new();
+        for h in self.inner_channel().writers.iter() {
+            let device = h.get_device().device_name();
+            match submit_child_io(|cb, arg| {
+                h.writev_blocks(iovs, iovcnt, offset, num_blocks, cb, arg)
+            }) {
+                Ok(r) => pending.push((device, r)),
+                Err(e) => {
+                    error!(?self, "{} write submission failed: {}", device, e);
+                    failed.push(device);
+                }
+            }
+        }
+
+        let mut written = 0;
+        for (device, r) in pending {
+            match r.await.expect("child I/O completion dropped") {
+                IoCompletionStatus::Success => written += 1,
+                status => {
+                    error!(?self, "{} write failed: {:?}", device, status);
+                    failed.push(device);
+                }
+            }
+        }
+
+        for device in failed {
+            if self.inner_channel_mut().fault_child(&device) {
+                self.do_retire(device);
+            }
+        }
+
+        if written == 0 {
+            return Err(CoreError::WriteFailed {
+                offset,
+                len: num_blocks,
+            });
+        }
+        Ok(IoCompletionStatus::Success)
+    }
+
+    /// Process an NVMe passthrough command with the handler registered for
+    /// it. The handler runs asynchronously on this core, and the IO completes
+    /// with its outcome. Commands without a handler fail with an invalid
+    /// opcode status.
+    fn submit_passthru(&mut self) -> Result<(), CoreError> {
+        let kind = if matches!(self.io_type(), IoType::NvmeAdmin) {
+            NexusCmdKind::Admin
+        } else {
+            NexusCmdKind::Io
+        };
+        let (cmd, buf, len) = unsafe {
+            let passthru = &(*self.as_ptr()).u.nvme_passthru;
+            (passthru.cmd, passthru.buf, passthru.nbytes as usize)
+        };
+
+        let handler = match lookup_nexus_cmd_handler(kind, &cmd) {
+            Some(handler) => handler,
+            None => {
+                trace!(?self, "no handler for opcode {:#x}", cmd.opc());
+                unsafe {
+                    spdk_bdev_io_complete_nvme_status(
+                        self.as_ptr(),
+                        0,
+                        SPDK_NVME_SCT_GENERIC as i32,
+                        SPDK_NVME_SC_INVALID_OPCODE as i32,
+                    );
+                }
+                return Err(CoreError::NotSupported {
+                    source: Errno::EOPNOTSUPP,
+                });
+            }
+        };
+
+        let name = self.nexus_as_ref().name.clone();
+        let io = self.as_ptr();
+        Reactors::current().send_future(async move {
+            let mut bio = NexusBio::from(io);
+            match (handler.run)(NexusCmd::new(name.clone(), cmd, buf, len))
+                .await
+            {
+                Ok(_) => bio.ok(),
+                Err(e) => {
+                    error!(
+                        "{}: passthrough command {:#x} failed: {}",
+                        name,
+                        cmd.opc(),
+                        e
+                    );
+                    bio.fail();
+                }
+            }
+        });
+        Ok(())
+    }
+
     /// Submit the IO to all underlying children, failing on the first error we
     /// find. When an IO is partially submitted -- we must wait until all
     /// the child IOs have completed before we mark the whole IO failed to
@@ -513,6 +767,60 @@ impl<'n> NexusBio<'n> {
     }
 }
 
+/// Completes a child I/O submitted by [`submit_child_io`].
+fn child_io_done(
+    _device: &dyn BlockDevice,
+    status: IoCompletionStatus,
+    ctx: *mut c_void,
+) {
+    let s = unsafe {
+        Box::from_raw(ctx as *mut oneshot::Sender<IoCompletionStatus>)
+    };
+    let _ = s.send(status);
+}
+
+/// Submits a child I/O with the given function, returning a receiver of its
+/// completion status.
+fn submit_child_io<F>(
+    submit: F,
+) -> Result<oneshot::Receiver<IoCompletionStatus>, CoreError>
+where
+    F: FnOnce(
+        IoCompletionCallback,
+        IoCompletionCallbackArg,
+    ) -> Result<(), CoreError>,
+{
+    let (s, r) = oneshot::channel::<IoCompletionStatus>();
+    let arg = cb_arg(s);
+    if let Err(e) = submit(child_io_done, arg) {
+        // the completion is never called when the submission fails
+        drop(unsafe {
+            Box::from_raw(arg as *mut oneshot::Sender<IoCompletionStatus>)
+        });
+        return Err(e);
+    }
+    Ok(r)
+}
+
+/// Returns whether the data of the I/O vectors equals the given data.
+fn iovs_match(iovs: &[IoVec], data: &[u8]) -> bool {
+    let mut offset = 0;
+    for iov in iovs {
+        let len = iov.iov_len as usize;
+        if offset + len > data.len() {
+            return false;
+        }
+        let chunk = unsafe {
+            std::slice::from_raw_parts(iov.iov_base as *const u8, len)
+        };
+        if chunk != &data[offset .. offset + len] {
+            return false;
+        }
+        offset += len;
+    }
+    offset == data.len()
+}
+
 /// TODO
 pub(crate) fn nexus_submit_request(
     chan: spdk_rs::IoChannel<NexusChannel>,
@@ -539,3 +847,36 @@ async fn nexus_child_retire(nexus_name: String, device: String) {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use spdk_rs::IoVec;
+
+    use super::iovs_match;
+
+    fn iov(data: &mut [u8]) -> IoVec {
+        let mut iov = IoVec::default();
+        iov.iov_base = data.as_mut_ptr().cast();
+        iov.iov_len = data.len() as _;
+        iov
+    }
+
+    #[test]
+    fn iovs_match_data() {
+        let mut first = vec![1u8; 512];
+        let mut second = vec![2u8; 512];
+        let iovs = [iov(&mut first), iov(&mut second)];
+
+        let mut data = vec![1u8; 512];
+        data.extend(vec![2u8; 512]);
+        assert!(iovs_match(&iovs, &data));
+
+        // a differing byte in the second vector
+        data[700] = 3;
+        assert!(!iovs_match(&iovs, &data));
+
+        // the vectors must cover all of the data, and not more
+        assert!(!iovs_match(&iovs[.. 1], &vec![1u8; 1024]));
+        assert!(!iovs_match(&iovs, &vec![1u8; 512]));
+    }
+}
@@ -0,0 +1,533 @@
+//! Passthrough of NVMe admin and I/O commands received by a nexus.
+//!
+//! NVMe commands which the NVMe-oF target does not translate into regular
+//! block I/O reach the nexus as NVMe passthrough I/O. They are processed by
+//! the handlers registered for their opcode, which typically forward the
+//! command to the children of the nexus and combine their results. Handlers
+//! of admin commands are also registered with the target, so hosts connected
+//! to a published nexus reach them. Commands without a handler are failed
+//! with an invalid opcode status.
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    convert::TryFrom,
+    pin::Pin,
+    rc::Rc,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use futures::{future::Future, FutureExt};
+use libc::c_void;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
+use spdk_rs::{
+    libspdk::{
+        nvme_cmd_cdw10_get,
+        nvme_cmd_cdw10_get_val,
+        nvme_cmd_cdw11_get_val,
+        spdk_nvme_cmd,
+        spdk_nvme_ctrlr_data,
+        spdk_nvmf_ctrlr_identify_ctrlr,
+        SPDK_NVME_IDENTIFY_CTRLR,
+        SPDK_NVME_LOG_HEALTH_INFORMATION,
+        SPDK_NVME_OPC_GET_LOG_PAGE,
+        SPDK_NVME_OPC_IDENTIFY,
+    },
+    nvme_admin_opc,
+};
+
+use super::{nexus_lookup, ChildState, NEXUS_MODULE_NAME};
+use crate::{
+    core::{BlockDeviceHandle, CoreError, UntypedBdev},
+    lvs::Lvol,
+    subsys::{
+        self,
+        register_admin_cmd_handler,
+        set_snapshot_time,
+        NvmfCmdStatus,
+        NvmfReq,
+    },
+};
+
+/// Future processing an NVMe command received by a nexus
+pub type NexusCmdFuture = Pin<Box<dyn Future<Output = Result<(), CoreError>>>>;
+
+/// Kind of an NVMe command, admin and I/O commands have separate opcodes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NexusCmdKind {
+    Admin,
+    Io,
+}
+
+/// An NVMe command received by a nexus, along with its data buffer
+pub struct NexusCmd {
+    /// name of the nexus
+    pub nexus: String,
+    /// the NVMe command
+    pub cmd: spdk_nvme_cmd,
+    /// data buffer of the command, null when it has no data
+    buf: *mut c_void,
+    /// length of the data buffer
+    len: usize,
+}
+
+impl NexusCmd {
+    pub(crate) fn new(
+        nexus: String,
+        cmd: spdk_nvme_cmd,
+        buf: *mut c_void,
+        len: usize,
+    ) -> Self {
+        Self {
+            nexus,
+            cmd,
+            buf,
+            len,
+        }
+    }
+
+    /// length of the data buffer of the command
+    pub fn buf_len(&self) -> usize {
+        if self.buf.is_null() {
+            0
+        } else {
+            self.len
+        }
+    }
+
+    /// copies the data into the data buffer of the command, truncated to its
+    /// length
+    pub fn copy_to_buf(&self, data: &[u8]) {
+        let len = data.len().min(self.buf_len());
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                data.as_ptr(),
+                self.buf as *mut u8,
+                len,
+            );
+        }
+    }
+}
+
+/// Handler of an NVMe command received by a nexus
+#[derive(Clone, Copy)]
+pub struct NexusCmdHandler {
+    /// whether the handler processes the command, opcodes such as Get Log
+    /// Page carry many different commands
+    pub accepts: fn(&spdk_nvme_cmd) -> bool,
+    /// processes the command, the nexus I/O completes with the outcome
+    pub run: fn(NexusCmd) -> NexusCmdFuture,
+}
+
+type NexusCmdHandlers = HashMap<(NexusCmdKind, u8), Vec<NexusCmdHandler>>;
+
+/// handlers per kind and opcode, in order of registration
+static NEXUS_CMD_HANDLERS: Lazy<Mutex<NexusCmdHandlers>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// bumped on every registration, for threads to refresh their copy of the
+/// handlers
+static NEXUS_CMD_HANDLERS_GEN: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// copy of the handlers and the generation it was taken at, as handlers
+    /// are looked up for every passthrough command
+    static LOCAL_NEXUS_CMD_HANDLERS: RefCell<(usize, Rc<NexusCmdHandlers>)> =
+        RefCell::new((0, Rc::new(HashMap::new())));
+}
+
+/// Registers a handler for NVMe commands received by a nexus. Admin command
+/// handlers are registered with the NVMe-oF target as well, which forwards
+/// the commands they accept from hosts connected to a published nexus.
+pub fn register_nexus_cmd_handler(
+    kind: NexusCmdKind,
+    opcode: u8,
+    handler: NexusCmdHandler,
+) {
+    let mut handlers = NEXUS_CMD_HANDLERS.lock();
+    let entry = handlers.entry((kind, opcode)).or_default();
+    if entry.is_empty() && kind == NexusCmdKind::Admin {
+        register_admin_cmd_handler(opcode, nvmf_nexus_admin_hdlr);
+    }
+    entry.push(handler);
+    NEXUS_CMD_HANDLERS_GEN.fetch_add(1, Ordering::Release);
+}
+
+/// Returns the first handler registered for the command which accepts it.
+pub(crate) fn lookup_nexus_cmd_handler(
+    kind: NexusCmdKind,
+    cmd: &spdk_nvme_cmd,
+) -> Option<NexusCmdHandler> {
+    let handlers = LOCAL_NEXUS_CMD_HANDLERS.with(|local| {
+        let generation = NEXUS_CMD_HANDLERS_GEN.load(Ordering::Acquire);
+        let mut local = local.borrow_mut();
+        if local.0 != generation {
+            *local = (generation, Rc::new(NEXUS_CMD_HANDLERS.lock().clone()));
+        }
+        local.1.clone()
+    });
+    handlers
+        .get(&(kind, cmd.opc() as u8))?
+        .iter()
+        .find(|h| (h.accepts)(cmd))
+        .copied()
+}
+
+/// returns the bdev of the subsystem of the request, if it is a nexus
+fn nexus_bdev(req: &NvmfReq) -> Option<UntypedBdev> {
+    req.bdev().filter(|bd| bd.driver() == NEXUS_MODULE_NAME)
+}
+
+/// Forwards admin commands received on a published nexus to the nexus, when
+/// one of its handlers accepts the command.
+fn nvmf_nexus_admin_hdlr(req: &NvmfReq) -> NvmfCmdStatus {
+    if nexus_bdev(req).is_none()
+        || lookup_nexus_cmd_handler(NexusCmdKind::Admin, req.cmd()).is_none()
+    {
+        return NvmfCmdStatus::NotHandled;
+    }
+    req.passthru_admin()
+}
+
+/// Sets the firmware revision of the controller data to the version of
+/// mayastor, truncated to the length of the field and padded with spaces.
+fn set_firmware_revision(cdata: &mut spdk_nvme_ctrlr_data) {
+    let version = env!("CARGO_PKG_VERSION").as_bytes();
+    cdata.fr.iter_mut().for_each(|b| *b = b' ' as _);
+    cdata
+        .fr
+        .iter_mut()
+        .zip(version.iter())
+        .for_each(|(b, v)| *b = *v as _);
+}
+
+/// Identify Controller on a published nexus reports the version of mayastor
+/// as the firmware revision, rather than the version of SPDK.
+fn nvmf_nexus_identify_hdlr(req: &NvmfReq) -> NvmfCmdStatus {
+    let cns = unsafe { nvme_cmd_cdw10_get_val(req.cmd()) } & 0xff;
+    if cns != SPDK_NVME_IDENTIFY_CTRLR || nexus_bdev(req).is_none() {
+        return NvmfCmdStatus::NotHandled;
+    }
+
+    let mut cdata = spdk_nvme_ctrlr_data::default();
+    let rc = unsafe { spdk_nvmf_ctrlr_identify_ctrlr(req.ctrlr(), &mut cdata) };
+    if rc != 0 {
+        return NvmfCmdStatus::NotHandled;
+    }
+
+    set_firmware_revision(&mut cdata);
+    let data = unsafe {
+        std::slice::from_raw_parts(
+            &cdata as *const _ as *const u8,
+            std::mem::size_of::<spdk_nvme_ctrlr_data>(),
+        )
+    };
+    req.copy_from_buf(data);
+    req.set_status(0, 0);
+    NvmfCmdStatus::Complete
+}
+
+/// returns I/O handles to the open children of the nexus which are NVMe
+/// devices, as only these process NVMe commands
+fn nvme_children_handles(nexus: &str) -> Vec<Box<dyn BlockDeviceHandle>> {
+    match nexus_lookup(nexus) {
+        Some(n) => n
+            .children
+            .iter()
+            .filter(|c| c.state() == ChildState::Open)
+            .filter(|c| c.is_local() == Some(false))
+            .filter_map(|c| c.get_io_handle().ok())
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+/// returns the names of the bdevs of the open children of the nexus which
+/// are local
+fn local_children(nexus: &str) -> Vec<String> {
+    match nexus_lookup(nexus) {
+        Some(n) => n
+            .children
+            .iter()
+            .filter(|c| c.state() == ChildState::Open)
+            .filter(|c| c.is_local() == Some(true))
+            .filter_map(|c| c.get_device().ok())
+            .map(|d| d.device_name())
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+/// Creates a snapshot on all children of the nexus, all named after the
+/// snapshot time of the command, which is set unless given by the sender.
+/// The NVMe children receive the command itself, and the snapshot of the
+/// local children is created as for a command received on a shared replica.
+fn create_snapshot(cmd: NexusCmd) -> NexusCmdFuture {
+    async move {
+        let mut pcmd = cmd.cmd;
+        let snapshot_time = unsafe {
+            nvme_cmd_cdw10_get_val(&pcmd) | nvme_cmd_cdw11_get_val(&pcmd)
+        };
+        if snapshot_time == 0 {
+            set_snapshot_time(&mut pcmd);
+        }
+
+        for hdl in nvme_children_handles(&cmd.nexus) {
+            hdl.nvme_admin(&pcmd, None).await?;
+        }
+
+        for name in local_children(&cmd.nexus) {
+            let lvol = UntypedBdev::lookup_by_name(&name)
+                .and_then(|bd| Lvol::try_from(bd).ok())
+                .ok_or(CoreError::NvmeAdminFailed {
+                    opcode: pcmd.opc(),
+                })?;
+            subsys::create_snapshot(lvol, &pcmd).await.map_err(|e| {
+                error!("{}: {}", cmd.nexus, e);
+                CoreError::NvmeAdminFailed {
+                    opcode: pcmd.opc(),
+                }
+            })?;
+        }
+
+        Ok(())
+    }
+    .boxed_local()
+}
+
+/// length of the SMART / Health Information log page
+const HEALTH_LOG_LEN: usize = 512;
+
+fn is_health_log(cmd: &spdk_nvme_cmd) -> bool {
+    let cdw10 = unsafe { nvme_cmd_cdw10_get_val(cmd) };
+    cdw10 & 0xff == SPDK_NVME_LOG_HEALTH_INFORMATION
+}
+
+/// offsets of the 128 bit counters which are summed up across children
+const HEALTH_LOG_SUMS: [usize; 7] = [32, 48, 64, 80, 96, 160, 176];
+/// offsets of the 128 bit counters of which the maximum is reported
+const HEALTH_LOG_MAXS: [usize; 3] = [112, 128, 144];
+
+fn u128_at(page: &[u8], offset: usize) -> u128 {
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&page[offset .. offset + 16]);
+    u128::from_le_bytes(bytes)
+}
+
+fn u32_at(page: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0u8; 4];
+    bytes.copy_from_slice(&page[offset .. offset + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+/// Combines the health log page of a child into the health log page of the
+/// nexus, such that the nexus reports the worst state of its children and
+/// the sum of their activity.
+fn merge_health_log(page: &mut [u8], child: &[u8], first: bool) {
+    if first {
+        page.copy_from_slice(child);
+        return;
+    }
+
+    // critical warning
+    page[0] |= child[0];
+    // composite temperature
+    let temp = u16::from_le_bytes([page[1], page[2]])
+        .max(u16::from_le_bytes([child[1], child[2]]));
+    page[1 .. 3].copy_from_slice(&temp.to_le_bytes());
+    // available spare, its threshold and percentage used
+    page[3] = page[3].min(child[3]);
+    page[4] = page[4].max(child[4]);
+    page[5] = page[5].max(child[5]);
+
+    for offset in HEALTH_LOG_SUMS.iter().copied() {
+        let sum = u128_at(page, offset).saturating_add(u128_at(child, offset));
+        page[offset .. offset + 16].copy_from_slice(&sum.to_le_bytes());
+    }
+    for offset in HEALTH_LOG_MAXS.iter().copied() {
+        let max = u128_at(page, offset).max(u128_at(child, offset));
+        page[offset .. offset + 16].copy_from_slice(&max.to_le_bytes());
+    }
+    // warning and critical composite temperature time
+    for offset in [192, 196].iter().copied() {
+        let max = u32_at(page, offset).max(u32_at(child, offset));
+        page[offset .. offset + 4].copy_from_slice(&max.to_le_bytes());
+    }
+    // temperature sensors are specific to a device
+    page[200 .. 216].iter_mut().for_each(|b| *b = 0);
+}
+
+/// Serves the SMART / Health Information log page of the nexus, aggregated
+/// from the log pages of its NVMe children.
+fn health_log(cmd: NexusCmd) -> NexusCmdFuture {
+    async move {
+        let mut lcmd = spdk_nvme_cmd::default();
+        lcmd.set_opc(SPDK_NVME_OPC_GET_LOG_PAGE as u16);
+        lcmd.nsid = 0xffffffff;
+        unsafe {
+            *nvme_cmd_cdw10_get(&mut lcmd) = SPDK_NVME_LOG_HEALTH_INFORMATION
+                | ((HEALTH_LOG_LEN as u32 / 4 - 1) << 16);
+        }
+
+        let mut page = [0u8; HEALTH_LOG_LEN];
+        let mut merged = 0;
+        for hdl in nvme_children_handles(&cmd.nexus) {
+            let mut buf =
+                hdl.dma_malloc(HEALTH_LOG_LEN as u64).map_err(|_| {
+                    CoreError::DmaAllocationError {
+                        size: HEALTH_LOG_LEN as u64,
+                    }
+                })?;
+            match hdl.nvme_admin(&lcmd, Some(&mut buf)).await {
+                Ok(_) => {
+                    merge_health_log(&mut page, buf.as_slice(), merged == 0);
+                    merged += 1;
+                }
+                Err(e) => {
+                    warn!(
+                        "{}: failed to get the health log of {}: {}",
+                        cmd.nexus,
+                        hdl.get_device().device_name(),
+                        e
+                    );
+                }
+            }
+        }
+
+        if merged == 0 {
+            return Err(CoreError::NvmeAdminFailed {
+                opcode: cmd.cmd.opc(),
+            });
+        }
+
+        cmd.copy_to_buf(&page);
+        Ok(())
+    }
+    .boxed_local()
+}
+
+fn any_cmd(_cmd: &spdk_nvme_cmd) -> bool {
+    true
+}
+
+/// Registers the handlers of the nexus.
+pub(crate) fn register_nexus_cmd_handlers() {
+    register_admin_cmd_handler(
+        SPDK_NVME_OPC_IDENTIFY as u8,
+        nvmf_nexus_identify_hdlr,
+    );
+
+    register_nexus_cmd_handler(
+        NexusCmdKind::Admin,
+        nvme_admin_opc::CREATE_SNAPSHOT,
+        NexusCmdHandler {
+            accepts: any_cmd,
+            run: create_snapshot,
+        },
+    );
+    register_nexus_cmd_handler(
+        NexusCmdKind::Admin,
+        SPDK_NVME_OPC_GET_LOG_PAGE as u8,
+        NexusCmdHandler {
+            accepts: is_health_log,
+            run: health_log,
+        },
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use spdk_rs::libspdk::{
+        nvme_cmd_cdw10_get,
+        spdk_nvme_cmd,
+        spdk_nvme_ctrlr_data,
+        SPDK_NVME_LOG_ERROR,
+        SPDK_NVME_LOG_HEALTH_INFORMATION,
+    };
+
+    use super::{
+        is_health_log,
+        merge_health_log,
+        set_firmware_revision,
+        u128_at,
+        HEALTH_LOG_LEN,
+    };
+
+    fn health_log(
+        warning: u8,
+        temp: u16,
+        spare: u8,
+        units_read: u128,
+        power_cycles: u128,
+    ) -> [u8; HEALTH_LOG_LEN] {
+        let mut page = [0u8; HEALTH_LOG_LEN];
+        page[0] = warning;
+        page[1 .. 3].copy_from_slice(&temp.to_le_bytes());
+        page[3] = spare;
+        page[32 .. 48].copy_from_slice(&units_read.to_le_bytes());
+        page[112 .. 128].copy_from_slice(&power_cycles.to_le_bytes());
+        page[200 .. 202].copy_from_slice(&temp.to_le_bytes());
+        page
+    }
+
+    #[test]
+    fn merge_health_logs() {
+        let first = health_log(0x1, 300, 90, 10, 2);
+        let second = health_log(0x4, 320, 80, 5, 1);
+
+        let mut page = [0u8; HEALTH_LOG_LEN];
+        merge_health_log(&mut page, &first, true);
+        assert_eq!(page[..], first[..]);
+
+        merge_health_log(&mut page, &second, false);
+        // the worst state of the children
+        assert_eq!(page[0], 0x5);
+        assert_eq!(u16::from_le_bytes([page[1], page[2]]), 320);
+        assert_eq!(page[3], 80);
+        // the sum of their activity
+        assert_eq!(u128_at(&page, 32), 15);
+        // the maximum of the power cycles
+        assert_eq!(u128_at(&page, 112), 2);
+        // and no temperature sensors
+        assert!(page[200 .. 216].iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn merge_health_log_saturates() {
+        let first = health_log(0, 0, 0, u128::MAX, 0);
+        let mut page = [0u8; HEALTH_LOG_LEN];
+        merge_health_log(&mut page, &first, true);
+        merge_health_log(&mut page, &first, false);
+        assert_eq!(u128_at(&page, 32), u128::MAX);
+    }
+
+    #[test]
+    fn health_log_page() {
+        let mut cmd = spdk_nvme_cmd::default();
+        unsafe {
+            *nvme_cmd_cdw10_get(&mut cmd) =
+                SPDK_NVME_LOG_HEALTH_INFORMATION | (127 << 16);
+        }
+        assert!(is_health_log(&cmd));
+
+        unsafe {
+            *nvme_cmd_cdw10_get(&mut cmd) = SPDK_NVME_LOG_ERROR | (127 << 16);
+        }
+        assert!(!is_health_log(&cmd));
+    }
+
+    #[test]
+    fn identify_firmware_revision() {
+        let mut cdata = spdk_nvme_ctrlr_data::default();
+        cdata.fr.iter_mut().for_each(|b| *b = b'x' as _);
+        set_firmware_revision(&mut cdata);
+
+        let fr = cdata.fr.iter().map(|b| *b as u8).collect::<Vec<u8>>();
+        let version = env!("CARGO_PKG_VERSION").as_bytes();
+        let len = version.len().min(fr.len());
+        assert_eq!(&fr[.. len], &version[.. len]);
+        assert!(fr[len ..].iter().all(|b| *b == b' '));
+    }
+}
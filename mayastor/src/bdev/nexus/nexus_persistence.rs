@@ -1,6 +1,11 @@
 use super::{ChildState, Nexus, NexusChild};
-use crate::{persistent_store::PersistentStore, sleep::mayastor_sleep};
+use crate::{
+    persistent_store::PersistentStore,
+    sleep::mayastor_sleep,
+    store::store_defs::{StoreError, StoreValue},
+};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::time::Duration;
 
 type ChildUri = String;
@@ -27,6 +32,12 @@ impl PersistentNexusInfo {
     fn inner_mut(&mut self) -> &mut NexusInfo {
         &mut self.inner
     }
+
+    /// Key under which the reservations of the hosts of the nexus are kept,
+    /// next to the NexusInfo structure.
+    fn reservations_key(&self, uuid: &str) -> String {
+        format!("{}/reservations", self.key.as_deref().unwrap_or(uuid))
+    }
 }
 
 /// Definition of the nexus information that gets saved in the persistent
@@ -146,13 +157,52 @@ impl<'n> Nexus<'n> {
         state == &ChildState::Open
     }
 
+    /// Persist the reservations of the hosts of the nexus, as kept by the
+    /// NVMe-oF target in the PTPL file of the nexus namespace.
+    pub(crate) async fn persist_reservations(&self, reservations: &Value) {
+        if !PersistentStore::enabled() {
+            return;
+        }
+
+        let key = self
+            .nexus_info
+            .lock()
+            .await
+            .reservations_key(&self.uuid().to_string());
+        self.put_retry(&key, reservations).await;
+    }
+
+    /// Load the persisted reservations of the hosts of the nexus, if any.
+    pub(crate) async fn load_reservations(&self) -> Option<Value> {
+        if !PersistentStore::enabled() {
+            return None;
+        }
+
+        let key = self
+            .nexus_info
+            .lock()
+            .await
+            .reservations_key(&self.uuid().to_string());
+        match PersistentStore::get(&key).await {
+            Ok(value) => Some(value),
+            Err(StoreError::MissingEntry {
+                ..
+            }) => None,
+            Err(e) => {
+                error!(
+                    "Failed to load the reservations of nexus {}: {}",
+                    self.name, e
+                );
+                None
+            }
+        }
+    }
+
     // Save the nexus info to the store. This is integral to ensuring data
     // consistency across restarts of Mayastor. Therefore, keep retrying
     // until successful.
     // TODO: Should we give up retrying eventually?
     async fn save(&self, info: &PersistentNexusInfo) {
-        let mut output_err = true;
-        let nexus_uuid = self.uuid().to_string();
         // If a key has been provided use this to store the NexusInfo.
         // If a key is not provided, use the nexus uuid as the key.
         let key = match &info.key {
@@ -160,8 +210,16 @@ impl<'n> Nexus<'n> {
             None => self.uuid().to_string(),
         };
 
+        self.put_retry(&key, &info.inner).await;
+    }
+
+    // Put a value in the store, retrying until successful.
+    async fn put_retry(&self, key: &str, value: &impl StoreValue) {
+        let mut output_err = true;
+        let nexus_uuid = self.uuid().to_string();
+
         loop {
-            match PersistentStore::put(&key, &info.inner).await {
+            match PersistentStore::put(&key, value).await {
                 Ok(_) => {
                     // The state was saved successfully.
                     break;
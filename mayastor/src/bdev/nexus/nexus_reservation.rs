@@ -0,0 +1,302 @@
+//! Reservations of the hosts connected to a published nexus.
+//!
+//! Reservation Register, Acquire, Release and Report commands from hosts are
+//! handled by the NVMe-oF target for the namespace of the nexus. The
+//! reservation state is kept with the nexus rather than on its children, so
+//! it is unaffected by children being added, replaced or rebuilt; children
+//! only ever carry the reservation the nexus itself holds to fence other
+//! nexuses.
+//!
+//! The target keeps the reservation state in a Persist Through Power Loss
+//! (PTPL) file per nexus, which it writes on every register, acquire and
+//! release once PTPL is activated, and reads back when the nexus is published
+//! again. The nexus activates PTPL itself when published rather than waiting
+//! for a host to do so, so hosts see their reservations persist even if they
+//! did not ask for it. Whenever the file has been written, the nexus mirrors
+//! it to the persistent store under the key of the volume. A host clearing
+//! PTPL with a register command stops the target writing the file for
+//! acquire and release until the nexus is published again.
+//!
+//! Keeping the state with the nexus suffices for failover: hosts only reach
+//! the volume through a nexus, whose target enforces their reservations, and
+//! a nexus re-created on another node restores the latest state from the
+//! persistent store before it is published. A nexus that lost its node is
+//! fenced from the children when the nexus replacing it preempts its
+//! reservation on them, so hosts never see diverging reservation states.
+
+use std::{
+    ffi::OsString,
+    io::ErrorKind,
+    os::unix::io::AsRawFd,
+    path::Path,
+    pin::Pin,
+};
+
+use nix::{
+    errno::Errno,
+    sys::inotify::{AddWatchFlags, InitFlags, Inotify},
+};
+use serde_json::{json, Value};
+
+use super::{nexus_lookup, Nexus};
+use crate::{
+    core::{poller, Reactors},
+    subsys::Config,
+};
+
+/// interval at which the changes of the PTPL files are collected
+const RESERVATION_POLL_INTERVAL_US: u64 = 100_000;
+
+/// Watch for the PTPL file of a nexus being written. The directory is watched
+/// as the file only exists once a host made its reservation persistent.
+struct PtplWatch {
+    inotify: Inotify,
+    name: OsString,
+}
+
+impl PtplWatch {
+    fn new(file: &str) -> Result<Self, Errno> {
+        let path = Path::new(file);
+        let (dir, name) = match (path.parent(), path.file_name()) {
+            (Some(dir), Some(name)) => (dir, name.to_owned()),
+            _ => return Err(Errno::EINVAL),
+        };
+
+        let inotify =
+            Inotify::init(InitFlags::IN_NONBLOCK | InitFlags::IN_CLOEXEC)?;
+        let watch = PtplWatch {
+            inotify,
+            name,
+        };
+        watch.inotify.add_watch(
+            dir,
+            AddWatchFlags::IN_CLOSE_WRITE | AddWatchFlags::IN_MOVED_TO,
+        )?;
+        Ok(watch)
+    }
+
+    /// whether the file has been written since the last call
+    fn changed(&self) -> bool {
+        let mut changed = false;
+        // drain all pending events, EAGAIN when there are none
+        while let Ok(events) = self.inotify.read_events() {
+            changed |= events
+                .iter()
+                .any(|e| e.name.as_deref() == Some(self.name.as_os_str()));
+        }
+        changed
+    }
+}
+
+impl Drop for PtplWatch {
+    fn drop(&mut self) {
+        let _ = nix::unistd::close(self.inotify.as_raw_fd());
+    }
+}
+
+/// Returns the reservations to seed the PTPL file of a namespace with, which
+/// activate PTPL for the target to keep the file up to date. Without any
+/// reservations, the file holds what the target writes when there are none.
+fn ptpl_reservations(reservations: Option<Value>, bdev_uuid: &str) -> Value {
+    let mut reservations = match reservations {
+        Some(Value::Object(map)) => Value::Object(map),
+        _ => json!({
+            "rtype": 0,
+            "crkey": 0,
+            "holder_uuid": uuid::Uuid::nil().to_string(),
+            "registrants": [],
+        }),
+    };
+    reservations["ptpl"] = json!(true);
+    reservations["bdev_uuid"] = json!(bdev_uuid);
+    reservations
+}
+
+impl<'n> Nexus<'n> {
+    /// file the NVMe-oF target keeps the reservations of the nexus in
+    pub(crate) fn ptpl_file(&self) -> String {
+        Path::new(&Config::get().nexus_opts.nvmf_ptpl_dir)
+            .join(format!("{}.json", self.uuid()))
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    /// Restore the persisted reservations of the hosts into the PTPL file,
+    /// or keep the ones in the file when none have been persisted, and
+    /// activate PTPL. Returns the file to publish the nexus with, without it
+    /// the reservations of the hosts do not persist.
+    pub(crate) async fn restore_reservations(&self) -> Option<String> {
+        let file = self.ptpl_file();
+        if let Some(dir) = Path::new(&file).parent() {
+            if let Err(e) = std::fs::create_dir_all(dir) {
+                error!(
+                    "{}: failed to create {}, reservations will not \
+                    persist: {}",
+                    self.name,
+                    dir.display(),
+                    e
+                );
+                return None;
+            }
+        }
+
+        let reservations = match self.load_reservations().await {
+            Some(reservations) => Some(reservations),
+            None => std::fs::read(&file)
+                .ok()
+                .and_then(|data| serde_json::from_slice(&data).ok()),
+        };
+        let bdev_uuid = unsafe { self.bdev().uuid_as_string() };
+        let reservations = ptpl_reservations(reservations, &bdev_uuid);
+        if let Err(e) = std::fs::write(&file, reservations.to_string()) {
+            error!(
+                "{}: failed to restore the reservations into {}: {}, \
+                reservations will not persist",
+                self.name, file, e
+            );
+            return None;
+        }
+
+        Some(file)
+    }
+
+    /// Mirror the reservations in the PTPL file to the persistent store.
+    pub(crate) async fn mirror_reservations(&self) {
+        let file = self.ptpl_file();
+        let data = match std::fs::read(&file) {
+            Ok(data) => data,
+            // no host made its reservation persistent (yet)
+            Err(e) if e.kind() == ErrorKind::NotFound => return,
+            Err(e) => {
+                warn!("{}: failed to read {}: {}", self.name, file, e);
+                return;
+            }
+        };
+
+        match serde_json::from_slice::<Value>(&data) {
+            Ok(reservations) => self.persist_reservations(&reservations).await,
+            Err(e) => {
+                warn!("{}: invalid reservations in {}: {}", self.name, file, e)
+            }
+        }
+    }
+
+    /// Start watching the reservation state of the published nexus. The
+    /// target writes the PTPL file whenever a reservation changes, so the
+    /// file is mirrored as soon as it has been written.
+    pub(crate) fn start_reservation_poller(mut self: Pin<&mut Self>) {
+        let file = self.ptpl_file();
+        let watch = match PtplWatch::new(&file) {
+            Ok(watch) => watch,
+            Err(e) => {
+                error!(
+                    "{}: failed to watch {}, reservations are only mirrored \
+                    when unpublished: {}",
+                    self.name, file, e
+                );
+                return;
+            }
+        };
+
+        let name = self.name.clone();
+        let poller = poller::Builder::new()
+            .with_name("nexus_reservations")
+            .with_interval(RESERVATION_POLL_INTERVAL_US)
+            .with_poll_fn(move || {
+                if !watch.changed() {
+                    return 0;
+                }
+
+                let name = name.clone();
+                Reactors::master().send_future(async move {
+                    if let Some(nexus) = nexus_lookup(&name) {
+                        nexus.mirror_reservations().await;
+                    }
+                });
+                1
+            })
+            .build();
+
+        unsafe {
+            self.as_mut().get_unchecked_mut().reservation_poller = Some(poller);
+        }
+    }
+
+    /// Stop watching the reservation state, the reservations are mirrored a
+    /// last time as the poller may not have seen the latest change.
+    pub(crate) async fn stop_reservation_poller(mut self: Pin<&mut Self>) {
+        let poller = unsafe {
+            self.as_mut().get_unchecked_mut().reservation_poller.take()
+        };
+        if let Some(poller) = poller {
+            poller.stop();
+            self.mirror_reservations().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::{ptpl_reservations, PtplWatch};
+
+    #[test]
+    fn ptpl_reservations_activate() {
+        let uuid = "e2cb5a9e-ed2d-4fe6-b1a6-e2b2a8a8a6c4";
+
+        // nothing persisted yet
+        let seeded = ptpl_reservations(None, uuid);
+        assert_eq!(seeded["ptpl"], json!(true));
+        assert_eq!(seeded["bdev_uuid"], json!(uuid));
+        assert_eq!(seeded["rtype"], json!(0));
+        assert_eq!(seeded["registrants"], json!([]));
+
+        // the reservations are kept, PTPL is activated even if the host
+        // cleared it
+        let persisted = json!({
+            "ptpl": false,
+            "rtype": 1,
+            "crkey": 42,
+            "bdev_uuid": "4d2a1c0e-7d0e-4b8e-9a55-2f1d3d1f6a10",
+            "holder_uuid": "0f1e2d3c-4b5a-6978-8796-a5b4c3d2e1f0",
+            "registrants": [{
+                "rkey": 42,
+                "host_uuid": "0f1e2d3c-4b5a-6978-8796-a5b4c3d2e1f0",
+            }],
+        });
+        let restored = ptpl_reservations(Some(persisted.clone()), uuid);
+        assert_eq!(restored["ptpl"], json!(true));
+        assert_eq!(restored["bdev_uuid"], json!(uuid));
+        for key in &["rtype", "crkey", "holder_uuid", "registrants"] {
+            assert_eq!(restored[key], persisted[key]);
+        }
+
+        // anything else is replaced
+        assert_eq!(
+            ptpl_reservations(Some(json!("garbage")), uuid),
+            ptpl_reservations(None, uuid)
+        );
+    }
+
+    #[test]
+    fn ptpl_watch() {
+        let dir = std::env::temp_dir().join("ptpl_watch");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("nexus.json");
+
+        let watch = PtplWatch::new(file.to_str().unwrap()).unwrap();
+        assert!(!watch.changed());
+
+        std::fs::write(&file, "{}").unwrap();
+        assert!(watch.changed());
+        assert!(!watch.changed());
+
+        // other files in the directory are of no interest
+        std::fs::write(dir.join("other.json"), "{}").unwrap();
+        assert!(!watch.changed());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
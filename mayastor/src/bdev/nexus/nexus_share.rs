@@ -6,13 +6,19 @@ use super::{
     Error,
     NbdDisk,
     Nexus,
+    NexusAnaParams,
     NexusTarget,
     ShareNbdNexus,
     ShareNvmfNexus,
     UnshareNexus,
+    UpdateAllowedHostsNexus,
+    NVME_MAX_ANA_GROUP,
 };
 
-use crate::core::{Bdev, Protocol, Share};
+use crate::{
+    core::{Bdev, NvmfShareProps, Protocol, Share},
+    subsys::NvmfConnection,
+};
 
 #[async_trait(? Send)]
 ///
@@ -29,14 +35,14 @@ impl<'n> Share for Nexus<'n> {
 
     async fn share_nvmf(
         mut self: Pin<&mut Self>,
-        cntlid_range: Option<(u16, u16)>,
+        props: Option<NvmfShareProps>,
     ) -> Result<Self::Output, Self::Error> {
         match self.shared() {
             Some(Protocol::Off) | None => {
                 let name = self.name.clone();
                 self.as_mut()
                     .pinned_bdev_mut()
-                    .share_nvmf(cntlid_range)
+                    .share_nvmf(props)
                     .await
                     .context(ShareNvmfNexus {
                         name,
@@ -65,11 +71,20 @@ impl<'n> Share for Nexus<'n> {
         unsafe { self.bdev().shared() }
     }
 
+    /// TODO
+    fn allowed_hosts(&self) -> Vec<String> {
+        unsafe { self.bdev().allowed_hosts() }
+    }
+
     /// TODO
     fn share_uri(&self) -> Option<String> {
         unsafe { self.bdev().share_uri() }
     }
 
+    fn share_uris(&self) -> Vec<String> {
+        unsafe { self.bdev().share_uris() }
+    }
+
     /// TODO
     fn bdev_uri(&self) -> Option<String> {
         unsafe { self.bdev().bdev_uri() }
@@ -135,16 +150,26 @@ impl<'n> Nexus<'n> {
                 Ok(uri)
             }
             Protocol::Nvmf => {
-                let args = Some((
-                    self.nvme_params.min_cntlid,
-                    self.nvme_params.max_cntlid,
-                ));
-                let uri = self.as_mut().share_nvmf(args).await?;
+                let props = NvmfShareProps::new()
+                    .with_range(Some((
+                        self.nvme_params.min_cntlid,
+                        self.nvme_params.max_cntlid,
+                    )))
+                    .with_allowed_hosts(self.allowed_hosts.clone())
+                    .with_allow_any_host(self.allow_any_host)
+                    .with_ana(
+                        self.ana_params.reporting,
+                        self.ana_params.group_id,
+                    )
+                    .with_nqn(self.ana_params.subsystem_nqn.clone())
+                    .with_ptpl_file(self.restore_reservations().await);
+                let uri = self.as_mut().share_nvmf(Some(props)).await?;
 
                 unsafe {
                     self.as_mut().get_unchecked_mut().nexus_target =
                         Some(NexusTarget::NexusNvmfTarget);
                 }
+                self.as_mut().start_reservation_poller();
                 Ok(uri)
             }
         }
@@ -158,6 +183,7 @@ impl<'n> Nexus<'n> {
                     disk.destroy();
                 }
                 Some(NexusTarget::NexusNvmfTarget) => {
+                    self.as_mut().stop_reservation_poller().await;
                     self.as_mut().unshare().await?;
                 }
                 None => {
@@ -179,6 +205,72 @@ impl<'n> Nexus<'n> {
         self.as_mut().unshare().await.unwrap();
     }
 
+    /// Replace the NQNs of the hosts allowed to connect to the nexus, which
+    /// restricts access to those hosts, i.e. no host may connect when the
+    /// list is empty. The list is kept with the nexus and applied again when
+    /// it is re-published.
+    pub async fn set_allowed_hosts(
+        mut self: Pin<&mut Self>,
+        hosts: Vec<String>,
+    ) -> Result<(), Error> {
+        if let Some(NexusTarget::NexusNvmfTarget) = self.nexus_target {
+            let name = self.name.clone();
+            let bdev = self.as_mut().pinned_bdev_mut();
+            bdev.set_allowed_hosts(&hosts).await.context(
+                UpdateAllowedHostsNexus {
+                    name,
+                },
+            )?;
+            bdev.set_allow_any_host(false);
+        }
+
+        unsafe {
+            let nexus = self.as_mut().get_unchecked_mut();
+            nexus.allowed_hosts = hosts;
+            nexus.allow_any_host = false;
+        }
+        Ok(())
+    }
+
+    /// Allow any host to connect to the nexus regardless of the allowed
+    /// hosts, or only the allowed hosts. This is kept with the nexus as
+    /// well.
+    pub fn set_allow_any_host(mut self: Pin<&mut Self>, enable: bool) {
+        if let Some(NexusTarget::NexusNvmfTarget) = self.nexus_target {
+            self.as_mut().pinned_bdev_mut().set_allow_any_host(enable);
+        }
+
+        unsafe {
+            self.as_mut().get_unchecked_mut().allow_any_host = enable;
+        }
+    }
+
+    /// Set the ANA parameters of the nexus, these take effect when the nexus
+    /// is published and cannot be changed while it is published.
+    pub fn set_ana_params(
+        mut self: Pin<&mut Self>,
+        params: NexusAnaParams,
+    ) -> Result<(), Error> {
+        if let Some(group) = params.group_id {
+            if group == 0 || group > NVME_MAX_ANA_GROUP {
+                return Err(Error::InvalidArguments {
+                    name: self.name.clone(),
+                    args: format!("invalid ANA group ID {}", group),
+                });
+            }
+        }
+        if self.nexus_target.is_some() && self.ana_params != params {
+            return Err(Error::AlreadyShared {
+                name: self.name.clone(),
+            });
+        }
+
+        unsafe {
+            self.as_mut().get_unchecked_mut().ana_params = params;
+        }
+        Ok(())
+    }
+
     /// TODO
     pub fn get_share_uri(&self) -> Option<String> {
         match self.nexus_target {
@@ -187,4 +279,22 @@ impl<'n> Nexus<'n> {
             None => None,
         }
     }
+
+    /// returns the hosts connected to the nexus when published over NVMe-oF
+    pub async fn nvmf_connections(&self) -> Vec<NvmfConnection> {
+        match self.nexus_target {
+            Some(NexusTarget::NexusNvmfTarget) => unsafe {
+                self.bdev().nvmf_connections().await
+            },
+            _ => Vec::new(),
+        }
+    }
+
+    /// returns the URIs of all paths the nexus is published through
+    pub fn get_share_uris(&self) -> Vec<String> {
+        match self.nexus_target {
+            Some(NexusTarget::NexusNvmfTarget) => self.share_uris(),
+            _ => self.get_share_uri().into_iter().collect(),
+        }
+    }
 }
@@ -1,10 +1,19 @@
 /* I/O channel for NVMe controller, one per core. */
 
-use std::{cmp::max, mem::size_of, os::raw::c_void, ptr::NonNull};
+use std::{
+    cmp::max,
+    collections::VecDeque,
+    mem::size_of,
+    os::raw::c_void,
+    ptr::NonNull,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use spdk_rs::libspdk::{
     nvme_qpair_abort_all_queued_reqs,
     nvme_transport_qpair_abort_reqs,
+    spdk_get_ticks_hz,
     spdk_io_channel,
     spdk_nvme_ctrlr_alloc_io_qpair,
     spdk_nvme_ctrlr_connect_io_qpair,
@@ -19,7 +28,9 @@ use spdk_rs::libspdk::{
     spdk_nvme_poll_group_process_completions,
     spdk_nvme_poll_group_remove,
     spdk_nvme_qpair,
+    spdk_nvme_qpair_get_failure_reason,
     spdk_put_io_channel,
+    SPDK_NVME_QPAIR_FAILURE_NONE,
 };
 
 use crate::{
@@ -27,12 +38,14 @@ use crate::{
         device_lookup,
         nvmx::{
             controller_inner::SpdkNvmeController,
+            handle::{abort_queued_io, resubmit_queued_io},
             nvme_bdev_running_config,
             NvmeControllerState,
+            NvmeNamespace,
             NVME_CONTROLLERS,
         },
     },
-    core::{poller, BlockDevice, BlockDeviceIoStats, CoreError, IoType},
+    core::{poller, BlockDevice, BlockDeviceIoStats, CoreError, Cores, IoType},
 };
 
 #[repr(C)]
@@ -172,6 +185,15 @@ impl IoQpair {
         self.qpair.as_ptr()
     }
 
+    /// Returns true unless the qpair failed, e.g. when its connection was
+    /// lost.
+    fn is_connected(&self) -> bool {
+        unsafe {
+            spdk_nvme_qpair_get_failure_reason(self.qpair.as_ptr())
+                == SPDK_NVME_QPAIR_FAILURE_NONE
+        }
+    }
+
     /// Connect qpair.
     fn connect(&mut self) -> i32 {
         unsafe {
@@ -257,6 +279,7 @@ impl std::fmt::Debug for NvmeIoChannelInner<'_> {
         f.debug_struct("NvmeIoChannelInner")
             .field("qpair", &self.qpair)
             .field("pending IO", &self.num_pending_ios)
+            .field("queued IO", &self.queued_io.len())
             .finish()
     }
 }
@@ -267,11 +290,18 @@ pub struct NvmeIoChannelInner<'a> {
     poller: poller::Poller<'a>,
     io_stats_controller: IoStatsController,
     pub device: Box<dyn BlockDevice>,
+    /// namespace I/O is submitted to
+    ns: Arc<NvmeNamespace>,
     /// to prevent the controller from being destroyed before the channel
     ctrl: Option<
         std::sync::Arc<parking_lot::Mutex<crate::bdev::NvmeController<'a>>>,
     >,
     num_pending_ios: u64,
+    /// I/O waiting for the controller to reconnect
+    queued_io: VecDeque<QueuedIo>,
+    /// time I/O is queued for while the controller reconnects, none when
+    /// I/O fails right away
+    io_queue_timeout: Option<Duration>,
 
     // Flag to indicate the shutdown state of the channel.
     // We need such a flag to differentiate between channel reset and shutdown.
@@ -287,6 +317,15 @@ pub struct NvmeIoChannelInner<'a> {
     is_shutdown: bool,
 }
 
+/// An I/O queued while the controller reconnects, or while the namespace is
+/// inaccessible.
+struct QueuedIo {
+    /// context of the I/O
+    ctx: *mut c_void,
+    /// time the I/O fails at unless it was resubmitted
+    expires_at: Instant,
+}
+
 impl NvmeIoChannelInner<'_> {
     /// Reset channel, making it unusable till reinitialize() is called.
     pub fn reset(&mut self) -> i32 {
@@ -298,6 +337,7 @@ impl NvmeIoChannelInner<'_> {
                 qpair.as_ptr(),
                 self.num_pending_ios
             );
+            self.io_stats_controller.qpair_resets += 1;
         }
         0
     }
@@ -317,10 +357,79 @@ impl NvmeIoChannelInner<'_> {
         if rc == 0 {
             self.is_shutdown = true;
             self.ctrl.take();
+            self.fail_queued_io(true);
         }
         rc
     }
 
+    /// Returns true when I/O which cannot be submitted is queued until the
+    /// controller reconnects, rather than failed.
+    #[inline]
+    pub fn queues_io(&self) -> bool {
+        !self.is_shutdown && self.io_queue_timeout.is_some()
+    }
+
+    /// Returns true when I/O is held back until the namespace is accessible
+    /// again, which is the case regardless of the reconnect policy.
+    #[inline]
+    pub fn holds_io(&self) -> bool {
+        !self.is_shutdown && !self.ns.is_accessible()
+    }
+
+    /// Queue an I/O until the channel is reinitialized, or the namespace is
+    /// accessible again. I/O held back for the namespace is given at least
+    /// the ANA transition time of the controller. The I/O remains accounted
+    /// as active.
+    pub fn queue_io(&mut self, ctx: *mut c_void) {
+        let mut timeout = self.io_queue_timeout.unwrap_or_default();
+        if !self.ns.is_accessible() {
+            timeout = timeout.max(self.ns.ana_transition_time());
+        }
+
+        self.queued_io.push_back(QueuedIo {
+            ctx,
+            expires_at: Instant::now() + timeout,
+        });
+    }
+
+    /// Resubmit the queued I/O to the qpair of the channel, once it is
+    /// connected and the namespace is accessible through it.
+    fn resubmit_queued_io(&mut self) {
+        let qpair = match self.qpair.as_ref() {
+            Some(qpair) if qpair.is_connected() && self.ns.is_accessible() => {
+                qpair.as_ptr()
+            }
+            _ => return,
+        };
+
+        let queued = std::mem::take(&mut self.queued_io);
+        if !queued.is_empty() {
+            debug!("resubmitting {} queued I/O", queued.len());
+        }
+        for io in queued {
+            if resubmit_queued_io(io.ctx, qpair) != 0 {
+                abort_queued_io(io.ctx);
+            }
+        }
+    }
+
+    /// Fail the queued I/O which timed out, or all of it.
+    fn fail_queued_io(&mut self, all: bool) {
+        let now = Instant::now();
+        let (expired, queued): (VecDeque<_>, VecDeque<_>) =
+            std::mem::take(&mut self.queued_io)
+                .into_iter()
+                .partition(|io| all || io.expires_at <= now);
+        self.queued_io = queued;
+
+        if !expired.is_empty() {
+            warn!("failing {} queued I/O", expired.len());
+        }
+        for io in expired {
+            abort_queued_io(io.ctx);
+        }
+    }
+
     /// Account active I/O for channel.
     #[inline]
     pub fn account_io(&mut self) {
@@ -387,6 +496,7 @@ impl NvmeIoChannelInner<'_> {
 
         debug!("{} I/O channel successfully reinitialized", ctrlr_name);
         self.qpair = Some(qpair);
+        self.resubmit_queued_io();
         0
     }
 
@@ -395,7 +505,48 @@ impl NvmeIoChannelInner<'_> {
     pub fn get_io_stats_controller(&mut self) -> &mut IoStatsController {
         &mut self.io_stats_controller
     }
+
+    /// Get the statistics and state of the qpair of the channel, must be
+    /// called on the core of the channel.
+    pub fn get_qpair_stats(&self) -> NvmeQpairStats {
+        NvmeQpairStats {
+            core: Cores::current(),
+            connected: self
+                .qpair
+                .as_ref()
+                .map_or(false, |qpair| qpair.is_connected()),
+            outstanding_io: self.num_pending_ios,
+            queued_io: self.queued_io.len() as u64,
+            resets: self.io_stats_controller.qpair_resets,
+            failed_io: self.io_stats_controller.failed_ops,
+            aborted_io: self.io_stats_controller.aborted_ops,
+            io_stats: self.io_stats_controller.get_io_stats(),
+        }
+    }
 }
+
+/// Statistics and state of the I/O qpair of an I/O channel, there is one
+/// channel per core submitting I/O to the controller.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NvmeQpairStats {
+    /// core of the I/O channel
+    pub core: u32,
+    /// the qpair exists and did not lose its connection
+    pub connected: bool,
+    /// I/O submitted and not completed yet, including queued I/O
+    pub outstanding_io: u64,
+    /// I/O queued until the controller reconnects
+    pub queued_io: u64,
+    /// times the qpair was torn down by a controller reset
+    pub resets: u64,
+    /// I/O which completed with an error, including aborted I/O
+    pub failed_io: u64,
+    /// I/O which was aborted, on request or along with its qpair
+    pub aborted_io: u64,
+    /// I/O completed successfully through the qpair
+    pub io_stats: BlockDeviceIoStats,
+}
+
 pub struct IoStatsController {
     // Note that for the sake of optimization, all bytes-related I/O stats
     // (bytes_read, bytes_written and bytes_unmapped) are accounted in
@@ -403,14 +554,45 @@ pub struct IoStatsController {
     // I/O stats to the caller, inside get_io_stats().
     io_stats: BlockDeviceIoStats,
     block_size: u64,
+    failed_ops: u64,
+    aborted_ops: u64,
+    qpair_resets: u64,
 }
 
 /// Top-level wrapper around device I/O statistics.
 impl IoStatsController {
     fn new(block_size: u64) -> Self {
         Self {
-            io_stats: BlockDeviceIoStats::default(),
+            io_stats: BlockDeviceIoStats {
+                tick_rate: unsafe { spdk_get_ticks_hz() },
+                ..Default::default()
+            },
             block_size,
+            failed_ops: 0,
+            aborted_ops: 0,
+            qpair_resets: 0,
+        }
+    }
+
+    #[inline]
+    /// Account the time an I/O took to complete, from its submission.
+    pub fn account_latency(&mut self, op: IoType, ticks: u64) {
+        match op {
+            IoType::Read => self.io_stats.read_latency_ticks += ticks,
+            IoType::Write | IoType::CompareAndWrite => {
+                self.io_stats.write_latency_ticks += ticks
+            }
+            IoType::Unmap => self.io_stats.unmap_latency_ticks += ticks,
+            _ => {}
+        }
+    }
+
+    #[inline]
+    /// Account an I/O which completed with an error.
+    pub fn account_failed_io(&mut self, aborted: bool) {
+        self.failed_ops += 1;
+        if aborted {
+            self.aborted_ops += 1;
         }
     }
 
@@ -435,7 +617,11 @@ impl IoStatsController {
                 self.io_stats.num_unmap_ops += num_ops;
                 self.io_stats.bytes_unmapped += num_blocks;
             }
-            IoType::WriteZeros => {}
+            IoType::CompareAndWrite => {
+                self.io_stats.num_write_ops += num_ops;
+                self.io_stats.bytes_written += num_blocks;
+            }
+            IoType::WriteZeros | IoType::Compare => {}
             _ => {
                 warn!("Unsupported I/O type for I/O statistics: {:?}", op);
             }
@@ -487,6 +673,13 @@ extern "C" fn nvme_poll(ctx: *mut c_void) -> i32 {
         )
     };
 
+    // I/O held back while the namespace was inaccessible is resubmitted as
+    // soon as it is accessible again.
+    if !inner.queued_io.is_empty() {
+        inner.resubmit_queued_io();
+        inner.fail_queued_io(false);
+    }
+
     if num_completions > 0 {
         1
     } else {
@@ -508,7 +701,7 @@ impl NvmeControllerIoChannel {
             Some(c) => c,
         };
 
-        let (cname, controller, block_size) = {
+        let (cname, controller, ns, reconnect) = {
             let controller = carc.lock();
             // Make sure controller is available.
             if controller.get_state() != NvmeControllerState::Running {
@@ -525,14 +718,14 @@ impl NvmeControllerIoChannel {
             // the reference to the controller instance (carc) which
             // guarantees that the controller exists during I/O channel
             // creation.
-            let block_size = controller
+            let ns = controller
                 .namespace()
-                .expect("No namespaces in active controller")
-                .block_len();
+                .expect("No namespaces in active controller");
             (
                 controller.get_name(),
                 controller.controller().unwrap(),
-                block_size,
+                ns,
+                controller.reconnect_policy(),
             )
         };
 
@@ -594,11 +787,18 @@ impl NvmeControllerIoChannel {
             qpair: Some(qpair),
             poll_group,
             poller,
-            io_stats_controller: IoStatsController::new(block_size),
+            io_stats_controller: IoStatsController::new(ns.block_len()),
             is_shutdown: false,
             device,
+            ns,
             ctrl: Some(carc),
             num_pending_ios: 0,
+            queued_io: VecDeque::new(),
+            io_queue_timeout: if reconnect.queues_io() {
+                Some(reconnect.fast_io_fail_timeout)
+            } else {
+                None
+            },
         });
 
         nvme_channel.inner = Box::into_raw(inner);
@@ -626,6 +826,7 @@ impl NvmeControllerIoChannel {
             if let Some(qpair) = inner.qpair.take() {
                 inner.poll_group.remove_qpair(&qpair);
             }
+            inner.fail_queued_io(true);
         }
 
         debug!(
@@ -22,30 +22,46 @@ use spdk_rs::libspdk::{
     spdk_nvme_ctrlr_is_active_ns,
     spdk_nvme_ctrlr_register_aer_callback,
     spdk_nvme_ctrlr_reset,
+    spdk_nvme_ctrlr_set_trid,
     spdk_nvme_detach,
+    spdk_nvme_qpair,
 };
 
 use crate::{
-    bdev::nvmx::{
-        channel::{NvmeControllerIoChannel, NvmeIoChannel, NvmeIoChannelInner},
-        controller_inner::{SpdkNvmeController, TimeoutConfig},
-        controller_state::{
-            ControllerFailureReason,
-            ControllerFlag,
-            ControllerStateMachine,
+    bdev::{
+        nexus::NvmeAnaState,
+        nvmx::{
+            channel::{
+                NvmeControllerIoChannel,
+                NvmeIoChannel,
+                NvmeIoChannelInner,
+                NvmeQpairStats,
+            },
+            controller_inner::{
+                NvmeControllerHealthStats,
+                ReconnectPolicy,
+                SpdkNvmeController,
+                TimeoutConfig,
+            },
+            controller_state::{
+                ControllerFailureReason,
+                ControllerFlag,
+                ControllerStateMachine,
+            },
+            discovery::DiscoveryWatch,
+            nvme_bdev_running_config,
+            uri::NvmeControllerContext,
+            utils::{
+                nvme_cpl_succeeded,
+                NvmeAerInfoNotice,
+                NvmeAerInfoNvmCommandSet,
+                NvmeAerType,
+            },
+            NvmeControllerState,
+            NvmeControllerState::*,
+            NvmeNamespace,
+            NVME_CONTROLLERS,
         },
-        nvme_bdev_running_config,
-        uri::NvmeControllerContext,
-        utils::{
-            nvme_cpl_succeeded,
-            NvmeAerInfoNotice,
-            NvmeAerInfoNvmCommandSet,
-            NvmeAerType,
-        },
-        NvmeControllerState,
-        NvmeControllerState::*,
-        NvmeNamespace,
-        NVME_CONTROLLERS,
     },
     core::{
         poller,
@@ -63,6 +79,8 @@ use crate::{
     sleep::mayastor_sleep,
 };
 
+use self::transport::NvmeTransportId;
+
 #[derive(Debug)]
 struct ResetCtx {
     name: String,
@@ -71,6 +89,10 @@ struct ResetCtx {
     spdk_handle: SpdkNvmeController,
     io_device: Arc<IoDevice>,
     shutdown_in_progress: bool,
+    /// paths to try in order when failing over, empty for a plain reset
+    failover_paths: Vec<(usize, NvmeTransportId)>,
+    /// the path the controller failed over to
+    active_path: Option<usize>,
 }
 
 struct ShutdownCtx {
@@ -127,11 +149,21 @@ pub struct NvmeController<'a> {
     prchk_flags: u32,
     inner: Option<NvmeControllerInner<'a>>,
     state_machine: ControllerStateMachine,
-    event_dispatcher: Mutex<DeviceEventDispatcher>,
+    event_dispatcher: Arc<Mutex<DeviceEventDispatcher>>,
     /// Timeout config is accessed by SPDK-driven timeout callback handlers,
     /// so it needs to be a raw pointer. Mutable members are made atomic to
     /// eliminate lock contention between API path and callback path.
     pub(crate) timeout_config: NonNull<TimeoutConfig>,
+    /// all paths to the subsystem, more than one for multipath controllers
+    paths: Vec<NvmeTransportId>,
+    /// index of the path the controller is connected through
+    active_path: usize,
+    /// the active path is no longer advertised by the discovery service
+    stale_path: bool,
+    /// watch of the discovery service the paths are refreshed from
+    discovery: Option<DiscoveryWatch>,
+    /// size of the namespace when it was last populated
+    num_blocks: u64,
 }
 
 impl<'a> fmt::Debug for NvmeController<'a> {
@@ -140,6 +172,9 @@ impl<'a> fmt::Debug for NvmeController<'a> {
             .field("name", &self.name)
             .field("prchk_flags", &self.prchk_flags)
             .field("state_machine", &self.state_machine)
+            .field("paths", &self.paths)
+            .field("active_path", &self.active_path)
+            .field("discovery", &self.discovery)
             .finish()
     }
 }
@@ -156,11 +191,18 @@ impl<'a> NvmeController<'a> {
             prchk_flags,
             state_machine: ControllerStateMachine::new(name),
             inner: None,
-            event_dispatcher: Mutex::new(DeviceEventDispatcher::new()),
+            event_dispatcher: Arc::new(
+                Mutex::new(DeviceEventDispatcher::new()),
+            ),
             timeout_config: NonNull::new(Box::into_raw(Box::new(
                 TimeoutConfig::new(name),
             )))
             .expect("failed to box timeout context"),
+            paths: Vec::new(),
+            active_path: 0,
+            stale_path: false,
+            discovery: None,
+            num_blocks: 0,
         };
 
         debug!("{}: new NVMe controller created", l.name);
@@ -193,6 +235,120 @@ impl<'a> NvmeController<'a> {
         id
     }
 
+    /// set the paths to the subsystem, and the one the controller is
+    /// connected through
+    pub(crate) fn set_paths(
+        &mut self,
+        paths: Vec<NvmeTransportId>,
+        active_path: usize,
+    ) {
+        assert!(active_path < paths.len(), "active path out of range");
+        if paths.len() > 1 {
+            info!(
+                "{}: {} paths, connected through {}:{}",
+                self.name,
+                paths.len(),
+                paths[active_path].traddr(),
+                paths[active_path].svcid()
+            );
+        }
+        self.paths = paths;
+        self.active_path = active_path;
+    }
+
+    /// returns true when the controller has alternate paths to fail over to
+    pub fn is_multipath(&self) -> bool {
+        self.paths.len() > 1
+    }
+
+    /// returns true when resets should connect through the failover paths
+    /// rather than the active path
+    pub(crate) fn fails_over(&self) -> bool {
+        self.is_multipath() || self.stale_path
+    }
+
+    /// set the watch of the discovery service the paths of the controller
+    /// are refreshed from
+    pub(crate) fn set_discovery(&mut self, discovery: Option<DiscoveryWatch>) {
+        self.discovery = discovery;
+    }
+
+    /// returns the generation counter of the discovery log page the paths of
+    /// the controller were last read from, for controllers created through a
+    /// discovery service
+    pub fn discovery_generation(&self) -> Option<u64> {
+        self.discovery.as_ref().map(|d| d.generation())
+    }
+
+    /// replace the paths with those advertised by the discovery service. The
+    /// controller stays on its active path while it is advertised, and
+    /// fails over to the advertised paths on the next reset otherwise.
+    pub(crate) fn set_discovered_paths(&mut self, paths: Vec<NvmeTransportId>) {
+        assert!(!paths.is_empty(), "no discovered paths");
+        let active = self.active_path().map(|p| (p.traddr(), p.svcid()));
+        match paths
+            .iter()
+            .position(|p| Some((p.traddr(), p.svcid())) == active)
+        {
+            Some(path) => {
+                self.active_path = path;
+                self.stale_path = false;
+            }
+            None => {
+                warn!(
+                    "{}: active path no longer advertised, {} paths discovered",
+                    self.name,
+                    paths.len()
+                );
+                // failing over starts with the path after the active one,
+                // so all discovered paths are tried in order
+                self.active_path = paths.len() - 1;
+                self.stale_path = true;
+            }
+        }
+        self.paths = paths;
+    }
+
+    /// returns the path the controller is connected through
+    pub fn active_path(&self) -> Option<&NvmeTransportId> {
+        self.paths.get(self.active_path)
+    }
+
+    /// the paths to try when failing over, starting with the one after the
+    /// active path and ending with the active path itself, as it may have
+    /// recovered in the meantime
+    fn failover_paths(&self) -> Vec<(usize, NvmeTransportId)> {
+        (1 ..= self.paths.len())
+            .map(|i| (self.active_path + i) % self.paths.len())
+            .map(|i| (i, self.paths[i].clone()))
+            .collect()
+    }
+
+    /// set the reconnect behaviour of the controller
+    pub(crate) fn set_reconnect_policy(&mut self, policy: ReconnectPolicy) {
+        unsafe { self.timeout_config.as_mut().set_reconnect_policy(policy) };
+    }
+
+    /// returns the reconnect behaviour of the controller
+    pub(crate) fn reconnect_policy(&self) -> ReconnectPolicy {
+        unsafe { self.timeout_config.as_ref().reconnect_policy() }
+    }
+
+    /// Bring a controller which was faulted by failed reconnect attempts
+    /// back into service once it reconnected.
+    pub(crate) fn recover(&mut self) {
+        if self
+            .state_machine
+            .transition_checked(
+                Faulted(ControllerFailureReason::Reset),
+                Running,
+            )
+            .is_ok()
+        {
+            info!("{} controller recovered", self.name);
+        }
+    }
+
     // As of now, only 1 namespace per controller is supported.
     pub fn namespace(&self) -> Option<Arc<NvmeNamespace>> {
         let inner = self
@@ -256,7 +412,9 @@ impl<'a> NvmeController<'a> {
             vec![]
         } else {
             debug!("{}: namespace successfully populated", self.name);
-            vec![Arc::new(NvmeNamespace::from_ptr(ns))]
+            let ns = NvmeNamespace::from_ptr(ns);
+            self.num_blocks = ns.num_blocks();
+            vec![Arc::new(ns)]
         };
 
         ctrlr_inner.namespaces = namespaces;
@@ -277,6 +435,23 @@ impl<'a> NvmeController<'a> {
         ns_active
     }
 
+    /// Rescan the namespace after its attributes changed. Returns true when
+    /// the namespace was resized.
+    fn namespace_attr_changed(&mut self) -> bool {
+        debug!("{}: populating namespaces in response to AER", self.name);
+
+        let num_blocks = self.num_blocks;
+        if !self.populate_namespaces() || self.num_blocks == num_blocks {
+            return false;
+        }
+
+        info!(
+            "{}: namespace resized from {} to {} blocks",
+            self.name, num_blocks, self.num_blocks
+        );
+        true
+    }
+
     /// Get controller state.
     pub fn get_state(&self) -> NvmeControllerState {
         self.state_machine.current_state()
@@ -319,12 +494,14 @@ impl<'a> NvmeController<'a> {
             self.name, failover
         );
 
-        if failover {
-            warn!(
-                "{} failover is not supported for controller reset",
-                self.name
-            );
-        }
+        let failover_paths = if failover && self.fails_over() {
+            self.failover_paths()
+        } else {
+            if failover {
+                warn!("{} no alternate paths to fail over to", self.name);
+            }
+            Vec::new()
+        };
 
         let io_device = self.inner.as_ref().unwrap().io_device.clone();
         let reset_ctx = ResetCtx {
@@ -336,6 +513,8 @@ impl<'a> NvmeController<'a> {
                 .expect("controller is may not be NULL"),
             io_device,
             shutdown_in_progress: false,
+            failover_paths,
+            active_path: None,
         };
 
         debug!("{}: starting reset", self.name);
@@ -487,6 +666,78 @@ impl<'a> NvmeController<'a> {
         Ok(())
     }
 
+    /// Get the counters of the resets, timeouts and keep alive failures of
+    /// the controller.
+    pub fn get_health_stats(&self) -> NvmeControllerHealthStats {
+        unsafe { self.timeout_config.as_ref().health_stats }
+    }
+
+    /// Get the statistics of the qpairs of all I/O channels of the
+    /// controller, one per core.
+    pub fn get_qpair_stats<T: 'static + Sized, F>(
+        &self,
+        cb: F,
+        cb_arg: T,
+    ) -> Result<(), CoreError>
+    where
+        F: Fn(Result<Vec<NvmeQpairStats>, CoreError>, T) + 'static,
+    {
+        struct QpairStatsCtx<V: 'static + Sized> {
+            cb: Box<
+                dyn Fn(Result<Vec<NvmeQpairStats>, CoreError>, V) + 'static,
+            >,
+            cb_arg: V,
+            qpair_stats: Vec<NvmeQpairStats>,
+        }
+
+        if self.state_machine.current_state() != Running {
+            error!(
+                "{} Controller is in '{:?}' state, qpair stats not available",
+                self.name,
+                self.state_machine.current_state()
+            );
+            return Err(CoreError::DeviceStatisticsError {
+                source: Errno::EAGAIN,
+            });
+        }
+
+        let ctx = QpairStatsCtx {
+            cb: Box::new(cb),
+            cb_arg,
+            qpair_stats: Vec::new(),
+        };
+
+        // Collect the qpair statistics on the core of the channel.
+        fn collect_qpair_stats<N>(
+            channel: &mut NvmeIoChannelInner,
+            ctx: &mut QpairStatsCtx<N>,
+        ) -> i32 {
+            ctx.qpair_stats.push(channel.get_qpair_stats());
+            0
+        }
+
+        fn collect_qpair_stats_done<N>(result: i32, ctx: QpairStatsCtx<N>) {
+            let stats = if result == 0 {
+                Ok(ctx.qpair_stats)
+            } else {
+                Err(CoreError::DeviceStatisticsError {
+                    source: Errno::EAGAIN,
+                })
+            };
+
+            (ctx.cb)(stats, ctx.cb_arg)
+        }
+
+        self.inner.as_ref().unwrap().io_device.traverse_io_channels(
+            collect_qpair_stats::<T>,
+            collect_qpair_stats_done::<T>,
+            NvmeIoChannel::inner_from_channel,
+            ctx,
+        );
+
+        Ok(())
+    }
+
     /// Shutdown the controller and all its resources.
     /// This function deallocates all controller's resources (I/O queues, I/O
     /// channels and pollers), aborts all active I/O operations and
@@ -545,6 +796,18 @@ impl<'a> NvmeController<'a> {
                 .clear_flag_exclusively(ControllerFlag::ResetActive)
                 .expect("Reset flag improperly cleared during reset");
 
+            if let Some(path) = reset_ctx.active_path {
+                controller.active_path = path;
+                controller.stale_path = false;
+            }
+
+            let health_stats =
+                unsafe { &mut controller.timeout_config.as_mut().health_stats };
+            health_stats.resets += 1;
+            if status != 0 {
+                health_stats.failed_resets += 1;
+            }
+
             if status != 0 {
                 // Transition controller into Faulted state, but only if the
                 // controller is in Running state, as concurrent
@@ -610,6 +873,8 @@ impl<'a> NvmeController<'a> {
             spdk_handle: self.controller().expect("controller may not be NULL"),
             io_device,
             shutdown_in_progress: false,
+            failover_paths: Vec::new(),
+            active_path: None,
         };
 
         let inner = self.inner.as_mut().unwrap();
@@ -623,7 +888,106 @@ impl<'a> NvmeController<'a> {
         Ok(())
     }
 
-    fn _reset_destroy_channels_done(status: i32, reset_ctx: ResetCtx) {
+    /// Reset the I/O qpair a command timed out on, leaving the I/O channels
+    /// of the other cores alone. The qpair is looked up on the core of each
+    /// channel, as it may be gone by the time the channels are visited.
+    pub(crate) fn reset_qpair(
+        &self,
+        qpair: *mut spdk_nvme_qpair,
+    ) -> Result<(), CoreError> {
+        if self.state_machine.current_state() != Running {
+            error!(
+                "{} Controller is in '{:?}' state, qpair reset not possible",
+                self.name,
+                self.state_machine.current_state()
+            );
+            return Err(CoreError::ResetDispatch {
+                source: Errno::EBUSY,
+            });
+        }
+
+        let name = self.name.clone();
+        let ctrlr = self.controller().expect("controller may not be NULL");
+
+        self.inner.as_ref().unwrap().io_device.traverse_io_channels(
+            move |channel: &mut NvmeIoChannelInner, found: &mut bool| {
+                if channel.is_shutdown()
+                    || !channel
+                        .qpair
+                        .as_ref()
+                        .map_or(false, |q| q.as_ptr() == qpair)
+                {
+                    return 0;
+                }
+
+                *found = true;
+                channel.reset();
+                let rc = channel.reinitialize(&name, ctrlr);
+                if rc == 0 {
+                    info!("{} qpair {:p} successfully reset", name, qpair);
+                } else {
+                    error!(
+                        "{} failed to reset qpair {:p}: {}",
+                        name, qpair, rc
+                    );
+                }
+                0
+            },
+            move |_, found| {
+                if !found {
+                    debug!("qpair {:p} no longer exists, not reset", qpair);
+                }
+            },
+            NvmeIoChannel::inner_from_channel,
+            false,
+        );
+
+        Ok(())
+    }
+
+    /// Reconnect the controller through the first of the failover paths
+    /// which is reachable. The path of a controller can only be changed
+    /// while the controller is failed, so it is failed first.
+    fn _failover(reset_ctx: &mut ResetCtx) -> i32 {
+        let ctrlr = reset_ctx.spdk_handle;
+        let mut rc = -(Errno::ENXIO as i32);
+
+        for (path, trid) in reset_ctx.failover_paths.iter() {
+            ctrlr.fail();
+            rc = unsafe {
+                spdk_nvme_ctrlr_set_trid(
+                    ctrlr.as_ptr(),
+                    trid.as_ptr() as *mut _,
+                )
+            };
+            if rc == 0 {
+                rc = unsafe { spdk_nvme_ctrlr_reset(ctrlr.as_ptr()) };
+            }
+
+            if rc == 0 {
+                info!(
+                    "{}: failed over to {}:{}",
+                    reset_ctx.name,
+                    trid.traddr(),
+                    trid.svcid()
+                );
+                reset_ctx.active_path = Some(*path);
+                break;
+            }
+
+            warn!(
+                "{}: failed to connect through {}:{}, rc = {}",
+                reset_ctx.name,
+                trid.traddr(),
+                trid.svcid(),
+                rc
+            );
+        }
+
+        rc
+    }
+
+    fn _reset_destroy_channels_done(status: i32, mut reset_ctx: ResetCtx) {
         if status != 0 {
             error!(
                 "{}: controller reset failed with status = {}",
@@ -644,8 +1008,11 @@ impl<'a> NvmeController<'a> {
             return;
         }
 
-        let rc =
-            unsafe { spdk_nvme_ctrlr_reset(reset_ctx.spdk_handle.as_ptr()) };
+        let rc = if reset_ctx.failover_paths.is_empty() {
+            unsafe { spdk_nvme_ctrlr_reset(reset_ctx.spdk_handle.as_ptr()) }
+        } else {
+            NvmeController::_failover(&mut reset_ctx)
+        };
         if rc != 0 {
             error!(
                 "{} failed to reset controller, rc = {}",
@@ -705,7 +1072,7 @@ impl<'a> NvmeController<'a> {
     ///
     /// Note: Keep a separate copy of all registered listeners in order to not
     /// invoke them with the lock held.
-    fn notify_listeners(&self, event: DeviceEventType) -> usize {
+    pub(crate) fn notify_listeners(&self, event: DeviceEventType) -> usize {
         let mut disp = self
             .event_dispatcher
             .lock()
@@ -715,6 +1082,12 @@ impl<'a> NvmeController<'a> {
         disp.count()
     }
 
+    /// Returns the dispatcher of the device events, to notify the listeners
+    /// without holding the lock of the controller.
+    fn event_dispatcher(&self) -> Arc<Mutex<DeviceEventDispatcher>> {
+        Arc::clone(&self.event_dispatcher)
+    }
+
     /// Register listener to monitor device events related to this controller.
     pub fn register_device_listener(
         &self,
@@ -796,27 +1169,68 @@ extern "C" fn aer_cb(ctx: *mut c_void, cpl: *const spdk_nvme_cpl) {
         event_type, event_info
     );
 
-    // Populate namespaces in response to AER.
     if event_type == NvmeAerType::Notice as u32
-        && event_info == NvmeAerInfoNotice::AttrChanged as u32
+        && (event_info == NvmeAerInfoNotice::AttrChanged as u32
+            || event_info == NvmeAerInfoNotice::AnaChange as u32)
     {
         let cid = ctx as u64;
 
-        match NVME_CONTROLLERS.lookup_by_name(cid.to_string()) {
-            Some(c) => {
-                let mut ctrlr = c.lock();
-                debug!(
-                    "{}: populating namespaces in response to AER",
-                    ctrlr.get_name()
-                );
-                ctrlr.populate_namespaces();
-            }
+        let carc = match NVME_CONTROLLERS.lookup_by_name(cid.to_string()) {
+            Some(c) => c,
             None => {
                 warn!(
-                    "No NVMe controller exists with ID 0x{:x}, no namespaces rescanned",
+                    "No NVMe controller exists with ID 0x{:x}, namespace notice ignored",
                     cid,
                 );
+                return;
+            }
+        };
+
+        // Populate namespaces in response to AER. The listeners are notified
+        // of a resize once the controller is unlocked, as they look up the
+        // device, which locks the controller.
+        if event_info == NvmeAerInfoNotice::AttrChanged as u32 {
+            let (resized, name, dispatcher) = {
+                let mut ctrlr = carc.lock();
+                (
+                    ctrlr.namespace_attr_changed(),
+                    ctrlr.get_name(),
+                    ctrlr.event_dispatcher(),
+                )
+            };
+            if resized {
+                dispatcher
+                    .lock()
+                    .expect("event dispatcher lock poisoned")
+                    .dispatch_event(DeviceEventType::DeviceResized, &name);
             }
+            return;
+        }
+
+        // The ANA states of the namespaces have already been updated from
+        // the ANA log page by SPDK. I/O is held back while the namespace is
+        // in transition, and routed through an alternate path once it is no
+        // longer accessible through this one.
+        let (name, state, timeout_config) = {
+            let ctrlr = carc.lock();
+            match ctrlr.namespace() {
+                Some(ns) => {
+                    (ctrlr.get_name(), ns.ana_state(), ctrlr.timeout_config)
+                }
+                None => return,
+            }
+        };
+
+        info!("{}: namespace ANA state changed to {:?}", name, state);
+        if matches!(
+            state,
+            NvmeAnaState::InaccessibleState | NvmeAnaState::PersistentLossState
+        ) && !TimeoutConfig::from_ptr(timeout_config.as_ptr()).failover()
+        {
+            warn!(
+                "{}: namespace is inaccessible and no alternate path is available",
+                name
+            );
         }
     } else if event_type == NvmeAerType::Io as u32
         && event_info == NvmeAerInfoNvmCommandSet::ReservationLogAvail as u32
@@ -842,6 +1256,15 @@ pub extern "C" fn nvme_poll_adminq(ctx: *mut c_void) -> i32 {
     let result = context.process_adminq();
 
     if result < 0 {
+        // A controller which lost its transport connection is failed over to
+        // an alternate path first, and otherwise reconnects unless disabled.
+        // Listeners are only notified when neither is possible.
+        if result == -(Errno::ENXIO as i32)
+            && (context.failover() || context.reconnect())
+        {
+            return 1;
+        }
+
         if context.start_device_destroy() {
             error!(
                 "process adminq: {}: {}",
@@ -1160,12 +1583,13 @@ pub(crate) mod options {
 }
 
 pub(crate) mod transport {
-    use std::{ffi::CStr, fmt::Debug, ptr::copy_nonoverlapping};
+    use std::{ffi::CStr, fmt::Debug, net::Ipv6Addr, ptr::copy_nonoverlapping};
 
     use libc::c_void;
 
     use spdk_rs::libspdk::spdk_nvme_transport_id;
 
+    #[derive(Clone)]
     pub struct NvmeTransportId(spdk_nvme_transport_id);
 
     impl Debug for NvmeTransportId {
@@ -1271,8 +1695,13 @@ pub(crate) mod transport {
             }
         }
 
-        /// the address to connect to
+        /// the address to connect to, the address family follows from it
         pub fn with_traddr(mut self, traddr: &str) -> Self {
+            self.adrfam = if traddr.parse::<Ipv6Addr>().is_ok() {
+                AdressFamily::NvmfAdrfamIpv6
+            } else {
+                AdressFamily::NvmfAdrfamIpv4
+            };
             self.traddr = traddr.to_string();
             self
         }
@@ -1293,7 +1722,7 @@ pub(crate) mod transport {
         pub fn build(self) -> NvmeTransportId {
             let trtype = String::from(TransportId::TCP);
             let mut trid = spdk_nvme_transport_id {
-                adrfam: AdressFamily::NvmfAdrfamIpv4 as u32,
+                adrfam: self.adrfam as u32,
                 trtype: TransportId::TCP as u32,
                 ..Default::default()
             };
@@ -1341,6 +1770,22 @@ pub(crate) mod transport {
             assert_eq!(transport.traddr(), "127.0.0.1");
             assert_eq!(transport.subnqn(), "nqn.2021-01-01:test.nqn");
             assert_eq!(transport.svcid(), "4420");
+            assert_eq!(
+                transport.0.adrfam,
+                transport::AdressFamily::NvmfAdrfamIpv4 as u32
+            );
+
+            let transport = transport::Builder::new()
+                .with_subnqn("nqn.2021-01-01:test.nqn")
+                .with_svcid("4420")
+                .with_traddr("fd00::1")
+                .build();
+
+            assert_eq!(transport.traddr(), "fd00::1");
+            assert_eq!(
+                transport.0.adrfam,
+                transport::AdressFamily::NvmfAdrfamIpv6 as u32
+            );
         }
     }
 }
@@ -1,4 +1,5 @@
 use std::{
+    collections::HashMap,
     convert::TryFrom,
     ops::{Deref, DerefMut},
     os::raw::c_void,
@@ -30,7 +31,15 @@ use crate::{
         NvmeController,
         NVME_CONTROLLERS,
     },
-    core::{CoreError, DeviceIoController, DeviceTimeoutAction},
+    core::{
+        CoreError,
+        DeviceEventType,
+        DeviceIoController,
+        DeviceTimeoutAction,
+        DeviceTimeoutPolicy,
+        Reactors,
+    },
+    sleep::mayastor_sleep,
 };
 
 impl TryFrom<u32> for DeviceTimeoutAction {
@@ -67,14 +76,101 @@ const MAX_RESET_ATTEMPTS: u32 = 1;
 /// after all current reset attempts have been used.
 const RESET_COOLDOWN_INTERVAL: Duration = Duration::from_secs(3);
 
+/// Reconnect behaviour of a controller which lost its connection.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) struct ReconnectPolicy {
+    /// time to keep reconnecting for before the device is removed, zero
+    /// disables reconnects
+    pub ctrlr_loss_timeout: Duration,
+    /// delay before the first reconnect attempt
+    pub reconnect_delay: Duration,
+    /// time I/O is queued for while reconnecting, zero fails I/O right away
+    pub fast_io_fail_timeout: Duration,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        let opts = nvme_bdev_running_config();
+        Self {
+            ctrlr_loss_timeout: Duration::from_secs(
+                opts.ctrlr_loss_timeout_sec as u64,
+            ),
+            reconnect_delay: Duration::from_secs(
+                opts.reconnect_delay_sec as u64,
+            ),
+            fast_io_fail_timeout: Duration::from_secs(
+                opts.fast_io_fail_timeout_sec as u64,
+            ),
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// returns true when a controller reconnects after losing its connection
+    pub fn is_enabled(&self) -> bool {
+        !self.ctrlr_loss_timeout.is_zero()
+    }
+
+    /// returns true when I/O is queued while the controller reconnects
+    pub fn queues_io(&self) -> bool {
+        self.is_enabled() && !self.fast_io_fail_timeout.is_zero()
+    }
+}
+
+/// Progress of the failover of a multipath controller to an alternate path.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum FailoverState {
+    Idle,
+    Active,
+    /// none of the paths were reachable, the controller is given up on
+    Exhausted,
+}
+
+/// Counters of the events affecting the health of a controller.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NvmeControllerHealthStats {
+    /// controller resets, including failovers and reconnect attempts
+    pub resets: u64,
+    /// controller resets which failed
+    pub failed_resets: u64,
+    /// commands which timed out, on the admin or I/O queues
+    pub timeouts: u64,
+    /// timed out commands which were aborted
+    pub aborted_commands: u64,
+    /// times processing the admin queue failed with ENXIO, as the admin
+    /// queue lost its connection. Keep alive failures surface this way, as
+    /// do any other transport failures of the admin queue.
+    pub adminq_failures: u64,
+}
+
 pub(crate) struct TimeoutConfig {
     pub name: String,
     timeout_action: AtomicCell<DeviceTimeoutAction>,
+    timeout_policy: AtomicCell<Option<DeviceTimeoutPolicy>>,
+    /// timeouts counted by the timeout policy, and the time of the last one
+    policy_timeouts: u32,
+    last_timeout: Option<Instant>,
+    /// stalled qpairs by address, null for the admin qpair, with the time
+    /// the stall was counted at and the action it is handled with
+    stalls: HashMap<usize, (Instant, DeviceTimeoutAction)>,
+    /// the I/O timeout, commands of a qpair which time out within it of the
+    /// first one belong to the same stall
+    timeout_period: Duration,
     reset_in_progress: AtomicCell<bool>,
     ctrlr: SpdkNvmeController,
     reset_attempts: u32,
     next_reset_time: Instant,
     destroy_in_progress: AtomicCell<bool>,
+    failover_state: AtomicCell<FailoverState>,
+    reconnect_policy: ReconnectPolicy,
+    /// time the controller lost its connection at, none while connected
+    reconnect_since: Option<Instant>,
+    /// delay before the next reconnect attempt
+    reconnect_delay: Duration,
+    reconnect_attempts: u32,
+    /// the admin queue lost its connection
+    adminq_failed: bool,
+    pub(crate) health_stats: NvmeControllerHealthStats,
 }
 
 impl Drop for TimeoutConfig {
@@ -90,11 +186,23 @@ impl TimeoutConfig {
         Self {
             name: String::from(ctrlr),
             timeout_action: AtomicCell::new(DeviceTimeoutAction::Ignore),
+            timeout_policy: AtomicCell::new(None),
+            policy_timeouts: 0,
+            last_timeout: None,
+            stalls: HashMap::new(),
+            timeout_period: Duration::default(),
             reset_in_progress: AtomicCell::new(false),
             ctrlr: SpdkNvmeController(NonNull::dangling()),
             reset_attempts: MAX_RESET_ATTEMPTS,
             next_reset_time: Instant::now(),
             destroy_in_progress: AtomicCell::new(false),
+            failover_state: AtomicCell::new(FailoverState::Idle),
+            reconnect_policy: ReconnectPolicy::default(),
+            reconnect_since: None,
+            reconnect_delay: Duration::default(),
+            reconnect_attempts: 0,
+            adminq_failed: false,
+            health_stats: NvmeControllerHealthStats::default(),
         }
     }
 
@@ -111,10 +219,22 @@ impl TimeoutConfig {
         self.ctrlr = ctrlr;
     }
 
-    pub fn process_adminq(&self) -> i32 {
-        unsafe {
+    pub fn process_adminq(&mut self) -> i32 {
+        let rc = unsafe {
             spdk_nvme_ctrlr_process_admin_completions(self.ctrlr.as_ptr())
+        };
+
+        // A lost connection of the admin queue is accounted once, until the
+        // admin queue is processed successfully again.
+        if rc == -libc::ENXIO {
+            if !self.adminq_failed {
+                self.adminq_failed = true;
+                self.health_stats.adminq_failures += 1;
+            }
+        } else if rc >= 0 {
+            self.adminq_failed = false;
         }
+        rc
     }
 
     fn reset_cb(success: bool, ctx: *mut c_void) {
@@ -182,6 +302,188 @@ impl TimeoutConfig {
             .map(|c| c.lock().hot_remove(hot_remove_cb, self.as_ptr()));
     }
 
+    fn failover_cb(success: bool, ctx: *mut c_void) {
+        let timeout_ctx = TimeoutConfig::from_ptr(ctx as *mut TimeoutConfig);
+
+        if success {
+            info!("{} controller successfully failed over", timeout_ctx.name);
+            timeout_ctx.failover_state.store(FailoverState::Idle);
+        } else {
+            error!(
+                "{} failed to fail over, no path to the subsystem is reachable",
+                timeout_ctx.name
+            );
+            timeout_ctx.failover_state.store(FailoverState::Exhausted);
+        }
+    }
+
+    /// Fails a multipath controller over to an alternate path after a
+    /// transport failure. Returns true when the failure is being handled by
+    /// a failover, and false when the controller has no path left to fail
+    /// over to.
+    pub(crate) fn failover(&mut self) -> bool {
+        match self.failover_state.load() {
+            FailoverState::Active => return true,
+            FailoverState::Exhausted => return false,
+            FailoverState::Idle => {}
+        }
+
+        let carc = match NVME_CONTROLLERS.lookup_by_name(&self.name) {
+            Some(c) => c,
+            None => return false,
+        };
+        let mut c = carc.lock();
+        if !c.is_multipath() {
+            return false;
+        }
+
+        self.failover_state.store(FailoverState::Active);
+        match c.reset(TimeoutConfig::failover_cb, self.as_ptr(), true) {
+            Ok(()) => {
+                warn!("{} transport failure, failover initiated", self.name);
+                true
+            }
+            Err(e) => {
+                error!("{}: failed to initiate failover: {}", self.name, e);
+                self.failover_state.store(FailoverState::Idle);
+                false
+            }
+        }
+    }
+
+    /// Set the reconnect behaviour of the controller.
+    pub(crate) fn set_reconnect_policy(&mut self, policy: ReconnectPolicy) {
+        self.reconnect_policy = policy;
+    }
+
+    /// Get the reconnect behaviour of the controller.
+    pub(crate) fn reconnect_policy(&self) -> ReconnectPolicy {
+        self.reconnect_policy
+    }
+
+    /// Starts reconnecting a controller which lost its connection, unless
+    /// reconnects are disabled. Returns true when the connection loss is
+    /// handled by reconnecting, in which case the device is only removed
+    /// once the controller loss timeout expires.
+    pub(crate) fn reconnect(&mut self) -> bool {
+        if !self.reconnect_policy.is_enabled() {
+            return false;
+        }
+
+        if self.reconnect_since.is_none() {
+            warn!(
+                "{} lost connection, reconnecting for up to {} secs",
+                self.name,
+                self.reconnect_policy.ctrlr_loss_timeout.as_secs()
+            );
+            self.reconnect_since = Some(Instant::now());
+            self.reconnect_attempts = 0;
+            self.reconnect_delay = self.reconnect_policy.reconnect_delay;
+            self.schedule_reconnect();
+        }
+        true
+    }
+
+    /// Schedule the next reconnect attempt after the current delay.
+    fn schedule_reconnect(&self) {
+        let name = self.name.clone();
+        let delay = self.reconnect_delay;
+
+        Reactors::current().send_future(async move {
+            if mayastor_sleep(delay).await.is_err() {
+                error!("{} failed to wait for reconnect delay", name);
+            }
+
+            // The controller may have been destroyed in the meantime.
+            let carc = match NVME_CONTROLLERS.lookup_by_name(&name) {
+                Some(c) => c,
+                None => return,
+            };
+            let mut c = carc.lock();
+            let ctx = TimeoutConfig::from_ptr(c.timeout_config.as_ptr());
+            ctx.reconnect_attempts += 1;
+
+            // Alternate paths are tried as well for multipath controllers,
+            // and for controllers whose path is no longer advertised by their
+            // discovery service.
+            let failover = c.fails_over();
+            if let Err(e) =
+                c.reset(TimeoutConfig::reconnect_cb, ctx.as_ptr(), failover)
+            {
+                error!("{} failed to initiate reconnect: {}", name, e);
+                drop(c);
+                ctx.reconnect_failed();
+            }
+        });
+    }
+
+    fn reconnect_cb(success: bool, ctx: *mut c_void) {
+        let timeout_ctx = TimeoutConfig::from_ptr(ctx as *mut TimeoutConfig);
+
+        if success {
+            timeout_ctx.reconnected();
+        } else {
+            timeout_ctx.reconnect_failed();
+        }
+    }
+
+    fn reconnected(&mut self) {
+        let since = self.reconnect_since.take().unwrap_or_else(Instant::now);
+        info!(
+            "{} reconnected after {} attempts in {:?}",
+            self.name,
+            self.reconnect_attempts,
+            since.elapsed()
+        );
+        self.failover_state.store(FailoverState::Idle);
+
+        // Failed attempts leave the controller faulted.
+        if let Some(c) = NVME_CONTROLLERS.lookup_by_name(&self.name) {
+            c.lock().recover();
+        }
+    }
+
+    /// Schedule the next attempt with twice the delay, bounded by the time
+    /// left until the loss timeout, or remove the device once it expired.
+    fn reconnect_failed(&mut self) {
+        let since = match self.reconnect_since {
+            Some(since) => since,
+            None => return,
+        };
+
+        let remaining = self
+            .reconnect_policy
+            .ctrlr_loss_timeout
+            .checked_sub(since.elapsed());
+
+        match remaining {
+            Some(remaining) if !remaining.is_zero() => {
+                self.reconnect_delay = (self.reconnect_delay * 2)
+                    .min(remaining)
+                    .max(Duration::from_millis(1));
+                warn!(
+                    "{} reconnect attempt {} failed, retrying in {:?}",
+                    self.name, self.reconnect_attempts, self.reconnect_delay
+                );
+                self.schedule_reconnect();
+            }
+            _ => {
+                error!(
+                    "{} failed to reconnect within {} secs, removing device",
+                    self.name,
+                    self.reconnect_policy.ctrlr_loss_timeout.as_secs()
+                );
+                if self.start_device_destroy() {
+                    if let Some(c) = NVME_CONTROLLERS.lookup_by_name(&self.name)
+                    {
+                        c.lock()
+                            .notify_listeners(DeviceEventType::DeviceRemoved);
+                    }
+                }
+            }
+        }
+    }
+
     /// Resets controller exclusively, taking into account existing active
     /// resets related to I/O timeout.
     pub(crate) fn reset_controller(&mut self) {
@@ -248,6 +550,83 @@ impl TimeoutConfig {
         self.timeout_action.load()
     }
 
+    /// Set new tiered timeout policy, which starts counting timeouts anew.
+    pub fn set_timeout_policy(&mut self, policy: Option<DeviceTimeoutPolicy>) {
+        self.timeout_policy.store(policy);
+        self.policy_timeouts = 0;
+        self.last_timeout = None;
+        self.stalls.clear();
+    }
+
+    /// Get current tiered timeout policy.
+    pub fn get_timeout_policy(&self) -> Option<DeviceTimeoutPolicy> {
+        self.timeout_policy.load()
+    }
+
+    /// Returns the action for a command which timed out on the given qpair,
+    /// which escalates with every stall when a timeout policy is set. All
+    /// commands in flight when a qpair stalls time out together, so they
+    /// are handled alike and count once.
+    fn next_timeout_action(
+        &mut self,
+        qpair: *mut spdk_nvme_qpair,
+        now: Instant,
+    ) -> DeviceTimeoutAction {
+        let policy = match self.timeout_policy.load() {
+            Some(policy) => policy,
+            None => return self.timeout_action.load(),
+        };
+
+        let period = self.timeout_period;
+        self.stalls.retain(|_, (since, _)| {
+            now.saturating_duration_since(*since) < period
+        });
+        if let Some((_, action)) = self.stalls.get(&(qpair as usize)) {
+            return *action;
+        }
+
+        if self.last_timeout.map_or(false, |last| {
+            now.duration_since(last) >= policy.recovery_interval
+        }) {
+            info!(
+                "{} no timeouts for {:?}, timeout policy starts over",
+                self.name, policy.recovery_interval
+            );
+            self.policy_timeouts = 0;
+        }
+        self.last_timeout = Some(now);
+        self.policy_timeouts = self.policy_timeouts.saturating_add(1);
+
+        let action = policy.action(self.policy_timeouts);
+        self.stalls.insert(qpair as usize, (now, action));
+        action
+    }
+
+    /// Returns the action to take when aborting a command which timed out
+    /// failed. The timeout policy escalates the stall of the qpair to the
+    /// next tier, the controller is reset otherwise.
+    fn abort_failed(
+        &mut self,
+        qpair: *mut spdk_nvme_qpair,
+    ) -> DeviceTimeoutAction {
+        if self.timeout_policy.load().is_none() {
+            return DeviceTimeoutAction::Reset;
+        }
+        if let Some((_, action)) = self.stalls.get_mut(&(qpair as usize)) {
+            *action = DeviceTimeoutAction::QpairReset;
+        }
+        DeviceTimeoutAction::QpairReset
+    }
+
+    /// Reset the I/O qpair a command timed out on. Returns false when the
+    /// reset could not be initiated.
+    fn reset_qpair(&self, qpair: *mut spdk_nvme_qpair) -> bool {
+        match NVME_CONTROLLERS.lookup_by_name(&self.name) {
+            Some(c) => c.lock().reset_qpair(qpair).is_ok(),
+            None => false,
+        }
+    }
+
     pub fn from_ptr(ptr: *mut TimeoutConfig) -> &'static mut TimeoutConfig {
         unsafe { &mut *(ptr as *mut TimeoutConfig) }
     }
@@ -336,6 +715,25 @@ impl<'a> DeviceIoController for NvmeController<'a> {
         info!("{} timeout action set to {:?}", self.name, action);
         Ok(())
     }
+
+    /// Get current tiered timeout policy.
+    fn get_timeout_policy(
+        &self,
+    ) -> Result<Option<DeviceTimeoutPolicy>, CoreError> {
+        Ok(unsafe { self.timeout_config.as_ref().get_timeout_policy() })
+    }
+
+    /// Set current tiered timeout policy.
+    fn set_timeout_policy(
+        &mut self,
+        policy: Option<DeviceTimeoutPolicy>,
+    ) -> Result<(), CoreError> {
+        unsafe {
+            self.timeout_config.as_mut().set_timeout_policy(policy);
+        };
+        info!("{} timeout policy set to {:?}", self.name, policy);
+        Ok(())
+    }
 }
 
 // I/O timeout handling for NVMe controller.
@@ -348,6 +746,7 @@ impl<'a> NvmeController<'a> {
 
         if nvme_cpl_succeeded(cpl) {
             info!("{} CID abort succeeded for controller.", timeout_ctx.name);
+            timeout_ctx.health_stats.aborted_commands += 1;
         } else {
             error!(
                 "{} CID abort failed, resetting the controller.",
@@ -365,12 +764,14 @@ impl<'a> NvmeController<'a> {
     ) {
         let spdk_ctrlr = SpdkNvmeController::from(ctrlr);
         let timeout_cfg = TimeoutConfig::from_ptr(cb_arg as *mut TimeoutConfig);
-        let mut timeout_action = timeout_cfg.timeout_action.load();
+        let mut timeout_action =
+            timeout_cfg.next_timeout_action(qpair, Instant::now());
 
         error!(
             "{}: detected timeout: qpair={:p}, cid={}, action={:?}",
             timeout_cfg.name, qpair, cid, timeout_action
         );
+        timeout_cfg.health_stats.timeouts += 1;
 
         // Check Controller Fatal Status for non-admin commands only to avoid
         // endless command resubmission in case of disconnected qpair.
@@ -387,7 +788,9 @@ impl<'a> NvmeController<'a> {
 
         //Handle timeout based on the action.
         match timeout_action {
-            DeviceTimeoutAction::Abort | DeviceTimeoutAction::Reset => {
+            DeviceTimeoutAction::Abort
+            | DeviceTimeoutAction::QpairReset
+            | DeviceTimeoutAction::Reset => {
                 if timeout_action == DeviceTimeoutAction::Abort {
                     // Abort commands only for non-admin queue, fallthrough
                     // to reset otherwise.
@@ -410,14 +813,37 @@ impl<'a> NvmeController<'a> {
                             "{}: unable to abort CID {}, reset required",
                             timeout_cfg.name, cid
                         );
+                        timeout_action = timeout_cfg.abort_failed(qpair);
                     } else {
                         info!(
                             "{}: skipping Abort timeout action for admin qpair",
                             timeout_cfg.name
                         );
                     }
-                    // Fallthrough to perform controller reset in case abort
-                    // fails.
+                    // Fallthrough to perform qpair or controller reset in
+                    // case abort fails.
+                }
+                if timeout_action == DeviceTimeoutAction::QpairReset {
+                    // Reset only the I/O qpair of the command, fallthrough
+                    // to reset for the admin qpair.
+                    if !qpair.is_null() {
+                        if timeout_cfg.reset_qpair(qpair) {
+                            info!(
+                                "{}: initiated reset of qpair {:p}",
+                                timeout_cfg.name, qpair
+                            );
+                            return;
+                        }
+                        error!(
+                            "{}: unable to reset qpair {:p}, reset required",
+                            timeout_cfg.name, qpair
+                        );
+                    } else {
+                        info!(
+                            "{}: skipping QpairReset timeout action for admin qpair",
+                            timeout_cfg.name
+                        );
+                    }
                 }
                 info!(
                     "{} resetting controller in response to I/O timeout",
@@ -468,6 +894,8 @@ impl<'a> NvmeController<'a> {
         self.set_timeout_action(action).unwrap();
 
         unsafe {
+            self.timeout_config.as_mut().timeout_period =
+                Duration::from_micros(device_defaults.timeout_us);
             spdk_nvme_ctrlr_register_timeout_callback(
                 self.ctrlr_as_ptr(),
                 device_defaults.timeout_us,
@@ -482,3 +910,149 @@ impl<'a> NvmeController<'a> {
         );
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use spdk_rs::libspdk::spdk_nvme_qpair;
+
+    use super::TimeoutConfig;
+    use crate::core::{DeviceTimeoutAction, DeviceTimeoutPolicy};
+
+    const PERIOD: Duration = Duration::from_secs(7);
+
+    fn timeout_config(policy: DeviceTimeoutPolicy) -> TimeoutConfig {
+        let mut cfg = TimeoutConfig::new("nvme0");
+        cfg.timeout_period = PERIOD;
+        cfg.set_timeout_policy(Some(policy));
+        cfg
+    }
+
+    fn qpair(n: usize) -> *mut spdk_nvme_qpair {
+        (n * 0x1000) as *mut spdk_nvme_qpair
+    }
+
+    #[test]
+    fn timeout_action_without_policy() {
+        let mut cfg = TimeoutConfig::new("nvme0");
+        cfg.set_timeout_action(DeviceTimeoutAction::QpairReset);
+        let now = Instant::now();
+
+        for _ in 0 .. 10 {
+            assert_eq!(
+                cfg.next_timeout_action(qpair(1), now),
+                DeviceTimeoutAction::QpairReset
+            );
+        }
+        assert_eq!(cfg.abort_failed(qpair(1)), DeviceTimeoutAction::Reset);
+    }
+
+    #[test]
+    fn timeout_policy_counts_stalls() {
+        let mut cfg = timeout_config(DeviceTimeoutPolicy::default());
+        let start = Instant::now();
+
+        // every command in flight when the qpair stalled counts once
+        for i in 0 .. 7 {
+            let now = start + Duration::from_millis(i * 100);
+            assert_eq!(
+                cfg.next_timeout_action(qpair(1), now),
+                DeviceTimeoutAction::Abort
+            );
+        }
+        assert_eq!(cfg.policy_timeouts, 1);
+
+        // a stall of another qpair is an incident of its own
+        let now = start + Duration::from_millis(700);
+        assert_eq!(
+            cfg.next_timeout_action(qpair(2), now),
+            DeviceTimeoutAction::Abort
+        );
+        assert_eq!(cfg.policy_timeouts, 2);
+
+        // commands of the qpair which time out after the stall escalate
+        let expected = [
+            DeviceTimeoutAction::Abort,
+            DeviceTimeoutAction::QpairReset,
+            DeviceTimeoutAction::QpairReset,
+            DeviceTimeoutAction::Reset,
+            DeviceTimeoutAction::HotRemove,
+        ];
+        for (i, action) in expected.iter().enumerate() {
+            let now = start + PERIOD * (i as u32 + 1);
+            assert_eq!(cfg.next_timeout_action(qpair(1), now), *action);
+            assert_eq!(cfg.next_timeout_action(qpair(1), now), *action);
+        }
+        assert_eq!(cfg.policy_timeouts, 7);
+    }
+
+    #[test]
+    fn timeout_policy_recovery_interval() {
+        let policy = DeviceTimeoutPolicy {
+            abort: 1,
+            qpair_reset: 1,
+            reset: 1,
+            recovery_interval: Duration::from_secs(60),
+        };
+        let mut cfg = timeout_config(policy);
+        let start = Instant::now();
+
+        assert_eq!(
+            cfg.next_timeout_action(qpair(1), start),
+            DeviceTimeoutAction::Abort
+        );
+        assert_eq!(
+            cfg.next_timeout_action(qpair(1), start + PERIOD),
+            DeviceTimeoutAction::QpairReset
+        );
+
+        // no timeouts for the recovery interval, the count starts over
+        let now = start + PERIOD + policy.recovery_interval;
+        assert_eq!(
+            cfg.next_timeout_action(qpair(1), now),
+            DeviceTimeoutAction::Abort
+        );
+        assert_eq!(cfg.policy_timeouts, 1);
+
+        // just short of the recovery interval, the count goes on
+        let now = now + policy.recovery_interval - Duration::from_secs(1);
+        assert_eq!(
+            cfg.next_timeout_action(qpair(1), now),
+            DeviceTimeoutAction::QpairReset
+        );
+
+        // setting the policy starts over as well
+        cfg.set_timeout_policy(Some(policy));
+        assert_eq!(
+            cfg.next_timeout_action(qpair(1), now),
+            DeviceTimeoutAction::Abort
+        );
+    }
+
+    #[test]
+    fn timeout_policy_abort_failed() {
+        let mut cfg = timeout_config(DeviceTimeoutPolicy::default());
+        let start = Instant::now();
+
+        assert_eq!(
+            cfg.next_timeout_action(qpair(1), start),
+            DeviceTimeoutAction::Abort
+        );
+
+        // the stall escalates to a qpair reset without counting again, and
+        // further commands of the stall are handled alike
+        assert_eq!(cfg.abort_failed(qpair(1)), DeviceTimeoutAction::QpairReset);
+        assert_eq!(
+            cfg.next_timeout_action(qpair(1), start + Duration::from_secs(1)),
+            DeviceTimeoutAction::QpairReset
+        );
+        assert_eq!(cfg.policy_timeouts, 1);
+
+        // other qpairs are not affected
+        assert_eq!(
+            cfg.next_timeout_action(qpair(2), start + Duration::from_secs(1)),
+            DeviceTimeoutAction::Abort
+        );
+    }
+}
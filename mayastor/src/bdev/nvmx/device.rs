@@ -23,11 +23,24 @@ use crate::{
         DeviceEventSink,
         DeviceIoController,
         DeviceTimeoutAction,
+        DeviceTimeoutPolicy,
         IoType,
+        ZoneGeometry,
     },
     ffihelper::{cb_arg, done_cb},
 };
 
+/// Identifies the namespace a device refers to on its target by the NQN of
+/// the subsystem and the UUID of the namespace.
+fn target_namespace(name: &str) -> Option<(String, Uuid)> {
+    let carc = NVME_CONTROLLERS.lookup_by_name(name)?;
+    let controller = carc.lock();
+    Some((
+        controller.active_path()?.subnqn(),
+        controller.namespace()?.uuid(),
+    ))
+}
+
 /// TODO
 pub struct NvmeBlockDevice {
     ns: Arc<NvmeNamespace>,
@@ -184,11 +197,39 @@ impl BlockDevice for NvmeBlockDevice {
             IoType::NvmeIoMd => self.ns.md_size() > 0,
             IoType::Unmap => self.ns.supports_deallocate(),
             IoType::WriteZeros => self.ns.supports_write_zeroes(),
-            IoType::CompareAndWrite => false,
+            IoType::CompareAndWrite => {
+                self.ns.supports_compare()
+                    && self.ns.supports_compare_and_write()
+            }
+            IoType::ZoneInfo | IoType::ZoneManagement | IoType::ZoneAppend => {
+                self.ns.is_zoned()
+            }
+            _ => false,
+        }
+    }
+
+    fn copy_supported(&self, dst: &dyn BlockDevice) -> bool {
+        // A Copy command copies blocks within a namespace, so both devices
+        // must refer to the same namespace of the same subsystem, which can
+        // be reached through several controllers. Copies across namespaces
+        // are not supported by SPDK.
+        if dst.driver_name() != self.driver_name() || !self.ns.supports_copy() {
+            return false;
+        }
+
+        match (
+            target_namespace(&self.name),
+            target_namespace(&dst.device_name()),
+        ) {
+            (Some(src), Some(dst)) => src == dst,
             _ => false,
         }
     }
 
+    fn zone_geometry(&self) -> Option<ZoneGeometry> {
+        self.ns.zone_geometry()
+    }
+
     async fn io_stats(&self) -> Result<BlockDeviceIoStats, CoreError> {
         let carc = NVME_CONTROLLERS.lookup_by_name(&self.name).ok_or(
             CoreError::BdevNotFound {
@@ -282,6 +323,25 @@ impl DeviceIoController for NvmeDeviceIoController {
 
         controller.set_timeout_action(action)
     }
+
+    fn get_timeout_policy(
+        &self,
+    ) -> Result<Option<DeviceTimeoutPolicy>, CoreError> {
+        let controller = self.lookup_controller()?;
+        let controller = controller.lock();
+
+        controller.get_timeout_policy()
+    }
+
+    fn set_timeout_policy(
+        &mut self,
+        policy: Option<DeviceTimeoutPolicy>,
+    ) -> Result<(), CoreError> {
+        let controller = self.lookup_controller()?;
+        let mut controller = controller.lock();
+
+        controller.set_timeout_policy(policy)
+    }
 }
 
 /*
@@ -0,0 +1,482 @@
+//! Discovery of the paths to an NVMe-oF subsystem.
+//!
+//! The discovery controller of a target is connected to in order to read its
+//! discovery log page, and the TCP entries of the requested subsystem are
+//! turned into transport IDs, in the order they are listed. The discovery
+//! controller is connected to with the NQN of this host, as the discovery
+//! service only lists subsystems the host may connect to.
+//!
+//! Controllers created through a discovery service stay connected to it,
+//! and re-read the paths to their subsystem whenever the discovery log page
+//! changes, independently of the state of the controller itself.
+
+use std::{
+    ffi::{c_void, CStr},
+    mem::size_of,
+    ptr::NonNull,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use futures::channel::oneshot;
+use nix::errno::Errno;
+
+use spdk_rs::libspdk::{
+    spdk_nvme_async_event_completion,
+    spdk_nvme_connect_async,
+    spdk_nvme_cpl,
+    spdk_nvme_ctrlr,
+    spdk_nvme_ctrlr_cmd_get_log_page,
+    spdk_nvme_ctrlr_opts,
+    spdk_nvme_ctrlr_process_admin_completions,
+    spdk_nvme_ctrlr_register_aer_callback,
+    spdk_nvme_detach,
+    spdk_nvme_probe_poll_async,
+    spdk_nvme_transport_id,
+    spdk_nvmf_discovery_log_page,
+    spdk_nvmf_discovery_log_page_entry,
+    SPDK_NVME_LOG_DISCOVERY,
+    SPDK_NVMF_DISCOVERY_NQN,
+    SPDK_NVMF_SUBTYPE_NVME,
+    SPDK_NVMF_TRTYPE_TCP,
+};
+
+use super::{
+    controller::{
+        options::{Builder, NvmeControllerOpts},
+        transport::{self, NvmeTransportId},
+    },
+    uri::host_nqn,
+    utils::{nvme_cpl_succeeded, NvmeAerInfoNotice, NvmeAerType},
+    NVME_CONTROLLERS,
+};
+use crate::{
+    core::{poller, Reactors},
+    sleep::mayastor_sleep,
+};
+
+/// largest portion of the discovery log page read by a single command
+const LOG_PAGE_CHUNK: usize = 4096;
+
+/// attempts to read a consistent discovery log page, which changes when its
+/// generation counter does
+const LOG_PAGE_ATTEMPTS: usize = 3;
+
+/// keep alive timeout of the connection to a watched discovery service, as
+/// the target drops connections which are idle for too long
+const WATCH_KEEP_ALIVE_TIMEOUT_MS: u32 = 10_000;
+
+/// interval at which the admin queue of a watched discovery controller is
+/// processed, which sends its keep alives and receives its notices
+const WATCH_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// delay before connecting again to a watched discovery service which is
+/// not reachable
+const WATCH_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// context of the connection to a discovery controller, the options must be
+/// the first member as the attach callback is passed a pointer to them
+#[repr(C)]
+struct DiscoveryCtx {
+    opts: NvmeControllerOpts,
+    ctrlr: *mut spdk_nvme_ctrlr,
+}
+
+extern "C" fn discovery_attach_cb(
+    cb_ctx: *mut c_void,
+    _trid: *const spdk_nvme_transport_id,
+    ctrlr: *mut spdk_nvme_ctrlr,
+    _opts: *const spdk_nvme_ctrlr_opts,
+) {
+    let ctx = unsafe { &mut *(cb_ctx as *mut DiscoveryCtx) };
+    ctx.ctrlr = ctrlr;
+}
+
+/// the NQN of the discovery subsystem
+fn discovery_nqn() -> String {
+    CStr::from_bytes_with_nul(SPDK_NVMF_DISCOVERY_NQN)
+        .unwrap()
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// returns the string of a fixed size field of a log page entry, which is
+/// padded with spaces or NUL characters
+fn padded_str(field: &[u8]) -> String {
+    String::from_utf8_lossy(field)
+        .trim_end_matches(|c| c == ' ' || c == '\0')
+        .to_string()
+}
+
+/// Connect to the discovery controller of the target, with keep alive
+/// disabled when the timeout is 0.
+async fn connect(
+    host: &str,
+    port: u16,
+    keep_alive_timeout_ms: u32,
+) -> Result<NonNull<spdk_nvme_ctrlr>, Errno> {
+    let trid = transport::Builder::new()
+        .with_subnqn(&discovery_nqn())
+        .with_svcid(&port.to_string())
+        .with_traddr(host)
+        .build();
+
+    let mut opts =
+        Builder::new().with_keep_alive_timeout_ms(keep_alive_timeout_ms);
+    if let Some(host_nqn) = host_nqn() {
+        opts = opts.with_hostnqn(host_nqn);
+    }
+    let ctx = Box::new(DiscoveryCtx {
+        opts: opts.build(),
+        ctrlr: std::ptr::null_mut(),
+    });
+
+    let probe_ctx = NonNull::new(unsafe {
+        spdk_nvme_connect_async(
+            trid.as_ptr(),
+            ctx.opts.as_ptr(),
+            Some(discovery_attach_cb),
+        )
+    })
+    .ok_or(Errno::ENODEV)?;
+
+    let (s, r) = oneshot::channel::<()>();
+    let mut sender = Some(s);
+    let _poller = poller::Builder::new()
+        .with_name("nvme_discovery_probe_poller")
+        .with_interval(1000)
+        .with_poll_fn(move || {
+            let rc = unsafe { spdk_nvme_probe_poll_async(probe_ctx.as_ptr()) };
+            if rc != -libc::EAGAIN {
+                if let Some(s) = sender.take() {
+                    let _ = s.send(());
+                }
+            }
+            rc
+        })
+        .build();
+
+    r.await.expect("discovery probe poller is gone");
+    NonNull::new(ctx.ctrlr).ok_or(Errno::ENXIO)
+}
+
+extern "C" fn get_log_page_done(ctx: *mut c_void, cpl: *const spdk_nvme_cpl) {
+    let sender = unsafe { Box::from_raw(ctx as *mut oneshot::Sender<bool>) };
+    let _ = sender.send(nvme_cpl_succeeded(cpl));
+}
+
+/// Read the discovery log page at the offset into the buffer.
+async fn get_log_page_at(
+    ctrlr: NonNull<spdk_nvme_ctrlr>,
+    buf: &mut [u8],
+    offset: u64,
+) -> Result<(), Errno> {
+    let (s, r) = oneshot::channel::<bool>();
+    let cb_arg = Box::into_raw(Box::new(s));
+    let rc = unsafe {
+        spdk_nvme_ctrlr_cmd_get_log_page(
+            ctrlr.as_ptr(),
+            SPDK_NVME_LOG_DISCOVERY as u8,
+            0,
+            buf.as_mut_ptr() as *mut c_void,
+            buf.len() as u32,
+            offset,
+            Some(get_log_page_done),
+            cb_arg as *mut c_void,
+        )
+    };
+    if rc != 0 {
+        drop(unsafe { Box::from_raw(cb_arg) });
+        return Err(Errno::from_i32(-rc));
+    }
+
+    let _poller = poller::Builder::new()
+        .with_name("nvme_discovery_adminq_poller")
+        .with_interval(1000)
+        .with_poll_fn(move || unsafe {
+            spdk_nvme_ctrlr_process_admin_completions(ctrlr.as_ptr())
+        })
+        .build();
+
+    if r.await.expect("discovery log page completion is gone") {
+        Ok(())
+    } else {
+        Err(Errno::EIO)
+    }
+}
+
+/// Read the start of the discovery log page into the buffer, in chunks which
+/// do not exceed the transfer size of the controller.
+async fn get_log_page(
+    ctrlr: NonNull<spdk_nvme_ctrlr>,
+    buf: &mut [u8],
+) -> Result<(), Errno> {
+    for (i, chunk) in buf.chunks_mut(LOG_PAGE_CHUNK).enumerate() {
+        get_log_page_at(ctrlr, chunk, (i * LOG_PAGE_CHUNK) as u64).await?;
+    }
+    Ok(())
+}
+
+/// returns the header at the start of the discovery log page
+fn log_page_header(page: &[u8]) -> &spdk_nvmf_discovery_log_page {
+    assert!(page.len() >= size_of::<spdk_nvmf_discovery_log_page>());
+    unsafe { &*(page.as_ptr() as *const spdk_nvmf_discovery_log_page) }
+}
+
+/// Read the discovery log page of the controller, and return its generation
+/// counter and the TCP paths to the subsystem. The log page is read again
+/// when it changed while it was read.
+async fn read_paths(
+    ctrlr: NonNull<spdk_nvme_ctrlr>,
+    subnqn: &str,
+) -> Result<(u64, Vec<NvmeTransportId>), Errno> {
+    let header_len = size_of::<spdk_nvmf_discovery_log_page>();
+    let entry_len = size_of::<spdk_nvmf_discovery_log_page_entry>();
+
+    let mut attempts = 0;
+    let (genctr, numrec, page) = loop {
+        // the header tells how many entries there are
+        let mut header = vec![0u8; header_len];
+        get_log_page(ctrlr, &mut header).await?;
+        let genctr = log_page_header(&header).genctr;
+        let numrec = log_page_header(&header).numrec as usize;
+
+        let mut page = vec![0u8; header_len + numrec * entry_len];
+        get_log_page(ctrlr, &mut page).await?;
+
+        // the entries are consistent unless the log changed in the meantime
+        get_log_page(ctrlr, &mut header).await?;
+        if log_page_header(&header).genctr == genctr {
+            break (genctr, numrec, page);
+        }
+
+        attempts += 1;
+        if attempts == LOG_PAGE_ATTEMPTS {
+            return Err(Errno::EAGAIN);
+        }
+    };
+
+    let mut paths: Vec<NvmeTransportId> = Vec::new();
+    for i in 0 .. numrec {
+        let entry = unsafe {
+            &*(page.as_ptr().add(header_len + i * entry_len)
+                as *const spdk_nvmf_discovery_log_page_entry)
+        };
+        if entry.subtype as u32 != SPDK_NVMF_SUBTYPE_NVME
+            || entry.trtype as u32 != SPDK_NVMF_TRTYPE_TCP
+            || padded_str(&entry.subnqn) != subnqn
+        {
+            continue;
+        }
+
+        let trid = transport::Builder::new()
+            .with_subnqn(subnqn)
+            .with_svcid(&padded_str(&entry.trsvcid))
+            .with_traddr(&padded_str(&entry.traddr))
+            .build();
+        if !paths
+            .iter()
+            .any(|p| p.traddr() == trid.traddr() && p.svcid() == trid.svcid())
+        {
+            paths.push(trid);
+        }
+    }
+
+    Ok((genctr, paths))
+}
+
+extern "C" fn discovery_aer_cb(ctx: *mut c_void, cpl: *const spdk_nvme_cpl) {
+    if !nvme_cpl_succeeded(cpl) {
+        return;
+    }
+
+    let mut event = spdk_nvme_async_event_completion::default();
+    event.raw = unsafe { (*cpl).cdw0 };
+    let (event_type, event_info) = unsafe {
+        (event.bits.async_event_type(), event.bits.async_event_info())
+    };
+
+    if event_type == NvmeAerType::Notice as u32
+        && event_info == NvmeAerInfoNotice::DiscoveryLogChange as u32
+    {
+        let log_changed = unsafe { &*(ctx as *const AtomicBool) };
+        log_changed.store(true, Ordering::SeqCst);
+    }
+}
+
+/// The discovery service a controller finds the paths to its subsystem
+/// through.
+#[derive(Debug, Clone)]
+pub(crate) struct DiscoveryService {
+    /// address of the discovery service
+    pub host: String,
+    /// port of the discovery service
+    pub port: u16,
+    /// the NQN of the subsystem
+    pub subnqn: String,
+}
+
+impl DiscoveryService {
+    /// Query the discovery service for the paths to the subsystem.
+    pub(crate) async fn paths(&self) -> Result<Vec<NvmeTransportId>, Errno> {
+        let ctrlr = connect(&self.host, self.port, 0).await?;
+        let paths = read_paths(ctrlr, &self.subnqn).await;
+        unsafe { spdk_nvme_detach(ctrlr.as_ptr()) };
+
+        let (_, paths) = paths?;
+        debug!(
+            "discovery service {}:{} lists {} paths to {}",
+            self.host,
+            self.port,
+            paths.len(),
+            self.subnqn
+        );
+        Ok(paths)
+    }
+
+    /// Follow the paths the discovery service advertises to the subsystem of
+    /// the controller, until the returned watch is dropped.
+    pub(crate) fn watch(&self, name: &str) -> DiscoveryWatch {
+        let watch = DiscoveryWatch {
+            stopped: Arc::new(AtomicBool::new(false)),
+            genctr: Arc::new(AtomicU64::new(0)),
+        };
+
+        let service = self.clone();
+        let name = name.to_string();
+        let stopped = watch.stopped.clone();
+        let genctr = watch.genctr.clone();
+        Reactors::current().send_future(async move {
+            service.follow(&name, &stopped, &genctr).await;
+            debug!("{}: stopped watching discovery service", name);
+        });
+
+        watch
+    }
+
+    /// Stay connected to the discovery service while the controller exists,
+    /// and update the paths of the controller whenever the discovery log page
+    /// changes. The log page is also read on every connect, as changes are
+    /// not noticed while not connected.
+    async fn follow(
+        &self,
+        name: &str,
+        stopped: &AtomicBool,
+        genctr: &AtomicU64,
+    ) {
+        let log_changed = Box::new(AtomicBool::new(false));
+        let watching = || {
+            !stopped.load(Ordering::SeqCst)
+                && NVME_CONTROLLERS.lookup_by_name(name).is_some()
+        };
+
+        while watching() {
+            let ctrlr = match connect(
+                &self.host,
+                self.port,
+                WATCH_KEEP_ALIVE_TIMEOUT_MS,
+            )
+            .await
+            {
+                Ok(ctrlr) => ctrlr,
+                Err(e) => {
+                    debug!(
+                        "{}: failed to connect to discovery service {}:{}: {}",
+                        name, self.host, self.port, e
+                    );
+                    let _ = mayastor_sleep(WATCH_RETRY_DELAY).await;
+                    continue;
+                }
+            };
+
+            unsafe {
+                spdk_nvme_ctrlr_register_aer_callback(
+                    ctrlr.as_ptr(),
+                    Some(discovery_aer_cb),
+                    &*log_changed as *const AtomicBool as *mut c_void,
+                );
+            }
+            log_changed.store(true, Ordering::SeqCst);
+
+            while watching() {
+                if log_changed.swap(false, Ordering::SeqCst) {
+                    match read_paths(ctrlr, &self.subnqn).await {
+                        Ok((generation, paths)) => {
+                            self.update(name, paths);
+                            genctr.store(generation, Ordering::SeqCst);
+                        }
+                        Err(e) => {
+                            warn!(
+                                "{}: failed to read the discovery log page of {}:{}: {}",
+                                name, self.host, self.port, e
+                            );
+                            break;
+                        }
+                    }
+                }
+
+                if unsafe {
+                    spdk_nvme_ctrlr_process_admin_completions(ctrlr.as_ptr())
+                } < 0
+                {
+                    warn!(
+                        "{}: lost connection to discovery service {}:{}",
+                        name, self.host, self.port
+                    );
+                    break;
+                }
+
+                let _ = mayastor_sleep(WATCH_POLL_INTERVAL).await;
+            }
+
+            unsafe { spdk_nvme_detach(ctrlr.as_ptr()) };
+            if watching() {
+                let _ = mayastor_sleep(WATCH_RETRY_DELAY).await;
+            }
+        }
+    }
+
+    /// Replace the paths of the controller with the advertised paths, unless
+    /// none is advertised, which leaves the controller on its current paths.
+    fn update(&self, name: &str, paths: Vec<NvmeTransportId>) {
+        if paths.is_empty() {
+            warn!(
+                "{}: discovery service {}:{} lists no paths to {}",
+                name, self.host, self.port, self.subnqn
+            );
+            return;
+        }
+
+        if let Some(c) = NVME_CONTROLLERS.lookup_by_name(name) {
+            c.lock().set_discovered_paths(paths);
+        }
+    }
+}
+
+/// Watch of a discovery service on behalf of a controller, which stops when
+/// dropped.
+#[derive(Debug)]
+pub(crate) struct DiscoveryWatch {
+    /// set when the watch is dropped
+    stopped: Arc<AtomicBool>,
+    /// generation counter of the discovery log page the paths were last read
+    /// from
+    genctr: Arc<AtomicU64>,
+}
+
+impl DiscoveryWatch {
+    /// returns the generation counter of the discovery log page the paths
+    /// were last read from, 0 until it is first read
+    pub(crate) fn generation(&self) -> u64 {
+        self.genctr.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for DiscoveryWatch {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+}
@@ -1,4 +1,10 @@
-use std::{alloc::Layout, mem::ManuallyDrop, os::raw::c_void, sync::Arc};
+use std::{
+    alloc::Layout,
+    cmp::min,
+    mem::{size_of, ManuallyDrop},
+    os::raw::c_void,
+    sync::Arc,
+};
 
 use async_trait::async_trait;
 use futures::channel::oneshot;
@@ -10,18 +16,39 @@ use spdk_rs::{
         iovec,
         nvme_cmd_cdw10_get,
         spdk_get_io_channel,
+        spdk_get_ticks,
         spdk_io_channel,
         spdk_nvme_cmd,
         spdk_nvme_cpl,
+        spdk_nvme_ctrlr_cmd_abort_ext,
         spdk_nvme_ctrlr_cmd_admin_raw,
         spdk_nvme_ctrlr_cmd_io_raw,
         spdk_nvme_dsm_range,
+        spdk_nvme_ns,
+        spdk_nvme_ns_cmd_comparev,
+        spdk_nvme_ns_cmd_copy,
         spdk_nvme_ns_cmd_dataset_management,
         spdk_nvme_ns_cmd_read,
         spdk_nvme_ns_cmd_readv,
         spdk_nvme_ns_cmd_write,
         spdk_nvme_ns_cmd_write_zeroes,
         spdk_nvme_ns_cmd_writev,
+        spdk_nvme_qpair,
+        spdk_nvme_scc_source_range,
+        spdk_nvme_zns_report_zones,
+        spdk_nvme_zns_reset_zone,
+        spdk_nvme_zns_zone_append,
+        spdk_nvme_zns_zone_desc,
+        spdk_nvme_zns_zone_report,
+        SPDK_NVME_IO_FLAGS_FUSE_FIRST,
+        SPDK_NVME_IO_FLAGS_FUSE_SECOND,
+        SPDK_NVME_ZONE_STATE_CLOSED,
+        SPDK_NVME_ZONE_STATE_EMPTY,
+        SPDK_NVME_ZONE_STATE_EOPEN,
+        SPDK_NVME_ZONE_STATE_FULL,
+        SPDK_NVME_ZONE_STATE_IOPEN,
+        SPDK_NVME_ZONE_STATE_RONLY,
+        SPDK_NVME_ZRA_LIST_ALL,
     },
     nvme_admin_opc,
     nvme_nvm_opcode,
@@ -36,6 +63,8 @@ use crate::{
         utils,
         utils::{
             nvme_command_status,
+            nvme_cpl_is_aborted,
+            nvme_cpl_is_miscompare,
             nvme_cpl_is_pi_error,
             nvme_cpl_succeeded,
         },
@@ -55,6 +84,8 @@ use crate::{
         IoCompletionStatus,
         IoType,
         NvmeCommandStatus,
+        ZoneInfo,
+        ZoneState,
     },
     ffihelper::{cb_arg, done_cb, FfiResult},
     subsys,
@@ -77,6 +108,28 @@ struct NvmeIoCtx {
     op: IoType,
     num_blocks: u64,
     channel: *mut spdk_io_channel,
+    /// needed to resubmit the I/O after the controller reconnected
+    offset_blocks: u64,
+    ns: *mut spdk_nvme_ns,
+    prchk_flags: u32,
+    /// write of a fused compare-and-write, the I/O vector above is the one
+    /// of the compare
+    fused: Option<FusedWrite>,
+    /// time the I/O was last submitted to the qpair at, in ticks, such that
+    /// the latency of queued I/O does not include the time it was queued
+    submitted_at: u64,
+}
+
+/// The write of a fused compare-and-write, which completes after the compare.
+struct FusedWrite {
+    iov: *mut iovec,
+    iovcnt: u64,
+    iovpos: u64,
+    iov_offset: u64,
+    /// completion of the compare, set once it completed
+    compare_cpl: Option<spdk_nvme_cpl>,
+    /// the write was submitted along with the compare
+    submitted: bool,
 }
 
 unsafe impl Send for NvmeIoCtx {}
@@ -242,14 +295,46 @@ fn complete_nvme_command(ctx: *mut NvmeIoCtx, cpl: *const spdk_nvme_cpl) {
     if op_succeeded {
         let stats_controller = inner.get_io_stats_controller();
         stats_controller.account_block_io(io_ctx.op, 1, io_ctx.num_blocks);
+        stats_controller.account_latency(
+            io_ctx.op,
+            unsafe { spdk_get_ticks() } - io_ctx.submitted_at,
+        );
+    }
+
+    // I/O aborted as the connection was lost is queued again rather than
+    // failed while the controller reconnects, and remains active.
+    if !op_succeeded
+        && inner.queues_io()
+        && is_requeueable(io_ctx.op)
+        && matches!(
+            nvme_command_status(cpl),
+            NvmeCommandStatus::GenericCommandStatus(
+                GenericStatusCode::AbortedSubmissionQueueDeleted
+            )
+        )
+    {
+        inner.queue_io(ctx.cast());
+        return;
     }
 
-    // Adjust the number of active I/O.
+    // Adjust the number of active I/O. A miscompare is the outcome of a
+    // compare rather than a failed I/O.
     inner.discard_io();
+    if !op_succeeded && !nvme_cpl_is_miscompare(cpl) {
+        inner
+            .get_io_stats_controller()
+            .account_failed_io(nvme_cpl_is_aborted(cpl));
+    }
 
     // Invoke caller's callback and free I/O context.
     if op_succeeded {
         (io_ctx.cb)(&*inner.device, IoCompletionStatus::Success, io_ctx.cb_arg);
+    } else if nvme_cpl_is_miscompare(cpl) {
+        (io_ctx.cb)(
+            &*inner.device,
+            IoCompletionStatus::Miscompare,
+            io_ctx.cb_arg,
+        );
     } else {
         (io_ctx.cb)(
             &*inner.device,
@@ -275,6 +360,107 @@ extern "C" fn nvme_writev_done(ctx: *mut c_void, cpl: *const spdk_nvme_cpl) {
     complete_nvme_command(nvme_io_ctx, cpl);
 }
 
+/// Completion handler for the compare of a fused compare-and-write. The I/O
+/// completes along with the write, which the controller aborts when the
+/// compare fails.
+extern "C" fn nvme_fused_compare_done(
+    ctx: *mut c_void,
+    cpl: *const spdk_nvme_cpl,
+) {
+    let nvme_io_ctx = ctx as *mut NvmeIoCtx;
+    let fused = unsafe { (*nvme_io_ctx).fused.as_mut().unwrap() };
+
+    if !fused.submitted {
+        // The write was never submitted, so the compare was aborted.
+        complete_nvme_command(nvme_io_ctx, cpl);
+        return;
+    }
+
+    fused.compare_cpl = Some(unsafe { *cpl });
+}
+
+/// Completion handler for the write of a fused compare-and-write.
+extern "C" fn nvme_fused_write_done(
+    ctx: *mut c_void,
+    cpl: *const spdk_nvme_cpl,
+) {
+    let nvme_io_ctx = ctx as *mut NvmeIoCtx;
+    let fused = unsafe { (*nvme_io_ctx).fused.as_ref().unwrap() };
+
+    // A failed compare fails the whole command, with the status of the
+    // compare rather than the one of the aborted write.
+    match fused.compare_cpl {
+        Some(compare_cpl) if !nvme_cpl_succeeded(&compare_cpl) => {
+            complete_nvme_command(nvme_io_ctx, &compare_cpl)
+        }
+        _ => complete_nvme_command(nvme_io_ctx, cpl),
+    }
+}
+
+extern "C" fn nvme_fused_abort_done(
+    _ctx: *mut c_void,
+    cpl: *const spdk_nvme_cpl,
+) {
+    if !nvme_cpl_succeeded(cpl) {
+        error!("failed to abort the compare of a fused compare-and-write");
+    }
+}
+
+extern "C" fn nvme_fused_reset_sgl(ctx: *mut c_void, sgl_offset: u32) {
+    let nvme_io_ctx = unsafe { &mut *(ctx as *mut NvmeIoCtx) };
+    let fused = nvme_io_ctx.fused.as_mut().unwrap();
+
+    fused.iov_offset = sgl_offset as u64;
+    fused.iovpos = 0;
+
+    while fused.iovpos < fused.iovcnt {
+        unsafe {
+            let iov = fused.iov.add(fused.iovpos as usize);
+            if fused.iov_offset < (*iov).iov_len {
+                break;
+            }
+
+            fused.iov_offset -= (*iov).iov_len;
+        }
+
+        fused.iovpos += 1;
+    }
+}
+
+extern "C" fn nvme_fused_next_sge(
+    ctx: *mut c_void,
+    address: *mut *mut c_void,
+    length: *mut u32,
+) -> i32 {
+    let nvme_io_ctx = unsafe { &mut *(ctx as *mut NvmeIoCtx) };
+    let fused = nvme_io_ctx.fused.as_mut().unwrap();
+
+    assert!(fused.iovpos < fused.iovcnt);
+
+    unsafe {
+        let iov = fused.iov.add(fused.iovpos as usize);
+
+        let mut a = (*iov).iov_base as u64;
+        *length = (*iov).iov_len as u32;
+
+        if fused.iov_offset > 0 {
+            assert!(fused.iov_offset <= (*iov).iov_len);
+            a += fused.iov_offset;
+            *length -= fused.iov_offset as u32;
+        }
+
+        fused.iov_offset += *length as u64;
+        if fused.iov_offset == (*iov).iov_len {
+            fused.iovpos += 1;
+            fused.iov_offset = 0;
+        }
+
+        *(address as *mut u64) = a;
+    }
+
+    0
+}
+
 /// I/O completion handler for all read requests (vectored/non-vectored)
 /// and non-vectored write requests.
 extern "C" fn nvme_io_done(ctx: *mut c_void, cpl: *const spdk_nvme_cpl) {
@@ -295,6 +481,33 @@ extern "C" fn nvme_async_io_completion(
     done_cb(ctx, nvme_cpl_succeeded(cpl));
 }
 
+extern "C" fn nvme_zone_append_completion(
+    ctx: *mut c_void,
+    cpl: *const spdk_nvme_cpl,
+) {
+    // the block the data was written at is returned in dwords 0 and 1
+    let lba = if nvme_cpl_succeeded(cpl) {
+        let cpl = unsafe { &*cpl };
+        Some(((cpl.cdw1 as u64) << 32) | cpl.cdw0 as u64)
+    } else {
+        None
+    };
+    done_cb(ctx, lba);
+}
+
+/// Converts the state of an NVMe zone descriptor.
+fn nvme_zone_state(desc: &spdk_nvme_zns_zone_desc) -> ZoneState {
+    match desc.zs() as u32 {
+        SPDK_NVME_ZONE_STATE_EMPTY => ZoneState::Empty,
+        SPDK_NVME_ZONE_STATE_IOPEN => ZoneState::ImplicitOpen,
+        SPDK_NVME_ZONE_STATE_EOPEN => ZoneState::ExplicitOpen,
+        SPDK_NVME_ZONE_STATE_CLOSED => ZoneState::Closed,
+        SPDK_NVME_ZONE_STATE_RONLY => ZoneState::ReadOnly,
+        SPDK_NVME_ZONE_STATE_FULL => ZoneState::Full,
+        _ => ZoneState::Offline,
+    }
+}
+
 extern "C" fn nvme_unmap_completion(
     ctx: *mut c_void,
     cpl: *const spdk_nvme_cpl,
@@ -361,6 +574,13 @@ fn io_type_to_err(
             offset: offset_blocks,
             len: num_blocks,
         },
+        IoType::Compare | IoType::CompareAndWrite => {
+            CoreError::CompareDispatch {
+                source,
+                offset: offset_blocks,
+                len: num_blocks,
+            }
+        }
         IoType::NvmeIo => CoreError::NvmeIoPassthruDispatch {
             source,
             opcode: 0xff,
@@ -402,6 +622,81 @@ fn free_nvme_io_ctx(ctx: *mut NvmeIoCtx) {
     pool.put(ctx);
 }
 
+/// Reads and writes can be queued while the controller reconnects.
+#[inline]
+fn is_requeueable(op: IoType) -> bool {
+    matches!(op, IoType::Read | IoType::Write)
+}
+
+/// Queue the I/O until the controller reconnects, or the namespace is
+/// accessible again.
+#[inline]
+fn queue_nvme_io(
+    inner: &mut NvmeIoChannelInner,
+    bio: *mut NvmeIoCtx,
+) -> Result<(), CoreError> {
+    inner.queue_io(bio.cast());
+    inner.account_io();
+    Ok(())
+}
+
+/// Resubmit an I/O which was queued while the controller reconnected.
+pub(crate) fn resubmit_queued_io(
+    ctx: *mut c_void,
+    qpair: *mut spdk_nvme_qpair,
+) -> i32 {
+    let io_ctx = unsafe { &mut *(ctx as *mut NvmeIoCtx) };
+    io_ctx.iovpos = 0;
+    io_ctx.iov_offset = 0;
+    io_ctx.submitted_at = unsafe { spdk_get_ticks() };
+
+    unsafe {
+        match io_ctx.op {
+            IoType::Read => spdk_nvme_ns_cmd_readv(
+                io_ctx.ns,
+                qpair,
+                io_ctx.offset_blocks,
+                io_ctx.num_blocks as u32,
+                Some(nvme_io_done),
+                ctx,
+                io_ctx.prchk_flags,
+                Some(nvme_queued_reset_sgl),
+                Some(nvme_queued_next_sge),
+            ),
+            IoType::Write => spdk_nvme_ns_cmd_writev(
+                io_ctx.ns,
+                qpair,
+                io_ctx.offset_blocks,
+                io_ctx.num_blocks as u32,
+                Some(nvme_writev_done),
+                ctx,
+                io_ctx.prchk_flags,
+                Some(nvme_queued_reset_sgl),
+                Some(nvme_queued_next_sge),
+            ),
+            _ => -libc::ENOTSUP,
+        }
+    }
+}
+
+/// Fail an I/O which was queued while the controller reconnected.
+pub(crate) fn abort_queued_io(ctx: *mut c_void) {
+    let io_ctx = unsafe { &mut *(ctx as *mut NvmeIoCtx) };
+    let inner = NvmeIoChannel::inner_from_channel(io_ctx.channel);
+
+    inner.discard_io();
+    inner.get_io_stats_controller().account_failed_io(true);
+    (io_ctx.cb)(
+        &*inner.device,
+        IoCompletionStatus::NvmeError(NvmeCommandStatus::GenericCommandStatus(
+            GenericStatusCode::AbortedSubmissionQueueDeleted,
+        )),
+        io_ctx.cb_arg,
+    );
+
+    free_nvme_io_ctx(io_ctx);
+}
+
 /// Check whether channel is suitable for serving I/O.
 fn check_channel_for_io(
     op: IoType,
@@ -412,8 +707,9 @@ fn check_channel_for_io(
     let mut errno = 0;
 
     // Check against concurrent controller reset, which results in valid
-    // I/O channel but deactivated I/O pair.
-    if inner.qpair.is_none() {
+    // I/O channel but deactivated I/O pair. Reads and writes are queued
+    // instead while the controller reconnects.
+    if inner.qpair.is_none() && !(inner.queues_io() && is_requeueable(op)) {
         errno = libc::ENODEV;
     }
 
@@ -485,12 +781,24 @@ impl BlockDeviceHandle for NvmeDeviceHandle {
         // Make sure channel allows I/O.
         check_channel_for_io(IoType::Read, inner, offset_blocks, num_blocks)?;
 
+        // Unlike the vectored I/O, there is nothing to queue this I/O on while
+        // the controller reconnects.
+        let qpair = inner.qpair.as_mut().ok_or_else(|| {
+            io_type_to_err(
+                IoType::Read,
+                libc::ENODEV,
+                offset_blocks,
+                num_blocks,
+            )
+        })?;
+
         let (s, r) = oneshot::channel::<bool>();
+        let submitted_at = unsafe { spdk_get_ticks() };
 
         let rc = unsafe {
             spdk_nvme_ns_cmd_read(
                 self.ns.as_ptr(),
-                inner.qpair.as_mut().unwrap().as_ptr(),
+                qpair.as_ptr(),
                 **buffer,
                 offset_blocks,
                 num_blocks as u32,
@@ -510,13 +818,15 @@ impl BlockDeviceHandle for NvmeDeviceHandle {
 
         inner.account_io();
         let ret = if r.await.expect("Failed awaiting at read_at()") {
-            inner.get_io_stats_controller().account_block_io(
+            let stats_controller = inner.get_io_stats_controller();
+            stats_controller.account_block_io(IoType::Read, 1, num_blocks);
+            stats_controller.account_latency(
                 IoType::Read,
-                1,
-                num_blocks,
+                unsafe { spdk_get_ticks() } - submitted_at,
             );
             Ok(buffer.len())
         } else {
+            inner.get_io_stats_controller().account_failed_io(false);
             Err(CoreError::ReadFailed {
                 offset,
                 len: buffer.len(),
@@ -558,12 +868,24 @@ impl BlockDeviceHandle for NvmeDeviceHandle {
         // Make sure channel allows I/O.
         check_channel_for_io(IoType::Write, inner, offset_blocks, num_blocks)?;
 
+        // Unlike the vectored I/O, there is nothing to queue this I/O on while
+        // the controller reconnects.
+        let qpair = inner.qpair.as_mut().ok_or_else(|| {
+            io_type_to_err(
+                IoType::Write,
+                libc::ENODEV,
+                offset_blocks,
+                num_blocks,
+            )
+        })?;
+
         let (s, r) = oneshot::channel::<bool>();
+        let submitted_at = unsafe { spdk_get_ticks() };
 
         let rc = unsafe {
             spdk_nvme_ns_cmd_write(
                 self.ns.as_ptr(),
-                inner.qpair.as_mut().unwrap().as_ptr(),
+                qpair.as_ptr(),
                 **buffer,
                 offset_blocks,
                 num_blocks as u32,
@@ -583,13 +905,15 @@ impl BlockDeviceHandle for NvmeDeviceHandle {
 
         inner.account_io();
         let ret = if r.await.expect("Failed awaiting at write_at()") {
-            inner.get_io_stats_controller().account_block_io(
+            let stats_controller = inner.get_io_stats_controller();
+            stats_controller.account_block_io(IoType::Write, 1, num_blocks);
+            stats_controller.account_latency(
                 IoType::Write,
-                1,
-                num_blocks,
+                unsafe { spdk_get_ticks() } - submitted_at,
             );
             Ok(buffer.len())
         } else {
+            inner.get_io_stats_controller().account_failed_io(false);
             Err(CoreError::WriteFailed {
                 offset,
                 len: buffer.len(),
@@ -629,11 +953,20 @@ impl BlockDeviceHandle for NvmeDeviceHandle {
                 channel,
                 op: IoType::Read,
                 num_blocks,
+                offset_blocks,
+                ns: self.ns.as_ptr(),
+                prchk_flags: self.prchk_flags,
+                fused: None,
+                submitted_at: unsafe { spdk_get_ticks() },
             },
             offset_blocks,
             num_blocks,
         )?;
 
+        if inner.qpair.is_none() || inner.holds_io() {
+            return queue_nvme_io(inner, bio);
+        }
+
         let rc;
 
         if iovcnt == 1 {
@@ -665,7 +998,9 @@ impl BlockDeviceHandle for NvmeDeviceHandle {
             }
         }
 
-        if rc < 0 {
+        if rc == -libc::ENXIO && inner.queues_io() {
+            queue_nvme_io(inner, bio)
+        } else if rc < 0 {
             Err(CoreError::ReadDispatch {
                 source: Errno::from_i32(-rc),
                 offset: offset_blocks,
@@ -706,11 +1041,20 @@ impl BlockDeviceHandle for NvmeDeviceHandle {
                 channel,
                 op: IoType::Write,
                 num_blocks,
+                offset_blocks,
+                ns: self.ns.as_ptr(),
+                prchk_flags: self.prchk_flags,
+                fused: None,
+                submitted_at: unsafe { spdk_get_ticks() },
             },
             offset_blocks,
             num_blocks,
         )?;
 
+        if inner.qpair.is_none() || inner.holds_io() {
+            return queue_nvme_io(inner, bio);
+        }
+
         let rc;
 
         if iovcnt == 1 {
@@ -742,7 +1086,9 @@ impl BlockDeviceHandle for NvmeDeviceHandle {
             }
         }
 
-        if rc < 0 {
+        if rc == -libc::ENXIO && inner.queues_io() {
+            queue_nvme_io(inner, bio)
+        } else if rc < 0 {
             Err(CoreError::WriteDispatch {
                 source: Errno::from_i32(-rc),
                 offset: offset_blocks,
@@ -754,6 +1100,208 @@ impl BlockDeviceHandle for NvmeDeviceHandle {
         }
     }
 
+    fn comparev_blocks(
+        &self,
+        iov: *mut iovec,
+        iovcnt: i32,
+        offset_blocks: u64,
+        num_blocks: u64,
+        cb: IoCompletionCallback,
+        cb_arg: IoCompletionCallbackArg,
+    ) -> Result<(), CoreError> {
+        check_io_args(IoType::Compare, iov, iovcnt, offset_blocks, num_blocks)?;
+
+        let channel = self.io_channel.as_ptr();
+        let inner = NvmeIoChannel::inner_from_channel(channel);
+
+        // Make sure channel allows I/O.
+        check_channel_for_io(
+            IoType::Compare,
+            inner,
+            offset_blocks,
+            num_blocks,
+        )?;
+
+        let bio = alloc_nvme_io_ctx(
+            IoType::Compare,
+            NvmeIoCtx {
+                cb,
+                cb_arg,
+                iov,
+                iovcnt: iovcnt as u64,
+                iovpos: 0,
+                iov_offset: 0,
+                channel,
+                op: IoType::Compare,
+                num_blocks,
+                offset_blocks,
+                ns: self.ns.as_ptr(),
+                prchk_flags: self.prchk_flags,
+                fused: None,
+                submitted_at: unsafe { spdk_get_ticks() },
+            },
+            offset_blocks,
+            num_blocks,
+        )?;
+
+        let rc = unsafe {
+            spdk_nvme_ns_cmd_comparev(
+                self.ns.as_ptr(),
+                inner.qpair.as_mut().unwrap().as_ptr(),
+                offset_blocks,
+                num_blocks as u32,
+                Some(nvme_io_done),
+                bio as *mut c_void,
+                self.prchk_flags,
+                Some(nvme_queued_reset_sgl),
+                Some(nvme_queued_next_sge),
+            )
+        };
+
+        if rc < 0 {
+            free_nvme_io_ctx(bio);
+            Err(CoreError::CompareDispatch {
+                source: Errno::from_i32(-rc),
+                offset: offset_blocks,
+                len: num_blocks,
+            })
+        } else {
+            inner.account_io();
+            Ok(())
+        }
+    }
+
+    fn comparev_and_writev_blocks(
+        &self,
+        compare_iov: *mut iovec,
+        compare_iovcnt: i32,
+        write_iov: *mut iovec,
+        write_iovcnt: i32,
+        offset_blocks: u64,
+        num_blocks: u64,
+        cb: IoCompletionCallback,
+        cb_arg: IoCompletionCallbackArg,
+    ) -> Result<(), CoreError> {
+        let op = IoType::CompareAndWrite;
+        check_io_args(
+            op,
+            compare_iov,
+            compare_iovcnt,
+            offset_blocks,
+            num_blocks,
+        )?;
+        check_io_args(op, write_iov, write_iovcnt, offset_blocks, num_blocks)?;
+
+        let channel = self.io_channel.as_ptr();
+        let inner = NvmeIoChannel::inner_from_channel(channel);
+
+        // Make sure channel allows I/O.
+        check_channel_for_io(op, inner, offset_blocks, num_blocks)?;
+
+        let bio = alloc_nvme_io_ctx(
+            op,
+            NvmeIoCtx {
+                cb,
+                cb_arg,
+                iov: compare_iov,
+                iovcnt: compare_iovcnt as u64,
+                iovpos: 0,
+                iov_offset: 0,
+                channel,
+                op,
+                num_blocks,
+                offset_blocks,
+                ns: self.ns.as_ptr(),
+                prchk_flags: self.prchk_flags,
+                fused: Some(FusedWrite {
+                    iov: write_iov,
+                    iovcnt: write_iovcnt as u64,
+                    iovpos: 0,
+                    iov_offset: 0,
+                    compare_cpl: None,
+                    submitted: false,
+                }),
+                submitted_at: unsafe { spdk_get_ticks() },
+            },
+            offset_blocks,
+            num_blocks,
+        )?;
+
+        let qpair = inner.qpair.as_mut().unwrap().as_ptr();
+
+        let rc = unsafe {
+            spdk_nvme_ns_cmd_comparev(
+                self.ns.as_ptr(),
+                qpair,
+                offset_blocks,
+                num_blocks as u32,
+                Some(nvme_fused_compare_done),
+                bio as *mut c_void,
+                self.prchk_flags | SPDK_NVME_IO_FLAGS_FUSE_FIRST,
+                Some(nvme_queued_reset_sgl),
+                Some(nvme_queued_next_sge),
+            )
+        };
+
+        if rc < 0 {
+            free_nvme_io_ctx(bio);
+            return Err(CoreError::CompareDispatch {
+                source: Errno::from_i32(-rc),
+                offset: offset_blocks,
+                len: num_blocks,
+            });
+        }
+
+        unsafe {
+            (*bio).fused.as_mut().unwrap().submitted = true;
+        }
+
+        let rc = unsafe {
+            spdk_nvme_ns_cmd_writev(
+                self.ns.as_ptr(),
+                qpair,
+                offset_blocks,
+                num_blocks as u32,
+                Some(nvme_fused_write_done),
+                bio as *mut c_void,
+                self.prchk_flags | SPDK_NVME_IO_FLAGS_FUSE_SECOND,
+                Some(nvme_fused_reset_sgl),
+                Some(nvme_fused_next_sge),
+            )
+        };
+
+        // The compare is on its way, so from here on the I/O completes through
+        // its completion handlers.
+        inner.account_io();
+
+        if rc < 0 {
+            // The controller holds the compare back until the write it is
+            // fused with arrives, so abort it to complete the I/O.
+            error!(
+                "{} compare-and-write failed to submit the write: rc = {}",
+                self.name, rc
+            );
+            unsafe {
+                (*bio).fused.as_mut().unwrap().submitted = false;
+                let rc = spdk_nvme_ctrlr_cmd_abort_ext(
+                    self.ctrlr.as_ptr(),
+                    qpair,
+                    bio as *mut c_void,
+                    Some(nvme_fused_abort_done),
+                    std::ptr::null_mut(),
+                );
+                if rc != 0 {
+                    error!(
+                        "{} failed to abort the compare: rc = {}",
+                        self.name, rc
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     fn reset(
         &self,
         cb: IoCompletionCallback,
@@ -817,6 +1365,11 @@ impl BlockDeviceHandle for NvmeDeviceHandle {
                 channel,
                 op: IoType::Unmap,
                 num_blocks,
+                offset_blocks,
+                ns: self.ns.as_ptr(),
+                prchk_flags: self.prchk_flags,
+                fused: None,
+                submitted_at: unsafe { spdk_get_ticks() },
             },
             offset_blocks,
             num_blocks,
@@ -914,6 +1467,11 @@ impl BlockDeviceHandle for NvmeDeviceHandle {
                 channel,
                 op: IoType::WriteZeros,
                 num_blocks,
+                offset_blocks,
+                ns: self.ns.as_ptr(),
+                prchk_flags: self.prchk_flags,
+                fused: None,
+                submitted_at: unsafe { spdk_get_ticks() },
             },
             offset_blocks,
             num_blocks,
@@ -943,6 +1501,296 @@ impl BlockDeviceHandle for NvmeDeviceHandle {
         }
     }
 
+    async fn copy_blocks(
+        &self,
+        src_offset_blocks: u64,
+        dst: &dyn BlockDeviceHandle,
+        dst_offset_blocks: u64,
+        num_blocks: u64,
+    ) -> Result<u64, CoreError> {
+        if !self.block_device.copy_supported(&*dst.get_device()) {
+            return Err(CoreError::NotSupported {
+                source: Errno::EOPNOTSUPP,
+            });
+        }
+
+        let inner = NvmeIoChannel::inner_from_channel(self.io_channel.as_ptr());
+        let max_blocks = self.ns.max_copy_blocks();
+        let mut copied = 0;
+
+        // Every command copies a single source range, whose length is limited
+        // by the namespace.
+        while copied < num_blocks {
+            let offset = src_offset_blocks + copied;
+            let len = min(num_blocks - copied, max_blocks);
+
+            let qpair =
+                inner.qpair.as_mut().ok_or(CoreError::CopyDispatch {
+                    source: Errno::ENODEV,
+                    offset,
+                    len,
+                })?;
+
+            let range = spdk_nvme_scc_source_range {
+                slba: offset,
+                // zero based
+                nlb: (len - 1) as u16,
+                ..Default::default()
+            };
+
+            let (s, r) = oneshot::channel::<bool>();
+
+            let rc = unsafe {
+                spdk_nvme_ns_cmd_copy(
+                    self.ns.as_ptr(),
+                    qpair.as_ptr(),
+                    &range,
+                    1,
+                    dst_offset_blocks + copied,
+                    Some(nvme_async_io_completion),
+                    cb_arg(s),
+                )
+            };
+
+            if rc != 0 {
+                error!("{} copy failed: rc = {}", self.name, rc);
+                return Err(CoreError::CopyDispatch {
+                    source: Errno::from_i32(-rc),
+                    offset,
+                    len,
+                });
+            }
+
+            inner.account_io();
+            let success = r.await.expect("Failed awaiting at copy_blocks()");
+            inner.discard_io();
+
+            if !success {
+                return Err(CoreError::CopyFailed {
+                    offset,
+                    len,
+                });
+            }
+
+            copied += len;
+        }
+
+        Ok(num_blocks)
+    }
+
+    async fn zone_report(
+        &self,
+        zone_id: u64,
+        num_zones: u64,
+    ) -> Result<Vec<ZoneInfo>, CoreError> {
+        let geometry =
+            self.ns.zone_geometry().ok_or(CoreError::NotSupported {
+                source: Errno::EOPNOTSUPP,
+            })?;
+
+        let inner = NvmeIoChannel::inner_from_channel(self.io_channel.as_ptr());
+        let header_len = size_of::<spdk_nvme_zns_zone_report>() as u64;
+        let desc_len = size_of::<spdk_nvme_zns_zone_desc>() as u64;
+        // Every report is limited by the transfer size of the namespace.
+        let max_descs = (self.ns.max_io_xfer_size() - header_len) / desc_len;
+
+        let mut zones = Vec::new();
+        let mut next_zone = zone_id;
+
+        while (zones.len() as u64) < num_zones
+            && next_zone < geometry.num_zones * geometry.zone_size
+        {
+            let count = min(num_zones - zones.len() as u64, max_descs);
+            let buf = self.dma_malloc(header_len + count * desc_len).map_err(
+                |_| CoreError::DmaAllocationError {
+                    size: header_len + count * desc_len,
+                },
+            )?;
+
+            let qpair =
+                inner.qpair.as_mut().ok_or(CoreError::ZoneReportDispatch {
+                    source: Errno::ENODEV,
+                    zone_id: next_zone,
+                })?;
+
+            let (s, r) = oneshot::channel::<bool>();
+
+            let rc = unsafe {
+                spdk_nvme_zns_report_zones(
+                    self.ns.as_ptr(),
+                    qpair.as_ptr(),
+                    *buf,
+                    buf.len() as u32,
+                    next_zone,
+                    SPDK_NVME_ZRA_LIST_ALL,
+                    true,
+                    Some(nvme_async_io_completion),
+                    cb_arg(s),
+                )
+            };
+
+            if rc != 0 {
+                error!("{} zone report failed: rc = {}", self.name, rc);
+                return Err(CoreError::ZoneReportDispatch {
+                    source: Errno::from_i32(-rc),
+                    zone_id: next_zone,
+                });
+            }
+
+            inner.account_io();
+            let success = r.await.expect("Failed awaiting at zone_report()");
+            inner.discard_io();
+
+            if !success {
+                return Err(CoreError::ZoneReportFailed {
+                    zone_id: next_zone,
+                });
+            }
+
+            let report = buf.as_slice();
+            let nr_zones = unsafe {
+                (*(report.as_ptr() as *const spdk_nvme_zns_zone_report))
+                    .nr_zones
+            };
+            if nr_zones == 0 {
+                break;
+            }
+
+            for i in 0 .. min(nr_zones, count) {
+                let desc = unsafe {
+                    &*(report.as_ptr().add((header_len + i * desc_len) as usize)
+                        as *const spdk_nvme_zns_zone_desc)
+                };
+                zones.push(ZoneInfo {
+                    zone_id: desc.zslba,
+                    write_pointer: desc.wp,
+                    capacity: desc.zcap,
+                    state: nvme_zone_state(desc),
+                });
+            }
+
+            next_zone = zones.last().unwrap().zone_id + geometry.zone_size;
+        }
+
+        Ok(zones)
+    }
+
+    async fn zone_append(
+        &self,
+        zone_id: u64,
+        buffer: &DmaBuf,
+    ) -> Result<u64, CoreError> {
+        let geometry =
+            self.ns.zone_geometry().ok_or(CoreError::NotSupported {
+                source: Errno::EOPNOTSUPP,
+            })?;
+
+        let (valid, _, num_blocks) = self.bytes_to_blocks(0, buffer.len());
+        if !valid || num_blocks == 0 || num_blocks > geometry.max_append_size {
+            error!(
+                "{} invalid zone append of {} bytes",
+                self.name,
+                buffer.len()
+            );
+            return Err(CoreError::ZoneAppendDispatch {
+                source: Errno::EINVAL,
+                zone_id,
+            });
+        }
+
+        let inner = NvmeIoChannel::inner_from_channel(self.io_channel.as_ptr());
+        let qpair =
+            inner.qpair.as_mut().ok_or(CoreError::ZoneAppendDispatch {
+                source: Errno::ENODEV,
+                zone_id,
+            })?;
+
+        let (s, r) = oneshot::channel::<Option<u64>>();
+
+        let rc = unsafe {
+            spdk_nvme_zns_zone_append(
+                self.ns.as_ptr(),
+                qpair.as_ptr(),
+                **buffer,
+                zone_id,
+                num_blocks as u32,
+                Some(nvme_zone_append_completion),
+                cb_arg(s),
+                self.prchk_flags,
+            )
+        };
+
+        if rc != 0 {
+            error!("{} zone append failed: rc = {}", self.name, rc);
+            return Err(CoreError::ZoneAppendDispatch {
+                source: Errno::from_i32(-rc),
+                zone_id,
+            });
+        }
+
+        inner.account_io();
+        let lba = r.await.expect("Failed awaiting at zone_append()");
+        inner.discard_io();
+
+        lba.ok_or(CoreError::ZoneAppendFailed {
+            zone_id,
+        })
+    }
+
+    async fn zone_reset(
+        &self,
+        zone_id: u64,
+        all: bool,
+    ) -> Result<(), CoreError> {
+        if !self.ns.is_zoned() {
+            return Err(CoreError::NotSupported {
+                source: Errno::EOPNOTSUPP,
+            });
+        }
+
+        let inner = NvmeIoChannel::inner_from_channel(self.io_channel.as_ptr());
+        let qpair =
+            inner.qpair.as_mut().ok_or(CoreError::ZoneResetDispatch {
+                source: Errno::ENODEV,
+                zone_id,
+            })?;
+
+        let (s, r) = oneshot::channel::<bool>();
+
+        // with select all, the device resets the zones that can be reset
+        // and skips the others, rather than failing on them
+        let rc = unsafe {
+            spdk_nvme_zns_reset_zone(
+                self.ns.as_ptr(),
+                qpair.as_ptr(),
+                zone_id,
+                all,
+                Some(nvme_async_io_completion),
+                cb_arg(s),
+            )
+        };
+
+        if rc != 0 {
+            error!("{} zone reset failed: rc = {}", self.name, rc);
+            return Err(CoreError::ZoneResetDispatch {
+                source: Errno::from_i32(-rc),
+                zone_id,
+            });
+        }
+
+        inner.account_io();
+        let success = r.await.expect("Failed awaiting at zone_reset()");
+        inner.discard_io();
+
+        if success {
+            Ok(())
+        } else {
+            Err(CoreError::ZoneResetFailed {
+                zone_id,
+            })
+        }
+    }
+
     async fn create_snapshot(&self) -> Result<u64, CoreError> {
         let mut cmd = spdk_nvme_cmd::default();
         cmd.set_opc(nvme_admin_opc::CREATE_SNAPSHOT.into());
@@ -1083,6 +1931,33 @@ impl BlockDeviceHandle for NvmeDeviceHandle {
         self.io_passthru(&cmd, Some(&mut buffer)).await
     }
 
+    /// NVMe Reservation Release
+    async fn nvme_resv_release(
+        &self,
+        current_key: u64,
+        release_action: u8,
+        resv_type: u8,
+    ) -> Result<(), CoreError> {
+        let mut cmd = spdk_nvme_cmd::default();
+        cmd.set_opc(nvme_nvm_opcode::RESERVATION_RELEASE.into());
+        cmd.nsid = 0x1;
+        unsafe {
+            cmd.__bindgen_anon_1
+                .cdw10_bits
+                .resv_release
+                .set_rrela(release_action.into());
+            cmd.__bindgen_anon_1
+                .cdw10_bits
+                .resv_release
+                .set_rtype(resv_type.into());
+        }
+        let mut buffer = self.dma_malloc(8).unwrap();
+        buffer
+            .as_mut_slice()
+            .copy_from_slice(&current_key.to_le_bytes());
+        self.io_passthru(&cmd, Some(&mut buffer)).await
+    }
+
     /// NVMe Reservation Report
     /// cdw11: bit 0- Extended Data Structure
     async fn nvme_resv_report(
@@ -3,13 +3,19 @@ use std::{collections::HashMap, fmt::Display, sync::Arc};
 use once_cell::sync::Lazy;
 use parking_lot::{Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
 
-pub use channel::{NvmeControllerIoChannel, NvmeIoChannel, NvmeIoChannelInner};
+pub use channel::{
+    NvmeControllerIoChannel,
+    NvmeIoChannel,
+    NvmeIoChannelInner,
+    NvmeQpairStats,
+};
 pub use controller::NvmeController;
+pub use controller_inner::NvmeControllerHealthStats;
 pub use controller_state::NvmeControllerState;
 pub use device::{lookup_by_name, open_by_name, NvmeBlockDevice};
 pub use handle::{nvme_io_ctx_pool_init, NvmeDeviceHandle};
 pub use namespace::NvmeNamespace;
-pub(crate) use uri::NvmfDeviceTemplate;
+pub(crate) use uri::{host_nqn, NvmfDeviceTemplate};
 
 use crate::{
     core::CoreError,
@@ -21,6 +27,7 @@ mod controller;
 mod controller_inner;
 mod controller_state;
 mod device;
+mod discovery;
 mod handle;
 mod namespace;
 mod uri;
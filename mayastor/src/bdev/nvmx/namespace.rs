@@ -1,19 +1,35 @@
-use std::ptr::NonNull;
+use std::{cmp::min, ptr::NonNull, time::Duration};
 
 use spdk_rs::libspdk::{
+    spdk_nvme_ctrlr_get_data,
+    spdk_nvme_ctrlr_get_flags,
     spdk_nvme_ns,
+    spdk_nvme_ns_get_ana_state,
+    spdk_nvme_ns_get_csi,
+    spdk_nvme_ns_get_ctrlr,
+    spdk_nvme_ns_get_data,
     spdk_nvme_ns_get_extended_sector_size,
     spdk_nvme_ns_get_flags,
+    spdk_nvme_ns_get_max_io_xfer_size,
     spdk_nvme_ns_get_md_size,
     spdk_nvme_ns_get_num_sectors,
     spdk_nvme_ns_get_optimal_io_boundary,
     spdk_nvme_ns_get_size,
     spdk_nvme_ns_get_uuid,
     spdk_nvme_ns_supports_compare,
+    spdk_nvme_zns_ctrlr_get_max_zone_append_size,
+    spdk_nvme_zns_ns_get_max_active_zones,
+    spdk_nvme_zns_ns_get_max_open_zones,
+    spdk_nvme_zns_ns_get_num_zones,
+    spdk_nvme_zns_ns_get_zone_size_sectors,
+    SPDK_NVME_CSI_ZNS,
+    SPDK_NVME_CTRLR_COMPARE_AND_WRITE_SUPPORTED,
     SPDK_NVME_NS_DEALLOCATE_SUPPORTED,
     SPDK_NVME_NS_WRITE_ZEROES_SUPPORTED,
 };
 
+use crate::{bdev::nexus::NvmeAnaState, core::ZoneGeometry};
+
 #[derive(Debug)]
 pub struct NvmeNamespace(NonNull<spdk_nvme_ns>);
 
@@ -45,6 +61,15 @@ impl NvmeNamespace {
         unsafe { spdk_nvme_ns_supports_compare(self.0.as_ptr()) }
     }
 
+    /// Returns true when the controller supports a fused Compare and Write.
+    pub fn supports_compare_and_write(&self) -> bool {
+        unsafe {
+            spdk_nvme_ctrlr_get_flags(spdk_nvme_ns_get_ctrlr(self.0.as_ptr()))
+                & SPDK_NVME_CTRLR_COMPARE_AND_WRITE_SUPPORTED as u64
+                > 0
+        }
+    }
+
     pub fn supports_deallocate(&self) -> bool {
         unsafe {
             spdk_nvme_ns_get_flags(self.0.as_ptr())
@@ -61,10 +86,88 @@ impl NvmeNamespace {
         }
     }
 
+    /// Returns true when the controller supports the NVMe Copy command.
+    pub fn supports_copy(&self) -> bool {
+        unsafe {
+            let cdata = spdk_nvme_ctrlr_get_data(spdk_nvme_ns_get_ctrlr(
+                self.0.as_ptr(),
+            ));
+            (*cdata).oncs.copy() != 0
+        }
+    }
+
+    /// Returns the maximum number of blocks a single Copy command with a
+    /// single source range can copy.
+    pub fn max_copy_blocks(&self) -> u64 {
+        let nsdata = unsafe { &*spdk_nvme_ns_get_data(self.0.as_ptr()) };
+        min(nsdata.mssrl as u64, nsdata.mcl as u64).max(1)
+    }
+
+    /// Returns true when the namespace uses the Zoned Namespace command set.
+    pub fn is_zoned(&self) -> bool {
+        unsafe { spdk_nvme_ns_get_csi(self.0.as_ptr()) == SPDK_NVME_CSI_ZNS }
+    }
+
+    /// Returns the zone geometry of a zoned namespace.
+    pub fn zone_geometry(&self) -> Option<ZoneGeometry> {
+        if !self.is_zoned() {
+            return None;
+        }
+
+        let ns = self.0.as_ptr();
+        unsafe {
+            let max_append_bytes = spdk_nvme_zns_ctrlr_get_max_zone_append_size(
+                spdk_nvme_ns_get_ctrlr(ns),
+            ) as u64;
+
+            Some(ZoneGeometry {
+                zone_size: spdk_nvme_zns_ns_get_zone_size_sectors(ns),
+                num_zones: spdk_nvme_zns_ns_get_num_zones(ns),
+                max_open_zones: spdk_nvme_zns_ns_get_max_open_zones(ns),
+                max_active_zones: spdk_nvme_zns_ns_get_max_active_zones(ns),
+                max_append_size: max_append_bytes / self.block_len(),
+            })
+        }
+    }
+
     pub fn alignment(&self) -> u64 {
         unsafe { spdk_nvme_ns_get_optimal_io_boundary(self.0.as_ptr()) as u64 }
     }
 
+    /// Returns the ANA state of the namespace as last reported by the
+    /// controller, invalid when the controller does not report ANA states.
+    pub fn ana_state(&self) -> NvmeAnaState {
+        let state = unsafe { spdk_nvme_ns_get_ana_state(self.0.as_ptr()) };
+        NvmeAnaState::from_i32(state as i32)
+            .unwrap_or(NvmeAnaState::InvalidState)
+    }
+
+    /// Returns true when I/O can be submitted to the namespace through the
+    /// controller, according to its ANA state.
+    pub fn is_accessible(&self) -> bool {
+        matches!(
+            self.ana_state(),
+            NvmeAnaState::OptimizedState
+                | NvmeAnaState::NonOptimizedState
+                | NvmeAnaState::InvalidState
+        )
+    }
+
+    /// Returns the time the controller may take to change the ANA state of
+    /// the namespace, 10 seconds when the controller does not report it.
+    pub fn ana_transition_time(&self) -> Duration {
+        let anatt = unsafe {
+            (*spdk_nvme_ctrlr_get_data(spdk_nvme_ns_get_ctrlr(self.0.as_ptr())))
+                .anatt
+        };
+        Duration::from_secs(if anatt == 0 { 10 } else { anatt as u64 })
+    }
+
+    /// Returns the maximum number of bytes a single command can transfer.
+    pub fn max_io_xfer_size(&self) -> u64 {
+        unsafe { spdk_nvme_ns_get_max_io_xfer_size(self.0.as_ptr()) as u64 }
+    }
+
     pub fn md_size(&self) -> u64 {
         unsafe { spdk_nvme_ns_get_md_size(self.0.as_ptr()) as u64 }
     }
@@ -15,6 +15,7 @@ use std::{
     ffi::c_void,
     ptr::NonNull,
     sync::Arc,
+    time::Duration,
 };
 use url::Url;
 use uuid::Uuid;
@@ -34,7 +35,8 @@ use crate::{
     bdev::{
         nvmx::{
             controller,
-            controller_inner::SpdkNvmeController,
+            controller_inner::{ReconnectPolicy, SpdkNvmeController},
+            discovery::DiscoveryService,
             NvmeControllerState,
             NVME_CONTROLLERS,
         },
@@ -100,12 +102,19 @@ pub struct NvmfDeviceTemplate {
     host: String,
     /// the transport service id (ie. port)
     port: u16,
+    /// alternate addresses and ports the subsystem is reachable through,
+    /// given by repeated traddr parameters
+    alternate_paths: Vec<(String, u16)>,
+    /// the paths are queried from the discovery service at host and port
+    discovery: bool,
     /// the nqn of the subsystem we want to connect to
     subnqn: String,
     /// Enable protection information checking (reftag, guard)
     prchk_flags: u32,
     /// uuid of the spdk bdev
     uuid: Option<uuid::Uuid>,
+    /// reconnect behaviour when the connection is lost
+    reconnect: ReconnectPolicy,
 }
 
 impl TryFrom<&Url> for NvmfDeviceTemplate {
@@ -113,29 +122,78 @@ impl TryFrom<&Url> for NvmfDeviceTemplate {
 
     fn try_from(url: &Url) -> Result<Self, Self::Error> {
         let host =
-            url.host_str().ok_or_else(|| NexusBdevError::UriInvalid {
+            uri::host(url).ok_or_else(|| NexusBdevError::UriInvalid {
                 uri: url.to_string(),
                 message: String::from("missing host"),
             })?;
 
+        // the subsystem of a discovery URI is given by its nqn parameter,
+        // and the host and port are those of the discovery service
+        let discovery = url.scheme() == "nvmf+discovery";
+
         let segments = uri::segments(url);
 
-        if segments.is_empty() {
+        if segments.is_empty() && !discovery {
             return Err(NexusBdevError::UriInvalid {
                 uri: url.to_string(),
                 message: String::from("no path segment"),
             });
         }
 
-        if segments.len() > 1 {
+        if segments.len() > 1 || (discovery && !segments.is_empty()) {
             return Err(NexusBdevError::UriInvalid {
                 uri: url.to_string(),
                 message: String::from("too many path segments"),
             });
         }
 
+        let port = url.port().unwrap_or(DEFAULT_NVMF_PORT);
+
+        // alternate paths may be given more than once, so they are gathered
+        // before the other parameters are collected
+        let mut alternate_paths: Vec<(String, u16)> = Vec::new();
+        for (key, value) in url.query_pairs() {
+            if key != "traddr" {
+                continue;
+            }
+            let path = uri::address(&value, port).ok_or_else(|| {
+                NexusBdevError::UriInvalid {
+                    uri: url.to_string(),
+                    message: format!("invalid traddr {}", value),
+                }
+            })?;
+            if path != (host.clone(), port) && !alternate_paths.contains(&path)
+            {
+                alternate_paths.push(path);
+            }
+        }
+
+        // the paths of a discovery URI are those the discovery service
+        // advertises
+        if discovery && !alternate_paths.is_empty() {
+            return Err(NexusBdevError::UriInvalid {
+                uri: url.to_string(),
+                message: String::from(
+                    "traddr can not be combined with discovery",
+                ),
+            });
+        }
+
         let mut parameters: HashMap<String, String> =
             url.query_pairs().into_owned().collect();
+        parameters.remove("traddr");
+
+        let subnqn = if discovery {
+            parameters
+                .remove("nqn")
+                .filter(|nqn| !nqn.is_empty())
+                .ok_or_else(|| NexusBdevError::UriInvalid {
+                    uri: url.to_string(),
+                    message: String::from("missing nqn"),
+                })?
+        } else {
+            segments[0].to_string()
+        };
 
         let mut prchk_flags: u32 = 0;
 
@@ -171,18 +229,50 @@ impl TryFrom<&Url> for NvmfDeviceTemplate {
         )?;
 
         Ok(NvmfDeviceTemplate {
-            name: url[url::Position::BeforeHost .. url::Position::AfterPath]
-                .to_string(),
+            name,
             alias: url.to_string(),
-            host: host.to_string(),
-            port: url.port().unwrap_or(DEFAULT_NVMF_PORT),
-            subnqn: segments[0].to_string(),
+            host,
+            port,
+            alternate_paths,
+            discovery,
+            subnqn,
             prchk_flags,
             uuid,
+            reconnect,
         })
     }
 }
 
+impl NvmfDeviceTemplate {
+    /// the discovery service of the subsystem, for discovery URIs
+    fn discovery_service(&self) -> Option<DiscoveryService> {
+        if self.discovery {
+            Some(DiscoveryService {
+                host: self.host.clone(),
+                port: self.port,
+                subnqn: self.subnqn.clone(),
+            })
+        } else {
+            None
+        }
+    }
+
+    /// transport IDs of all paths to the subsystem, the path of the URI
+    /// first followed by the alternate paths
+    fn transport_ids(&self) -> Vec<NvmeTransportId> {
+        std::iter::once((self.host.clone(), self.port))
+            .chain(self.alternate_paths.iter().cloned())
+            .map(|(host, port)| {
+                controller::transport::Builder::new()
+                    .with_subnqn(&self.subnqn)
+                    .with_svcid(&port.to_string())
+                    .with_traddr(&host)
+                    .build()
+            })
+            .collect()
+    }
+}
+
 impl GetName for NvmfDeviceTemplate {
     fn get_name(&self) -> String {
         format!("{}n1", self.name)
@@ -200,14 +290,24 @@ pub(crate) struct NvmeControllerContext<'probe> {
     attached: bool,
 }
 
-impl<'probe> NvmeControllerContext<'probe> {
-    pub fn new(template: &NvmfDeviceTemplate) -> NvmeControllerContext {
-        let trid = controller::transport::Builder::new()
-            .with_subnqn(&template.subnqn)
-            .with_svcid(&template.port.to_string())
-            .with_traddr(&template.host)
-            .build();
+/// Returns the NQN used by this node when connecting to NVMe-oF targets,
+/// replicas must allow it for a nexus on this node to connect to them.
+pub(crate) fn host_nqn() -> Option<String> {
+    if let Ok(host_nqn) = std::env::var("HOSTNQN") {
+        return Some(host_nqn);
+    }
+
+    std::env::var("MAYASTOR_NVMF_HOSTID")
+        .ok()
+        .and_then(|id| Uuid::parse_str(&id).ok())
+        .map(|uuid| format!("nqn.2019-05.io.openebs:uuid:{}", uuid))
+}
 
+impl<'probe> NvmeControllerContext<'probe> {
+    pub fn new(
+        template: &NvmfDeviceTemplate,
+        trid: NvmeTransportId,
+    ) -> NvmeControllerContext {
         // setting the HOSTNQN allows tracking who is connected to what. These
         // makes debugging connections easier in certain cases. If no
         // HOSTNQN is provided.
@@ -223,16 +323,10 @@ impl<'probe> NvmeControllerContext<'probe> {
         if let Ok(ext_host_id) = std::env::var("MAYASTOR_NVMF_HOSTID") {
             if let Ok(uuid) = Uuid::parse_str(&ext_host_id) {
                 opts = opts.with_ext_host_id(*uuid.as_bytes());
-                if std::env::var("HOSTNQN").is_err() {
-                    opts = opts.with_hostnqn(format!(
-                        "nqn.2019-05.io.openebs:uuid:{}",
-                        uuid
-                    ));
-                }
             }
         }
 
-        if let Ok(host_nqn) = std::env::var("HOSTNQN") {
+        if let Some(host_nqn) = host_nqn() {
             opts = opts.with_hostnqn(host_nqn);
         }
 
@@ -263,30 +357,10 @@ impl<'probe> NvmeControllerContext<'probe> {
         self.sender.take().expect("no sender available")
     }
 }
-#[async_trait(?Send)]
-impl CreateDestroy for NvmfDeviceTemplate {
-    type Error = NexusBdevError;
-
-    async fn create(&self) -> Result<String, Self::Error> {
-        info!("::create() {}", self.get_name());
-        let cname = self.get_name();
-        if NVME_CONTROLLERS.lookup_by_name(&cname).is_some() {
-            return Err(NexusBdevError::BdevExists {
-                name: cname,
-            });
-        }
-
-        // Insert a new controller instance (uninitialized) as a guard, and
-        // release the lock to keep the write path as short, as
-        // possible.
-        let rc = Arc::new(Mutex::new(
-            controller::NvmeController::new(&cname, self.prchk_flags)
-                .expect("failed to create new NVMe controller instance"),
-        ));
-
-        NVME_CONTROLLERS.insert_controller(cname.clone(), rc);
-
-        let mut context = NvmeControllerContext::new(self);
+impl NvmfDeviceTemplate {
+    /// Connect the controller to the subsystem through the given path.
+    async fn connect(&self, trid: NvmeTransportId) -> Result<(), Errno> {
+        let mut context = NvmeControllerContext::new(self, trid);
 
         // Initiate connection with remote NVMe target.
         let probe_ctx = match NonNull::new(unsafe {
@@ -297,14 +371,7 @@ impl CreateDestroy for NvmfDeviceTemplate {
             )
         }) {
             Some(ctx) => ctx,
-            None => {
-                // Remove controller record before returning error.
-                NVME_CONTROLLERS.remove_by_name(&cname).unwrap();
-                return Err(NexusBdevError::CreateBdev {
-                    name: cname,
-                    source: Errno::ENODEV,
-                });
-            }
+            None => return Err(Errno::ENODEV),
         };
 
         struct AttachCtx {
@@ -355,7 +422,60 @@ impl CreateDestroy for NvmfDeviceTemplate {
 
         context.poller = Some(poller);
 
-        let attach_status = context.receiver.await.unwrap();
+        context.receiver.await.unwrap()
+    }
+}
+
+#[async_trait(?Send)]
+impl CreateDestroy for NvmfDeviceTemplate {
+    type Error = NexusBdevError;
+
+    async fn create(&self) -> Result<String, Self::Error> {
+        info!("::create() {}", self.get_name());
+        let cname = self.get_name();
+        if NVME_CONTROLLERS.lookup_by_name(&cname).is_some() {
+            return Err(NexusBdevError::BdevExists {
+                name: cname,
+            });
+        }
+
+        // Insert a new controller instance (uninitialized) as a guard, and
+        // release the lock to keep the write path as short, as
+        // possible.
+        let rc = Arc::new(Mutex::new(
+            controller::NvmeController::new(&cname, self.prchk_flags)
+                .expect("failed to create new NVMe controller instance"),
+        ));
+
+        rc.lock().set_reconnect_policy(self.reconnect);
+        NVME_CONTROLLERS.insert_controller(cname.clone(), rc);
+
+        // Try the paths in order until the controller attaches through one
+        // of them. Once attached, failures are not caused by the path and
+        // the remaining paths are not tried.
+        let mut attach_status = Err(Errno::ENODEV);
+        let mut active_path = 0;
+        for (path, trid) in trids.iter().enumerate() {
+            if path > 0 {
+                warn!(
+                    "{}: connecting through alternate path {}:{}",
+                    cname,
+                    trid.traddr(),
+                    trid.svcid()
+                );
+            }
+
+            attach_status = self.connect(trid.clone()).await;
+            active_path = path;
+
+            let attached =
+                NVME_CONTROLLERS.lookup_by_name(&cname).map_or(false, |c| {
+                    c.lock().get_state() != NvmeControllerState::New
+                });
+            if attach_status.is_ok() || attached {
+                break;
+            }
+        }
 
         match attach_status {
             Err(e) => {
@@ -376,7 +496,7 @@ impl CreateDestroy for NvmfDeviceTemplate {
                     .lookup_by_name(&cname)
                     .expect("no controller in the list");
 
-                let controller = controller.lock();
+                let mut controller = controller.lock();
 
                 // Successfully attached controllers must be in Running state.
                 assert_eq!(
@@ -385,6 +505,9 @@ impl CreateDestroy for NvmfDeviceTemplate {
                     "NVMe controller is not fully initialized"
                 );
 
+                controller.set_paths(trids, active_path);
+                controller.set_discovery(discovery.map(|d| d.watch(&cname)));
+
                 info!("{} NVMe controller successfully initialized", cname);
                 Ok(cname)
             }
@@ -12,10 +12,15 @@ pub enum NvmeMediaErrorStatusCode {
     Guard = 0x82,
     ApplicationTag = 0x83,
     ReferenceTag = 0x84,
+    CompareFailure = 0x85,
 }
 #[derive(Debug, PartialEq)]
 enum NvmeGenericCommandStatusCode {
     Success = 0x0,
+    AbortedByRequest = 0x7,
+    AbortedSqDeletion = 0x8,
+    AbortedFailedFused = 0x9,
+    AbortedMissingFused = 0xa,
 }
 #[derive(Debug, PartialEq)]
 #[allow(dead_code)]
@@ -30,6 +35,8 @@ pub enum NvmeAerType {
 #[derive(Debug, PartialEq)]
 pub enum NvmeAerInfoNotice {
     AttrChanged = 0x0,
+    AnaChange = 0x3,
+    DiscoveryLogChange = 0xf0,
 }
 
 #[derive(Debug, PartialEq)]
@@ -63,6 +70,22 @@ pub(crate) fn nvme_cpl_is_pi_error(cpl: *const spdk_nvme_cpl) -> bool {
         || sc == NvmeMediaErrorStatusCode::ReferenceTag as u16
 }
 
+/// Check if the Completion Queue Entry indicates that the data of a compare
+/// command did not match the data of the namespace.
+#[inline]
+pub(crate) fn nvme_cpl_is_miscompare(cpl: *const spdk_nvme_cpl) -> bool {
+    let (sct, sc) = unsafe {
+        let cplr = &*cpl;
+        (
+            cplr.__bindgen_anon_1.status.sct(),
+            cplr.__bindgen_anon_1.status.sc(),
+        )
+    };
+
+    sct == NvmeStatusCodeType::MediaError as u16
+        && sc == NvmeMediaErrorStatusCode::CompareFailure as u16
+}
+
 #[inline]
 /// Check if NVMe controller command completed successfully.
 pub(crate) fn nvme_cpl_succeeded(cpl: *const spdk_nvme_cpl) -> bool {
@@ -79,6 +102,25 @@ pub(crate) fn nvme_cpl_succeeded(cpl: *const spdk_nvme_cpl) -> bool {
         && sc == NvmeGenericCommandStatusCode::Success as u16
 }
 
+/// Check if the command was aborted, either on request or as its queue was
+/// deleted.
+#[inline]
+pub(crate) fn nvme_cpl_is_aborted(cpl: *const spdk_nvme_cpl) -> bool {
+    let (sct, sc) = unsafe {
+        let cplr = &*cpl;
+        (
+            cplr.__bindgen_anon_1.status.sct(),
+            cplr.__bindgen_anon_1.status.sc(),
+        )
+    };
+
+    sct == NvmeStatusCodeType::Generic as u16
+        && (sc == NvmeGenericCommandStatusCode::AbortedByRequest as u16
+            || sc == NvmeGenericCommandStatusCode::AbortedSqDeletion as u16
+            || sc == NvmeGenericCommandStatusCode::AbortedFailedFused as u16
+            || sc == NvmeGenericCommandStatusCode::AbortedMissingFused as u16)
+}
+
 /// Translates NVMe completion status into NvmeCommandStatus.
 pub(crate) fn nvme_command_status(
     cpl: *const spdk_nvme_cpl,
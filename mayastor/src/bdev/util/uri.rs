@@ -1,8 +1,42 @@
 //! Simple utility functions to help with parsing URIs.
 
-use std::str::ParseBoolError;
+use std::{
+    net::{IpAddr, SocketAddr},
+    str::ParseBoolError,
+};
 
-use url::Url;
+use url::{Host, Url};
+
+/// Returns the host of the URI as an address to connect to, that is IPv6
+/// addresses without the enclosing brackets.
+pub(crate) fn host(url: &Url) -> Option<String> {
+    match url.host()? {
+        Host::Ipv6(address) => Some(address.to_string()),
+        host => Some(host.to_string()),
+    }
+}
+
+/// Parse an address given as host[:port], IPv6 addresses with a port must be
+/// enclosed in brackets. Returns the host, without brackets, and the port.
+pub(crate) fn address(value: &str, default_port: u16) -> Option<(String, u16)> {
+    if let Ok(address) = value.parse::<SocketAddr>() {
+        return Some((address.ip().to_string(), address.port()));
+    }
+
+    let unbracketed = value.trim_start_matches('[').trim_end_matches(']');
+    if let Ok(address) = unbracketed.parse::<IpAddr>() {
+        return Some((address.to_string(), default_port));
+    }
+
+    match value.rsplit_once(':') {
+        Some((host, port)) if !host.is_empty() => {
+            port.parse().ok().map(|port| (host.to_string(), port))
+        }
+        Some(_) => None,
+        None if value.is_empty() => None,
+        None => Some((value.to_string(), default_port)),
+    }
+}
 
 pub(crate) fn segments(url: &Url) -> Vec<&str> {
     if let Some(iter) = url.path_segments() {
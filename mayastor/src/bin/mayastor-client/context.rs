@@ -1,4 +1,10 @@
-use crate::{BdevClient, JsonClient, MayaClient};
+use crate::{
+    BdevClient,
+    JsonClient,
+    MayaClient,
+    NexusV1Client,
+    ReplicaV1Client,
+};
 use byte_unit::Byte;
 use bytes::Bytes;
 use clap::ArgMatches;
@@ -57,6 +63,8 @@ pub struct Context {
     pub(crate) client: MayaClient,
     pub(crate) bdev: BdevClient,
     pub(crate) json: JsonClient,
+    pub(crate) v1_nexus: NexusV1Client,
+    pub(crate) v1_replica: ReplicaV1Client,
     verbosity: u64,
     units: char,
     pub(crate) output: OutputFormat,
@@ -110,12 +118,16 @@ impl Context {
 
         let client = MayaClient::connect(host.clone()).await.unwrap();
         let bdev = BdevClient::connect(host.clone()).await.unwrap();
-        let json = JsonClient::connect(host).await.unwrap();
+        let json = JsonClient::connect(host.clone()).await.unwrap();
+        let v1_nexus = NexusV1Client::connect(host.clone()).await.unwrap();
+        let v1_replica = ReplicaV1Client::connect(host).await.unwrap();
 
         Ok(Context {
             client,
             bdev,
             json,
+            v1_nexus,
+            v1_replica,
             verbosity,
             units,
             output,
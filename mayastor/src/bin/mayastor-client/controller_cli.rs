@@ -2,9 +2,9 @@
 //! methods to interact with NVMe controllers
 
 use super::context::Context;
-use crate::{context::OutputFormat, GrpcStatus};
+use crate::{context::OutputFormat, Error, GrpcStatus};
 use ::rpc::mayastor as rpc;
-use clap::{App, AppSettings, ArgMatches, SubCommand};
+use clap::{value_t, App, AppSettings, Arg, ArgMatches, SubCommand};
 use colored_json::ToColoredJson;
 use snafu::ResultExt;
 use tonic::Status;
@@ -13,7 +13,61 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
     let list =
         SubCommand::with_name("list").about("List existing NVMe controllers");
     let stats = SubCommand::with_name("stats")
-        .about("Display I/O statistics for NVMe controllers");
+        .about("Display I/O statistics for NVMe controllers")
+        .arg(
+            Arg::with_name("qpairs")
+                .short("q")
+                .long("qpairs")
+                .takes_value(false)
+                .help("Display the statistics of the I/O qpair of each core"),
+        );
+
+    let timeout_policy = SubCommand::with_name("timeout-policy")
+        .about(
+            "Set the tiered timeout policy of an NVMe controller, where \
+             timed out commands are aborted first, then their qpair is \
+             reset, then the controller is reset and finally removed",
+        )
+        .arg(
+            Arg::with_name("name")
+                .required(true)
+                .index(1)
+                .help("NVMe controller name"),
+        )
+        .arg(
+            Arg::with_name("abort")
+                .long("abort")
+                .takes_value(true)
+                .default_value("3")
+                .help("Number of timeouts handled by aborting the command"),
+        )
+        .arg(
+            Arg::with_name("qpair-reset")
+                .long("qpair-reset")
+                .takes_value(true)
+                .default_value("2")
+                .help("Number of further timeouts handled by resetting the qpair"),
+        )
+        .arg(
+            Arg::with_name("reset")
+                .long("reset")
+                .takes_value(true)
+                .default_value("1")
+                .help("Number of further timeouts handled by resetting the controller"),
+        )
+        .arg(
+            Arg::with_name("recovery-interval")
+                .long("recovery-interval")
+                .takes_value(true)
+                .default_value("60")
+                .help("Seconds without timeouts after which the count starts over"),
+        )
+        .arg(
+            Arg::with_name("disable")
+                .long("disable")
+                .takes_value(false)
+                .help("Revert to the timeout action of the controller"),
+        );
 
     SubCommand::with_name("controller")
         .settings(&[
@@ -24,6 +78,7 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
         .about("NVMe controllers")
         .subcommand(list)
         .subcommand(stats)
+        .subcommand(timeout_policy)
 }
 
 pub async fn handler(
@@ -33,6 +88,9 @@ pub async fn handler(
     match matches.subcommand() {
         ("list", Some(args)) => list_controllers(ctx, args).await,
         ("stats", Some(args)) => controller_stats(ctx, args).await,
+        ("timeout-policy", Some(args)) => {
+            controller_timeout_policy(ctx, args).await
+        }
         (cmd, _) => {
             Err(Status::not_found(format!("command {} does not exist", cmd)))
                 .context(GrpcStatus)
@@ -54,7 +112,7 @@ fn controller_state_to_str(idx: i32) -> String {
 
 async fn controller_stats(
     mut ctx: Context,
-    _matches: &ArgMatches<'_>,
+    matches: &ArgMatches<'_>,
 ) -> crate::Result<()> {
     let response = ctx
         .client
@@ -79,15 +137,59 @@ async fn controller_stats(
                 return Ok(());
             }
 
+            if matches.is_present("qpairs") {
+                let table: Vec<Vec<String>> = controllers
+                    .iter()
+                    .flat_map(|c| {
+                        c.qpairs.iter().map(move |q| {
+                            vec![
+                                c.name.to_string(),
+                                q.core.to_string(),
+                                q.connected.to_string(),
+                                q.outstanding_io.to_string(),
+                                q.queued_io.to_string(),
+                                q.failed_io.to_string(),
+                                q.aborted_io.to_string(),
+                                q.resets.to_string(),
+                                q.read_latency_us.to_string(),
+                                q.write_latency_us.to_string(),
+                            ]
+                        })
+                    })
+                    .collect();
+
+                let hdr = vec![
+                    "NAME",
+                    "CORE",
+                    "CONNECTED",
+                    "OUTSTANDING",
+                    "QUEUED",
+                    "FAILED",
+                    "ABORTED",
+                    "RESETS",
+                    "READ/US",
+                    "WRITE/US",
+                ];
+                ctx.print_list(hdr, table);
+                return Ok(());
+            }
+
             let table: Vec<Vec<String>> = controllers
                 .iter()
                 .map(|c| {
                     let stats = c.stats.as_ref().unwrap();
+                    let health = c.health.clone().unwrap_or_default();
 
                     let num_read_ops = stats.num_read_ops.to_string();
                     let num_write_ops = stats.num_write_ops.to_string();
                     let bytes_read = stats.bytes_read.to_string();
                     let bytes_written = stats.bytes_written.to_string();
+                    let outstanding = c
+                        .qpairs
+                        .iter()
+                        .map(|q| q.outstanding_io)
+                        .sum::<u64>()
+                        .to_string();
 
                     vec![
                         c.name.to_string(),
@@ -95,11 +197,25 @@ async fn controller_stats(
                         num_write_ops,
                         bytes_read,
                         bytes_written,
+                        outstanding,
+                        health.resets.to_string(),
+                        health.timeouts.to_string(),
+                        health.adminq_failures.to_string(),
                     ]
                 })
                 .collect();
 
-            let hdr = vec!["NAME", "READS", "WRITES", "READ/B", "WRITTEN/B"];
+            let hdr = vec![
+                "NAME",
+                "READS",
+                "WRITES",
+                "READ/B",
+                "WRITTEN/B",
+                "OUTSTANDING",
+                "RESETS",
+                "TIMEOUTS",
+                "ADMINQ-FAILURES",
+            ];
             ctx.print_list(hdr, table);
         }
     }
@@ -107,6 +223,76 @@ async fn controller_stats(
     Ok(())
 }
 
+fn timeout_policy_to_str(
+    policy: &Option<rpc::NvmeControllerTimeoutPolicy>,
+) -> String {
+    match policy {
+        Some(p) => format!(
+            "abort:{},qpair-reset:{},reset:{},recovery:{}s",
+            p.abort, p.qpair_reset, p.reset, p.recovery_interval_secs
+        ),
+        None => "-".to_string(),
+    }
+}
+
+async fn controller_timeout_policy(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> crate::Result<()> {
+    let name = matches
+        .value_of("name")
+        .ok_or_else(|| Error::MissingValue {
+            field: "name".to_string(),
+        })?
+        .to_owned();
+
+    let policy = if matches.is_present("disable") {
+        None
+    } else {
+        Some(rpc::NvmeControllerTimeoutPolicy {
+            abort: value_t!(matches.value_of("abort"), u32)
+                .unwrap_or_else(|e| e.exit()),
+            qpair_reset: value_t!(matches.value_of("qpair-reset"), u32)
+                .unwrap_or_else(|e| e.exit()),
+            reset: value_t!(matches.value_of("reset"), u32)
+                .unwrap_or_else(|e| e.exit()),
+            recovery_interval_secs: value_t!(
+                matches.value_of("recovery-interval"),
+                u64
+            )
+            .unwrap_or_else(|e| e.exit()),
+        })
+    };
+
+    let response = ctx
+        .client
+        .set_nvme_controller_timeout_policy(
+            rpc::SetNvmeControllerTimeoutPolicyRequest {
+                name: name.clone(),
+                policy: policy.clone(),
+            },
+        )
+        .await
+        .context(GrpcStatus)?;
+
+    match ctx.output {
+        OutputFormat::Json => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&response.get_ref())
+                    .unwrap()
+                    .to_colored_json_auto()
+                    .unwrap()
+            );
+        }
+        OutputFormat::Default => {
+            println!("{} {}", name, timeout_policy_to_str(&policy));
+        }
+    }
+
+    Ok(())
+}
+
 async fn list_controllers(
     mut ctx: Context,
     _matches: &ArgMatches<'_>,
@@ -140,12 +326,14 @@ async fn list_controllers(
                     let size = c.size.to_string();
                     let blk_size = c.blk_size.to_string();
                     let state = controller_state_to_str(c.state);
+                    let policy = timeout_policy_to_str(&c.timeout_policy);
 
-                    vec![c.name.clone(), size, state, blk_size]
+                    vec![c.name.clone(), size, state, blk_size, policy]
                 })
                 .collect();
 
-            let hdr = vec!["NAMEs", "SIZE", "STATE", "BLKSIZE"];
+            let hdr =
+                vec!["NAMEs", "SIZE", "STATE", "BLKSIZE", "TIMEOUT POLICY"];
             ctx.print_list(hdr, table);
         }
     }
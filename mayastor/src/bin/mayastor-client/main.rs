@@ -8,6 +8,7 @@ use ::rpc::mayastor::{
     bdev_rpc_client::BdevRpcClient,
     json_rpc_client::JsonRpcClient,
     mayastor_client::MayastorClient,
+    v1::{nexus::NexusRpcClient, replica::ReplicaRpcClient},
 };
 
 mod bdev_cli;
@@ -17,6 +18,7 @@ mod device_cli;
 mod jsonrpc_cli;
 mod nexus_child_cli;
 mod nexus_cli;
+mod nvmf_cli;
 mod perf_cli;
 mod pool_cli;
 mod rebuild_cli;
@@ -26,6 +28,8 @@ mod snapshot_cli;
 type MayaClient = MayastorClient<Channel>;
 type BdevClient = BdevRpcClient<Channel>;
 type JsonClient = JsonRpcClient<Channel>;
+type NexusV1Client = NexusRpcClient<Channel>;
+type ReplicaV1Client = ReplicaRpcClient<Channel>;
 
 #[derive(Debug, Snafu)]
 pub enum Error {
@@ -49,6 +53,15 @@ pub(crate) fn parse_size(src: &str) -> Result<Byte, String> {
     Byte::from_str(src).map_err(|_| src.to_string())
 }
 
+/// parse a reservation key, given in decimal or as hex with a 0x prefix
+pub(crate) fn parse_key(src: &str) -> Result<u64, String> {
+    match src.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => src.parse(),
+    }
+    .map_err(|_| src.to_string())
+}
+
 #[tokio::main(worker_threads = 2)]
 async fn main() -> crate::Result<()> {
     env_logger::init();
@@ -110,6 +123,7 @@ async fn main() -> crate::Result<()> {
         .subcommand(snapshot_cli::subcommands())
         .subcommand(jsonrpc_cli::subcommands())
         .subcommand(controller_cli::subcommands())
+        .subcommand(nvmf_cli::subcommands())
         .get_matches();
 
     let ctx = Context::new(&matches).await.context(ContextError)?;
@@ -124,6 +138,7 @@ async fn main() -> crate::Result<()> {
         ("rebuild", Some(args)) => rebuild_cli::handler(ctx, args).await,
         ("snapshot", Some(args)) => snapshot_cli::handler(ctx, args).await,
         ("controller", Some(args)) => controller_cli::handler(ctx, args).await,
+        ("nvmf", Some(args)) => nvmf_cli::handler(ctx, args).await,
         ("jsonrpc", Some(args)) => jsonrpc_cli::json_rpc_call(ctx, args).await,
         _ => panic!("Command not found"),
     };
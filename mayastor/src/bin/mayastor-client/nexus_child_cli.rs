@@ -3,10 +3,11 @@
 
 use crate::{
     context::{Context, OutputFormat},
+    parse_key,
     Error,
     GrpcStatus,
 };
-use ::rpc::mayastor as rpc;
+use ::rpc::mayastor::{self as rpc, v1};
 use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 use colored_json::ToColoredJson;
 use snafu::ResultExt;
@@ -18,6 +19,11 @@ pub async fn handler(
 ) -> crate::Result<()> {
     match matches.subcommand() {
         ("fault", Some(args)) => fault(ctx, args).await,
+        ("reservations", Some(args)) => reservations(ctx, args).await,
+        ("preempt", Some(args)) => preempt(ctx, args).await,
+        ("clear-reservations", Some(args)) => {
+            clear_reservations(ctx, args).await
+        }
         (cmd, _) => {
             Err(Status::not_found(format!("command {} does not exist", cmd)))
                 .context(GrpcStatus)
@@ -41,6 +47,57 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
                 .help("uri of the child"),
         );
 
+    let reservations = SubCommand::with_name("reservations")
+        .about("list the NVMe reservations held on a child")
+        .arg(
+            Arg::with_name("uuid")
+                .required(true)
+                .index(1)
+                .help("uuid of the nexus"),
+        )
+        .arg(
+            Arg::with_name("uri")
+                .required(true)
+                .index(2)
+                .help("uri of the child"),
+        );
+
+    let preempt = SubCommand::with_name("preempt")
+        .about("preempt a stale reservation on a child with the nexus key")
+        .arg(
+            Arg::with_name("uuid")
+                .required(true)
+                .index(1)
+                .help("uuid of the nexus"),
+        )
+        .arg(
+            Arg::with_name("uri")
+                .required(true)
+                .index(2)
+                .help("uri of the child"),
+        )
+        .arg(
+            Arg::with_name("preempt-key")
+                .required(true)
+                .index(3)
+                .help("reservation key of the registration to preempt"),
+        );
+
+    let clear = SubCommand::with_name("clear-reservations")
+        .about("clear all reservations and registrations on a child")
+        .arg(
+            Arg::with_name("uuid")
+                .required(true)
+                .index(1)
+                .help("uuid of the nexus"),
+        )
+        .arg(
+            Arg::with_name("uri")
+                .required(true)
+                .index(2)
+                .help("uri of the child"),
+        );
+
     SubCommand::with_name("child")
         .settings(&[
             AppSettings::SubcommandRequiredElseHelp,
@@ -49,6 +106,9 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
         ])
         .about("Nexus child management")
         .subcommand(fault)
+        .subcommand(reservations)
+        .subcommand(preempt)
+        .subcommand(clear)
 }
 
 async fn fault(
@@ -94,3 +154,146 @@ async fn fault(
 
     Ok(())
 }
+
+async fn reservations(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> crate::Result<()> {
+    let uuid = matches
+        .value_of("uuid")
+        .ok_or_else(|| Error::MissingValue {
+            field: "uuid".to_string(),
+        })?
+        .to_string();
+    let uri = matches
+        .value_of("uri")
+        .ok_or_else(|| Error::MissingValue {
+            field: "uri".to_string(),
+        })?
+        .to_string();
+
+    let response = ctx
+        .v1_nexus
+        .get_child_reservations(v1::nexus::GetChildReservationsRequest {
+            uuid,
+            uri,
+        })
+        .await
+        .context(GrpcStatus)?;
+
+    print_reservations(&ctx, response.get_ref());
+    Ok(())
+}
+
+async fn preempt(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> crate::Result<()> {
+    let uuid = matches
+        .value_of("uuid")
+        .ok_or_else(|| Error::MissingValue {
+            field: "uuid".to_string(),
+        })?
+        .to_string();
+    let uri = matches
+        .value_of("uri")
+        .ok_or_else(|| Error::MissingValue {
+            field: "uri".to_string(),
+        })?
+        .to_string();
+    let preempt_key =
+        parse_key(matches.value_of("preempt-key").ok_or_else(|| {
+            Error::MissingValue {
+                field: "preempt-key".to_string(),
+            }
+        })?)
+        .map_err(|k| Status::invalid_argument(format!("Bad key '{}'", k)))
+        .context(GrpcStatus)?;
+
+    let response = ctx
+        .v1_nexus
+        .preempt_child_reservation(v1::nexus::PreemptChildReservationRequest {
+            uuid,
+            uri,
+            preempt_key,
+        })
+        .await
+        .context(GrpcStatus)?;
+
+    print_reservations(&ctx, response.get_ref());
+    Ok(())
+}
+
+async fn clear_reservations(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> crate::Result<()> {
+    let uuid = matches
+        .value_of("uuid")
+        .ok_or_else(|| Error::MissingValue {
+            field: "uuid".to_string(),
+        })?
+        .to_string();
+    let uri = matches
+        .value_of("uri")
+        .ok_or_else(|| Error::MissingValue {
+            field: "uri".to_string(),
+        })?
+        .to_string();
+
+    let response = ctx
+        .v1_nexus
+        .clear_child_reservations(v1::nexus::ClearChildReservationsRequest {
+            uuid,
+            uri,
+        })
+        .await
+        .context(GrpcStatus)?;
+
+    print_reservations(&ctx, response.get_ref());
+    Ok(())
+}
+
+/// print the registrants of a reservation report, shared with the replica
+/// reservation commands
+pub(crate) fn print_reservations(
+    ctx: &Context,
+    report: &v1::nexus::ReservationReport,
+) {
+    match ctx.output {
+        OutputFormat::Json => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(report)
+                    .unwrap()
+                    .to_colored_json_auto()
+                    .unwrap()
+            );
+        }
+        OutputFormat::Default => {
+            ctx.v1(&format!(
+                "generation {}, type {}, ptpl {}",
+                report.generation, report.reservation_type, report.ptpl
+            ));
+            if report.registrants.is_empty() {
+                ctx.v1("No hosts are registered");
+                return;
+            }
+
+            let header = vec!["HOST_ID", ">CNTLID", ">KEY", "HOLDER"];
+            let table = report
+                .registrants
+                .iter()
+                .map(|r| {
+                    vec![
+                        r.host_id.clone(),
+                        format!("{:#x}", r.controller_id),
+                        format!("{:#x}", r.key),
+                        r.holder.to_string(),
+                    ]
+                })
+                .collect();
+            ctx.print_list(header, table);
+        }
+    }
+}
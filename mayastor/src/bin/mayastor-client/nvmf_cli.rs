@@ -0,0 +1,132 @@
+//!
+//! methods to inspect the NVMe-oF target
+
+use super::context::Context;
+use crate::{context::OutputFormat, Error, GrpcStatus};
+use ::rpc::mayastor::v1;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use colored_json::ToColoredJson;
+use snafu::ResultExt;
+use tonic::Status;
+
+pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
+    let connections = SubCommand::with_name("connections")
+        .about("List the hosts connected to a published nexus or replica")
+        .arg(
+            Arg::with_name("uuid")
+                .required(true)
+                .index(1)
+                .help("Nexus or replica uuid"),
+        )
+        .arg(
+            Arg::with_name("replica")
+                .short("r")
+                .long("replica")
+                .takes_value(false)
+                .help("The uuid is the uuid of a replica"),
+        );
+
+    SubCommand::with_name("nvmf")
+        .settings(&[
+            AppSettings::SubcommandRequiredElseHelp,
+            AppSettings::ColoredHelp,
+            AppSettings::ColorAlways,
+        ])
+        .about("NVMe-oF target")
+        .subcommand(connections)
+}
+
+pub async fn handler(
+    ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> crate::Result<()> {
+    match matches.subcommand() {
+        ("connections", Some(args)) => connections(ctx, args).await,
+        (cmd, _) => {
+            Err(Status::not_found(format!("command {} does not exist", cmd)))
+                .context(GrpcStatus)
+        }
+    }
+}
+
+async fn connections(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> crate::Result<()> {
+    let uuid = matches
+        .value_of("uuid")
+        .ok_or_else(|| Error::MissingValue {
+            field: "uuid".to_string(),
+        })?
+        .to_owned();
+
+    let response = if matches.is_present("replica") {
+        ctx.v1_replica
+            .get_replica_connections(
+                v1::replica::GetReplicaConnectionsRequest {
+                    uuid,
+                },
+            )
+            .await
+    } else {
+        ctx.v1_nexus
+            .get_nexus_connections(v1::nexus::GetNexusConnectionsRequest {
+                uuid,
+            })
+            .await
+    }
+    .context(GrpcStatus)?;
+
+    match ctx.output {
+        OutputFormat::Json => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(response.get_ref())
+                    .unwrap()
+                    .to_colored_json_auto()
+                    .unwrap()
+            );
+        }
+        OutputFormat::Default => {
+            let connections = &response.get_ref().connections;
+            if connections.is_empty() {
+                ctx.v1("No hosts are connected");
+                return Ok(());
+            }
+
+            let header = vec![
+                "HOST_NQN",
+                "HOST_ID",
+                "ADDRESS",
+                ">CNTLID",
+                ">QUEUES",
+                "CONNECTED",
+                ">READS",
+                ">BYTES_READ",
+                ">WRITES",
+                ">BYTES_WRITTEN",
+            ];
+            let table = connections
+                .iter()
+                .map(|c| {
+                    let stats = c.stats.clone().unwrap_or_default();
+                    vec![
+                        c.host_nqn.clone(),
+                        c.host_id.clone(),
+                        c.address.clone(),
+                        c.controller_id.to_string(),
+                        c.io_queues.to_string(),
+                        c.connected_since.clone(),
+                        stats.num_read_ops.to_string(),
+                        stats.bytes_read.to_string(),
+                        stats.num_write_ops.to_string(),
+                        stats.bytes_written.to_string(),
+                    ]
+                })
+                .collect();
+            ctx.print_list(header, table);
+        }
+    }
+
+    Ok(())
+}
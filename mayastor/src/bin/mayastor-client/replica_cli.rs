@@ -1,10 +1,12 @@
 use crate::{
     context::{Context, OutputFormat},
+    nexus_child_cli::print_reservations,
+    parse_key,
     parse_size,
     Error,
     GrpcStatus,
 };
-use ::rpc::mayastor as rpc;
+use ::rpc::mayastor::{self as rpc, v1};
 use byte_unit::Byte;
 use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 use colored_json::ToColoredJson;
@@ -105,6 +107,51 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
                 .index(2)
                 .help("Name of a protocol (nvmf) used for sharing or \"none\" to unshare the replica"));
 
+    let reservations = SubCommand::with_name("reservations")
+        .about("List the NVMe reservations held on a shared replica")
+        .arg(
+            Arg::with_name("uuid")
+                .required(true)
+                .index(1)
+                .help("Replica uuid"),
+        );
+
+    let preempt = SubCommand::with_name("preempt")
+        .about("Preempt a stale reservation on a shared replica")
+        .arg(
+            Arg::with_name("uuid")
+                .required(true)
+                .index(1)
+                .help("Replica uuid"),
+        )
+        .arg(
+            Arg::with_name("key")
+                .required(true)
+                .index(2)
+                .help("Reservation key to preempt with"),
+        )
+        .arg(
+            Arg::with_name("preempt-key")
+                .required(true)
+                .index(3)
+                .help("Reservation key of the registration to preempt"),
+        );
+
+    let clear = SubCommand::with_name("clear-reservations")
+        .about("Clear all reservations and registrations on a shared replica")
+        .arg(
+            Arg::with_name("uuid")
+                .required(true)
+                .index(1)
+                .help("Replica uuid"),
+        )
+        .arg(
+            Arg::with_name("key")
+                .required(true)
+                .index(2)
+                .help("Reservation key to clear with"),
+        );
+
     SubCommand::with_name("replica")
         .settings(&[
             AppSettings::SubcommandRequiredElseHelp,
@@ -116,6 +163,9 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
         .subcommand(create_v2)
         .subcommand(destroy)
         .subcommand(share)
+        .subcommand(reservations)
+        .subcommand(preempt)
+        .subcommand(clear)
         .subcommand(SubCommand::with_name("list").about("List replicas"))
         .subcommand(SubCommand::with_name("list2").about("List replicas"))
         .subcommand(
@@ -135,6 +185,11 @@ pub async fn handler(
         ("list2", Some(args)) => replica_list2(ctx, args).await,
         ("share", Some(args)) => replica_share(ctx, args).await,
         ("stats", Some(args)) => replica_stat(ctx, args).await,
+        ("reservations", Some(args)) => replica_reservations(ctx, args).await,
+        ("preempt", Some(args)) => replica_preempt(ctx, args).await,
+        ("clear-reservations", Some(args)) => {
+            replica_clear_reservations(ctx, args).await
+        }
         (cmd, _) => {
             Err(Status::not_found(format!("command {} does not exist", cmd)))
                 .context(GrpcStatus)
@@ -489,6 +544,93 @@ async fn replica_stat(
     Ok(())
 }
 
+async fn replica_reservations(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> crate::Result<()> {
+    let uuid = matches
+        .value_of("uuid")
+        .ok_or_else(|| Error::MissingValue {
+            field: "uuid".to_string(),
+        })?
+        .to_owned();
+
+    let response = ctx
+        .v1_replica
+        .get_replica_reservations(v1::replica::GetReplicaReservationsRequest {
+            uuid,
+        })
+        .await
+        .context(GrpcStatus)?;
+
+    print_reservations(&ctx, response.get_ref());
+    Ok(())
+}
+
+async fn replica_preempt(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> crate::Result<()> {
+    let uuid = matches
+        .value_of("uuid")
+        .ok_or_else(|| Error::MissingValue {
+            field: "uuid".to_string(),
+        })?
+        .to_owned();
+    let key = replica_key(matches, "key")?;
+    let preempt_key = replica_key(matches, "preempt-key")?;
+
+    let response = ctx
+        .v1_replica
+        .preempt_replica_reservation(
+            v1::replica::PreemptReplicaReservationRequest {
+                uuid,
+                key,
+                preempt_key,
+            },
+        )
+        .await
+        .context(GrpcStatus)?;
+
+    print_reservations(&ctx, response.get_ref());
+    Ok(())
+}
+
+async fn replica_clear_reservations(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> crate::Result<()> {
+    let uuid = matches
+        .value_of("uuid")
+        .ok_or_else(|| Error::MissingValue {
+            field: "uuid".to_string(),
+        })?
+        .to_owned();
+    let key = replica_key(matches, "key")?;
+
+    let response = ctx
+        .v1_replica
+        .clear_replica_reservations(
+            v1::replica::ClearReplicaReservationsRequest {
+                uuid,
+                key,
+            },
+        )
+        .await
+        .context(GrpcStatus)?;
+
+    print_reservations(&ctx, response.get_ref());
+    Ok(())
+}
+
+fn replica_key(matches: &ArgMatches<'_>, field: &str) -> crate::Result<u64> {
+    parse_key(matches.value_of(field).ok_or_else(|| Error::MissingValue {
+        field: field.to_string(),
+    })?)
+    .map_err(|k| Status::invalid_argument(format!("Bad key '{}'", k)))
+    .context(GrpcStatus)
+}
+
 fn parse_replica_protocol(pcol: Option<&str>) -> Result<i32, Status> {
     match pcol {
         None => Ok(rpc::ShareProtocolReplica::ReplicaNone as i32),
@@ -0,0 +1,183 @@
+//! Offline consistency check and repair of the metadata of a storage pool.
+//!
+//! The pool must not be imported by a running mayastor instance as the
+//! blobstore is loaded directly from the disk given on the command line.
+
+extern crate clap;
+#[macro_use]
+extern crate tracing;
+
+use clap::{App, Arg};
+use colored_json::ToColoredJson;
+
+use mayastor::{
+    core::{mayastor_env_stop, MayastorCliArgs, MayastorEnvironment, Reactor},
+    jsonrpc::print_error_chain,
+    logger,
+    lvs::{Lvs, LvsCheckReport},
+    pool::PoolArgs,
+    subsys,
+    subsys::Config,
+};
+
+unsafe extern "C" fn run_static_initializers() {
+    spdk_rs::libspdk::spdk_add_subsystem(subsys::ConfigSubsystem::new().0)
+}
+
+#[used]
+static INIT_ARRAY: [unsafe extern "C" fn(); 1] = [run_static_initializers];
+
+/// exit codes, loosely modeled after fsck
+const EXIT_CLEAN: i32 = 0;
+const EXIT_REPAIRED: i32 = 1;
+const EXIT_UNCORRECTED: i32 = 4;
+const EXIT_ERROR: i32 = 8;
+
+fn print_report(report: &LvsCheckReport, json: bool) {
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(report)
+                .unwrap()
+                .to_colored_json_auto()
+                .unwrap()
+        );
+        return;
+    }
+
+    println!("pool:       {} ({})", report.name, report.uuid);
+    println!("disk:       {}", report.disk);
+    println!(
+        "clusters:   {} total, {} free, {} bytes each",
+        report.total_clusters, report.free_clusters, report.cluster_size
+    );
+    println!("lvols:      {}", report.lvols.len());
+    println!("leaked:     {} clusters", report.leaked_clusters);
+    println!("unmarked:   {} clusters", report.unmarked_clusters);
+    for o in &report.orphaned {
+        println!(
+            "orphan:     blob {:#x} name {} uuid {} reason {:?} clusters {}{}",
+            o.blob_id,
+            o.name.as_deref().unwrap_or("-"),
+            o.uuid.as_deref().unwrap_or("-"),
+            o.reason,
+            o.clusters,
+            if o.reclaimed { " (reclaimed)" } else { "" }
+        );
+        for id in &o.conflicts_with {
+            println!("            conflicts with blob {:#x}", id);
+        }
+    }
+    for e in &report.errors {
+        println!("error:      {}", e);
+    }
+}
+
+fn main() {
+    let matches = App::new("Storage pool metadata check")
+        .about("Check and optionally repair the metadata of an exported pool")
+        .arg(
+            Arg::with_name("POOL")
+                .help("Name of the pool")
+                .required(true)
+                .index(1),
+        )
+        .arg(
+            Arg::with_name("DISK")
+                .help("Disk device file or URI the pool lives on")
+                .required(true)
+                .index(2),
+        )
+        .arg(
+            Arg::with_name("uuid")
+                .long("uuid")
+                .value_name("UUID")
+                .help("Expected UUID of the pool")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("repair")
+                .short("r")
+                .long("repair")
+                .help("Delete orphaned lvols and reclaim leaked clusters"),
+        )
+        .arg(
+            Arg::with_name("delete")
+                .short("d")
+                .long("delete")
+                .value_name("BLOB_ID")
+                .help("Delete the conflicting lvol with the given blob id")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .requires("repair"),
+        )
+        .arg(
+            Arg::with_name("json")
+                .short("j")
+                .long("json")
+                .help("Print the report as JSON"),
+        )
+        .get_matches();
+
+    logger::init("INFO");
+
+    let args = PoolArgs {
+        name: matches.value_of("POOL").unwrap().to_owned(),
+        disks: vec![matches.value_of("DISK").unwrap().to_owned()],
+        uuid: matches.value_of("uuid").map(String::from),
+    };
+    let repair = matches.is_present("repair");
+    let delete = match matches
+        .values_of("delete")
+        .into_iter()
+        .flatten()
+        .map(|id| match id.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16),
+            None => id.parse(),
+        })
+        .collect::<Result<Vec<u64>, _>>()
+    {
+        Ok(delete) => delete,
+        Err(e) => {
+            eprintln!("invalid blob id: {}", e);
+            std::process::exit(EXIT_ERROR);
+        }
+    };
+    let json = matches.is_present("json");
+
+    // This tool works on local disks only, so don't start NVMe-oF services.
+    Config::get_or_init(|| {
+        let mut cfg = Config::default();
+        cfg.nexus_opts.nvmf_enable = false;
+        cfg
+    });
+
+    let ms = MayastorEnvironment::new(MayastorCliArgs::default());
+
+    ms.init();
+    let fut = async move {
+        match Lvs::check(args, repair, &delete).await {
+            Ok(report) => {
+                print_report(&report, json);
+                if report.is_clean() {
+                    EXIT_CLEAN
+                } else if report.is_repaired() {
+                    EXIT_REPAIRED
+                } else {
+                    EXIT_UNCORRECTED
+                }
+            }
+            Err(e) => {
+                error!("{}", print_error_chain(&e));
+                EXIT_ERROR
+            }
+        }
+    };
+
+    Reactor::block_on(async move {
+        let rc = fut.await;
+        mayastor_env_stop(0);
+        std::process::exit(rc);
+    });
+}
@@ -1,30 +1,66 @@
 use std::{
     fmt::{Debug, Display, Formatter},
     ops::{Deref, DerefMut},
+    os::raw::c_void,
     pin::Pin,
 };
 
 use async_trait::async_trait;
+use futures::channel::oneshot;
 use nix::errno::Errno;
+use serde::{Deserialize, Serialize};
 use snafu::ResultExt;
 
-use spdk_rs::libspdk::spdk_bdev;
+use spdk_rs::libspdk::{
+    spdk_bdev,
+    spdk_bdev_get_qos_rate_limits,
+    spdk_bdev_set_qos_rate_limits,
+};
 
 use crate::{
     bdev::SpdkBlockDevice,
     core::{
-        share::{Protocol, Share},
+        share::{NvmfShareProps, Protocol, Share},
         BlockDeviceIoStats,
         CoreError,
         Descriptor,
         ShareNvmf,
         UnshareNvmf,
+        UpdateAllowedHosts,
     },
+    ffihelper::{cb_arg, pair, FfiResult},
     nexus_uri::bdev_uri_eq,
-    subsys::NvmfSubsystem,
+    subsys::{NvmfConnection, NvmfSubsystem},
     target::nvmf,
 };
 
+/// indices into the rate limit array, mirrors spdk_bdev_qos_rate_limit_type
+const QOS_RW_IOPS_RATE_LIMIT: usize = 0;
+const QOS_RW_BPS_RATE_LIMIT: usize = 1;
+const QOS_R_BPS_RATE_LIMIT: usize = 2;
+const QOS_W_BPS_RATE_LIMIT: usize = 3;
+const QOS_NUM_RATE_LIMIT_TYPES: usize = 4;
+/// leave the limit of the given type as is
+const QOS_LIMIT_NOT_DEFINED: u64 = u64::MAX;
+
+/// QoS rate limits of a bdev, enforced by the bdev layer for all I/O
+/// submitted to the bdev. A value of zero means unlimited. IOPS limits are
+/// rounded up to a multiple of 1000 by SPDK.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RateLimits {
+    pub rw_ios_per_sec: u64,
+    pub rw_mbytes_per_sec: u64,
+    pub r_mbytes_per_sec: u64,
+    pub w_mbytes_per_sec: u64,
+}
+
+impl RateLimits {
+    /// returns true if none of the limits are set
+    pub fn is_unlimited(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
 /// Newtype structure that represents a block device. The soundness of the API
 /// is based on the fact that opening and finding of a bdev, returns a valid
 /// bdev or None. Once the bdev is given, the operations on the bdev are safe.
@@ -153,6 +189,98 @@ impl<T: spdk_rs::BdevOps> Bdev<T> {
         BdevIter::<T>::new().next()
     }
 
+    /// replace the hosts allowed to connect to the NVMe-oF share of the
+    /// bdev, no host may connect when the list is empty unless any host is
+    /// allowed. This is a no-op when the bdev is not shared.
+    pub async fn set_allowed_hosts(
+        &self,
+        hosts: &[String],
+    ) -> Result<(), CoreError> {
+        if let Some(subsystem) = NvmfSubsystem::nqn_lookup(self.name()) {
+            subsystem.set_allowed_hosts(hosts).await.context(
+                UpdateAllowedHosts {
+                    name: self.name().to_string(),
+                },
+            )?;
+        }
+        Ok(())
+    }
+
+    /// allow any host to connect to the NVMe-oF share of the bdev, or only
+    /// the allowed hosts. This is a no-op when the bdev is not shared.
+    pub fn set_allow_any_host(&self, enable: bool) {
+        if let Some(subsystem) = NvmfSubsystem::nqn_lookup(self.name()) {
+            subsystem.allow_any(enable);
+        }
+    }
+
+    /// returns the hosts connected to the NVMe-oF share of the bdev, which is
+    /// empty when the bdev is not shared
+    pub async fn nvmf_connections(&self) -> Vec<NvmfConnection> {
+        match NvmfSubsystem::nqn_lookup(self.name()) {
+            Some(subsystem) => subsystem.connections().await,
+            None => Vec::new(),
+        }
+    }
+
+    /// set the QoS rate limits of the bdev, this can be done while I/O is
+    /// in flight
+    pub async fn set_rate_limits(
+        &mut self,
+        limits: &RateLimits,
+    ) -> Result<(), CoreError> {
+        extern "C" fn qos_cb(arg: *mut c_void, status: i32) {
+            let sender =
+                unsafe { Box::from_raw(arg as *mut oneshot::Sender<i32>) };
+            sender.send(status).expect("receiver gone");
+        }
+
+        let mut raw = [QOS_LIMIT_NOT_DEFINED; QOS_NUM_RATE_LIMIT_TYPES];
+        raw[QOS_RW_IOPS_RATE_LIMIT] = limits.rw_ios_per_sec;
+        raw[QOS_RW_BPS_RATE_LIMIT] = limits.rw_mbytes_per_sec;
+        raw[QOS_R_BPS_RATE_LIMIT] = limits.r_mbytes_per_sec;
+        raw[QOS_W_BPS_RATE_LIMIT] = limits.w_mbytes_per_sec;
+
+        let (s, r) = pair::<i32>();
+        unsafe {
+            spdk_bdev_set_qos_rate_limits(
+                self.unsafe_inner_mut_ptr(),
+                raw.as_mut_ptr(),
+                Some(qos_cb),
+                cb_arg(s),
+            )
+        };
+
+        r.await.expect("QoS callback is gone").to_result(|e| {
+            CoreError::RateLimits {
+                source: Errno::from_i32(e.abs()),
+            }
+        })?;
+
+        info!("{}: QoS rate limits set to {:?}", self.name(), limits);
+        Ok(())
+    }
+
+    /// returns the QoS rate limits currently in effect
+    pub fn rate_limits(&mut self) -> RateLimits {
+        let mut raw = [0u64; QOS_NUM_RATE_LIMIT_TYPES];
+        unsafe {
+            spdk_bdev_get_qos_rate_limits(
+                self.unsafe_inner_mut_ptr(),
+                raw.as_mut_ptr(),
+            )
+        };
+
+        // bandwidth limits are returned in megabytes per second, just as
+        // they are set
+        RateLimits {
+            rw_ios_per_sec: raw[QOS_RW_IOPS_RATE_LIMIT],
+            rw_mbytes_per_sec: raw[QOS_RW_BPS_RATE_LIMIT],
+            r_mbytes_per_sec: raw[QOS_R_BPS_RATE_LIMIT],
+            w_mbytes_per_sec: raw[QOS_W_BPS_RATE_LIMIT],
+        }
+    }
+
     /// TODO
     pub async fn stats_async(&self) -> Result<BlockDeviceIoStats, CoreError> {
         match self.inner.stats_async().await {
@@ -163,6 +291,10 @@ impl<T: spdk_rs::BdevOps> Bdev<T> {
                 bytes_written: stat.bytes_written,
                 num_unmap_ops: stat.num_unmap_ops,
                 bytes_unmapped: stat.bytes_unmapped,
+                read_latency_ticks: stat.read_latency_ticks,
+                write_latency_ticks: stat.write_latency_ticks,
+                unmap_latency_ticks: stat.unmap_latency_ticks,
+                tick_rate: stat.ticks_rate,
             }),
             Err(err) => Err(CoreError::DeviceStatisticsError {
                 source: err,
@@ -179,19 +311,28 @@ where
     type Error = CoreError;
     type Output = String;
 
-    /// share the bdev over NVMe-OF TCP
+    /// share the bdev over NVMe-OF TCP, any host may connect when no
+    /// properties are given
     async fn share_nvmf(
         self: Pin<&mut Self>,
-        cntlid_range: Option<(u16, u16)>,
+        props: Option<NvmfShareProps>,
     ) -> Result<Self::Output, Self::Error> {
         let me = unsafe { self.get_unchecked_mut() };
+        let props = props
+            .unwrap_or_else(|| NvmfShareProps::new().with_allow_any_host(true));
 
-        let subsystem = NvmfSubsystem::try_from(me).context(ShareNvmf {})?;
-        if let Some((cntlid_min, cntlid_max)) = cntlid_range {
+        let subsystem =
+            NvmfSubsystem::try_from_props(me, &props).context(ShareNvmf {})?;
+        if let Some((cntlid_min, cntlid_max)) = props.cntlid_range() {
             subsystem
                 .set_cntlid_range(cntlid_min, cntlid_max)
                 .context(ShareNvmf {})?;
         }
+        if let Err(e) = subsystem.set_allowed_hosts(props.allowed_hosts()).await
+        {
+            subsystem.destroy();
+            return Err(e).context(ShareNvmf {});
+        }
         subsystem.start().await.context(ShareNvmf {})
     }
 
@@ -222,6 +363,16 @@ where
         }
     }
 
+    /// returns the hosts allowed to connect to the nvmf share
+    fn allowed_hosts(&self) -> Vec<String> {
+        match self.shared() {
+            Some(Protocol::Nvmf) => NvmfSubsystem::nqn_lookup(self.name())
+                .map(|s| s.allowed_hosts())
+                .unwrap_or_default(),
+            _ => Vec::new(),
+        }
+    }
+
     /// return share URI for nvmf (does "share path" not sound better?)
     fn share_uri(&self) -> Option<String> {
         match self.shared() {
@@ -230,6 +381,15 @@ where
         }
     }
 
+    fn share_uris(&self) -> Vec<String> {
+        match self.shared() {
+            Some(Protocol::Nvmf) => NvmfSubsystem::nqn_lookup(self.name())
+                .and_then(|ss| ss.uri_endpoints())
+                .unwrap_or_default(),
+            _ => self.share_uri().into_iter().collect(),
+        }
+    }
+
     /// return the URI that was used to construct the bdev
     fn bdev_uri(&self) -> Option<String> {
         for alias in self.aliases().iter() {
@@ -5,7 +5,7 @@ use spdk_rs::{DmaBuf, DmaError, IoVec};
 use async_trait::async_trait;
 use merge::Merge;
 use nix::errno::Errno;
-use std::os::raw::c_void;
+use std::{os::raw::c_void, time::Duration};
 use uuid::Uuid;
 
 /// TODO
@@ -23,6 +23,114 @@ pub struct BlockDeviceIoStats {
     pub num_unmap_ops: u64,
     #[merge(strategy = merge::num::saturating_add)]
     pub bytes_unmapped: u64,
+    #[merge(strategy = merge::num::saturating_add)]
+    pub read_latency_ticks: u64,
+    #[merge(strategy = merge::num::saturating_add)]
+    pub write_latency_ticks: u64,
+    #[merge(strategy = merge::num::saturating_add)]
+    pub unmap_latency_ticks: u64,
+    /// ticks per second of the latency counters, zero when the device does
+    /// not account latencies
+    #[merge(strategy = merge::ord::max)]
+    pub tick_rate: u64,
+}
+
+impl BlockDeviceIoStats {
+    fn avg_latency_us(ticks: u64, ops: u64, tick_rate: u64) -> u64 {
+        if ops == 0 || tick_rate == 0 {
+            0
+        } else {
+            ((ticks as u128) * 1_000_000 / (ops as u128 * tick_rate as u128))
+                as u64
+        }
+    }
+
+    /// average read latency in microseconds
+    pub fn avg_read_latency_us(&self) -> u64 {
+        Self::avg_latency_us(
+            self.read_latency_ticks,
+            self.num_read_ops,
+            self.tick_rate,
+        )
+    }
+
+    /// average write latency in microseconds
+    pub fn avg_write_latency_us(&self) -> u64 {
+        Self::avg_latency_us(
+            self.write_latency_ticks,
+            self.num_write_ops,
+            self.tick_rate,
+        )
+    }
+
+    /// average unmap latency in microseconds
+    pub fn avg_unmap_latency_us(&self) -> u64 {
+        Self::avg_latency_us(
+            self.unmap_latency_ticks,
+            self.num_unmap_ops,
+            self.tick_rate,
+        )
+    }
+}
+
+/// Zone geometry of a zoned block device, all sizes in blocks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ZoneGeometry {
+    /// number of blocks of a zone
+    pub zone_size: u64,
+    /// number of zones of the device
+    pub num_zones: u64,
+    /// maximum number of zones that can be open at once, zero if unlimited
+    pub max_open_zones: u32,
+    /// maximum number of zones that can be active at once, zero if unlimited
+    pub max_active_zones: u32,
+    /// maximum number of blocks a single zone append can write
+    pub max_append_size: u64,
+}
+
+/// State of a zone of a zoned block device.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ZoneState {
+    Empty,
+    ImplicitOpen,
+    ExplicitOpen,
+    Closed,
+    ReadOnly,
+    Full,
+    Offline,
+}
+
+impl ZoneState {
+    /// Returns whether the write pointer of a zone in this state can be
+    /// reset. Empty zones have nothing to reset, and read-only and offline
+    /// zones fail the reset.
+    pub fn is_resettable(&self) -> bool {
+        matches!(
+            self,
+            Self::ImplicitOpen | Self::ExplicitOpen | Self::Closed | Self::Full
+        )
+    }
+}
+
+/// Returns the zones of a zone report that can be reset.
+pub(crate) fn resettable_zones(zones: &[ZoneInfo]) -> Vec<u64> {
+    zones
+        .iter()
+        .filter(|zone| zone.state.is_resettable())
+        .map(|zone| zone.zone_id)
+        .collect()
+}
+
+/// Zone of a zoned block device, as reported by the device.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ZoneInfo {
+    /// the first block of the zone, which identifies it
+    pub zone_id: u64,
+    /// the block the next write to the zone goes to
+    pub write_pointer: u64,
+    /// number of blocks of the zone that can be written
+    pub capacity: u64,
+    pub state: ZoneState,
 }
 
 /// Core trait that represents a block device.
@@ -56,6 +164,19 @@ pub trait BlockDevice {
     /// Checks whether target I/O type is supported by the device.
     fn io_type_supported(&self, io_type: IoType) -> bool;
 
+    /// Checks whether blocks can be copied from the device to the target
+    /// device by the devices themselves, without the data passing through
+    /// us.
+    fn copy_supported(&self, _dst: &dyn BlockDevice) -> bool {
+        false
+    }
+
+    /// Returns the zone geometry of the device, or None if the device is
+    /// not zoned.
+    fn zone_geometry(&self) -> Option<ZoneGeometry> {
+        None
+    }
+
     /// Obtains I/O statistics for the device.
     async fn io_stats(&self) -> Result<BlockDeviceIoStats, CoreError>;
 
@@ -161,6 +282,42 @@ pub trait BlockDeviceHandle {
         cb_arg: IoCompletionCallbackArg,
     ) -> Result<(), CoreError>;
 
+    /// Compares the blocks of the device to the data of the I/O vector,
+    /// completing with `IoCompletionStatus::Miscompare` when they differ.
+    fn comparev_blocks(
+        &self,
+        _iov: *mut IoVec,
+        _iovcnt: i32,
+        _offset_blocks: u64,
+        _num_blocks: u64,
+        _cb: IoCompletionCallback,
+        _cb_arg: IoCompletionCallbackArg,
+    ) -> Result<(), CoreError> {
+        Err(CoreError::NotSupported {
+            source: Errno::EOPNOTSUPP,
+        })
+    }
+
+    /// Atomically writes the data of the write I/O vector to the blocks of
+    /// the device, provided they compare equal to the data of the compare
+    /// I/O vector. Nothing is written on a miscompare.
+    #[allow(clippy::too_many_arguments)]
+    fn comparev_and_writev_blocks(
+        &self,
+        _compare_iov: *mut IoVec,
+        _compare_iovcnt: i32,
+        _write_iov: *mut IoVec,
+        _write_iovcnt: i32,
+        _offset_blocks: u64,
+        _num_blocks: u64,
+        _cb: IoCompletionCallback,
+        _cb_arg: IoCompletionCallbackArg,
+    ) -> Result<(), CoreError> {
+        Err(CoreError::NotSupported {
+            source: Errno::EOPNOTSUPP,
+        })
+    }
+
     /// TODO
     fn reset(
         &self,
@@ -186,6 +343,60 @@ pub trait BlockDeviceHandle {
         cb_arg: IoCompletionCallbackArg,
     ) -> Result<(), CoreError>;
 
+    /// Copies blocks of the device to the device of the destination handle,
+    /// as offloaded to the devices. Only valid when `copy_supported` holds
+    /// for both devices, callers fall back to reading and writing the
+    /// blocks otherwise.
+    async fn copy_blocks(
+        &self,
+        _src_offset_blocks: u64,
+        _dst: &dyn BlockDeviceHandle,
+        _dst_offset_blocks: u64,
+        _num_blocks: u64,
+    ) -> Result<u64, CoreError> {
+        Err(CoreError::NotSupported {
+            source: Errno::EOPNOTSUPP,
+        })
+    }
+
+    // Zoned devices only.
+
+    /// Reports the zones of the device, starting with the zone of the given
+    /// id, up to `num_zones` of them.
+    async fn zone_report(
+        &self,
+        _zone_id: u64,
+        _num_zones: u64,
+    ) -> Result<Vec<ZoneInfo>, CoreError> {
+        Err(CoreError::NotSupported {
+            source: Errno::EOPNOTSUPP,
+        })
+    }
+
+    /// Appends the buffer to the zone of the given id, and returns the block
+    /// the device wrote it at.
+    async fn zone_append(
+        &self,
+        _zone_id: u64,
+        _buffer: &DmaBuf,
+    ) -> Result<u64, CoreError> {
+        Err(CoreError::NotSupported {
+            source: Errno::EOPNOTSUPP,
+        })
+    }
+
+    /// Resets the write pointer of the zone of the given id, or of all zones
+    /// of the device if `all` is set.
+    async fn zone_reset(
+        &self,
+        _zone_id: u64,
+        _all: bool,
+    ) -> Result<(), CoreError> {
+        Err(CoreError::NotSupported {
+            source: Errno::EOPNOTSUPP,
+        })
+    }
+
     // NVMe only.
 
     /// TODO
@@ -230,6 +441,18 @@ pub trait BlockDeviceHandle {
         })
     }
 
+    /// NVMe Reservation Release
+    async fn nvme_resv_release(
+        &self,
+        _current_key: u64,
+        _release_action: u8,
+        _resv_type: u8,
+    ) -> Result<(), CoreError> {
+        Err(CoreError::NotSupported {
+            source: Errno::EOPNOTSUPP,
+        })
+    }
+
     /// TODO
     async fn nvme_resv_report(
         &self,
@@ -268,6 +491,8 @@ pub trait LbaRangeController {}
 pub enum DeviceTimeoutAction {
     /// Abort I/O operation that times out.
     Abort,
+    /// Reconnect the I/O queue of the command that times out.
+    QpairReset,
     /// Reset the  whole device in case any single command times out.
     Reset,
     /// Do not take any actions on command timeout.
@@ -280,6 +505,7 @@ impl ToString for DeviceTimeoutAction {
     fn to_string(&self) -> String {
         match *self {
             Self::Abort => "Abort",
+            Self::QpairReset => "QpairReset",
             Self::Reset => "Reset",
             Self::Ignore => "Ignore",
             Self::HotRemove => "HotRemove",
@@ -288,6 +514,54 @@ impl ToString for DeviceTimeoutAction {
     }
 }
 
+/// Tiered handling of command timeouts, where each timeout is handled by
+/// the least disruptive action whose threshold is not reached yet: the
+/// command is aborted first, then its I/O queue is reset, then the whole
+/// device is reset, and the device is removed once all thresholds are
+/// reached. A threshold of zero skips the action. Timeouts are counted until
+/// no command timed out for the recovery interval.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct DeviceTimeoutPolicy {
+    /// number of timeouts handled by aborting the command
+    pub abort: u32,
+    /// number of further timeouts handled by resetting the I/O queue
+    pub qpair_reset: u32,
+    /// number of further timeouts handled by resetting the device
+    pub reset: u32,
+    /// time without timeouts after which the count starts over
+    pub recovery_interval: Duration,
+}
+
+impl Default for DeviceTimeoutPolicy {
+    fn default() -> Self {
+        Self {
+            abort: 3,
+            qpair_reset: 2,
+            reset: 1,
+            recovery_interval: Duration::from_secs(60),
+        }
+    }
+}
+
+impl DeviceTimeoutPolicy {
+    /// Returns the action for the given timeout, counting from one.
+    pub fn action(&self, timeouts: u32) -> DeviceTimeoutAction {
+        let mut limit = self.abort;
+        if timeouts <= limit {
+            return DeviceTimeoutAction::Abort;
+        }
+        limit = limit.saturating_add(self.qpair_reset);
+        if timeouts <= limit {
+            return DeviceTimeoutAction::QpairReset;
+        }
+        limit = limit.saturating_add(self.reset);
+        if timeouts <= limit {
+            return DeviceTimeoutAction::Reset;
+        }
+        DeviceTimeoutAction::HotRemove
+    }
+}
+
 /// TODO
 pub trait DeviceIoController {
     /// TODO
@@ -298,4 +572,102 @@ pub trait DeviceIoController {
         &mut self,
         action: DeviceTimeoutAction,
     ) -> Result<(), CoreError>;
+
+    /// Get the tiered timeout policy of the device, none when every timeout
+    /// is handled by the timeout action.
+    fn get_timeout_policy(
+        &self,
+    ) -> Result<Option<DeviceTimeoutPolicy>, CoreError>;
+
+    /// Set the tiered timeout policy of the device, which takes precedence
+    /// over the timeout action. None reverts to the timeout action.
+    fn set_timeout_policy(
+        &mut self,
+        policy: Option<DeviceTimeoutPolicy>,
+    ) -> Result<(), CoreError>;
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        resettable_zones,
+        DeviceTimeoutAction,
+        DeviceTimeoutPolicy,
+        ZoneInfo,
+        ZoneState,
+    };
+
+    #[test]
+    fn timeout_policy_tiers() {
+        let policy = DeviceTimeoutPolicy::default();
+        let actions: Vec<_> = (1 ..= 7).map(|t| policy.action(t)).collect();
+        assert_eq!(
+            actions,
+            vec![
+                DeviceTimeoutAction::Abort,
+                DeviceTimeoutAction::Abort,
+                DeviceTimeoutAction::Abort,
+                DeviceTimeoutAction::QpairReset,
+                DeviceTimeoutAction::QpairReset,
+                DeviceTimeoutAction::Reset,
+                DeviceTimeoutAction::HotRemove,
+            ]
+        );
+        assert_eq!(policy.action(u32::MAX), DeviceTimeoutAction::HotRemove);
+    }
+
+    #[test]
+    fn timeout_policy_empty_tiers() {
+        let policy = DeviceTimeoutPolicy {
+            abort: 0,
+            qpair_reset: 1,
+            reset: 0,
+            ..Default::default()
+        };
+        assert_eq!(policy.action(1), DeviceTimeoutAction::QpairReset);
+        assert_eq!(policy.action(2), DeviceTimeoutAction::HotRemove);
+
+        let policy = DeviceTimeoutPolicy {
+            abort: 0,
+            qpair_reset: 0,
+            reset: 0,
+            ..Default::default()
+        };
+        assert_eq!(policy.action(1), DeviceTimeoutAction::HotRemove);
+    }
+
+    #[test]
+    fn timeout_policy_saturates() {
+        let policy = DeviceTimeoutPolicy {
+            abort: u32::MAX,
+            qpair_reset: 1,
+            reset: 1,
+            ..Default::default()
+        };
+        assert_eq!(policy.action(u32::MAX), DeviceTimeoutAction::Abort);
+    }
+
+    #[test]
+    fn resettable_zones_of_report() {
+        let states = [
+            ZoneState::Empty,
+            ZoneState::ImplicitOpen,
+            ZoneState::ExplicitOpen,
+            ZoneState::Closed,
+            ZoneState::ReadOnly,
+            ZoneState::Full,
+            ZoneState::Offline,
+        ];
+        let zones: Vec<_> = states
+            .iter()
+            .enumerate()
+            .map(|(i, state)| ZoneInfo {
+                zone_id: i as u64 * 1024,
+                write_pointer: i as u64 * 1024,
+                capacity: 1024,
+                state: *state,
+            })
+            .collect();
+        assert_eq!(resettable_zones(&zones), vec![1024, 2048, 3072, 5120]);
+    }
 }
@@ -113,6 +113,9 @@ pub struct MayastorCliArgs {
     #[structopt(short = "P")]
     /// Path to pool config file.
     pub pool_config: Option<String>,
+    #[structopt(long = "discover-pools")]
+    /// Import the pools found on the local block devices at startup.
+    pub discover_pools: bool,
     #[structopt(long = "huge-dir")]
     /// Path to hugedir.
     pub hugedir: Option<String>,
@@ -167,6 +170,7 @@ impl Default for MayastorCliArgs {
             log_components: vec![],
             mayastor_config: None,
             pool_config: None,
+            discover_pools: false,
             hugedir: None,
             core_list: None,
             bdev_io_ctx_pool_size: 65535,
@@ -227,6 +231,7 @@ pub struct MayastorEnvironment {
     persistent_store_endpoint: Option<String>,
     mayastor_config: Option<String>,
     pool_config: Option<String>,
+    discover_pools: bool,
     delay_subsystem_init: bool,
     enable_coredump: bool,
     env_context: Option<String>,
@@ -265,6 +270,7 @@ impl Default for MayastorEnvironment {
             persistent_store_endpoint: None,
             mayastor_config: None,
             pool_config: None,
+            discover_pools: false,
             delay_subsystem_init: false,
             enable_coredump: true,
             env_context: None,
@@ -376,6 +382,7 @@ impl MayastorEnvironment {
             node_name: args.node_name.unwrap_or_else(|| "mayastor-node".into()),
             mayastor_config: args.mayastor_config,
             pool_config: args.pool_config,
+            discover_pools: args.discover_pools,
             log_component: args.log_components,
             mem_size: args.mem_size,
             no_pci: args.no_pci,
@@ -701,6 +708,12 @@ impl MayastorEnvironment {
             config.import_pools();
         }
 
+        // import any other pools found on the local block devices, pools
+        // from the config file take precedence in case of a name conflict
+        if self.discover_pools {
+            PoolConfig::discover_pools();
+        }
+
         self
     }
 
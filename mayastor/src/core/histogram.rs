@@ -0,0 +1,227 @@
+//! Latency histograms as collected by the SPDK bdev layer.
+//!
+//! SPDK only provides the histogram helpers as static inline functions which
+//! are not part of the generated bindings, so the bucket layout is mirrored
+//! here. A histogram has a number of ranges, each range being twice the size
+//! of the previous one and divided into the same number of buckets.
+
+use std::os::raw::c_void;
+
+use futures::channel::oneshot;
+use nix::errno::Errno;
+use serde::Serialize;
+use spdk_rs::libspdk::{
+    spdk_bdev_histogram_enable,
+    spdk_bdev_histogram_get,
+    spdk_get_ticks_hz,
+    spdk_histogram_data,
+};
+
+use crate::{
+    core::{Bdev, CoreError},
+    ffihelper::{cb_arg, pair, FfiResult},
+};
+
+/// bucket shift as used by the bdev layer for the per channel histograms,
+/// merging requires both histograms to have the same shift
+const BUCKET_SHIFT_DEFAULT: u32 = 7;
+
+/// Latency histogram of a bdev, values are in ticks.
+#[derive(Debug, Clone)]
+pub struct LatencyHistogram {
+    bucket_shift: u32,
+    buckets: Vec<u64>,
+}
+
+/// Latency summary of a histogram, in microseconds.
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+pub struct LatencyPercentiles {
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+    pub p999: u64,
+    pub max: u64,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self::new(BUCKET_SHIFT_DEFAULT)
+    }
+}
+
+impl LatencyHistogram {
+    fn new(bucket_shift: u32) -> Self {
+        let per_range = 1usize << bucket_shift;
+        let ranges = (64 - bucket_shift + 1) as usize;
+        Self {
+            bucket_shift,
+            buckets: vec![0; per_range * ranges],
+        }
+    }
+
+    fn buckets_per_range(&self) -> usize {
+        1 << self.bucket_shift
+    }
+
+    /// first value (exclusive upper bound) of the bucket at range/index
+    fn bucket_end(&self, range: u32, index: u32) -> u64 {
+        let index = u64::from(index) + 1;
+        if range > 0 {
+            (1u64 << (range + self.bucket_shift - 1))
+                .saturating_add(index << (range - 1))
+        } else {
+            index
+        }
+    }
+
+    /// total number of I/Os accounted in this histogram
+    pub fn count(&self) -> u64 {
+        self.buckets.iter().sum()
+    }
+
+    /// add the values of another histogram to this one
+    pub fn merge(&mut self, other: &LatencyHistogram) {
+        assert_eq!(self.bucket_shift, other.bucket_shift);
+        self.buckets
+            .iter_mut()
+            .zip(other.buckets.iter())
+            .for_each(|(a, b)| *a = a.saturating_add(*b));
+    }
+
+    /// returns the upper bound, in ticks, of the bucket which contains the
+    /// given percentile of all I/Os
+    pub fn percentile_ticks(&self, percentile: f64) -> u64 {
+        let total = self.count();
+        if total == 0 {
+            return 0;
+        }
+
+        let target = ((total as f64) * percentile / 100.0).ceil() as u64;
+        let per_range = self.buckets_per_range();
+        let mut so_far = 0;
+
+        for (i, count) in self.buckets.iter().enumerate() {
+            so_far += count;
+            if *count > 0 && so_far >= target {
+                return self.bucket_end(
+                    (i / per_range) as u32,
+                    (i % per_range) as u32,
+                );
+            }
+        }
+        0
+    }
+
+    /// returns the upper bound, in ticks, of the highest non-empty bucket
+    pub fn max_ticks(&self) -> u64 {
+        let per_range = self.buckets_per_range();
+        self.buckets
+            .iter()
+            .rposition(|c| *c > 0)
+            .map(|i| {
+                self.bucket_end((i / per_range) as u32, (i % per_range) as u32)
+            })
+            .unwrap_or(0)
+    }
+
+    /// summarize the histogram into the commonly used percentiles
+    pub fn percentiles(&self) -> LatencyPercentiles {
+        LatencyPercentiles {
+            p50: ticks_to_us(self.percentile_ticks(50.0)),
+            p90: ticks_to_us(self.percentile_ticks(90.0)),
+            p99: ticks_to_us(self.percentile_ticks(99.0)),
+            p999: ticks_to_us(self.percentile_ticks(99.9)),
+            max: ticks_to_us(self.max_ticks()),
+        }
+    }
+}
+
+/// converts ticks as returned by the bdev layer into microseconds
+pub fn ticks_to_us(ticks: u64) -> u64 {
+    let hz = unsafe { spdk_get_ticks_hz() };
+    if hz == 0 {
+        return 0;
+    }
+    ((ticks as u128) * 1_000_000 / (hz as u128)) as u64
+}
+
+impl<T: spdk_rs::BdevOps> Bdev<T> {
+    /// returns true if latency histograms are collected for this bdev
+    pub fn histogram_enabled(&mut self) -> bool {
+        unsafe { (*self.unsafe_inner_mut_ptr()).internal.histogram_enabled }
+    }
+
+    /// enable or disable the collection of latency histograms, disabling
+    /// discards the collected data
+    pub async fn enable_histogram(
+        &mut self,
+        enable: bool,
+    ) -> Result<(), CoreError> {
+        extern "C" fn histogram_enable_cb(arg: *mut c_void, status: i32) {
+            let sender =
+                unsafe { Box::from_raw(arg as *mut oneshot::Sender<i32>) };
+            sender.send(status).expect("receiver gone");
+        }
+
+        let (s, r) = pair::<i32>();
+        unsafe {
+            spdk_bdev_histogram_enable(
+                self.unsafe_inner_mut_ptr(),
+                Some(histogram_enable_cb),
+                cb_arg(s),
+                enable,
+            )
+        };
+
+        r.await
+            .expect("histogram enable callback is gone")
+            .to_result(|e| CoreError::DeviceStatisticsError {
+                source: Errno::from_i32(e.abs()),
+            })
+    }
+
+    /// returns the latency histogram merged across all channels of the bdev
+    pub async fn latency_histogram(
+        &mut self,
+    ) -> Result<LatencyHistogram, CoreError> {
+        extern "C" fn histogram_get_cb(
+            arg: *mut c_void,
+            status: i32,
+            _histogram: *mut spdk_histogram_data,
+        ) {
+            let sender =
+                unsafe { Box::from_raw(arg as *mut oneshot::Sender<i32>) };
+            sender.send(status).expect("receiver gone");
+        }
+
+        if !self.histogram_enabled() {
+            return Err(CoreError::DeviceStatisticsError {
+                source: Errno::ENODATA,
+            });
+        }
+
+        let mut histogram = LatencyHistogram::default();
+        let mut data = spdk_histogram_data {
+            bucket_shift: histogram.bucket_shift,
+            bucket: histogram.buckets.as_mut_ptr(),
+        };
+
+        let (s, r) = pair::<i32>();
+        unsafe {
+            spdk_bdev_histogram_get(
+                self.unsafe_inner_mut_ptr(),
+                &mut data,
+                Some(histogram_get_cb),
+                cb_arg(s),
+            )
+        };
+
+        r.await
+            .expect("histogram get callback is gone")
+            .to_result(|e| CoreError::DeviceStatisticsError {
+                source: Errno::from_i32(e.abs()),
+            })?;
+
+        Ok(histogram)
+    }
+}
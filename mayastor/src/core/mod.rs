@@ -5,7 +5,8 @@ use std::{fmt::Debug, sync::atomic::AtomicUsize, time::Duration};
 use nix::errno::Errno;
 use snafu::Snafu;
 
-pub use bdev::{Bdev, BdevIter, UntypedBdev};
+pub use bdev::{Bdev, BdevIter, RateLimits, UntypedBdev};
+pub(crate) use block_device::resettable_zones;
 pub use block_device::{
     BlockDevice,
     BlockDeviceDescriptor,
@@ -13,11 +14,15 @@ pub use block_device::{
     BlockDeviceIoStats,
     DeviceIoController,
     DeviceTimeoutAction,
+    DeviceTimeoutPolicy,
     IoCompletionCallback,
     IoCompletionCallbackArg,
     LbaRangeController,
     OpCompletionCallback,
     OpCompletionCallbackArg,
+    ZoneGeometry,
+    ZoneInfo,
+    ZoneState,
 };
 pub use channel::IoChannel;
 pub use cpu_cores::{Core, Cores};
@@ -36,10 +41,20 @@ pub use env::{
     SIG_RECEIVED,
 };
 pub use handle::BdevHandle;
+pub use histogram::{ticks_to_us, LatencyHistogram, LatencyPercentiles};
 pub use io_device::IoDevice;
 pub use reactor::{Reactor, ReactorState, Reactors, REACTOR_LIST};
+pub use reservation::{
+    clear_reservations,
+    preempt_reservation,
+    register_key,
+    reservation_report,
+    unregister_key,
+    Registrant,
+    ReservationReport,
+};
 pub use runtime::spawn;
-pub use share::{Protocol, Share};
+pub use share::{NvmfShareProps, Protocol, Share};
 pub use spdk_rs::{
     cpu_cores,
     GenericStatusCode,
@@ -59,12 +74,14 @@ mod descriptor;
 mod device_events;
 mod env;
 mod handle;
+mod histogram;
 mod io_device;
 pub mod io_driver;
 pub mod mempool;
 pub mod partition;
 pub mod poller;
 mod reactor;
+mod reservation;
 pub mod runtime;
 mod share;
 pub(crate) mod thread;
@@ -144,6 +161,53 @@ pub enum CoreError {
         offset: u64,
         len: u64,
     },
+    #[snafu(display(
+        "Failed to dispatch compare at offset {} length {}",
+        offset,
+        len
+    ))]
+    CompareDispatch {
+        source: Errno,
+        offset: u64,
+        len: u64,
+    },
+    #[snafu(display(
+        "Failed to dispatch copy at offset {} length {}",
+        offset,
+        len
+    ))]
+    CopyDispatch {
+        source: Errno,
+        offset: u64,
+        len: u64,
+    },
+    #[snafu(display(
+        "Failed to dispatch zone report from zone {}: {}",
+        zone_id,
+        source
+    ))]
+    ZoneReportDispatch {
+        source: Errno,
+        zone_id: u64,
+    },
+    #[snafu(display(
+        "Failed to dispatch zone append to zone {}: {}",
+        zone_id,
+        source
+    ))]
+    ZoneAppendDispatch {
+        source: Errno,
+        zone_id: u64,
+    },
+    #[snafu(display(
+        "Failed to dispatch zone reset of zone {}: {}",
+        zone_id,
+        source
+    ))]
+    ZoneResetDispatch {
+        source: Errno,
+        zone_id: u64,
+    },
     #[snafu(display(
         "Failed to dispatch NVMe IO passthru command {:x}h: {}",
         opcode,
@@ -174,6 +238,45 @@ pub enum CoreError {
         offset: u64,
         len: u64,
     },
+    #[snafu(display("Copy failed at offset {} length {}", offset, len))]
+    CopyFailed {
+        offset: u64,
+        len: u64,
+    },
+    #[snafu(display(
+        "Failed to lock LBA range at offset {} length {}: {}",
+        offset,
+        len,
+        source
+    ))]
+    LockLbaRange {
+        source: Errno,
+        offset: u64,
+        len: u64,
+    },
+    #[snafu(display(
+        "Failed to unlock LBA range at offset {} length {}: {}",
+        offset,
+        len,
+        source
+    ))]
+    UnlockLbaRange {
+        source: Errno,
+        offset: u64,
+        len: u64,
+    },
+    #[snafu(display("Zone report from zone {} failed", zone_id))]
+    ZoneReportFailed {
+        zone_id: u64,
+    },
+    #[snafu(display("Zone append to zone {} failed", zone_id))]
+    ZoneAppendFailed {
+        zone_id: u64,
+    },
+    #[snafu(display("Zone reset of zone {} failed", zone_id))]
+    ZoneResetFailed {
+        zone_id: u64,
+    },
     #[snafu(display("NVMe Admin command {:x}h failed", opcode))]
     NvmeAdminFailed {
         opcode: u16,
@@ -190,6 +293,11 @@ pub enum CoreError {
     UnshareNvmf {
         source: NvmfError,
     },
+    #[snafu(display("failed to update the allowed hosts of {}", name))]
+    UpdateAllowedHosts {
+        source: NvmfError,
+        name: String,
+    },
     #[snafu(display("the operation is invalid for this bdev: {}", source))]
     NotSupported {
         source: Errno,
@@ -208,6 +316,10 @@ pub enum CoreError {
     },
     #[snafu(display("No devices available for I/O"))]
     NoDevicesAvailable {},
+    #[snafu(display("Failed to set QoS rate limits: {}", source))]
+    RateLimits {
+        source: Errno,
+    },
 }
 
 // Generic I/O completion status for block devices, which supports per-protocol
@@ -216,6 +328,8 @@ pub enum CoreError {
 pub enum IoCompletionStatus {
     Success,
     NvmeError(NvmeCommandStatus),
+    /// the data of a compare did not match the data of the device
+    Miscompare,
 }
 
 // TODO move this elsewhere ASAP
@@ -0,0 +1,169 @@
+//! NVMe reservations of the namespace behind a block device handle.
+
+use std::mem::size_of;
+
+use spdk_rs::{
+    libspdk::{
+        spdk_nvme_registered_ctrlr_extended_data,
+        spdk_nvme_reservation_status_extended_data,
+    },
+    nvme_reservation_acquire_action,
+    nvme_reservation_register_action,
+    nvme_reservation_register_cptpl,
+    nvme_reservation_release_action,
+};
+
+use crate::core::{BlockDeviceHandle, CoreError};
+
+/// size of the buffer the reservation report is read into
+const REPORT_BUFFER_SIZE: u64 = 4096;
+
+/// A host registered with the namespace
+#[derive(Debug, Clone, PartialEq)]
+pub struct Registrant {
+    /// controller ID the host is connected through, 0xffff when the host is
+    /// registered but not connected
+    pub cntlid: u16,
+    /// host identifier
+    pub host_id: [u8; 16],
+    /// reservation key of the host
+    pub key: u64,
+    /// whether the host holds the reservation
+    pub holder: bool,
+}
+
+/// The reservation status of a namespace
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReservationReport {
+    /// generation, incremented on every registration change
+    pub generation: u32,
+    /// reservation type, 0 when the namespace is not reserved
+    pub rtype: u8,
+    /// whether the reservation persists through power loss
+    pub ptpl: bool,
+    /// registered hosts
+    pub registrants: Vec<Registrant>,
+}
+
+impl ReservationReport {
+    /// the registrant holding the reservation, for reservation types with
+    /// a single holder
+    pub fn holder(&self) -> Option<&Registrant> {
+        self.registrants.iter().find(|r| r.holder)
+    }
+}
+
+/// Get the reservation report of the namespace.
+pub async fn reservation_report(
+    hdl: &dyn BlockDeviceHandle,
+) -> Result<ReservationReport, CoreError> {
+    let mut buffer = hdl.dma_malloc(REPORT_BUFFER_SIZE).map_err(|_| {
+        CoreError::DmaAllocationError {
+            size: REPORT_BUFFER_SIZE,
+        }
+    })?;
+    // request the extended data structure, which holds 128 bit host IDs
+    hdl.nvme_resv_report(1, &mut buffer).await?;
+
+    let (stext, sl) = buffer
+        .as_slice()
+        .split_at(size_of::<spdk_nvme_reservation_status_extended_data>());
+    let (pre, status, post) = unsafe {
+        stext.align_to::<spdk_nvme_reservation_status_extended_data>()
+    };
+    assert!(pre.is_empty());
+    assert!(post.is_empty());
+
+    let mut report = ReservationReport {
+        generation: status[0].data.gen,
+        rtype: status[0].data.rtype,
+        ptpl: status[0].data.ptpls != 0,
+        registrants: Vec::new(),
+    };
+
+    let (pre, ctrlrs, _post) =
+        unsafe { sl.align_to::<spdk_nvme_registered_ctrlr_extended_data>() };
+    if !pre.is_empty() {
+        return Ok(report);
+    }
+
+    let regctl: usize = status[0].data.regctl.into();
+    if regctl > ctrlrs.len() {
+        warn!(
+            "Expecting data for {} controllers, received {}",
+            regctl,
+            ctrlrs.len()
+        );
+    }
+    report.registrants = ctrlrs
+        .iter()
+        .take(regctl)
+        .map(|c| Registrant {
+            cntlid: c.cntlid,
+            host_id: c.hostid,
+            key: c.rkey,
+            holder: c.rcsts.status() == 1,
+        })
+        .collect();
+
+    Ok(report)
+}
+
+/// Register the given key for the host, which succeeds as well when the host
+/// is already registered with it.
+pub async fn register_key(
+    hdl: &dyn BlockDeviceHandle,
+    key: u64,
+) -> Result<(), CoreError> {
+    hdl.nvme_resv_register(
+        0,
+        key,
+        nvme_reservation_register_action::REGISTER_KEY,
+        nvme_reservation_register_cptpl::NO_CHANGES,
+    )
+    .await
+}
+
+/// Remove the registration of the host.
+pub async fn unregister_key(
+    hdl: &dyn BlockDeviceHandle,
+    key: u64,
+) -> Result<(), CoreError> {
+    hdl.nvme_resv_register(
+        key,
+        0,
+        nvme_reservation_register_action::UNREGISTER_KEY,
+        nvme_reservation_register_cptpl::NO_CHANGES,
+    )
+    .await
+}
+
+/// Remove the registration of the hosts registered with preempt_key, and
+/// take over their reservation with the given type. The host is registered
+/// with key first.
+pub async fn preempt_reservation(
+    hdl: &dyn BlockDeviceHandle,
+    key: u64,
+    preempt_key: u64,
+    rtype: u8,
+) -> Result<(), CoreError> {
+    register_key(hdl, key).await?;
+    hdl.nvme_resv_acquire(
+        key,
+        preempt_key,
+        nvme_reservation_acquire_action::PREEMPT,
+        rtype,
+    )
+    .await
+}
+
+/// Release the reservation and remove the registrations of all hosts. The
+/// host is registered with key first.
+pub async fn clear_reservations(
+    hdl: &dyn BlockDeviceHandle,
+    key: u64,
+) -> Result<(), CoreError> {
+    register_key(hdl, key).await?;
+    hdl.nvme_resv_release(key, nvme_reservation_release_action::CLEAR, 0)
+        .await
+}
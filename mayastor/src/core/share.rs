@@ -39,13 +39,103 @@ impl Display for Protocol {
     }
 }
 
+/// Properties of an NVMe-oF share
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct NvmfShareProps {
+    /// range of controller IDs handed out to connecting hosts
+    cntlid_range: Option<(u16, u16)>,
+    /// NQNs of the hosts allowed to connect, no host when empty unless any
+    /// host is allowed
+    allowed_hosts: Vec<String>,
+    /// allow any host to connect, regardless of the allowed hosts
+    allow_any_host: bool,
+    /// report the ANA state of the namespace to hosts
+    ana_reporting: bool,
+    /// ANA group of the namespace, the target picks one when None
+    ana_group: Option<u32>,
+    /// NQN to share under instead of the one derived from the name
+    nqn: Option<String>,
+    /// file to persist the reservations of hosts in
+    ptpl_file: Option<String>,
+}
+
+impl NvmfShareProps {
+    /// create share properties with the default settings
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// set the controller ID range
+    pub fn with_range(mut self, range: Option<(u16, u16)>) -> Self {
+        self.cntlid_range = range;
+        self
+    }
+    /// set the NQNs of the hosts allowed to connect
+    pub fn with_allowed_hosts<H, S>(mut self, hosts: H) -> Self
+    where
+        H: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.allowed_hosts = hosts.into_iter().map(Into::into).collect();
+        self
+    }
+    /// allow any host to connect
+    pub fn with_allow_any_host(mut self, enable: bool) -> Self {
+        self.allow_any_host = enable;
+        self
+    }
+    /// enable ANA reporting, with the namespace in the given ANA group
+    pub fn with_ana(mut self, reporting: bool, group: Option<u32>) -> Self {
+        self.ana_reporting = reporting;
+        self.ana_group = group;
+        self
+    }
+    /// set the NQN to share under
+    pub fn with_nqn(mut self, nqn: Option<String>) -> Self {
+        self.nqn = nqn;
+        self
+    }
+    /// set the file to persist the reservations of hosts in
+    pub fn with_ptpl_file(mut self, file: Option<String>) -> Self {
+        self.ptpl_file = file;
+        self
+    }
+    /// get the controller ID range
+    pub fn cntlid_range(&self) -> Option<(u16, u16)> {
+        self.cntlid_range
+    }
+    /// get the NQNs of the hosts allowed to connect
+    pub fn allowed_hosts(&self) -> &Vec<String> {
+        &self.allowed_hosts
+    }
+    /// get whether any host may connect
+    pub fn allow_any_host(&self) -> bool {
+        self.allow_any_host
+    }
+    /// get whether ANA reporting is enabled
+    pub fn ana_reporting(&self) -> bool {
+        self.ana_reporting
+    }
+    /// get the ANA group of the namespace
+    pub fn ana_group(&self) -> Option<u32> {
+        self.ana_group
+    }
+    /// get the NQN to share under
+    pub fn nqn(&self) -> Option<&str> {
+        self.nqn.as_deref()
+    }
+    /// get the file to persist the reservations of hosts in
+    pub fn ptpl_file(&self) -> Option<&str> {
+        self.ptpl_file.as_deref()
+    }
+}
+
 #[async_trait(? Send)]
 pub trait Share: std::fmt::Debug {
     type Error;
     type Output: std::fmt::Display + std::fmt::Debug;
     async fn share_nvmf(
         self: Pin<&mut Self>,
-        cntlid_range: Option<(u16, u16)>,
+        props: Option<NvmfShareProps>,
     ) -> Result<Self::Output, Self::Error>;
 
     /// TODO
@@ -55,9 +145,17 @@ pub trait Share: std::fmt::Debug {
     /// TODO
     fn shared(&self) -> Option<Protocol>;
 
+    /// returns the NQNs of the hosts allowed to connect to the share, an
+    /// empty list means any host is allowed
+    fn allowed_hosts(&self) -> Vec<String>;
+
     /// TODO
     fn share_uri(&self) -> Option<String>;
 
+    /// returns the URIs of all endpoints the share can be reached through,
+    /// one for each address the target listens on
+    fn share_uris(&self) -> Vec<String>;
+
     /// TODO
     fn bdev_uri(&self) -> Option<String>;
 
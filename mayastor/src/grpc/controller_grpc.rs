@@ -1,6 +1,17 @@
 use crate::{
-    bdev::{NvmeController, NvmeControllerState, NVME_CONTROLLERS},
-    core::{BlockDeviceIoStats, CoreError},
+    bdev::{
+        NvmeController,
+        NvmeControllerHealthStats,
+        NvmeControllerState,
+        NvmeQpairStats,
+        NVME_CONTROLLERS,
+    },
+    core::{
+        BlockDeviceIoStats,
+        CoreError,
+        DeviceIoController,
+        DeviceTimeoutPolicy,
+    },
     ffihelper::{cb_arg, done_cb},
 };
 use futures::channel::oneshot;
@@ -11,6 +22,7 @@ pub struct NvmeControllerInfo {
     pub state: NvmeControllerState,
     pub size: u64,
     pub blk_size: u32,
+    pub timeout_policy: Option<DeviceTimeoutPolicy>,
 }
 
 impl<'a> NvmeController<'a> {
@@ -24,6 +36,7 @@ impl<'a> NvmeController<'a> {
             state: self.get_state(),
             size,
             blk_size,
+            timeout_policy: self.get_timeout_policy().unwrap_or_default(),
         }
     }
 }
@@ -58,6 +71,64 @@ pub async fn controller_stats(
     }
 }
 
+/// Returns the statistics of the qpairs of the given NVMe Controller, one per
+/// core
+pub async fn controller_qpair_stats(
+    controller_name: &str,
+) -> Result<Vec<NvmeQpairStats>, CoreError> {
+    if let Some(ctrlr) = NVME_CONTROLLERS.lookup_by_name(controller_name) {
+        let (s, r) =
+            oneshot::channel::<Result<Vec<NvmeQpairStats>, CoreError>>();
+        {
+            let ctrlr = ctrlr.lock();
+            if let Err(e) = ctrlr.get_qpair_stats(
+                |stats, ch| {
+                    done_cb(ch, stats);
+                },
+                cb_arg(s),
+            ) {
+                error!(
+                    "{}: failed to get qpair stats for NVMe controller: {:?}",
+                    controller_name, e
+                );
+                return Err(e);
+            }
+        }
+        r.await.expect("Failed awaiting at qpair_stats")
+    } else {
+        Err(CoreError::BdevNotFound {
+            name: controller_name.to_string(),
+        })
+    }
+}
+
+/// Returns the reset, timeout and keep alive failure counts of the given NVMe
+/// Controller
+pub fn controller_health_stats(
+    controller_name: &str,
+) -> Result<NvmeControllerHealthStats, CoreError> {
+    NVME_CONTROLLERS
+        .lookup_by_name(controller_name)
+        .map(|c| c.lock().get_health_stats())
+        .ok_or_else(|| CoreError::BdevNotFound {
+            name: controller_name.to_string(),
+        })
+}
+
+/// Sets the tiered timeout policy of the given NVMe Controller, none reverts
+/// to its timeout action
+pub fn set_controller_timeout_policy(
+    controller_name: &str,
+    policy: Option<DeviceTimeoutPolicy>,
+) -> Result<(), CoreError> {
+    NVME_CONTROLLERS
+        .lookup_by_name(controller_name)
+        .ok_or_else(|| CoreError::BdevNotFound {
+            name: controller_name.to_string(),
+        })
+        .and_then(|c| c.lock().set_timeout_policy(policy))
+}
+
 /// Lists all the NVMe Controllers
 pub async fn list_controllers() -> Vec<NvmeControllerInfo> {
     NVME_CONTROLLERS
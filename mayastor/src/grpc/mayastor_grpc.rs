@@ -9,19 +9,29 @@
 //! without the need for setting up a grpc client.
 
 use crate::{
-    bdev::{nexus, NvmeControllerState as ControllerState},
+    bdev::{
+        nexus,
+        NvmeControllerHealthStats as ControllerHealthStats,
+        NvmeControllerState as ControllerState,
+        NvmeQpairStats as QpairStats,
+    },
     core::{
         BlockDeviceIoStats,
         CoreError,
+        DeviceTimeoutPolicy,
         MayastorFeatures,
         Protocol,
+        RateLimits,
         Share,
         UntypedBdev,
     },
     grpc::{
         controller_grpc::{
+            controller_health_stats,
+            controller_qpair_stats,
             controller_stats,
             list_controllers,
+            set_controller_timeout_policy,
             NvmeControllerInfo,
         },
         nexus_grpc::{
@@ -211,6 +221,17 @@ impl From<BlockDeviceIoStats> for Stats {
     }
 }
 
+impl From<ReplicaRateLimits> for RateLimits {
+    fn from(l: ReplicaRateLimits) -> Self {
+        Self {
+            rw_ios_per_sec: l.rw_ios_per_sec,
+            rw_mbytes_per_sec: l.rw_mbytes_per_sec,
+            r_mbytes_per_sec: l.r_mbytes_per_sec,
+            w_mbytes_per_sec: l.w_mbytes_per_sec,
+        }
+    }
+}
+
 impl From<Lvol> for Replica {
     fn from(l: Lvol) -> Self {
         Self {
@@ -336,6 +357,31 @@ impl From<NvmeControllerInfo> for NvmeController {
             state: NvmeControllerState::from(n.state) as i32,
             size: n.size,
             blk_size: n.blk_size,
+            timeout_policy: n
+                .timeout_policy
+                .map(NvmeControllerTimeoutPolicy::from),
+        }
+    }
+}
+
+impl From<DeviceTimeoutPolicy> for NvmeControllerTimeoutPolicy {
+    fn from(p: DeviceTimeoutPolicy) -> Self {
+        Self {
+            abort: p.abort,
+            qpair_reset: p.qpair_reset,
+            reset: p.reset,
+            recovery_interval_secs: p.recovery_interval.as_secs(),
+        }
+    }
+}
+
+impl From<NvmeControllerTimeoutPolicy> for DeviceTimeoutPolicy {
+    fn from(p: NvmeControllerTimeoutPolicy) -> Self {
+        Self {
+            abort: p.abort,
+            qpair_reset: p.qpair_reset,
+            reset: p.reset,
+            recovery_interval: Duration::from_secs(p.recovery_interval_secs),
         }
     }
 }
@@ -368,6 +414,36 @@ impl From<BlockDeviceIoStats> for NvmeControllerIoStats {
     }
 }
 
+impl From<QpairStats> for NvmeQpairStats {
+    fn from(q: QpairStats) -> Self {
+        Self {
+            core: q.core,
+            connected: q.connected,
+            outstanding_io: q.outstanding_io,
+            queued_io: q.queued_io,
+            resets: q.resets,
+            failed_io: q.failed_io,
+            aborted_io: q.aborted_io,
+            read_latency_us: q.io_stats.avg_read_latency_us(),
+            write_latency_us: q.io_stats.avg_write_latency_us(),
+            unmap_latency_us: q.io_stats.avg_unmap_latency_us(),
+            stats: Some(NvmeControllerIoStats::from(q.io_stats)),
+        }
+    }
+}
+
+impl From<ControllerHealthStats> for NvmeControllerHealthStats {
+    fn from(h: ControllerHealthStats) -> Self {
+        Self {
+            resets: h.resets,
+            failed_resets: h.failed_resets,
+            timeouts: h.timeouts,
+            aborted_commands: h.aborted_commands,
+            adminq_failures: h.adminq_failures,
+        }
+    }
+}
+
 #[tonic::async_trait]
 impl mayastor_server::Mayastor for MayastorSvc {
     #[named]
@@ -725,6 +801,13 @@ impl mayastor_server::Mayastor for MayastorSvc {
                         Some(bdev) => {
                             let mut lvol = Lvol::try_from(bdev)?;
 
+                            // rate limits can be changed on a shared replica
+                            if let Some(limits) = args.rate_limits {
+                                Pin::new(&mut lvol)
+                                    .set_rate_limits(limits.into())
+                                    .await?;
+                            }
+
                             // if we are already shared ...
                             if lvol.shared()
                                 == Some(Protocol::try_from(args.share)?)
@@ -1383,6 +1466,33 @@ impl mayastor_server::Mayastor for MayastorSvc {
         .await
     }
 
+    #[named]
+    async fn set_nvme_controller_timeout_policy(
+        &self,
+        request: Request<SetNvmeControllerTimeoutPolicyRequest>,
+    ) -> GrpcResult<Null> {
+        self.locked(
+            GrpcClientContext::new(&request, function_name!()),
+            async move {
+                let args = request.into_inner();
+                info!("{:?}", args);
+                let rx = rpc_submit::<_, _, CoreError>(async move {
+                    set_controller_timeout_policy(
+                        &args.name,
+                        args.policy.map(DeviceTimeoutPolicy::from),
+                    )?;
+                    Ok(Null {})
+                })?;
+
+                rx.await
+                    .map_err(|_| Status::cancelled("cancelled"))?
+                    .map_err(Status::from)
+                    .map(Response::new)
+            },
+        )
+        .await
+    }
+
     #[named]
     async fn stat_nvme_controllers(
         &self,
@@ -1397,11 +1507,22 @@ impl mayastor_server::Mayastor for MayastorSvc {
                     for ctrl in ctrls {
                         let stats = controller_stats(&ctrl.name).await;
                         if stats.is_ok() {
+                            let qpairs = controller_qpair_stats(&ctrl.name)
+                                .await
+                                .unwrap_or_default();
+                            let health = controller_health_stats(&ctrl.name)
+                                .ok()
+                                .map(NvmeControllerHealthStats::from);
                             res.push(NvmeControllerStats {
                                 name: ctrl.name,
                                 stats: stats
                                     .ok()
                                     .map(NvmeControllerIoStats::from),
+                                qpairs: qpairs
+                                    .into_iter()
+                                    .map(NvmeQpairStats::from)
+                                    .collect(),
+                                health,
                             });
                         }
                     }
@@ -47,6 +47,7 @@ pub mod v1 {
     pub mod nexus;
     pub mod pool;
     pub mod replica;
+    mod stats;
 }
 
 #[derive(Debug)]
@@ -121,12 +122,16 @@ pub fn default_endpoint() -> std::net::SocketAddr {
 }
 
 /// If endpoint is missing a port number then add the default one.
+/// IPv6 addresses with a port must be enclosed in brackets.
 pub fn endpoint(endpoint: String) -> std::net::SocketAddr {
-    (if endpoint.contains(':') {
-        endpoint
-    } else {
-        format!("{}:{}", endpoint, default_port())
-    })
-    .parse()
-    .expect("Invalid gRPC endpoint")
+    if let Ok(address) = endpoint.parse::<std::net::SocketAddr>() {
+        return address;
+    }
+
+    endpoint
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse::<std::net::IpAddr>()
+        .map(|ip| std::net::SocketAddr::new(ip, default_port()))
+        .expect("Invalid gRPC endpoint")
 }
@@ -1,8 +1,16 @@
 use crate::{
-    bdev::{nexus, NvmeControllerState},
+    bdev::{
+        nexus,
+        nvmx,
+        NvmeControllerHealthStats,
+        NvmeControllerState,
+        NvmeQpairStats,
+    },
     core::{BlockDeviceIoStats, CoreError, MayastorFeatures},
     grpc::{
         controller_grpc::{
+            controller_health_stats,
+            controller_qpair_stats,
             controller_stats,
             list_controllers,
             NvmeControllerInfo,
@@ -200,6 +208,36 @@ impl From<BlockDeviceIoStats> for rpc::NvmeControllerIoStats {
     }
 }
 
+impl From<NvmeQpairStats> for rpc::NvmeQpairStats {
+    fn from(q: NvmeQpairStats) -> Self {
+        Self {
+            core: q.core,
+            connected: q.connected,
+            outstanding_io: q.outstanding_io,
+            queued_io: q.queued_io,
+            resets: q.resets,
+            failed_io: q.failed_io,
+            aborted_io: q.aborted_io,
+            read_latency_us: q.io_stats.avg_read_latency_us(),
+            write_latency_us: q.io_stats.avg_write_latency_us(),
+            unmap_latency_us: q.io_stats.avg_unmap_latency_us(),
+            stats: Some(rpc::NvmeControllerIoStats::from(q.io_stats)),
+        }
+    }
+}
+
+impl From<NvmeControllerHealthStats> for rpc::NvmeControllerHealthStats {
+    fn from(h: NvmeControllerHealthStats) -> Self {
+        Self {
+            resets: h.resets,
+            failed_resets: h.failed_resets,
+            timeouts: h.timeouts,
+            aborted_commands: h.aborted_commands,
+            adminq_failures: h.adminq_failures,
+        }
+    }
+}
+
 #[tonic::async_trait]
 impl rpc::HostRpc for HostService {
     async fn get_mayastor_info(
@@ -215,6 +253,7 @@ impl rpc::HostRpc for HostService {
             )
             .to_string(),
             supported_features: Some(features),
+            nvme_host_nqn: nvmx::host_nqn().unwrap_or_default(),
         };
 
         Ok(Response::new(response))
@@ -287,14 +326,19 @@ impl rpc::HostRpc for HostService {
             async move {
                 let args = request.into_inner();
                 let rx = rpc_submit::<_, _, CoreError>(async move {
-                    controller_stats(&args.name)
-                        .await
-                        .map(|blk_stat| {
-                            Some(rpc::NvmeControllerIoStats::from(blk_stat))
-                        })
-                        .map(|ctrl_stat| rpc::StatNvmeControllerResponse {
-                            stats: ctrl_stat,
-                        })
+                    let stats = controller_stats(&args.name).await?;
+                    let qpairs = controller_qpair_stats(&args.name).await?;
+                    let health = controller_health_stats(&args.name)?;
+                    Ok(rpc::StatNvmeControllerResponse {
+                        stats: Some(rpc::NvmeControllerIoStats::from(stats)),
+                        qpairs: qpairs
+                            .into_iter()
+                            .map(rpc::NvmeQpairStats::from)
+                            .collect(),
+                        health: Some(rpc::NvmeControllerHealthStats::from(
+                            health,
+                        )),
+                    })
                 })?;
                 rx.await
                     .map_err(|_| Status::cancelled("cancelled"))?
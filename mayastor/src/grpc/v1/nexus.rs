@@ -185,6 +185,7 @@ impl<'n> nexus::Nexus<'n> {
             size: self.req_size,
             state: NexusState::from(self.status()) as i32,
             device_uri: self.get_share_uri().unwrap_or_default(),
+            device_uris: self.get_share_uris(),
             children: self
                 .children
                 .iter()
@@ -192,6 +193,75 @@ impl<'n> nexus::Nexus<'n> {
                 .collect::<Vec<_>>(),
             rebuilds: RebuildJob::count() as u32,
             ana_state: ana_state as i32,
+            allowed_hosts: self.allowed_hosts.clone(),
+            ana: Some((&self.ana_params).into()),
+        }
+    }
+}
+
+impl From<&NexusAnaConfig> for nexus::NexusAnaParams {
+    fn from(c: &NexusAnaConfig) -> Self {
+        Self {
+            reporting: c.reporting,
+            group_id: if c.group_id == 0 {
+                None
+            } else {
+                Some(c.group_id)
+            },
+            subsystem_nqn: if c.subsystem_nqn.is_empty() {
+                None
+            } else {
+                Some(c.subsystem_nqn.clone())
+            },
+        }
+    }
+}
+
+impl From<&nexus::NexusAnaParams> for NexusAnaConfig {
+    fn from(p: &nexus::NexusAnaParams) -> Self {
+        Self {
+            reporting: p.reporting,
+            group_id: p.group_id.unwrap_or_default(),
+            subsystem_nqn: p.subsystem_nqn.clone().unwrap_or_default(),
+        }
+    }
+}
+
+impl From<crate::core::ReservationReport> for ReservationReport {
+    fn from(r: crate::core::ReservationReport) -> Self {
+        Self {
+            generation: r.generation,
+            reservation_type: r.rtype as u32,
+            ptpl: r.ptpl,
+            registrants: r
+                .registrants
+                .into_iter()
+                .map(|reg| Registrant {
+                    controller_id: reg.cntlid as u32,
+                    host_id: uuid::Uuid::from_bytes(reg.host_id).to_string(),
+                    key: reg.key,
+                    holder: reg.holder,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<crate::subsys::NvmfConnection> for NvmfConnection {
+    fn from(c: crate::subsys::NvmfConnection) -> Self {
+        Self {
+            host_nqn: c.host_nqn,
+            host_id: uuid::Uuid::from_bytes(c.host_id).to_string(),
+            address: c.address.map(|a| a.to_string()).unwrap_or_default(),
+            controller_id: c.cntlid as u32,
+            io_queues: c.io_queues,
+            connected_since: c.connected_since.to_rfc3339(),
+            stats: Some(NvmfConnectionStats {
+                num_read_ops: c.stats.num_read_ops,
+                bytes_read: c.stats.bytes_read,
+                num_write_ops: c.stats.num_write_ops,
+                bytes_written: c.stats.bytes_written,
+            }),
         }
     }
 }
@@ -442,6 +512,24 @@ impl NexusRpc for NexusService {
                 });
             }
 
+            // like the allowed hosts, the ANA parameters are kept with the
+            // nexus when none are given
+            if let Some(ana) = &args.ana {
+                nexus_lookup(&args.uuid)?.set_ana_params(ana.into())?;
+            }
+
+            // the allowed hosts are kept with the nexus, so publishing again
+            // without any hosts uses the ones given before; once hosts are
+            // set, any host is only allowed when asked for explicitly
+            if !args.allowed_hosts.is_empty() {
+                nexus_lookup(&args.uuid)?
+                    .set_allowed_hosts(args.allowed_hosts)
+                    .await?;
+            }
+            if args.allow_any_host {
+                nexus_lookup(&args.uuid)?.set_allow_any_host(true);
+            }
+
             let device_uri =
                 nexus_lookup(&args.uuid)?.share(share_protocol, key).await?;
 
@@ -483,6 +571,59 @@ impl NexusRpc for NexusService {
             })
     }
 
+    async fn add_nexus_allowed_host(
+        &self,
+        request: Request<AddNexusAllowedHostRequest>,
+    ) -> GrpcResult<Nexus> {
+        let rx = rpc_submit::<_, _, nexus::Error>(async {
+            let args = request.into_inner();
+            trace!("{:?}", args);
+            let mut nexus = nexus_lookup(&args.uuid)?;
+
+            let mut hosts = nexus.allowed_hosts.clone();
+            if !hosts.contains(&args.host_nqn) {
+                hosts.push(args.host_nqn.clone());
+                nexus.as_mut().set_allowed_hosts(hosts).await?;
+                info!("Allowed host {} on nexus {}", args.host_nqn, args.uuid);
+            }
+
+            Ok(nexus.into_grpc().await)
+        })?;
+
+        rx.await
+            .map_err(|_| Status::cancelled("cancelled"))?
+            .map_err(Status::from)
+            .map(Response::new)
+    }
+
+    async fn remove_nexus_allowed_host(
+        &self,
+        request: Request<RemoveNexusAllowedHostRequest>,
+    ) -> GrpcResult<Nexus> {
+        let rx = rpc_submit::<_, _, nexus::Error>(async {
+            let args = request.into_inner();
+            trace!("{:?}", args);
+            let mut nexus = nexus_lookup(&args.uuid)?;
+
+            let mut hosts = nexus.allowed_hosts.clone();
+            if hosts.contains(&args.host_nqn) {
+                hosts.retain(|h| h != &args.host_nqn);
+                nexus.as_mut().set_allowed_hosts(hosts).await?;
+                info!(
+                    "Disallowed host {} on nexus {}",
+                    args.host_nqn, args.uuid
+                );
+            }
+
+            Ok(nexus.into_grpc().await)
+        })?;
+
+        rx.await
+            .map_err(|_| Status::cancelled("cancelled"))?
+            .map_err(Status::from)
+            .map(Response::new)
+    }
+
     async fn get_nvme_ana_state(
         &self,
         request: Request<GetNvmeAnaStateRequest>,
@@ -747,4 +888,109 @@ impl NexusRpc for NexusService {
         )
         .await
     }
+
+    #[named]
+    async fn get_child_reservations(
+        &self,
+        request: Request<GetChildReservationsRequest>,
+    ) -> GrpcResult<ReservationReport> {
+        self.locked(
+            GrpcClientContext::new(&request, function_name!()),
+            async move {
+                let args = request.into_inner();
+                trace!("{:?}", args);
+                let rx = rpc_submit::<_, _, nexus::Error>(async move {
+                    nexus_lookup(&args.uuid)?
+                        .child_reservations(&args.uri)
+                        .await
+                        .map(ReservationReport::from)
+                })?;
+                rx.await
+                    .map_err(|_| Status::cancelled("cancelled"))?
+                    .map_err(Status::from)
+                    .map(Response::new)
+            },
+        )
+        .await
+    }
+
+    #[named]
+    async fn preempt_child_reservation(
+        &self,
+        request: Request<PreemptChildReservationRequest>,
+    ) -> GrpcResult<ReservationReport> {
+        self.locked(
+            GrpcClientContext::new(&request, function_name!()),
+            async move {
+                let args = request.into_inner();
+                info!("{:?}", args);
+                let rx = rpc_submit::<_, _, nexus::Error>(async move {
+                    nexus_lookup(&args.uuid)?
+                        .preempt_child_reservation(&args.uri, args.preempt_key)
+                        .await
+                        .map(ReservationReport::from)
+                })?;
+                rx.await
+                    .map_err(|_| Status::cancelled("cancelled"))?
+                    .map_err(Status::from)
+                    .map(Response::new)
+            },
+        )
+        .await
+    }
+
+    #[named]
+    async fn clear_child_reservations(
+        &self,
+        request: Request<ClearChildReservationsRequest>,
+    ) -> GrpcResult<ReservationReport> {
+        self.locked(
+            GrpcClientContext::new(&request, function_name!()),
+            async move {
+                let args = request.into_inner();
+                info!("{:?}", args);
+                let rx = rpc_submit::<_, _, nexus::Error>(async move {
+                    nexus_lookup(&args.uuid)?
+                        .clear_child_reservations(&args.uri)
+                        .await
+                        .map(ReservationReport::from)
+                })?;
+                rx.await
+                    .map_err(|_| Status::cancelled("cancelled"))?
+                    .map_err(Status::from)
+                    .map(Response::new)
+            },
+        )
+        .await
+    }
+
+    #[named]
+    async fn get_nexus_connections(
+        &self,
+        request: Request<GetNexusConnectionsRequest>,
+    ) -> GrpcResult<NvmfConnections> {
+        self.locked(
+            GrpcClientContext::new(&request, function_name!()),
+            async move {
+                let args = request.into_inner();
+                trace!("{:?}", args);
+                let rx = rpc_submit::<_, _, nexus::Error>(async move {
+                    let connections = nexus_lookup(&args.uuid)?
+                        .nvmf_connections()
+                        .await
+                        .into_iter()
+                        .map(NvmfConnection::from)
+                        .collect();
+                    Ok(NvmfConnections {
+                        connections,
+                    })
+                })?;
+                rx.await
+                    .map_err(|_| Status::cancelled("cancelled"))?
+                    .map_err(Status::from)
+                    .map(Response::new)
+            },
+        )
+        .await
+    }
 }
@@ -3,6 +3,7 @@ use crate::{
     grpc::{rpc_submit, GrpcClientContext, GrpcResult, Serializer},
     lvs::{Error as LvsError, Lvs},
     pool::{PoolArgs, PoolBackend},
+    subsys::{discovered_pools, DiscoveredPool, ImportStatus},
 };
 use futures::FutureExt;
 use nix::errno::Errno;
@@ -145,6 +146,41 @@ impl From<Lvs> for Pool {
             capacity: l.capacity(),
             used: l.used(),
             pooltype: PoolType::Lvs as i32,
+            import_status: None,
+        }
+    }
+}
+
+impl From<&ImportStatus> for PoolImportStatus {
+    fn from(s: &ImportStatus) -> Self {
+        let (state, error) = match s {
+            ImportStatus::Imported => (PoolImportState::PoolImported, ""),
+            ImportStatus::NameConflict => {
+                (PoolImportState::PoolImportNameConflict, "")
+            }
+            ImportStatus::Failed(e) => {
+                (PoolImportState::PoolImportFailed, e.as_str())
+            }
+        };
+        Self {
+            state: state as i32,
+            error: error.to_string(),
+        }
+    }
+}
+
+/// a discovered pool which has not been imported
+impl From<DiscoveredPool> for Pool {
+    fn from(p: DiscoveredPool) -> Self {
+        Self {
+            uuid: p.uuid,
+            name: p.name,
+            disks: p.disks,
+            state: PoolState::PoolUnknown.into(),
+            capacity: 0,
+            used: 0,
+            pooltype: PoolType::Lvs as i32,
+            import_status: Some((&p.status).into()),
         }
     }
 }
@@ -302,13 +338,32 @@ impl PoolRpc for PoolService {
                 let rx = rpc_submit::<_, _, LvsError>(async move {
                     let mut pools = Vec::new();
                     let args = request.into_inner();
-                    if let Some(name) = args.name {
-                        if let Some(l) = Lvs::lookup(&name) {
+                    if let Some(name) = &args.name {
+                        if let Some(l) = Lvs::lookup(name) {
                             pools.push(l.into())
                         };
                     } else {
                         Lvs::iter().for_each(|l| pools.push(l.into()));
                     }
+
+                    // report the outcome of the discovery at startup, pools
+                    // which were not imported are listed as well
+                    for d in discovered_pools() {
+                        if matches!(&args.name, Some(n) if n != &d.name) {
+                            continue;
+                        }
+                        if d.status == ImportStatus::Imported {
+                            if let Some(p) = pools
+                                .iter_mut()
+                                .find(|p| p.name == d.name && p.uuid == d.uuid)
+                            {
+                                p.import_status = Some((&d.status).into());
+                            }
+                        } else {
+                            pools.push(d.into());
+                        }
+                    }
+
                     Ok(ListPoolsResponse {
                         pools,
                     })
@@ -322,4 +377,49 @@ impl PoolRpc for PoolService {
         )
         .await
     }
+
+    #[named]
+    async fn stat_pools(
+        &self,
+        request: Request<ListPoolOptions>,
+    ) -> GrpcResult<StatPoolsResponse> {
+        self.locked(
+            GrpcClientContext::new(&request, function_name!()),
+            async move {
+                let rx = rpc_submit::<_, _, LvsError>(async move {
+                    let args = request.into_inner();
+                    let pools: Vec<Lvs> = if let Some(name) = args.name {
+                        Lvs::lookup(&name).into_iter().collect()
+                    } else {
+                        Lvs::iter().collect()
+                    };
+
+                    let mut stats = Vec::new();
+                    for pool in pools {
+                        let s = pool.stats().await?;
+                        let mut io = IoStats::from(&s.io);
+                        io.latency = Some(s.latency().into());
+                        stats.push(PoolStats {
+                            name: pool.name().into(),
+                            uuid: pool.uuid(),
+                            stats: Some(io),
+                            allocated_clusters: s.allocated_clusters,
+                            cluster_size: s.cluster_size,
+                            num_replicas: s.num_lvols,
+                        });
+                    }
+
+                    Ok(StatPoolsResponse {
+                        stats,
+                    })
+                })?;
+
+                rx.await
+                    .map_err(|_| Status::cancelled("cancelled"))?
+                    .map_err(Status::from)
+                    .map(Response::new)
+            },
+        )
+        .await
+    }
 }
@@ -1,14 +1,14 @@
 use crate::{
-    core::{Bdev, Protocol, Share, UntypedBdev},
+    core::{Bdev, NvmfShareProps, Protocol, RateLimits, Share, UntypedBdev},
     grpc::{rpc_submit, GrpcClientContext, GrpcResult, Serializer},
-    lvs::{Error as LvsError, Lvol, Lvs},
+    lvs::{Error as LvsError, LabelSelector, Lvol, Lvs, PropName, PropValue},
     nexus_uri::NexusBdevError,
 };
 use ::function_name::named;
 use futures::FutureExt;
 use nix::errno::Errno;
 use rpc::mayastor::v1::replica::*;
-use std::{convert::TryFrom, panic::AssertUnwindSafe, pin::Pin};
+use std::{convert::TryFrom, panic::AssertUnwindSafe, pin::Pin, str::FromStr};
 use tonic::{Request, Response, Status};
 
 #[derive(Debug)]
@@ -69,10 +69,94 @@ impl From<Lvol> for Replica {
             size: l.size(),
             share: l.shared().unwrap().into(),
             uri: l.share_uri().unwrap(),
+            uris: l.share_uris(),
+            labels: l.labels(),
+            rate_limits: Some(l.rate_limits().into()),
+            allowed_hosts: l.allowed_hosts(),
         }
     }
 }
 
+impl From<ReplicaRateLimits> for RateLimits {
+    fn from(l: ReplicaRateLimits) -> Self {
+        Self {
+            rw_ios_per_sec: l.rw_ios_per_sec,
+            rw_mbytes_per_sec: l.rw_mbytes_per_sec,
+            r_mbytes_per_sec: l.r_mbytes_per_sec,
+            w_mbytes_per_sec: l.w_mbytes_per_sec,
+        }
+    }
+}
+
+impl From<RateLimits> for ReplicaRateLimits {
+    fn from(l: RateLimits) -> Self {
+        Self {
+            rw_ios_per_sec: l.rw_ios_per_sec,
+            rw_mbytes_per_sec: l.rw_mbytes_per_sec,
+            r_mbytes_per_sec: l.r_mbytes_per_sec,
+            w_mbytes_per_sec: l.w_mbytes_per_sec,
+        }
+    }
+}
+
+/// returns all lvols matching the list options
+fn list_lvols(args: ListReplicaOptions) -> Result<Vec<Lvol>, LvsError> {
+    let mut lvols = Vec::new();
+    if let Some(bdev) = UntypedBdev::bdev_first() {
+        lvols = bdev
+            .into_iter()
+            .filter(|b| b.driver() == "lvol")
+            .map(|b| Lvol::try_from(b).unwrap())
+            .collect();
+    }
+
+    // perform filtering on lvols
+    if let Some(pool_name) = args.poolname {
+        lvols = lvols
+            .into_iter()
+            .filter(|l| l.pool() == pool_name)
+            .collect();
+    }
+
+    if let Some(name) = args.name {
+        lvols = lvols.into_iter().filter(|l| l.name() == name).collect();
+    }
+
+    if let Some(selector) = args.label_selector {
+        let selector = LabelSelector::from_str(&selector)?;
+        lvols = lvols
+            .into_iter()
+            .filter(|l| selector.matches(&l.labels()))
+            .collect();
+    }
+
+    Ok(lvols)
+}
+
+/// returns the lvol with the given uuid
+fn replica_lookup(uuid: &str) -> Result<Lvol, LvsError> {
+    match Bdev::lookup_by_uuid_str(uuid) {
+        Some(bdev) => Lvol::try_from(bdev),
+        None => Err(LvsError::InvalidBdev {
+            source: NexusBdevError::BdevNotFound {
+                name: uuid.to_string(),
+            },
+            name: uuid.to_string(),
+        }),
+    }
+}
+
+/// returns the allowed hosts stored with the share of the lvol
+async fn allowed_hosts(lvol: &Lvol) -> Result<Vec<String>, LvsError> {
+    match lvol.get(PropName::AllowedHosts).await? {
+        PropValue::AllowedHosts(hosts) => Ok(hosts),
+        prop => Err(LvsError::Invalid {
+            source: Errno::EINVAL,
+            msg: format!("unexpected property {:?}", prop),
+        }),
+    }
+}
+
 impl Default for ReplicaService {
     fn default() -> Self {
         Self::new()
@@ -121,10 +205,30 @@ impl ReplicaRpc for ReplicaService {
                 };
                 // if pooltype is not Lvs, the provided replica uuid need to be added as
                 // a metadata on the volume.
-                match lvs.create_lvol(&args.name, args.size, Some(&args.uuid), false).await {
+                let lvol = match lvs.create_lvol(&args.name, args.size, Some(&args.uuid), false).await {
+                    Ok(mut lvol) if !args.labels.is_empty() => {
+                        match Pin::new(&mut lvol).set(PropValue::Labels(args.labels)).await {
+                            Ok(_) => Ok(lvol),
+                            Err(e) => {
+                                debug!(
+                                    "failed to label created lvol {}: {} (destroying)",
+                                    lvol,
+                                    e.to_string()
+                                );
+                                let _ = lvol.destroy().await;
+                                Err(e)
+                            }
+                        }
+                    }
+                    r => r,
+                };
+                match lvol {
                     Ok(mut lvol)
                     if Protocol::try_from(args.share)? == Protocol::Nvmf => {
-                        match Pin::new(&mut lvol).share_nvmf(None).await {
+                        let props = NvmfShareProps::new()
+                            .with_allowed_hosts(args.allowed_hosts)
+                            .with_allow_any_host(args.allow_any_host);
+                        match Pin::new(&mut lvol).share_nvmf(Some(props)).await {
                             Ok(s) => {
                                 debug!("created and shared {} as {}", lvol, s);
                                 Ok(Replica::from(lvol))
@@ -198,37 +302,48 @@ impl ReplicaRpc for ReplicaService {
             let args = request.into_inner();
             info!("{:?}", args);
             let rx = rpc_submit::<_, _, LvsError>(async move {
-                let mut lvols = Vec::new();
-                if let Some(bdev) = UntypedBdev::bdev_first() {
-                    lvols = bdev
-                        .into_iter()
-                        .filter(|b| b.driver() == "lvol")
-                        .map(|b| Lvol::try_from(b).unwrap())
-                        .collect();
-                }
+                let replicas =
+                    list_lvols(args)?.into_iter().map(Replica::from).collect();
 
-                // perform filtering on lvols
-                if let Some(pool_name) = args.poolname {
-                    lvols = lvols
-                        .into_iter()
-                        .filter(|l| l.pool() == pool_name)
-                        .collect();
-                }
+                Ok(ListReplicasResponse {
+                    replicas,
+                })
+            })?;
 
-                // convert lvols to replicas
-                let mut replicas: Vec<Replica> =
-                    lvols.into_iter().map(Replica::from).collect();
+            rx.await
+                .map_err(|_| Status::cancelled("cancelled"))?
+                .map_err(Status::from)
+                .map(Response::new)
+        })
+        .await
+    }
 
-                // perform the filtering on the replica list
-                if let Some(name) = args.name {
-                    replicas = replicas
-                        .into_iter()
-                        .filter(|r| r.name == name)
-                        .collect();
+    #[named]
+    async fn stat_replicas(
+        &self,
+        request: Request<ListReplicaOptions>,
+    ) -> GrpcResult<StatReplicasResponse> {
+        self.locked(GrpcClientContext::new(&request, function_name!()), async {
+            let args = request.into_inner();
+            info!("{:?}", args);
+            let rx = rpc_submit::<_, _, LvsError>(async move {
+                let mut stats = Vec::new();
+                for lvol in list_lvols(args)? {
+                    let s = lvol.stats().await?;
+                    let mut io = IoStats::from(&s.io);
+                    io.latency = Some(s.latency().into());
+                    stats.push(ReplicaStats {
+                        name: lvol.name(),
+                        uuid: lvol.uuid(),
+                        pooluuid: lvol.pool_uuid(),
+                        stats: Some(io),
+                        allocated_clusters: s.allocated_clusters,
+                        cluster_size: s.cluster_size,
+                    });
                 }
 
-                Ok(ListReplicasResponse {
-                    replicas,
+                Ok(StatReplicasResponse {
+                    stats,
                 })
             })?;
 
@@ -255,6 +370,13 @@ impl ReplicaRpc for ReplicaService {
                         Some(bdev) => {
                             let mut lvol = Lvol::try_from(bdev)?;
 
+                            // rate limits can be changed on a shared replica
+                            if let Some(limits) = args.rate_limits {
+                                Pin::new(&mut lvol)
+                                    .set_rate_limits(limits.into())
+                                    .await?;
+                            }
+
                             // if we are already shared with the same protocol
                             if lvol.shared()
                                 == Some(Protocol::try_from(args.share)?)
@@ -271,8 +393,13 @@ impl ReplicaRpc for ReplicaService {
                                     })
                                 }
                                 Protocol::Nvmf => {
+                                    let props = NvmfShareProps::new()
+                                        .with_allowed_hosts(args.allowed_hosts)
+                                        .with_allow_any_host(
+                                            args.allow_any_host,
+                                        );
                                     Pin::new(&mut lvol)
-                                        .share_nvmf(None)
+                                        .share_nvmf(Some(props))
                                         .await?;
                                 }
                             }
@@ -333,4 +460,171 @@ impl ReplicaRpc for ReplicaService {
         )
         .await
     }
+
+    #[named]
+    async fn add_replica_allowed_host(
+        &self,
+        request: Request<AddReplicaAllowedHostRequest>,
+    ) -> GrpcResult<Replica> {
+        self.locked(
+            GrpcClientContext::new(&request, function_name!()),
+            async move {
+                let args = request.into_inner();
+                info!("{:?}", args);
+                let rx = rpc_submit(async move {
+                    let mut lvol = replica_lookup(&args.uuid)?;
+                    let mut hosts = allowed_hosts(&lvol).await?;
+                    if !hosts.contains(&args.host_nqn) {
+                        hosts.push(args.host_nqn);
+                        Pin::new(&mut lvol).set_allowed_hosts(hosts).await?;
+                    }
+                    Ok(Replica::from(lvol))
+                })?;
+
+                rx.await
+                    .map_err(|_| Status::cancelled("cancelled"))?
+                    .map_err(Status::from)
+                    .map(Response::new)
+            },
+        )
+        .await
+    }
+
+    #[named]
+    async fn remove_replica_allowed_host(
+        &self,
+        request: Request<RemoveReplicaAllowedHostRequest>,
+    ) -> GrpcResult<Replica> {
+        self.locked(
+            GrpcClientContext::new(&request, function_name!()),
+            async move {
+                let args = request.into_inner();
+                info!("{:?}", args);
+                let rx = rpc_submit(async move {
+                    let mut lvol = replica_lookup(&args.uuid)?;
+                    let mut hosts = allowed_hosts(&lvol).await?;
+                    if hosts.contains(&args.host_nqn) {
+                        hosts.retain(|h| h != &args.host_nqn);
+                        Pin::new(&mut lvol).set_allowed_hosts(hosts).await?;
+                    }
+                    Ok(Replica::from(lvol))
+                })?;
+
+                rx.await
+                    .map_err(|_| Status::cancelled("cancelled"))?
+                    .map_err(Status::from)
+                    .map(Response::new)
+            },
+        )
+        .await
+    }
+
+    #[named]
+    async fn get_replica_reservations(
+        &self,
+        request: Request<GetReplicaReservationsRequest>,
+    ) -> GrpcResult<ReservationReport> {
+        self.locked(
+            GrpcClientContext::new(&request, function_name!()),
+            async move {
+                let args = request.into_inner();
+                trace!("{:?}", args);
+                let rx = rpc_submit(async move {
+                    replica_lookup(&args.uuid)?
+                        .reservations()
+                        .await
+                        .map(ReservationReport::from)
+                })?;
+
+                rx.await
+                    .map_err(|_| Status::cancelled("cancelled"))?
+                    .map_err(Status::from)
+                    .map(Response::new)
+            },
+        )
+        .await
+    }
+
+    #[named]
+    async fn preempt_replica_reservation(
+        &self,
+        request: Request<PreemptReplicaReservationRequest>,
+    ) -> GrpcResult<ReservationReport> {
+        self.locked(
+            GrpcClientContext::new(&request, function_name!()),
+            async move {
+                let args = request.into_inner();
+                info!("{:?}", args);
+                let rx = rpc_submit(async move {
+                    replica_lookup(&args.uuid)?
+                        .preempt_reservation(args.key, args.preempt_key)
+                        .await
+                        .map(ReservationReport::from)
+                })?;
+
+                rx.await
+                    .map_err(|_| Status::cancelled("cancelled"))?
+                    .map_err(Status::from)
+                    .map(Response::new)
+            },
+        )
+        .await
+    }
+
+    #[named]
+    async fn clear_replica_reservations(
+        &self,
+        request: Request<ClearReplicaReservationsRequest>,
+    ) -> GrpcResult<ReservationReport> {
+        self.locked(
+            GrpcClientContext::new(&request, function_name!()),
+            async move {
+                let args = request.into_inner();
+                info!("{:?}", args);
+                let rx = rpc_submit(async move {
+                    replica_lookup(&args.uuid)?
+                        .clear_reservations(args.key)
+                        .await
+                        .map(ReservationReport::from)
+                })?;
+
+                rx.await
+                    .map_err(|_| Status::cancelled("cancelled"))?
+                    .map_err(Status::from)
+                    .map(Response::new)
+            },
+        )
+        .await
+    }
+
+    #[named]
+    async fn get_replica_connections(
+        &self,
+        request: Request<GetReplicaConnectionsRequest>,
+    ) -> GrpcResult<NvmfConnections> {
+        self.locked(
+            GrpcClientContext::new(&request, function_name!()),
+            async move {
+                let args = request.into_inner();
+                trace!("{:?}", args);
+                let rx = rpc_submit::<_, _, LvsError>(async move {
+                    let connections = replica_lookup(&args.uuid)?
+                        .connections()
+                        .await
+                        .into_iter()
+                        .map(NvmfConnection::from)
+                        .collect();
+                    Ok(NvmfConnections {
+                        connections,
+                    })
+                })?;
+
+                rx.await
+                    .map_err(|_| Status::cancelled("cancelled"))?
+                    .map_err(Status::from)
+                    .map(Response::new)
+            },
+        )
+        .await
+    }
 }
@@ -0,0 +1,32 @@
+//! Conversions of I/O statistics shared by the pool and replica services.
+use crate::core::{BlockDeviceIoStats, LatencyPercentiles};
+use rpc::mayastor::v1::pool::{IoStats, LatencyStats};
+
+impl From<LatencyPercentiles> for LatencyStats {
+    fn from(l: LatencyPercentiles) -> Self {
+        Self {
+            p50_us: l.p50,
+            p90_us: l.p90,
+            p99_us: l.p99,
+            p999_us: l.p999,
+            max_us: l.max,
+        }
+    }
+}
+
+impl From<&BlockDeviceIoStats> for IoStats {
+    fn from(io: &BlockDeviceIoStats) -> Self {
+        Self {
+            num_read_ops: io.num_read_ops,
+            num_write_ops: io.num_write_ops,
+            num_unmap_ops: io.num_unmap_ops,
+            bytes_read: io.bytes_read,
+            bytes_written: io.bytes_written,
+            bytes_unmapped: io.bytes_unmapped,
+            read_latency_us: io.avg_read_latency_us(),
+            write_latency_us: io.avg_write_latency_us(),
+            unmap_latency_us: io.avg_unmap_latency_us(),
+            latency: None,
+        }
+    }
+}
@@ -38,6 +38,9 @@ pub enum Error {
     #[snafu(display("failed to destroy lvol {}", name))]
     RepDestroy { source: Errno, name: String },
 
+    #[snafu(display("errno: {} failed to snapshot lvol {}", source, name))]
+    RepSnapshot { source: Errno, name: String },
+
     #[snafu(display("bdev {} is not a lvol", name))]
     NotALvol { source: Errno, name: String },
 
@@ -47,6 +50,12 @@ pub enum Error {
     #[snafu(display("failed to unshare lvol {}", name))]
     LvolUnShare { source: CoreError, name: String },
 
+    #[snafu(display("failed to set rate limits of lvol {}", name))]
+    LvolRateLimits { source: CoreError, name: String },
+
+    #[snafu(display("failed to set the allowed hosts of lvol {}", name))]
+    LvolAllowedHosts { source: CoreError, name: String },
+
     #[snafu(display(
         "failed to get property {} ({}) from {}",
         prop,
@@ -68,6 +77,21 @@ pub enum Error {
     SyncProperty { source: Errno, name: String },
     #[snafu(display("invalid property value: {}", name))]
     Property { source: Errno, name: String },
+    #[snafu(display("failed to get statistics of {}: {}", name, source))]
+    Stats { source: CoreError, name: String },
+    #[snafu(display("failed to connect to the share of lvol {}", name))]
+    LvolConnect {
+        source: NexusBdevError,
+        name: String,
+    },
+    #[snafu(display(
+        "failed to manage the reservations of lvol {}: {}",
+        name,
+        source
+    ))]
+    LvolReservation { source: CoreError, name: String },
+    #[snafu(display("errno: {} failed to check pool {}", source, name))]
+    Check { source: Errno, name: String },
     #[snafu(display("invalid replica share protocol value: {}", value))]
     ReplicaShareProtocol { value: i32 },
 }
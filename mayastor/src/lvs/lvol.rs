@@ -1,4 +1,5 @@
 use std::{
+    collections::HashMap,
     convert::TryFrom,
     ffi::{c_void, CStr},
     fmt::Display,
@@ -12,26 +13,44 @@ use futures::channel::oneshot;
 use nix::errno::Errno;
 use pin_utils::core_reexport::fmt::Formatter;
 
-use spdk_rs::libspdk::{
-    spdk_bdev_io,
-    spdk_bdev_io_get_thread,
-    spdk_blob_get_xattr_value,
-    spdk_blob_is_read_only,
-    spdk_blob_is_snapshot,
-    spdk_blob_set_xattr,
-    spdk_blob_sync_md,
-    spdk_lvol,
-    spdk_nvmf_request_complete,
-    vbdev_lvol_create_snapshot,
-    vbdev_lvol_destroy,
-    vbdev_lvol_get_from_bdev,
-    LVS_CLEAR_WITH_UNMAP,
-    SPDK_BDEV_LARGE_BUF_MAX_SIZE,
+use spdk_rs::{
+    libspdk::{
+        spdk_blob_calc_used_clusters,
+        spdk_blob_get_xattr_value,
+        spdk_blob_is_read_only,
+        spdk_blob_is_snapshot,
+        spdk_blob_set_xattr,
+        spdk_blob_sync_md,
+        spdk_lvol,
+        spdk_nvmf_request_complete,
+        vbdev_lvol_create_snapshot,
+        vbdev_lvol_destroy,
+        vbdev_lvol_get_from_bdev,
+        LVS_CLEAR_WITH_UNMAP,
+        SPDK_BDEV_LARGE_BUF_MAX_SIZE,
+    },
+    nvme_reservation_type,
 };
 
 use crate::{
-    bdev::nexus::Nexus,
-    core::{Bdev, Mthread, Protocol, Share, UntypedBdev},
+    bdev::{device_create, device_destroy, device_open, nvmx::host_nqn},
+    core::{
+        clear_reservations,
+        preempt_reservation,
+        reservation_report,
+        unregister_key,
+        Bdev,
+        BlockDeviceIoStats,
+        CoreError,
+        LatencyHistogram,
+        LatencyPercentiles,
+        NvmfShareProps,
+        Protocol,
+        RateLimits,
+        ReservationReport,
+        Share,
+        UntypedBdev,
+    },
     ffihelper::{
         cb_arg,
         errno_result_from_i32,
@@ -41,27 +60,51 @@ use crate::{
         IntoCString,
     },
     lvs::{error::Error, lvs_pool::Lvs},
-    subsys::NvmfReq,
+    subsys::{NvmfConnection, NvmfReq, NvmfSubsystem},
 };
 
+/// arbitrary key/value pairs attached to a lvol by the control plane, for
+/// example the volume or nexus owning the replica
+pub type Labels = HashMap<String, String>;
+
+/// reservation management performed on the share of a lvol
+#[derive(Debug, Clone, Copy)]
+enum ReservationAction {
+    Report,
+    Preempt { key: u64, preempt_key: u64 },
+    Clear { key: u64 },
+}
+
 /// properties we allow for being set on the lvol, this information is stored on
 /// disk
-#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 #[non_exhaustive]
 pub enum PropValue {
     Shared(bool),
+    Labels(Labels),
+    RateLimits(RateLimits),
+    AllowedHosts(Vec<String>),
+    AllowAnyHost(bool),
 }
 
 #[derive(Debug)]
 #[non_exhaustive]
 pub enum PropName {
     Shared,
+    Labels,
+    RateLimits,
+    AllowedHosts,
+    AllowAnyHost,
 }
 
-impl From<PropValue> for PropName {
-    fn from(v: PropValue) -> Self {
+impl From<&PropValue> for PropName {
+    fn from(v: &PropValue) -> Self {
         match v {
             PropValue::Shared(_) => Self::Shared,
+            PropValue::Labels(_) => Self::Labels,
+            PropValue::RateLimits(_) => Self::RateLimits,
+            PropValue::AllowedHosts(_) => Self::AllowedHosts,
+            PropValue::AllowAnyHost(_) => Self::AllowAnyHost,
         }
     }
 }
@@ -70,11 +113,109 @@ impl Display for PropName {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let name = match self {
             PropName::Shared => "shared",
+            PropName::Labels => "labels",
+            PropName::RateLimits => "rate_limits",
+            PropName::AllowedHosts => "allowed_hosts",
+            PropName::AllowAnyHost => "allow_any_host",
         };
         write!(f, "{}", name)
     }
 }
 
+/// A single requirement of a label selector. The syntax follows the
+/// equality and set-less existence based selectors of kubernetes, i.e.
+/// `key=value`, `key!=value`, `key` and `!key`.
+#[derive(Debug, Clone, PartialEq)]
+enum LabelRequirement {
+    Equals(String, String),
+    NotEquals(String, String),
+    Exists(String),
+    NotExists(String),
+}
+
+/// selects lvols based on their labels, all requirements must be met
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LabelSelector(Vec<LabelRequirement>);
+
+impl std::str::FromStr for LabelSelector {
+    type Err = Error;
+
+    fn from_str(selector: &str) -> Result<Self, Self::Err> {
+        let invalid = |term: &str| Error::Invalid {
+            source: Errno::EINVAL,
+            msg: format!("invalid label selector term '{}'", term),
+        };
+
+        let mut requirements = Vec::new();
+        for term in selector.split(',').map(str::trim) {
+            if term.is_empty() {
+                continue;
+            }
+
+            let requirement = if let Some((k, v)) = term.split_once("!=") {
+                LabelRequirement::NotEquals(k.trim().into(), v.trim().into())
+            } else if let Some((k, v)) = term.split_once("==") {
+                LabelRequirement::Equals(k.trim().into(), v.trim().into())
+            } else if let Some((k, v)) = term.split_once('=') {
+                LabelRequirement::Equals(k.trim().into(), v.trim().into())
+            } else if let Some(k) = term.strip_prefix('!') {
+                LabelRequirement::NotExists(k.trim().into())
+            } else {
+                LabelRequirement::Exists(term.into())
+            };
+
+            match &requirement {
+                LabelRequirement::Equals(k, _)
+                | LabelRequirement::NotEquals(k, _)
+                | LabelRequirement::Exists(k)
+                | LabelRequirement::NotExists(k)
+                    if k.is_empty() =>
+                {
+                    return Err(invalid(term))
+                }
+                _ => requirements.push(requirement),
+            }
+        }
+
+        Ok(Self(requirements))
+    }
+}
+
+impl LabelSelector {
+    /// returns true if the labels satisfy all requirements of the selector
+    pub fn matches(&self, labels: &Labels) -> bool {
+        self.0.iter().all(|r| match r {
+            LabelRequirement::Equals(k, v) => labels.get(k) == Some(v),
+            LabelRequirement::NotEquals(k, v) => labels.get(k) != Some(v),
+            LabelRequirement::Exists(k) => labels.contains_key(k),
+            LabelRequirement::NotExists(k) => !labels.contains_key(k),
+        })
+    }
+}
+
+/// I/O statistics and space usage of a lvol
+#[derive(Debug, Default, Clone)]
+pub struct LvolStats {
+    pub io: BlockDeviceIoStats,
+    /// latencies of all I/O types, collected since the first time the
+    /// statistics were requested
+    pub histogram: LatencyHistogram,
+    pub allocated_clusters: u64,
+    pub cluster_size: u64,
+}
+
+impl LvolStats {
+    /// latency percentiles in microseconds
+    pub fn latency(&self) -> LatencyPercentiles {
+        self.histogram.percentiles()
+    }
+
+    /// number of bytes allocated from the pool
+    pub fn allocated_bytes(&self) -> u64 {
+        self.allocated_clusters * self.cluster_size
+    }
+}
+
 #[derive(Debug)]
 /// struct representing an lvol
 pub struct Lvol(pub(crate) NonNull<spdk_lvol>);
@@ -115,13 +256,39 @@ impl Share for Lvol {
     type Error = Error;
     type Output = String;
 
-    /// share the lvol as a nvmf target
+    /// Share the lvol as a nvmf target. Without properties, the hosts
+    /// stored with the previous share are allowed to connect. When the
+    /// properties neither allow any host nor name the allowed hosts, the
+    /// stored hosts are used or, when there are none, the host NQN that a
+    /// nexus on this node connects with.
     async fn share_nvmf(
         mut self: Pin<&mut Self>,
-        cntlid_range: Option<(u16, u16)>,
+        props: Option<NvmfShareProps>,
     ) -> Result<Self::Output, Self::Error> {
+        let props = match props {
+            None => NvmfShareProps::new()
+                .with_allowed_hosts(self.stored_allowed_hosts())
+                .with_allow_any_host(self.stored_allow_any_host()),
+            Some(props)
+                if !props.allow_any_host()
+                    && props.allowed_hosts().is_empty() =>
+            {
+                let mut hosts = self.stored_allowed_hosts();
+                if hosts.is_empty() {
+                    hosts = host_nqn().into_iter().collect();
+                }
+                if hosts.is_empty() {
+                    warn!("{}: shared without any allowed hosts", self);
+                }
+                props.with_allowed_hosts(hosts)
+            }
+            Some(props) => props,
+        };
+        let hosts = props.allowed_hosts().clone();
+        let allow_any = props.allow_any_host();
+
         let share = Pin::new(&mut self.as_bdev())
-            .share_nvmf(cntlid_range)
+            .share_nvmf(Some(props))
             .await
             .map_err(|e| Error::LvolShare {
                 source: e,
@@ -129,6 +296,10 @@ impl Share for Lvol {
             })?;
 
         self.as_mut().set(PropValue::Shared(true)).await?;
+        self.as_mut().set(PropValue::AllowedHosts(hosts)).await?;
+        self.as_mut()
+            .set(PropValue::AllowAnyHost(allow_any))
+            .await?;
         info!("shared {}", self);
         Ok(share)
     }
@@ -155,6 +326,11 @@ impl Share for Lvol {
         self.as_bdev().shared()
     }
 
+    /// returns the hosts allowed to connect to the share
+    fn allowed_hosts(&self) -> Vec<String> {
+        self.as_bdev().allowed_hosts()
+    }
+
     /// returns the share URI this lvol is shared as
     /// this URI includes a UUID as a query parameter which can be used to
     /// uniquely identify a replica as the replica UUID is currently set to its
@@ -164,6 +340,16 @@ impl Share for Lvol {
         uri_no_uuid.map(|uri| format!("{}?uuid={}", uri, self.uuid()))
     }
 
+    /// returns the URIs of all endpoints the lvol is shared through, including
+    /// the UUID as with the share URI
+    fn share_uris(&self) -> Vec<String> {
+        self.as_bdev()
+            .share_uris()
+            .into_iter()
+            .map(|uri| format!("{}?uuid={}", uri, self.uuid()))
+            .collect()
+    }
+
     /// returns the URI that is used to construct the bdev. This is always None
     /// as lvols can not be created by URIs directly, but only through the
     /// ['Lvs'] interface.
@@ -287,6 +473,242 @@ impl Lvol {
         unsafe { spdk_blob_is_snapshot(self.0.as_ref().blob) }
     }
 
+    /// returns the number of clusters allocated to this lvol
+    pub fn allocated_clusters(&self) -> u64 {
+        unsafe { spdk_blob_calc_used_clusters(self.0.as_ref().blob) }
+    }
+
+    /// returns the I/O statistics of the lvol. Latency histograms are
+    /// collected from the first call onward, as collecting them has a small
+    /// cost for every I/O.
+    pub async fn stats(&self) -> Result<LvolStats, Error> {
+        let mut bdev = self.as_bdev();
+
+        let io = bdev.stats_async().await.map_err(|e| Error::Stats {
+            source: e,
+            name: self.name(),
+        })?;
+
+        let histogram = if bdev.histogram_enabled() {
+            bdev.latency_histogram().await
+        } else {
+            bdev.enable_histogram(true)
+                .await
+                .map(|_| LatencyHistogram::default())
+        }
+        .map_err(|e| Error::Stats {
+            source: e,
+            name: self.name(),
+        })?;
+
+        Ok(LvolStats {
+            io,
+            histogram,
+            allocated_clusters: self.allocated_clusters(),
+            cluster_size: self.lvs().cluster_size(),
+        })
+    }
+
+    /// set the QoS rate limits of the lvol and store them on disk, so they
+    /// are restored when the pool is imported
+    pub async fn set_rate_limits(
+        mut self: Pin<&mut Self>,
+        limits: RateLimits,
+    ) -> Result<(), Error> {
+        self.as_bdev().set_rate_limits(&limits).await.map_err(|e| {
+            Error::LvolRateLimits {
+                source: e,
+                name: self.name(),
+            }
+        })?;
+
+        self.as_mut().set(PropValue::RateLimits(limits)).await
+    }
+
+    /// returns the QoS rate limits currently in effect
+    pub fn rate_limits(&self) -> RateLimits {
+        self.as_bdev().rate_limits()
+    }
+
+    /// replace the hosts allowed to connect to the share of the lvol, which
+    /// restricts access to those hosts, and store them on disk so they are
+    /// used again when the lvol is shared
+    pub async fn set_allowed_hosts(
+        mut self: Pin<&mut Self>,
+        hosts: Vec<String>,
+    ) -> Result<(), Error> {
+        let bdev = self.as_bdev();
+        bdev.set_allowed_hosts(&hosts).await.map_err(|e| {
+            Error::LvolAllowedHosts {
+                source: e,
+                name: self.name(),
+            }
+        })?;
+        bdev.set_allow_any_host(false);
+
+        self.as_mut().set(PropValue::AllowedHosts(hosts)).await?;
+        self.as_mut().set(PropValue::AllowAnyHost(false)).await
+    }
+
+    /// returns whether any host may connect as stored on disk, lvols shared
+    /// before access was restricted allow any host unless hosts are stored
+    fn stored_allow_any_host(&self) -> bool {
+        match self.get_xattr(PropName::AllowAnyHost) {
+            Ok(value) => value == "true",
+            Err(_) => self.stored_allowed_hosts().is_empty(),
+        }
+    }
+
+    /// returns the allowed hosts stored on disk, none when the lvol was
+    /// never shared with a host allow-list
+    fn stored_allowed_hosts(&self) -> Vec<String> {
+        match self.get_xattr(PropName::AllowedHosts) {
+            Ok(value) => serde_json::from_str(&value).unwrap_or_else(|e| {
+                warn!("{}: ignoring invalid allowed hosts: {}", self, e);
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// returns the hosts connected to the share of the lvol
+    pub async fn connections(&self) -> Vec<NvmfConnection> {
+        self.as_bdev().nvmf_connections().await
+    }
+
+    /// returns the NVMe reservations held on the share of the lvol
+    pub async fn reservations(&self) -> Result<ReservationReport, Error> {
+        self.manage_reservations(ReservationAction::Report).await
+    }
+
+    /// remove the registration, and reservation, of the host registered with
+    /// preempt_key on the share of the lvol, for example a nexus on a node
+    /// which was lost
+    pub async fn preempt_reservation(
+        &self,
+        key: u64,
+        preempt_key: u64,
+    ) -> Result<ReservationReport, Error> {
+        self.manage_reservations(ReservationAction::Preempt {
+            key,
+            preempt_key,
+        })
+        .await
+    }
+
+    /// release the reservation and remove all registrations on the share of
+    /// the lvol
+    pub async fn clear_reservations(
+        &self,
+        key: u64,
+    ) -> Result<ReservationReport, Error> {
+        self.manage_reservations(ReservationAction::Clear {
+            key,
+        })
+        .await
+    }
+
+    /// reservations are handled by the NVMe-oF target rather than the lvol,
+    /// so connect to the share of the lvol to manage them. When the share is
+    /// restricted to other hosts, the host NQN of this node is allowed for
+    /// as long as the connection lasts.
+    async fn manage_reservations(
+        &self,
+        action: ReservationAction,
+    ) -> Result<ReservationReport, Error> {
+        let uri = match (self.shared(), self.share_uri()) {
+            (Some(Protocol::Nvmf), Some(uri)) => uri,
+            _ => {
+                return Err(Error::Invalid {
+                    source: Errno::EINVAL,
+                    msg: format!("lvol {} is not shared over NVMe-oF", self),
+                })
+            }
+        };
+
+        let added_host =
+            match (NvmfSubsystem::nqn_lookup(&self.name()), host_nqn()) {
+                (Some(subsystem), Some(nqn))
+                    if !subsystem.host_allowed(&nqn) =>
+                {
+                    subsystem.allow_host(&nqn).map_err(|e| {
+                        Error::LvolAllowedHosts {
+                            source: CoreError::ShareNvmf {
+                                source: e,
+                            },
+                            name: self.name(),
+                        }
+                    })?;
+                    Some((subsystem, nqn))
+                }
+                _ => None,
+            };
+
+        let device = match device_create(&uri).await {
+            Ok(device) => device,
+            Err(e) => {
+                self.disallow_added_host(added_host).await;
+                return Err(Error::LvolConnect {
+                    source: e,
+                    name: self.name(),
+                });
+            }
+        };
+
+        let result = async {
+            let hdl = device_open(&device, false)?.into_handle()?;
+            match action {
+                ReservationAction::Report => {}
+                ReservationAction::Preempt {
+                    key,
+                    preempt_key,
+                } => {
+                    // take over the reservation with the type it was held
+                    // with, then drop our own registration again
+                    let rtype = match reservation_report(&*hdl).await?.rtype {
+                        0 => nvme_reservation_type::WRITE_EXCLUSIVE_ALL_REGS,
+                        rtype => rtype,
+                    };
+                    preempt_reservation(&*hdl, key, preempt_key, rtype).await?;
+                    unregister_key(&*hdl, key).await?;
+                }
+                ReservationAction::Clear {
+                    key,
+                } => clear_reservations(&*hdl, key).await?,
+            }
+            reservation_report(&*hdl).await
+        }
+        .await
+        .map_err(|e: CoreError| Error::LvolReservation {
+            source: e,
+            name: self.name(),
+        });
+
+        if let Err(e) = device_destroy(&uri).await {
+            warn!("{}: failed to disconnect from {}: {}", self, uri, e);
+        }
+        self.disallow_added_host(added_host).await;
+
+        result
+    }
+
+    /// disallow the host NQN of this node again once done with the share
+    async fn disallow_added_host(
+        &self,
+        added: Option<(NvmfSubsystem, String)>,
+    ) {
+        if let Some((subsystem, nqn)) = added {
+            if let Err(e) = subsystem.disallow_host(&nqn).await {
+                warn!("{}: failed to disallow host {}: {}", self, nqn, e);
+            }
+        }
+    }
+
+    /// returns the lvs this lvol belongs to
+    pub(crate) fn lvs(&self) -> Lvs {
+        unsafe { Lvs(NonNull::new_unchecked(self.0.as_ref().lvol_store)) }
+    }
+
     /// destroy the lvol
     pub async fn destroy(mut self) -> Result<String, Error> {
         extern "C" fn destroy_cb(sender: *mut c_void, errno: i32) {
@@ -341,26 +763,53 @@ impl Lvol {
         if self.is_read_only() {
             warn!("{} is read-only", self.name());
         }
-        match prop {
-            PropValue::Shared(val) => {
-                let name = PropName::from(prop).to_string().into_cstring();
-                let value = if val { "true" } else { "false" }.into_cstring();
-                unsafe {
-                    spdk_blob_set_xattr(
-                        blob,
-                        name.as_ptr(),
-                        value.as_bytes_with_nul().as_ptr() as *const _,
-                        value.as_bytes_with_nul().len() as u16,
-                    )
-                }
-                .to_result(|e| Error::SetProperty {
-                    source: Errno::from_i32(e),
-                    prop: prop.into(),
-                    name: self.name(),
-                })?;
+
+        let value = match &prop {
+            PropValue::Shared(val) | PropValue::AllowAnyHost(val) => {
+                if *val { "true" } else { "false" }.to_string()
             }
+            PropValue::Labels(labels) => serde_json::to_string(labels)
+                .map_err(|_| Error::Property {
+                    source: Errno::EINVAL,
+                    name: self.name(),
+                })?,
+            PropValue::RateLimits(limits) => serde_json::to_string(limits)
+                .map_err(|_| Error::Property {
+                    source: Errno::EINVAL,
+                    name: self.name(),
+                })?,
+            PropValue::AllowedHosts(hosts) => serde_json::to_string(hosts)
+                .map_err(|_| Error::Property {
+                    source: Errno::EINVAL,
+                    name: self.name(),
+                })?,
         };
 
+        let name = PropName::from(&prop).to_string().into_cstring();
+        let value = value.into_cstring();
+
+        // the length of an xattr value is limited to 16 bits
+        let value_len = u16::try_from(value.as_bytes_with_nul().len())
+            .map_err(|_| Error::SetProperty {
+                source: Errno::E2BIG,
+                prop: PropName::from(&prop),
+                name: self.name(),
+            })?;
+
+        unsafe {
+            spdk_blob_set_xattr(
+                blob,
+                name.as_ptr(),
+                value.as_bytes_with_nul().as_ptr() as *const _,
+                value_len,
+            )
+        }
+        .to_result(|e| Error::SetProperty {
+            source: Errno::from_i32(e),
+            prop: PropName::from(&prop),
+            name: self.name(),
+        })?;
+
         let (s, r) = pair::<i32>();
         unsafe {
             spdk_blob_sync_md(blob, Some(Self::blob_sync_cb), cb_arg(s));
@@ -376,39 +825,76 @@ impl Lvol {
         Ok(())
     }
 
-    /// get/read a property from this lvol from disk
-    pub async fn get(&self, prop: PropName) -> Result<PropValue, Error> {
+    /// read the raw value of the xattr belonging to the property
+    fn get_xattr(&self, prop: PropName) -> Result<String, Error> {
         let blob = unsafe { self.0.as_ref().blob };
         assert!(!blob.is_null());
 
+        let name = prop.to_string().into_cstring();
+        let mut value: *const libc::c_char = std::ptr::null::<libc::c_char>();
+        let mut value_len: u64 = 0;
+        unsafe {
+            spdk_blob_get_xattr_value(
+                blob,
+                name.as_ptr(),
+                &mut value as *mut *const c_char as *mut *const c_void,
+                &mut value_len,
+            )
+        }
+        .to_result(|e| Error::GetProperty {
+            source: Errno::from_i32(e),
+            prop,
+            name: self.name(),
+        })?;
+
+        match unsafe { CStr::from_ptr(value).to_str() } {
+            Ok(value) => Ok(value.to_string()),
+            Err(_) => Err(Error::Property {
+                source: Errno::EINVAL,
+                name: self.name(),
+            }),
+        }
+    }
+
+    /// get/read a property from this lvol from disk
+    pub async fn get(&self, prop: PropName) -> Result<PropValue, Error> {
         match prop {
-            PropName::Shared => {
-                let name = prop.to_string().into_cstring();
-                let mut value: *const libc::c_char =
-                    std::ptr::null::<libc::c_char>();
-                let mut value_len: u64 = 0;
-                unsafe {
-                    spdk_blob_get_xattr_value(
-                        blob,
-                        name.as_ptr(),
-                        &mut value as *mut *const c_char as *mut *const c_void,
-                        &mut value_len,
-                    )
-                }
-                .to_result(|e| Error::GetProperty {
-                    source: Errno::from_i32(e),
-                    prop,
+            PropName::Shared => match self.get_xattr(prop)?.as_str() {
+                "true" => Ok(PropValue::Shared(true)),
+                "false" => Ok(PropValue::Shared(false)),
+                _ => Err(Error::Property {
+                    source: Errno::EINVAL,
                     name: self.name(),
-                })?;
-                match unsafe { CStr::from_ptr(value).to_str() } {
-                    Ok("true") => Ok(PropValue::Shared(true)),
-                    Ok("false") => Ok(PropValue::Shared(false)),
-                    _ => Err(Error::Property {
+                }),
+            },
+            PropName::Labels => Ok(PropValue::Labels(self.labels())),
+            PropName::RateLimits => {
+                serde_json::from_str(&self.get_xattr(prop)?)
+                    .map(PropValue::RateLimits)
+                    .map_err(|_| Error::Property {
                         source: Errno::EINVAL,
                         name: self.name(),
-                    }),
-                }
+                    })
             }
+            PropName::AllowedHosts => {
+                Ok(PropValue::AllowedHosts(self.stored_allowed_hosts()))
+            }
+            PropName::AllowAnyHost => {
+                Ok(PropValue::AllowAnyHost(self.stored_allow_any_host()))
+            }
+        }
+    }
+
+    /// returns the labels of this lvol, lvols created before labels were
+    /// introduced have none
+    pub fn labels(&self) -> Labels {
+        match self.get_xattr(PropName::Labels) {
+            Ok(value) => serde_json::from_str(&value).unwrap_or_else(|e| {
+                warn!("{}: ignoring invalid labels: {}", self, e);
+                Labels::new()
+            }),
+            // the xattr does not exist
+            Err(_) => Labels::new(),
         }
     }
 
@@ -461,35 +947,31 @@ impl Lvol {
         info!("Creating snapshot {} on {}", snapshot_name, &self);
     }
 
-    /// Create snapshot for local replica
+    /// Create snapshot for local replica, as for the local children of a
+    /// nexus
     pub async fn create_snapshot_local(
         &self,
-        io: *mut spdk_bdev_io,
         snapshot_name: &str,
-    ) {
-        extern "C" fn snapshot_done_cb(
-            bio_ptr: *mut c_void,
-            _lvol_ptr: *mut spdk_lvol,
-            errno: i32,
-        ) {
-            if errno != 0 {
-                error!("vbdev_lvol_create_snapshot errno {}", errno);
-            }
-            // Must complete IO on thread IO was submitted from
-            Mthread::from(unsafe { spdk_bdev_io_get_thread(bio_ptr.cast()) })
-                .with(|| Nexus::io_completion_local(errno == 0, bio_ptr));
-        }
-
+    ) -> Result<(), Error> {
         let c_snapshot_name = snapshot_name.into_cstring();
+        let (s, r) = pair::<ErrnoResult<*mut spdk_lvol>>();
         unsafe {
             vbdev_lvol_create_snapshot(
                 self.0.as_ptr(),
                 c_snapshot_name.as_ptr(),
-                Some(snapshot_done_cb),
-                io.cast(),
+                Some(Self::lvol_cb),
+                cb_arg(s),
             )
         };
 
         info!("Creating snapshot {} on {}", snapshot_name, &self);
+
+        r.await
+            .expect("lvol snapshot callback is gone")
+            .map_err(|e| Error::RepSnapshot {
+                source: e,
+                name: self.name(),
+            })?;
+        Ok(())
     }
 }
@@ -0,0 +1,897 @@
+//! Offline consistency check of the metadata of a lvol store.
+//!
+//! The check loads the blobstore which lives on the base bdev of the pool
+//! directly, without going through the lvol layer. This way we can inspect
+//! the metadata of a pool that fails to import. Every blob is validated
+//! against the metadata the lvol layer expects (name and uuid xattrs) and the
+//! clusters owned by the blobs are compared with the used cluster mask of the
+//! blobstore. Optionally, orphaned blobs are deleted and leaked clusters are
+//! reclaimed.
+//!
+//! Blobs which share a name or uuid with another blob are all reported as
+//! conflicting, as there is no telling which of them holds the data the user
+//! cares about. A repair only deletes the conflicting blobs it is explicitly
+//! asked to delete.
+
+use std::{
+    collections::HashMap,
+    ffi::CStr,
+    mem::size_of,
+    os::raw::{c_char, c_void},
+    ptr,
+};
+
+use futures::channel::oneshot;
+use nix::errno::Errno;
+use serde::Serialize;
+use spdk_rs::libspdk::{
+    spdk_bdev,
+    spdk_bdev_create_bs_dev_ext,
+    spdk_bdev_event_type,
+    spdk_blob,
+    spdk_blob_calc_used_clusters,
+    spdk_blob_close,
+    spdk_blob_get_id,
+    spdk_blob_get_num_clusters,
+    spdk_blob_get_xattr_value,
+    spdk_blob_id,
+    spdk_blob_is_clone,
+    spdk_blob_is_snapshot,
+    spdk_blob_is_thin_provisioned,
+    spdk_blob_store,
+    spdk_bs_delete_blob,
+    spdk_bs_dev,
+    spdk_bs_free_cluster_count,
+    spdk_bs_get_cluster_size,
+    spdk_bs_get_super,
+    spdk_bs_iter_first,
+    spdk_bs_iter_next,
+    spdk_bs_load,
+    spdk_bs_open_blob,
+    spdk_bs_opts,
+    spdk_bs_opts_init,
+    spdk_bs_total_data_cluster_count,
+    spdk_bs_unload,
+};
+
+use crate::{
+    bdev::{device_open, uri},
+    core::UntypedBdev,
+    ffihelper::{
+        cb_arg,
+        errno_result_from_i32,
+        pair,
+        ErrnoResult,
+        FfiResult,
+        IntoCString,
+    },
+    lvs::{Error, Lvs},
+    nexus_uri::NexusBdevError,
+    pool::PoolArgs,
+};
+
+/// blobstore type as written by the lvol layer
+const LVS_BSTYPE: &str = "LVOLSTORE";
+/// layout of the super block of a blobstore, see struct spdk_bs_super_block
+const BS_SUPER_BLOCK_SIZE: u64 = 4096;
+const BS_SUPER_SIGNATURE: &[u8] = b"SPDKBLOB";
+const BS_SUPER_BSTYPE_OFFSET: usize = 60;
+const BS_BSTYPE_LEN: usize = 16;
+/// xattr names used by the lvol layer for both the lvs and the lvols
+const XATTR_NAME: &str = "name";
+const XATTR_UUID: &str = "uuid";
+
+/// reason why a blob can not be turned into a lvol (and hence a bdev)
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum OrphanReason {
+    /// the name xattr is missing, typically an interrupted lvol create
+    MissingName,
+    /// the uuid xattr is missing or does not contain a valid uuid
+    InvalidUuid,
+    /// other blobs with the same name exist within the store
+    DuplicateName,
+    /// other blobs with the same uuid exist within the store
+    DuplicateUuid,
+}
+
+/// a blob that would not be exposed as a bdev when importing the pool
+#[derive(Debug, Clone, Serialize)]
+pub struct OrphanedBlob {
+    pub blob_id: u64,
+    pub name: Option<String>,
+    pub uuid: Option<String>,
+    pub reason: OrphanReason,
+    /// ids of the other blobs with the same name or uuid
+    pub conflicts_with: Vec<u64>,
+    pub clusters: u64,
+    /// set when the blob has been deleted as part of a repair
+    pub reclaimed: bool,
+}
+
+impl OrphanedBlob {
+    /// returns true if the blob conflicts with other blobs, in which case it
+    /// is only deleted when explicitly asked for
+    pub fn is_conflict(&self) -> bool {
+        matches!(
+            self.reason,
+            OrphanReason::DuplicateName | OrphanReason::DuplicateUuid
+        )
+    }
+}
+
+/// a blob that passed validation and will be exposed as a lvol
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckedLvol {
+    pub blob_id: u64,
+    pub name: String,
+    pub uuid: String,
+    pub thin: bool,
+    pub snapshot: bool,
+    pub clone: bool,
+    pub num_clusters: u64,
+    pub used_clusters: u64,
+}
+
+/// result of a consistency check of a single lvol store
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct LvsCheckReport {
+    pub name: String,
+    pub uuid: String,
+    pub disk: String,
+    pub cluster_size: u64,
+    pub total_clusters: u64,
+    pub free_clusters: u64,
+    /// clusters which are marked as used but are not owned by any blob
+    pub leaked_clusters: u64,
+    /// clusters which are owned by a blob but are marked as free, new lvols
+    /// may overwrite the data of existing ones
+    pub unmarked_clusters: u64,
+    pub lvols: Vec<CheckedLvol>,
+    pub orphaned: Vec<OrphanedBlob>,
+    /// errors encountered while repairing the pool
+    pub errors: Vec<String>,
+    /// true when repair was requested and carried out
+    pub repaired: bool,
+}
+
+impl LvsCheckReport {
+    /// returns true if no inconsistencies were found
+    pub fn is_clean(&self) -> bool {
+        self.leaked_clusters == 0
+            && self.unmarked_clusters == 0
+            && self.orphaned.is_empty()
+            && self.errors.is_empty()
+    }
+
+    /// returns true if the pool was repaired and no orphaned blobs remain
+    pub fn is_repaired(&self) -> bool {
+        self.repaired
+            && self.errors.is_empty()
+            && self.orphaned.iter().all(|o| o.reclaimed)
+    }
+}
+
+/// metadata of a blob as found when walking the store
+#[derive(Debug, Clone, Default)]
+struct BlobInfo {
+    blob_id: u64,
+    name: Option<String>,
+    uuid: Option<String>,
+    thin: bool,
+    snapshot: bool,
+    clone: bool,
+    num_clusters: u64,
+    used_clusters: u64,
+}
+
+/// Sort the blobs into the ones that are exposed as lvols on import and the
+/// ones that are not. All blobs sharing a name or uuid are orphaned.
+fn classify(blobs: &[BlobInfo]) -> (Vec<CheckedLvol>, Vec<OrphanedBlob>) {
+    let valid_uuid = |b: &BlobInfo| {
+        b.uuid
+            .as_ref()
+            .map(|u| uuid::Uuid::parse_str(u).is_ok())
+            .unwrap_or(false)
+    };
+
+    let mut names: HashMap<&str, Vec<u64>> = HashMap::new();
+    let mut uuids: HashMap<&str, Vec<u64>> = HashMap::new();
+    for b in blobs.iter().filter(|b| b.name.is_some() && valid_uuid(b)) {
+        names
+            .entry(b.name.as_deref().unwrap())
+            .or_default()
+            .push(b.blob_id);
+        uuids
+            .entry(b.uuid.as_deref().unwrap())
+            .or_default()
+            .push(b.blob_id);
+    }
+
+    let others = |ids: &[u64], id: u64| {
+        ids.iter().copied().filter(|i| *i != id).collect::<Vec<_>>()
+    };
+
+    let mut lvols = Vec::new();
+    let mut orphaned = Vec::new();
+    for b in blobs {
+        let (reason, conflicts_with) = match &b.name {
+            None => (Some(OrphanReason::MissingName), Vec::new()),
+            Some(_) if !valid_uuid(b) => {
+                (Some(OrphanReason::InvalidUuid), Vec::new())
+            }
+            Some(name) if names[name.as_str()].len() > 1 => (
+                Some(OrphanReason::DuplicateName),
+                others(&names[name.as_str()], b.blob_id),
+            ),
+            Some(_) if uuids[b.uuid.as_deref().unwrap()].len() > 1 => (
+                Some(OrphanReason::DuplicateUuid),
+                others(&uuids[b.uuid.as_deref().unwrap()], b.blob_id),
+            ),
+            Some(_) => (None, Vec::new()),
+        };
+
+        match reason {
+            Some(reason) => orphaned.push(OrphanedBlob {
+                blob_id: b.blob_id,
+                name: b.name.clone(),
+                uuid: b.uuid.clone(),
+                reason,
+                conflicts_with,
+                clusters: b.used_clusters,
+                reclaimed: false,
+            }),
+            None => lvols.push(CheckedLvol {
+                blob_id: b.blob_id,
+                name: b.name.clone().unwrap(),
+                uuid: b.uuid.clone().unwrap(),
+                thin: b.thin,
+                snapshot: b.snapshot,
+                clone: b.clone,
+                num_clusters: b.num_clusters,
+                used_clusters: b.used_clusters,
+            }),
+        }
+    }
+
+    (lvols, orphaned)
+}
+
+/// loaded blobstore of a pool which is checked
+struct BlobStore(*mut spdk_blob_store);
+
+impl BlobStore {
+    extern "C" fn bs_event_cb(
+        event: spdk_bdev_event_type,
+        bdev: *mut spdk_bdev,
+        _ctx: *mut c_void,
+    ) {
+        // the blobstore is only loaded for the duration of the check, there
+        // is nothing to do about the events other than letting the user
+        // know
+        warn!(?event, ?bdev, "event on bdev while checking the pool");
+    }
+
+    extern "C" fn bs_op_with_handle_cb(
+        sender_ptr: *mut c_void,
+        bs: *mut spdk_blob_store,
+        errno: i32,
+    ) {
+        let sender = unsafe {
+            Box::from_raw(
+                sender_ptr
+                    as *mut oneshot::Sender<ErrnoResult<*mut spdk_blob_store>>,
+            )
+        };
+        sender
+            .send(errno_result_from_i32(bs, errno))
+            .expect("receiver gone");
+    }
+
+    extern "C" fn blob_op_with_handle_cb(
+        sender_ptr: *mut c_void,
+        blob: *mut spdk_blob,
+        errno: i32,
+    ) {
+        let sender = unsafe {
+            Box::from_raw(
+                sender_ptr as *mut oneshot::Sender<ErrnoResult<*mut spdk_blob>>,
+            )
+        };
+        sender
+            .send(errno_result_from_i32(blob, errno))
+            .expect("receiver gone");
+    }
+
+    extern "C" fn blob_op_with_id_cb(
+        sender_ptr: *mut c_void,
+        id: spdk_blob_id,
+        errno: i32,
+    ) {
+        let sender = unsafe {
+            Box::from_raw(
+                sender_ptr as *mut oneshot::Sender<ErrnoResult<spdk_blob_id>>,
+            )
+        };
+        sender
+            .send(errno_result_from_i32(id, errno))
+            .expect("receiver gone");
+    }
+
+    extern "C" fn op_cb(sender_ptr: *mut c_void, errno: i32) {
+        let sender =
+            unsafe { Box::from_raw(sender_ptr as *mut oneshot::Sender<i32>) };
+        sender.send(errno).expect("receiver gone");
+    }
+
+    /// Returns true if the super block on the given bdev belongs to a lvol
+    /// store. Unlike loading the blobstore, which marks it dirty on disk,
+    /// this only reads from the bdev.
+    async fn has_lvs_super(bdev: &str) -> Result<bool, Errno> {
+        let handle = device_open(bdev, false)
+            .and_then(|d| d.into_handle())
+            .map_err(|_| Errno::ENODEV)?;
+        let mut buf = handle
+            .dma_malloc(BS_SUPER_BLOCK_SIZE)
+            .map_err(|_| Errno::ENOMEM)?;
+        handle.read_at(0, &mut buf).await.map_err(|_| Errno::EIO)?;
+
+        let block = buf.as_slice();
+        let bstype = &block
+            [BS_SUPER_BSTYPE_OFFSET .. BS_SUPER_BSTYPE_OFFSET + BS_BSTYPE_LEN];
+        let (name, padding) = bstype.split_at(LVS_BSTYPE.len());
+
+        Ok(block.starts_with(BS_SUPER_SIGNATURE)
+            && name == LVS_BSTYPE.as_bytes()
+            && padding.iter().all(|b| *b == 0))
+    }
+
+    /// load the blobstore from the given bdev, when recover is set the used
+    /// cluster mask is rebuilt from the metadata of the blobs
+    async fn load(bdev: &str, recover: bool) -> Result<Self, Errno> {
+        let mut bs_dev: *mut spdk_bs_dev = ptr::null_mut();
+        let cname = bdev.into_cstring();
+
+        unsafe {
+            spdk_bdev_create_bs_dev_ext(
+                cname.as_ptr(),
+                Some(Self::bs_event_cb),
+                ptr::null_mut(),
+                &mut bs_dev,
+            )
+        }
+        .to_result(|e| Errno::from_i32(e.abs()))?;
+
+        let mut opts: spdk_bs_opts = Default::default();
+        unsafe {
+            spdk_bs_opts_init(&mut opts, size_of::<spdk_bs_opts>() as u64)
+        };
+
+        for (dst, src) in opts
+            .bstype
+            .bstype
+            .iter_mut()
+            .zip(LVS_BSTYPE.as_bytes().iter())
+        {
+            *dst = *src as c_char;
+        }
+        opts.force_recover = recover;
+
+        let (s, r) = pair::<ErrnoResult<*mut spdk_blob_store>>();
+        unsafe {
+            spdk_bs_load(
+                bs_dev,
+                &mut opts,
+                Some(Self::bs_op_with_handle_cb),
+                cb_arg(s),
+            )
+        };
+
+        r.await
+            .expect("blobstore load callback is gone")
+            .map(BlobStore)
+    }
+
+    /// unload the blobstore, this persists the used cluster mask and marks
+    /// the store clean
+    async fn unload(self) -> Result<(), Errno> {
+        let (s, r) = pair::<i32>();
+        unsafe { spdk_bs_unload(self.0, Some(Self::op_cb), cb_arg(s)) };
+        r.await
+            .expect("blobstore unload callback is gone")
+            .to_result(|e| Errno::from_i32(e.abs()))
+    }
+
+    fn cluster_size(&self) -> u64 {
+        unsafe { spdk_bs_get_cluster_size(self.0) }
+    }
+
+    fn total_clusters(&self) -> u64 {
+        unsafe { spdk_bs_total_data_cluster_count(self.0) }
+    }
+
+    fn free_clusters(&self) -> u64 {
+        unsafe { spdk_bs_free_cluster_count(self.0) }
+    }
+
+    /// returns the id of the super blob, which holds the lvs metadata
+    async fn super_blob(&self) -> Result<spdk_blob_id, Errno> {
+        let (s, r) = pair::<ErrnoResult<spdk_blob_id>>();
+        unsafe {
+            spdk_bs_get_super(self.0, Some(Self::blob_op_with_id_cb), cb_arg(s))
+        };
+        r.await.expect("get super callback is gone")
+    }
+
+    async fn open_blob(
+        &self,
+        id: spdk_blob_id,
+    ) -> Result<*mut spdk_blob, Errno> {
+        let (s, r) = pair::<ErrnoResult<*mut spdk_blob>>();
+        unsafe {
+            spdk_bs_open_blob(
+                self.0,
+                id,
+                Some(Self::blob_op_with_handle_cb),
+                cb_arg(s),
+            )
+        };
+        r.await.expect("open blob callback is gone")
+    }
+
+    async fn close_blob(blob: *mut spdk_blob) -> Result<(), Errno> {
+        let (s, r) = pair::<i32>();
+        unsafe { spdk_blob_close(blob, Some(Self::op_cb), cb_arg(s)) };
+        r.await
+            .expect("close blob callback is gone")
+            .to_result(|e| Errno::from_i32(e.abs()))
+    }
+
+    async fn delete_blob(&self, id: spdk_blob_id) -> Result<(), Errno> {
+        let (s, r) = pair::<i32>();
+        unsafe {
+            spdk_bs_delete_blob(self.0, id, Some(Self::op_cb), cb_arg(s))
+        };
+        r.await
+            .expect("delete blob callback is gone")
+            .to_result(|e| Errno::from_i32(e.abs()))
+    }
+
+    /// returns the first blob of the store, Ok(None) when the store is empty
+    async fn iter_first(&self) -> Result<Option<*mut spdk_blob>, Errno> {
+        let (s, r) = pair::<ErrnoResult<*mut spdk_blob>>();
+        unsafe {
+            spdk_bs_iter_first(
+                self.0,
+                Some(Self::blob_op_with_handle_cb),
+                cb_arg(s),
+            )
+        };
+        Self::iter_result(r.await.expect("iter callback is gone"))
+    }
+
+    /// returns the next blob, the current blob is closed by the blobstore
+    async fn iter_next(
+        &self,
+        blob: *mut spdk_blob,
+    ) -> Result<Option<*mut spdk_blob>, Errno> {
+        let (s, r) = pair::<ErrnoResult<*mut spdk_blob>>();
+        unsafe {
+            spdk_bs_iter_next(
+                self.0,
+                blob,
+                Some(Self::blob_op_with_handle_cb),
+                cb_arg(s),
+            )
+        };
+        Self::iter_result(r.await.expect("iter callback is gone"))
+    }
+
+    fn iter_result(
+        r: ErrnoResult<*mut spdk_blob>,
+    ) -> Result<Option<*mut spdk_blob>, Errno> {
+        match r {
+            Ok(blob) => Ok(Some(blob)),
+            Err(Errno::ENOENT) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// returns the name and uuid of the lvs as stored in the super blob,
+    /// EILSEQ is returned when either of them is missing
+    async fn lvs_identity(&self) -> Result<(String, String), Errno> {
+        let super_id = self.super_blob().await?;
+        let super_blob = self.open_blob(super_id).await?;
+        let name = Self::xattr(super_blob, XATTR_NAME);
+        let uuid = Self::xattr(super_blob, XATTR_UUID);
+        Self::close_blob(super_blob).await?;
+
+        match (name, uuid) {
+            (Some(name), Some(uuid)) => Ok((name, uuid)),
+            _ => Err(Errno::EILSEQ),
+        }
+    }
+
+    /// read a string xattr from the blob
+    fn xattr(blob: *mut spdk_blob, name: &str) -> Option<String> {
+        let cname = name.into_cstring();
+        let mut value: *const c_void = ptr::null();
+        let mut value_len: u64 = 0;
+
+        let rc = unsafe {
+            spdk_blob_get_xattr_value(
+                blob,
+                cname.as_ptr(),
+                &mut value,
+                &mut value_len,
+            )
+        };
+
+        if rc != 0 || value.is_null() || value_len == 0 {
+            return None;
+        }
+
+        // the lvol layer stores the values including the terminating nul
+        unsafe { CStr::from_ptr(value as *const c_char) }
+            .to_str()
+            .ok()
+            .map(String::from)
+    }
+}
+
+impl Lvs {
+    /// Returns the name and uuid of the pool on the given bdev without
+    /// importing it. The bdev must exist and must not be claimed. When the
+    /// bdev does not contain a pool, EILSEQ is returned and nothing is
+    /// written to the bdev.
+    pub async fn probe(bdev: &str) -> Result<(String, String), Error> {
+        let probe_err = |source: Errno| Error::Import {
+            source,
+            name: bdev.to_string(),
+        };
+
+        let claimed = UntypedBdev::lookup_by_name(bdev)
+            .map(|b| b.is_claimed())
+            .ok_or_else(|| probe_err(Errno::ENODEV))?;
+        if claimed {
+            return Err(probe_err(Errno::EBUSY));
+        }
+
+        // loading the blobstore writes to the bdev, make sure it holds a
+        // pool before doing so
+        if !BlobStore::has_lvs_super(bdev).await.map_err(probe_err)? {
+            return Err(probe_err(Errno::EILSEQ));
+        }
+
+        let bs = BlobStore::load(bdev, false).await.map_err(probe_err)?;
+        let identity = bs.lvs_identity().await;
+        bs.unload().await.map_err(probe_err)?;
+
+        identity.map_err(probe_err)
+    }
+
+    /// Check the metadata of the pool that lives on the disk given in args.
+    /// The pool must not be imported. When repair is set, orphaned blobs are
+    /// deleted and leaked clusters are returned to the free pool. Blobs that
+    /// conflict with other blobs are only deleted when their id is listed in
+    /// delete. The base bdev is created when it does not exist and destroyed
+    /// when done, just as with an import followed by an export.
+    #[tracing::instrument(level = "debug", err)]
+    pub async fn check(
+        args: PoolArgs,
+        repair: bool,
+        delete: &[u64],
+    ) -> Result<LvsCheckReport, Error> {
+        let disk = Self::parse_disk(args.disks.clone())?;
+
+        let parsed = uri::parse(&disk).map_err(|e| Error::InvalidBdev {
+            source: e,
+            name: args.name.clone(),
+        })?;
+
+        if let Some(pool) = Self::lookup(&args.name) {
+            return Err(Error::Check {
+                source: Errno::EBUSY,
+                name: format!("pool {} is imported", pool.name()),
+            });
+        }
+
+        let (bdev, created) = match parsed.create().await {
+            Err(NexusBdevError::BdevExists {
+                ..
+            }) => (parsed.get_name(), false),
+            Err(e) => {
+                return Err(Error::InvalidBdev {
+                    source: e,
+                    name: args.disks[0].clone(),
+                })
+            }
+            Ok(name) => (name, true),
+        };
+
+        let claimed = UntypedBdev::lookup_by_name(&bdev)
+            .map(|b| b.is_claimed())
+            .unwrap_or(true);
+
+        let result = if claimed {
+            Err(Error::Check {
+                source: Errno::EBUSY,
+                name: args.name.clone(),
+            })
+        } else {
+            Self::check_bdev(&args, &bdev, &disk, repair, delete).await
+        };
+
+        // leave a base bdev that existed before the check alone
+        if created {
+            if let Err(e) = parsed.destroy().await {
+                error!(
+                    "failed to destroy base bdev {} after checking pool {}: {}",
+                    bdev,
+                    args.name,
+                    e.to_string()
+                );
+            }
+        }
+
+        result
+    }
+
+    async fn check_bdev(
+        args: &PoolArgs,
+        bdev: &str,
+        disk: &str,
+        repair: bool,
+        delete: &[u64],
+    ) -> Result<LvsCheckReport, Error> {
+        let check_err = |source: Errno| Error::Check {
+            source,
+            name: args.name.clone(),
+        };
+
+        let bs = BlobStore::load(bdev, false).await.map_err(check_err)?;
+
+        let (mut report, blobs) = match Self::walk_blobs(&bs, args).await {
+            Ok(walked) => walked,
+            Err(e) => {
+                let _ = bs.unload().await;
+                return Err(e);
+            }
+        };
+        report.disk = disk.to_string();
+
+        // only conflicting blobs are deleted on request, refuse anything
+        // else before touching the store
+        if let Some(id) = delete.iter().find(|id| {
+            !report
+                .orphaned
+                .iter()
+                .any(|o| o.blob_id == **id && o.is_conflict())
+        }) {
+            let _ = bs.unload().await;
+            return Err(Error::Check {
+                source: Errno::EINVAL,
+                name: format!(
+                    "blob {:#x} of pool {} is not a conflicting blob",
+                    id, args.name
+                ),
+            });
+        }
+
+        if !repair || report.is_clean() {
+            bs.unload().await.map_err(check_err)?;
+            return Ok(report);
+        }
+
+        let mut reclaimed = Vec::new();
+        for orphan in report.orphaned.iter_mut() {
+            if orphan.is_conflict() && !delete.contains(&orphan.blob_id) {
+                continue;
+            }
+
+            match bs.delete_blob(orphan.blob_id).await {
+                Ok(_) => {
+                    info!(
+                        "pool {}: deleted orphaned blob {:#x} ({:?})",
+                        report.name, orphan.blob_id, orphan.reason
+                    );
+                    orphan.reclaimed = true;
+                    reclaimed.push(orphan.clone());
+                }
+                Err(e) => report.errors.push(format!(
+                    "failed to delete orphaned blob {:#x}: {}",
+                    orphan.blob_id, e
+                )),
+            }
+        }
+
+        // a conflict is resolved once a single blob of it remains, which
+        // then is exposed as a lvol again
+        let remaining: Vec<BlobInfo> = blobs
+            .into_iter()
+            .filter(|b| !reclaimed.iter().any(|o| o.blob_id == b.blob_id))
+            .collect();
+        let (lvols, orphaned) = classify(&remaining);
+        report.lvols = lvols;
+        report.orphaned = reclaimed;
+        report.orphaned.extend(orphaned);
+
+        bs.unload().await.map_err(check_err)?;
+
+        // a load with forced recovery rebuilds the used cluster mask from
+        // the blob metadata, the subsequent unload persists it
+        if report.leaked_clusters > 0 || report.unmarked_clusters > 0 {
+            let bs = BlobStore::load(bdev, true).await.map_err(check_err)?;
+            let free = bs.free_clusters();
+            bs.unload().await.map_err(check_err)?;
+            info!(
+                "pool {}: rebuilt used cluster mask, free clusters {} -> {}",
+                report.name, report.free_clusters, free
+            );
+            report.free_clusters = free;
+        }
+
+        report.repaired = true;
+        Ok(report)
+    }
+
+    /// validate the super blob and all blobs within the store
+    async fn walk_blobs(
+        bs: &BlobStore,
+        args: &PoolArgs,
+    ) -> Result<(LvsCheckReport, Vec<BlobInfo>), Error> {
+        let check_err = |source: Errno| Error::Check {
+            source,
+            name: args.name.clone(),
+        };
+
+        // without the lvs metadata there is nothing sensible we can say
+        // about the blobs within the store
+        let (name, uuid) = bs.lvs_identity().await.map_err(check_err)?;
+        let super_id = bs.super_blob().await.map_err(check_err)?;
+
+        if name != args.name {
+            return Err(Error::Check {
+                source: Errno::EINVAL,
+                name: format!(
+                    "a pool currently exists on the device with name: {}",
+                    name
+                ),
+            });
+        }
+
+        if args.uuid.is_some() && args.uuid.as_ref() != Some(&uuid) {
+            return Err(Error::Check {
+                source: Errno::EINVAL,
+                name: format!(
+                    "invalid uuid {}, found pool with uuid {}",
+                    args.uuid.as_ref().unwrap(),
+                    uuid
+                ),
+            });
+        }
+
+        let mut report = LvsCheckReport {
+            name,
+            uuid,
+            cluster_size: bs.cluster_size(),
+            total_clusters: bs.total_clusters(),
+            free_clusters: bs.free_clusters(),
+            ..Default::default()
+        };
+
+        let mut blobs = Vec::new();
+        let mut next = bs.iter_first().await.map_err(check_err)?;
+        while let Some(blob) = next {
+            let blob_id = unsafe { spdk_blob_get_id(blob) };
+
+            if blob_id != super_id {
+                blobs.push(BlobInfo {
+                    blob_id,
+                    name: BlobStore::xattr(blob, XATTR_NAME),
+                    uuid: BlobStore::xattr(blob, XATTR_UUID),
+                    thin: unsafe { spdk_blob_is_thin_provisioned(blob) },
+                    snapshot: unsafe { spdk_blob_is_snapshot(blob) },
+                    clone: unsafe { spdk_blob_is_clone(blob) },
+                    num_clusters: unsafe { spdk_blob_get_num_clusters(blob) },
+                    used_clusters: unsafe {
+                        spdk_blob_calc_used_clusters(blob)
+                    },
+                });
+            }
+
+            next = bs.iter_next(blob).await.map_err(check_err)?;
+        }
+
+        let (lvols, orphaned) = classify(&blobs);
+        report.lvols = lvols;
+        report.orphaned = orphaned;
+
+        let owned_clusters: u64 = blobs.iter().map(|b| b.used_clusters).sum();
+        let marked_used = report.total_clusters - report.free_clusters;
+        if owned_clusters > marked_used {
+            report.unmarked_clusters = owned_clusters - marked_used;
+        } else {
+            report.leaked_clusters = marked_used - owned_clusters;
+        }
+
+        Ok((report, blobs))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{classify, BlobInfo, OrphanReason};
+
+    fn blob(id: u64, name: Option<&str>, uuid: Option<&str>) -> BlobInfo {
+        BlobInfo {
+            blob_id: id,
+            name: name.map(String::from),
+            uuid: uuid.map(String::from),
+            used_clusters: id,
+            ..Default::default()
+        }
+    }
+
+    const UUID1: &str = "0ad7c7cc-7a1c-4e3f-9a4c-2b2f8f5e6b01";
+    const UUID2: &str = "0ad7c7cc-7a1c-4e3f-9a4c-2b2f8f5e6b02";
+    const UUID3: &str = "0ad7c7cc-7a1c-4e3f-9a4c-2b2f8f5e6b03";
+
+    #[test]
+    fn lvs_check_classify() {
+        let (lvols, orphaned) = classify(&[
+            blob(1, Some("vol-1"), Some(UUID1)),
+            blob(2, None, Some(UUID2)),
+            blob(3, Some("vol-3"), Some("not-a-uuid")),
+            blob(4, Some("vol-4"), None),
+        ]);
+        assert_eq!(lvols.len(), 1);
+        assert_eq!(lvols[0].blob_id, 1);
+        assert_eq!(
+            orphaned
+                .iter()
+                .map(|o| o.reason.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                OrphanReason::MissingName,
+                OrphanReason::InvalidUuid,
+                OrphanReason::InvalidUuid
+            ]
+        );
+        assert!(orphaned.iter().all(|o| !o.is_conflict()));
+    }
+
+    #[test]
+    fn lvs_check_classify_conflicts() {
+        // all blobs sharing a name conflict, regardless of the order
+        let (lvols, orphaned) = classify(&[
+            blob(1, Some("vol"), Some(UUID1)),
+            blob(2, Some("vol"), Some(UUID2)),
+            blob(3, Some("other"), Some(UUID3)),
+        ]);
+        assert_eq!(lvols.len(), 1);
+        assert_eq!(lvols[0].blob_id, 3);
+        assert_eq!(orphaned.len(), 2);
+        assert!(orphaned
+            .iter()
+            .all(|o| o.reason == OrphanReason::DuplicateName));
+        assert_eq!(orphaned[0].conflicts_with, vec![2]);
+        assert_eq!(orphaned[1].conflicts_with, vec![1]);
+        assert_eq!(orphaned[1].clusters, 2);
+
+        let (lvols, orphaned) = classify(&[
+            blob(1, Some("vol-1"), Some(UUID1)),
+            blob(2, Some("vol-2"), Some(UUID1)),
+        ]);
+        assert!(lvols.is_empty());
+        assert!(orphaned
+            .iter()
+            .all(|o| o.reason == OrphanReason::DuplicateUuid));
+
+        // once all but one of the conflicting blobs are gone, the remaining
+        // one is a lvol again
+        let (lvols, orphaned) = classify(&[blob(2, Some("vol"), Some(UUID2))]);
+        assert_eq!(lvols.len(), 1);
+        assert!(orphaned.is_empty());
+    }
+}
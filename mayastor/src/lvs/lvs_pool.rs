@@ -7,6 +7,7 @@ use std::{
 };
 
 use futures::channel::oneshot;
+use merge::Merge;
 use nix::errno::Errno;
 use pin_utils::core_reexport::fmt::Formatter;
 use spdk_rs::libspdk::{
@@ -36,7 +37,15 @@ use url::Url;
 
 use crate::{
     bdev::uri,
-    core::{Bdev, IoType, Share, UntypedBdev},
+    core::{
+        Bdev,
+        BlockDeviceIoStats,
+        IoType,
+        LatencyHistogram,
+        LatencyPercentiles,
+        Share,
+        UntypedBdev,
+    },
     ffihelper::{cb_arg, pair, AsStr, ErrnoResult, FfiResult, IntoCString},
     lvs::{Error, Lvol, PropName, PropValue},
     nexus_uri::{bdev_destroy, NexusBdevError},
@@ -100,6 +109,23 @@ impl Debug for Lvs {
     }
 }
 
+/// I/O statistics and space usage of a lvol store
+#[derive(Debug, Default, Clone)]
+pub struct LvsStats {
+    pub io: BlockDeviceIoStats,
+    pub histogram: LatencyHistogram,
+    pub allocated_clusters: u64,
+    pub cluster_size: u64,
+    pub num_lvols: u64,
+}
+
+impl LvsStats {
+    /// latency percentiles in microseconds of all lvols in the store
+    pub fn latency(&self) -> LatencyPercentiles {
+        self.histogram.percentiles()
+    }
+}
+
 /// Logical Volume Store (LVS) stores the lvols
 pub struct Lvs(pub(crate) NonNull<spdk_lvol_store>);
 
@@ -193,6 +219,33 @@ impl Lvs {
         self.capacity() - self.available()
     }
 
+    /// returns the size of a cluster, the allocation unit of the store
+    pub fn cluster_size(&self) -> u64 {
+        let blobs = unsafe { self.0.as_ref().blobstore };
+        unsafe { spdk_bs_get_cluster_size(blobs) }
+    }
+
+    /// returns the statistics of the store, which are the accumulated
+    /// statistics of all its lvols
+    pub async fn stats(&self) -> Result<LvsStats, Error> {
+        let mut stats = LvsStats {
+            allocated_clusters: self.used() / self.cluster_size(),
+            cluster_size: self.cluster_size(),
+            ..Default::default()
+        };
+
+        if let Some(lvols) = self.lvols() {
+            for lvol in lvols {
+                let l = lvol.stats().await?;
+                stats.io.merge(l.io);
+                stats.histogram.merge(&l.histogram);
+                stats.num_lvols += 1;
+            }
+        }
+
+        Ok(stats)
+    }
+
     /// returns the base bdev of this lvs
     pub fn base_bdev(&self) -> UntypedBdev {
         unsafe {
@@ -210,7 +263,7 @@ impl Lvs {
     }
 
     // checks for the disks length and parses to correct format
-    fn parse_disk(disks: Vec<String>) -> Result<String, Error> {
+    pub(super) fn parse_disk(disks: Vec<String>) -> Result<String, Error> {
         let disk = match disks.first() {
             Some(disk) if disks.len() == 1 => {
                 if Url::parse(disk).is_err() {
@@ -537,10 +590,28 @@ impl Lvs {
     }
 
     /// share all lvols who have the shared property set, this is implicitly
-    /// shared over nvmf
+    /// shared over nvmf. Rate limits and allowed hosts stored on disk are
+    /// restored as well.
     async fn share_all(&self) {
         if let Some(lvols) = self.lvols() {
             for mut l in lvols {
+                match l.get(PropName::RateLimits).await {
+                    Ok(PropValue::RateLimits(limits))
+                        if !limits.is_unlimited() =>
+                    {
+                        if let Err(e) =
+                            l.as_bdev().set_rate_limits(&limits).await
+                        {
+                            error!(
+                                "failed to restore rate limits of {} {}",
+                                l.name(),
+                                e.to_string()
+                            );
+                        }
+                    }
+                    _ => {}
+                }
+
                 if let Ok(prop) = l.get(PropName::Shared).await {
                     match prop {
                         PropValue::Shared(true) => {
@@ -558,6 +629,11 @@ impl Lvs {
                         PropValue::Shared(false) => {
                             debug!("{} not shared on disk", l.name())
                         }
+                        _ => error!(
+                            "{}: unexpected shared property {:?}",
+                            l.name(),
+                            prop
+                        ),
                     }
                 }
             }
@@ -1,7 +1,9 @@
 pub use error::Error;
-pub use lvol::{Lvol, PropName, PropValue};
-pub use lvs_pool::Lvs;
+pub use lvol::{LabelSelector, Labels, Lvol, LvolStats, PropName, PropValue};
+pub use lvs_check::{CheckedLvol, LvsCheckReport, OrphanReason, OrphanedBlob};
+pub use lvs_pool::{Lvs, LvsStats};
 
 mod error;
 mod lvol;
+mod lvs_check;
 mod lvs_pool;
@@ -134,7 +134,7 @@ where
         Ok(device) if device.get_name() == bdev.name() => {
             bdev.driver()
                 == match uri.scheme() {
-                    "nvmf" | "pcie" => "nvme",
+                    "nvmf" | "nvmf+discovery" | "pcie" => "nvme",
                     scheme => scheme,
                 }
         }
@@ -151,7 +151,7 @@ where
         Ok(device) if device.get_name() == bdev.name() => {
             bdev.driver()
                 == match uri.scheme() {
-                    "nvmf" | "pcie" => "nvme",
+                    "nvmf" | "nvmf+discovery" | "pcie" => "nvme",
                     scheme => scheme,
                 }
         }
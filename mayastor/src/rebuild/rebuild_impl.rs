@@ -324,6 +324,30 @@ impl RebuildJob {
         let source_hdl = Self::get_io_handle(&*self.src_descriptor)?;
         let destination_hdl = Self::get_io_handle(&*self.dst_descriptor)?;
 
+        // Let the devices copy the segment themselves when they can, which
+        // saves transferring the data to us and back out. The segment is
+        // copied through us should that fail.
+        if source_hdl
+            .get_device()
+            .copy_supported(&*destination_hdl.get_device())
+        {
+            match source_hdl
+                .copy_blocks(
+                    blk,
+                    &*destination_hdl,
+                    blk,
+                    self.get_segment_size_blks(blk),
+                )
+                .await
+            {
+                Ok(_) => return Ok(()),
+                Err(e) => warn!(
+                    "{}: failed to offload the copy of segment {}, copying it through us: {}",
+                    self.source, blk, e
+                ),
+            }
+        }
+
         let copy_buffer = if self.get_segment_size_blks(blk)
             == self.segment_size_blks
         {
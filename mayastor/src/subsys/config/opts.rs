@@ -44,6 +44,15 @@ pub struct NexusOpts {
     /// NOTE: we do not (yet) differentiate between
     /// the nexus and replica nvmf target
     pub nvmf_replica_port: u16,
+    /// addresses, or names of network interfaces, over which nexuses are
+    /// exported to hosts, the pod address is used when empty
+    pub nvmf_host_addresses: Vec<String>,
+    /// addresses, or names of network interfaces, over which replicas are
+    /// exported to nexuses, the pod address is used when empty
+    pub nvmf_replica_addresses: Vec<String>,
+    /// directory holding the reservations of hosts which activated Persist
+    /// Through Power Loss, one file per nexus
+    pub nvmf_ptpl_dir: String,
 }
 
 /// Default nvmf port used for replicas.
@@ -59,6 +68,9 @@ impl Default for NexusOpts {
             nvmf_discovery_enable: true,
             nvmf_nexus_port: NVMF_PORT_NEXUS,
             nvmf_replica_port: NVMF_PORT_REPLICA,
+            nvmf_host_addresses: Vec::new(),
+            nvmf_replica_addresses: Vec::new(),
+            nvmf_ptpl_dir: "/var/tmp/mayastor/ptpl".to_string(),
         }
     }
 }
@@ -243,6 +255,16 @@ pub struct NvmeBdevOpts {
     pub delay_cmd_submit: bool,
     /// attempts per I/O in bdev layer before I/O fails
     pub bdev_retry_count: i32,
+    /// time in seconds to keep reconnecting to a controller which lost its
+    /// connection before it is removed, 0 removes it right away
+    pub ctrlr_loss_timeout_sec: u32,
+    /// time in seconds to wait before the first reconnect attempt, doubled
+    /// after each failed attempt
+    pub reconnect_delay_sec: u32,
+    /// time in seconds I/O is queued for while reconnecting before it fails,
+    /// 0 fails I/O right away, which lets a nexus retire the child without
+    /// waiting for the reconnect to give up
+    pub fast_io_fail_timeout_sec: u32,
 }
 
 impl GetOpts for NvmeBdevOpts {
@@ -251,7 +273,13 @@ impl GetOpts for NvmeBdevOpts {
         unsafe {
             bdev_nvme_get_opts(&opts as *const _ as *mut spdk_bdev_nvme_opts)
         };
-        opts.into()
+        // reconnects are handled by the nvmx controllers rather than by SPDK
+        Self {
+            ctrlr_loss_timeout_sec: self.ctrlr_loss_timeout_sec,
+            reconnect_delay_sec: self.reconnect_delay_sec,
+            fast_io_fail_timeout_sec: self.fast_io_fail_timeout_sec,
+            ..opts.into()
+        }
     }
 
     fn set(&self) -> bool {
@@ -284,6 +312,15 @@ impl Default for NvmeBdevOpts {
             io_queue_requests: 0,
             delay_cmd_submit: true,
             bdev_retry_count: try_from_env("NVME_BDEV_RETRY_COUNT", 0),
+            ctrlr_loss_timeout_sec: try_from_env(
+                "NVME_CTRLR_LOSS_TIMEOUT_SEC",
+                10,
+            ),
+            reconnect_delay_sec: try_from_env("NVME_RECONNECT_DELAY_SEC", 1),
+            fast_io_fail_timeout_sec: try_from_env(
+                "NVME_FAST_IO_FAIL_TIMEOUT_SEC",
+                0,
+            ),
         }
     }
 }
@@ -305,6 +342,7 @@ impl From<spdk_bdev_nvme_opts> for NvmeBdevOpts {
             io_queue_requests: o.io_queue_requests,
             delay_cmd_submit: o.delay_cmd_submit,
             bdev_retry_count: o.bdev_retry_count,
+            ..Default::default()
         }
     }
 }
@@ -1,4 +1,10 @@
-use std::{fmt::Display, fs, path::Path, sync::Mutex};
+use std::{
+    collections::HashSet,
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
 
 use futures::channel::oneshot;
 use once_cell::sync::{Lazy, OnceCell};
@@ -9,13 +15,19 @@ use crate::{
     bdev::nexus::VerboseError,
     core::{runtime, Cores, Mthread, Reactor, Share},
     grpc::rpc_submit,
+    host::blk_device::{list_block_devices, BlockDevice},
     lvs::{Error as LvsError, Lvs},
+    nexus_uri::{bdev_create, bdev_destroy, NexusBdevError},
     pool::{Pool as SpdkPool, PoolArgs, PoolsIter},
     replica::ShareType,
 };
 
 static CONFIG_FILE: OnceCell<String> = OnceCell::new();
 
+/// pools found on local block devices during discovery
+static DISCOVERED_POOLS: Lazy<Mutex<Vec<DiscoveredPool>>> =
+    Lazy::new(|| Mutex::new(Vec::new()));
+
 /// Initialise the config file location
 fn init_config_file<P>(file: P)
 where
@@ -154,6 +166,165 @@ impl PoolConfig {
             }
         });
     }
+
+    /// Import the pools found on the eligible local block devices, pools
+    /// with a name that is already in use are skipped. The outcome of the
+    /// discovery is available via `discovered_pools()`.
+    pub fn discover_pools() {
+        assert_eq!(Cores::current(), Cores::first());
+        Reactor::block_on(Self::discover());
+    }
+
+    /// Discover and import pools as `discover_pools()` does, replacing the
+    /// outcome of any earlier discovery.
+    pub async fn discover() {
+        let devices = match list_block_devices(false).await {
+            Ok(devices) => devices,
+            Err(error) => {
+                error!("failed to list block devices: {}", error);
+                return;
+            }
+        };
+
+        let in_use = pool_devices();
+        let mut pools = Vec::new();
+        for device in devices {
+            if in_use.contains(&canonical_path(&device.devname)) {
+                continue;
+            }
+            if let Some(pool) = discover_pool(&device).await {
+                pools.push(pool);
+            }
+        }
+
+        info!(
+            "discovered {} pool(s), {} imported",
+            pools.len(),
+            pools
+                .iter()
+                .filter(|p| p.status == ImportStatus::Imported)
+                .count()
+        );
+        *DISCOVERED_POOLS.lock().unwrap() = pools;
+    }
+}
+
+/// Outcome of importing a pool found during discovery
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum ImportStatus {
+    /// the pool has been imported
+    Imported,
+    /// a pool with the same name was already imported
+    NameConflict,
+    /// the import failed with the given error
+    Failed(String),
+}
+
+/// A pool found on a local block device during discovery
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DiscoveredPool {
+    pub name: String,
+    pub uuid: String,
+    pub disks: Vec<String>,
+    pub status: ImportStatus,
+}
+
+/// Returns the pools found during discovery, including the ones which could
+/// not be imported.
+pub fn discovered_pools() -> Vec<DiscoveredPool> {
+    DISCOVERED_POOLS.lock().unwrap().clone()
+}
+
+fn canonical_path(path: &str) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path))
+}
+
+/// devices backing the pools that are currently imported
+fn pool_devices() -> HashSet<PathBuf> {
+    Lvs::iter()
+        .map(|l| canonical_path(&l.base_bdev().name()))
+        .collect()
+}
+
+/// URI of the device to use for the pool, prefer a stable link over the
+/// kernel name as the latter may change across reboots
+fn device_uri(device: &BlockDevice) -> String {
+    let path = device
+        .devlinks
+        .iter()
+        .find(|l| l.starts_with("/dev/disk/by-id/"))
+        .unwrap_or(&device.devname);
+    format!("aio://{}", path)
+}
+
+/// Examine the device for a pool and import it when found. The bdev created
+/// for the device is destroyed unless the pool has been imported.
+async fn discover_pool(device: &BlockDevice) -> Option<DiscoveredPool> {
+    let uri = device_uri(device);
+
+    let bdev = match bdev_create(&uri).await {
+        Ok(bdev) => bdev,
+        Err(NexusBdevError::BdevExists {
+            ..
+        }) => {
+            // someone else created a bdev for the device, leave it alone
+            return None;
+        }
+        Err(error) => {
+            debug!("skipping device {}: {}", uri, error.verbose());
+            return None;
+        }
+    };
+
+    let (name, uuid) = match Lvs::probe(&bdev).await {
+        Ok(identity) => identity,
+        Err(error) => {
+            debug!("no pool found on {}: {}", uri, error.verbose());
+            destroy_bdev(&uri).await;
+            return None;
+        }
+    };
+
+    let status = if Lvs::lookup(&name).is_some() {
+        warn!(
+            "pool {} on {} not imported, a pool with the same name exists",
+            name, uri
+        );
+        ImportStatus::NameConflict
+    } else {
+        match Lvs::import(&name, &bdev).await {
+            Ok(_) => {
+                info!("discovered and imported pool {} on {}", name, uri);
+                ImportStatus::Imported
+            }
+            Err(error) => {
+                error!(
+                    "failed to import discovered pool {} on {}: {}",
+                    name,
+                    uri,
+                    error.verbose()
+                );
+                ImportStatus::Failed(error.verbose())
+            }
+        }
+    };
+
+    if status != ImportStatus::Imported {
+        destroy_bdev(&uri).await;
+    }
+
+    Some(DiscoveredPool {
+        name,
+        uuid,
+        disks: vec![uri],
+        status,
+    })
+}
+
+async fn destroy_bdev(uri: &str) {
+    if let Err(error) = bdev_destroy(uri).await {
+        error!("failed to destroy bdev {}: {}", uri, error.verbose());
+    }
 }
 
 #[derive(Debug, Default, PartialEq, Serialize, Deserialize, Clone)]
@@ -3,15 +3,26 @@
 
 pub use config::{
     opts::{NexusOpts, NvmeBdevOpts},
-    pool::PoolConfig,
+    pool::{discovered_pools, DiscoveredPool, ImportStatus, PoolConfig},
     Config,
     ConfigSubsystem,
 };
 pub use nvmf::{
     create_snapshot,
+    discovery_endpoints,
+    discovery_entries,
+    discovery_generation,
+    discovery_referrals,
+    register_admin_cmd_handler,
+    set_discovery_referrals,
     set_snapshot_time,
+    AdminCmdHandler,
+    DiscoveryEntry,
     Error as NvmfError,
     NvmeCpl,
+    NvmfCmdStatus,
+    NvmfConnection,
+    NvmfConnectionStats,
     NvmfReq,
     NvmfSubsystem,
     SubType,
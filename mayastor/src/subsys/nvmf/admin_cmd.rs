@@ -1,16 +1,24 @@
-//! Handlers for custom NVMe Admin commands
+//! Registry of handlers for NVMe Admin commands received by the target, and
+//! the handler of the custom snapshot command
 
 use std::{
+    cell::RefCell,
+    collections::HashMap,
     convert::TryFrom,
     ffi::c_void,
     ptr::NonNull,
+    rc::Rc,
+    sync::atomic::{AtomicUsize, Ordering},
     time::{SystemTime, UNIX_EPOCH},
 };
 
+use futures::channel::oneshot;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
 use crate::{
-    bdev::nexus,
-    core::{Bdev, Reactors},
-    lvs::Lvol,
+    core::{Bdev, Reactors, UntypedBdev},
+    lvs::{Error as LvsError, Lvol},
 };
 
 use spdk_rs::{
@@ -22,18 +30,22 @@ use spdk_rs::{
         nvme_status_get,
         spdk_bdev,
         spdk_bdev_desc,
-        spdk_bdev_io,
         spdk_io_channel,
         spdk_nvme_cmd,
         spdk_nvme_cpl,
         spdk_nvme_status,
         spdk_nvmf_bdev_ctrlr_nvme_passthru_admin,
+        spdk_nvmf_ctrlr,
         spdk_nvmf_request,
+        spdk_nvmf_request_complete,
+        spdk_nvmf_request_copy_from_buf,
         spdk_nvmf_request_get_bdev,
         spdk_nvmf_request_get_cmd,
+        spdk_nvmf_request_get_ctrlr,
         spdk_nvmf_request_get_response,
         spdk_nvmf_request_get_subsystem,
         spdk_nvmf_set_custom_admin_cmd_hdlr,
+        spdk_nvmf_subsystem,
         spdk_nvmf_subsystem_get_max_nsid,
     },
     nvme_admin_opc,
@@ -64,12 +76,211 @@ impl NvmfReq {
     }
 }
 
+impl NvmfReq {
+    /// Returns the NVMe command of the request
+    pub(crate) fn cmd(&self) -> &mut spdk_nvme_cmd {
+        unsafe { &mut *spdk_nvmf_request_get_cmd(self.0.as_ptr()) }
+    }
+
+    /// Returns the opcode of the NVMe command
+    pub(crate) fn opcode(&self) -> u8 {
+        self.cmd().opc() as u8
+    }
+
+    /// Returns the controller the request is received on
+    pub(crate) fn ctrlr(&self) -> *mut spdk_nvmf_ctrlr {
+        unsafe { spdk_nvmf_request_get_ctrlr(self.0.as_ptr()) }
+    }
+
+    /// Returns the subsystem the request is received on, null for requests
+    /// on a qpair which is not connected to a subsystem
+    pub(crate) fn subsystem(&self) -> *mut spdk_nvmf_subsystem {
+        unsafe { spdk_nvmf_request_get_subsystem(self.0.as_ptr()) }
+    }
+
+    /// Returns the bdev of the subsystem the request is received on, when the
+    /// subsystem has exactly one namespace
+    pub(crate) fn bdev(&self) -> Option<UntypedBdev> {
+        let subsys = self.subsystem();
+        if subsys.is_null() {
+            debug!("subsystem is null");
+            return None;
+        }
+
+        // only process this request if it has exactly one namespace
+        if unsafe { spdk_nvmf_subsystem_get_max_nsid(subsys) } != 1 {
+            debug!("multiple namespaces");
+            return None;
+        }
+
+        let mut bdev: *mut spdk_bdev = std::ptr::null_mut();
+        let mut desc: *mut spdk_bdev_desc = std::ptr::null_mut();
+        let mut ch: *mut spdk_io_channel = std::ptr::null_mut();
+        let rc = unsafe {
+            spdk_nvmf_request_get_bdev(
+                1,
+                self.0.as_ptr(),
+                &mut bdev,
+                &mut desc,
+                &mut ch,
+            )
+        };
+        if rc != 0 {
+            debug!("no bdev found");
+            return None;
+        }
+
+        unsafe { Bdev::checked_from_ptr(bdev) }
+    }
+
+    /// Forwards the request to the bdev of the first namespace as an NVMe
+    /// admin passthrough command
+    pub(crate) fn passthru_admin(&self) -> NvmfCmdStatus {
+        let mut bdev: *mut spdk_bdev = std::ptr::null_mut();
+        let mut desc: *mut spdk_bdev_desc = std::ptr::null_mut();
+        let mut ch: *mut spdk_io_channel = std::ptr::null_mut();
+        let req = self.0.as_ptr();
+        unsafe {
+            if spdk_nvmf_request_get_bdev(1, req, &mut bdev, &mut desc, &mut ch)
+                != 0
+            {
+                return NvmfCmdStatus::NotHandled;
+            }
+            spdk_nvmf_bdev_ctrlr_nvme_passthru_admin(bdev, desc, ch, req, None)
+        }
+        .into()
+    }
+
+    /// Copies the data into the buffer of the request, truncated to the
+    /// length of the buffer
+    pub(crate) fn copy_from_buf(&self, data: &[u8]) {
+        unsafe {
+            spdk_nvmf_request_copy_from_buf(
+                self.0.as_ptr(),
+                data.as_ptr() as *mut c_void,
+                data.len() as u64,
+            );
+        }
+    }
+
+    /// Sets the status of the NVMe completion
+    pub(crate) fn set_status(&self, sct: u32, sc: u32) {
+        let mut cpl = self.response();
+        cpl.status().set_sct(sct as u16);
+        cpl.status().set_sc(sc as u16);
+    }
+
+    /// Completes a request for which its handler returned
+    /// `NvmfCmdStatus::Pending`
+    pub(crate) fn complete(&self) {
+        unsafe {
+            spdk_nvmf_request_complete(self.0.as_ptr());
+        }
+    }
+}
+
 impl From<*mut c_void> for NvmfReq {
     fn from(ptr: *mut c_void) -> Self {
         NvmfReq(NonNull::new(ptr as *mut spdk_nvmf_request).unwrap())
     }
 }
 
+/// Outcome of an NVMe-oF admin command handler
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NvmfCmdStatus {
+    /// the command is not processed by the handler, and is passed on to the
+    /// next handler, or to the target when no handler is left
+    NotHandled,
+    /// the command is processed and its completion is filled in
+    Complete,
+    /// the command is processed asynchronously, the handler completes it
+    /// through `NvmfReq::complete`
+    Pending,
+}
+
+impl From<i32> for NvmfCmdStatus {
+    fn from(rc: i32) -> Self {
+        match rc {
+            0 => Self::Complete, // SPDK_NVMF_REQUEST_EXEC_STATUS_COMPLETE
+            1 => Self::Pending,  // SPDK_NVMF_REQUEST_EXEC_STATUS_ASYNCHRONOUS
+            _ => Self::NotHandled,
+        }
+    }
+}
+
+/// Handler of an NVMe admin command received by the target
+pub type AdminCmdHandler = fn(&NvmfReq) -> NvmfCmdStatus;
+
+type AdminCmdHandlers = HashMap<u8, Vec<AdminCmdHandler>>;
+
+/// handlers per admin opcode, in order of registration
+static ADMIN_CMD_HANDLERS: Lazy<Mutex<AdminCmdHandlers>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// bumped on every registration, for threads to refresh their copy of the
+/// handlers
+static ADMIN_CMD_HANDLERS_GEN: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// copy of the handlers and the generation it was taken at; handlers are
+    /// looked up for every admin command, while they are registered rarely
+    /// and mostly at startup
+    static LOCAL_ADMIN_CMD_HANDLERS: RefCell<(usize, Rc<AdminCmdHandlers>)> =
+        RefCell::new((0, Rc::new(HashMap::new())));
+}
+
+/// returns the registered handlers, without locking them unless handlers
+/// were registered since the last lookup on this thread
+fn admin_cmd_handlers() -> Rc<AdminCmdHandlers> {
+    LOCAL_ADMIN_CMD_HANDLERS.with(|local| {
+        let generation = ADMIN_CMD_HANDLERS_GEN.load(Ordering::Acquire);
+        let mut local = local.borrow_mut();
+        if local.0 != generation {
+            *local = (generation, Rc::new(ADMIN_CMD_HANDLERS.lock().clone()));
+        }
+        local.1.clone()
+    })
+}
+
+/// Registers a handler for the admin opcode. The target only supports a
+/// single custom handler per opcode, so the handlers of an opcode are tried
+/// in order of registration until one of them processes the command.
+/// Commands no handler processes are processed by the target itself.
+pub fn register_admin_cmd_handler(opcode: u8, handler: AdminCmdHandler) {
+    let mut handlers = ADMIN_CMD_HANDLERS.lock();
+    let entry = handlers.entry(opcode).or_default();
+    if entry.is_empty() {
+        unsafe {
+            spdk_nvmf_set_custom_admin_cmd_hdlr(
+                opcode,
+                Some(nvmf_admin_cmd_hdlr),
+            );
+        }
+    }
+    entry.push(handler);
+    ADMIN_CMD_HANDLERS_GEN.fetch_add(1, Ordering::Release);
+}
+
+/// NVMf custom command handler for all opcodes with registered handlers
+/// Called from nvmf_ctrlr_process_admin_cmd
+/// Return: <0 for any error, caller handles it as unsupported opcode
+extern "C" fn nvmf_admin_cmd_hdlr(req: *mut spdk_nvmf_request) -> i32 {
+    let req = NvmfReq(NonNull::new(req).unwrap());
+    let opcode = req.opcode();
+
+    let handlers = admin_cmd_handlers();
+    for handler in handlers.get(&opcode).into_iter().flatten() {
+        match handler(&req) {
+            NvmfCmdStatus::NotHandled => continue,
+            NvmfCmdStatus::Complete => return 0,
+            NvmfCmdStatus::Pending => return 1,
+        }
+    }
+
+    debug!("admin command {:#x} not handled", opcode);
+    -1
+}
+
 /// Set the snapshot time in an spdk_nvme_cmd struct to the current time
 /// Returns seconds since Unix epoch
 pub fn set_snapshot_time(cmd: &mut spdk_nvme_cmd) -> u64 {
@@ -85,83 +296,59 @@ pub fn set_snapshot_time(cmd: &mut spdk_nvme_cmd) -> u64 {
     now as u64
 }
 
-/// NVMf custom command handler for opcode c0h
-/// Called from nvmf_ctrlr_process_admin_cmd
-/// Return: <0 for any error, caller handles it as unsupported opcode
-extern "C" fn nvmf_create_snapshot_hdlr(req: *mut spdk_nvmf_request) -> i32 {
-    debug!("nvmf_create_snapshot_hdlr {:?}", req);
-
-    let subsys = unsafe { spdk_nvmf_request_get_subsystem(req) };
-    if subsys.is_null() {
-        debug!("subsystem is null");
-        return -1;
-    }
-
-    /* Only process this request if it has exactly one namespace */
-    if unsafe { spdk_nvmf_subsystem_get_max_nsid(subsys) } != 1 {
-        debug!("multiple namespaces");
-        return -1;
+/// Returns the snapshot time encoded in cdw10/11 of the command
+fn snapshot_time(cmd: &spdk_nvme_cmd) -> u64 {
+    unsafe {
+        nvme_cmd_cdw10_get_val(cmd) as u64
+            | (nvme_cmd_cdw11_get_val(cmd) as u64) << 32
     }
+}
 
-    /* Forward to first namespace if it supports NVME admin commands */
-    let mut bdev: *mut spdk_bdev = std::ptr::null_mut();
-    let mut desc: *mut spdk_bdev_desc = std::ptr::null_mut();
-    let mut ch: *mut spdk_io_channel = std::ptr::null_mut();
-    let rc = unsafe {
-        spdk_nvmf_request_get_bdev(1, req, &mut bdev, &mut desc, &mut ch)
+/// Create a snapshot of the shared replica (lvol) the command is received on.
+/// Commands received on a published nexus are left to the handler of the
+/// nexus, which creates the snapshot on all of its children.
+fn nvmf_create_snapshot_hdlr(req: &NvmfReq) -> NvmfCmdStatus {
+    let bd = match req.bdev() {
+        Some(bd) => bd,
+        None => return NvmfCmdStatus::NotHandled,
     };
-    if rc != 0 {
-        /* No bdev found for this namespace. Continue. */
-        debug!("no bdev found");
-        return -1;
-    }
 
-    let bd = unsafe { Bdev::checked_from_ptr(bdev).unwrap() };
-    if bd.driver() == nexus::NEXUS_MODULE_NAME {
-        // Received command on a published Nexus
-        set_snapshot_time(unsafe { &mut *spdk_nvmf_request_get_cmd(req) });
-        unsafe {
-            spdk_nvmf_bdev_ctrlr_nvme_passthru_admin(bdev, desc, ch, req, None)
-        }
-    } else if let Ok(lvol) = Lvol::try_from(bd) {
+    if let Ok(lvol) = Lvol::try_from(bd) {
         // Received command on a shared replica (lvol)
-        let cmd = unsafe { spdk_nvmf_request_get_cmd(req) };
-        let snapshot_time = unsafe {
-            nvme_cmd_cdw10_get_val(cmd) as u64
-                | (nvme_cmd_cdw11_get_val(cmd) as u64) << 32
-        };
         let snapshot_name =
-            Lvol::format_snapshot_name(&lvol.name(), snapshot_time);
-        let nvmf_req = NvmfReq(NonNull::new(req).unwrap());
+            Lvol::format_snapshot_name(&lvol.name(), snapshot_time(req.cmd()));
+        let nvmf_req = req.clone();
         // Blobfs operations must be on md_thread
         Reactors::master().send_future(async move {
             lvol.create_snapshot(&nvmf_req, &snapshot_name).await;
         });
-        1 // SPDK_NVMF_REQUEST_EXEC_STATUS_ASYNCHRONOUS
+        NvmfCmdStatus::Pending
     } else {
         debug!("unsupported bdev driver");
-        -1
+        NvmfCmdStatus::NotHandled
     }
 }
 
-pub fn create_snapshot(lvol: Lvol, cmd: &spdk_nvme_cmd, io: *mut spdk_bdev_io) {
-    let snapshot_time = unsafe {
-        nvme_cmd_cdw10_get_val(&*cmd) as u64
-            | (nvme_cmd_cdw11_get_val(&*cmd) as u64) << 32
-    };
-    let snapshot_name = Lvol::format_snapshot_name(&lvol.name(), snapshot_time);
+/// Create a snapshot of a local replica (lvol), named after the snapshot time
+/// of the command, as for the local children of a nexus
+pub async fn create_snapshot(
+    lvol: Lvol,
+    cmd: &spdk_nvme_cmd,
+) -> Result<(), LvsError> {
+    let snapshot_name =
+        Lvol::format_snapshot_name(&lvol.name(), snapshot_time(cmd));
+    let (s, r) = oneshot::channel();
     // Blobfs operations must be on md_thread
     Reactors::master().send_future(async move {
-        lvol.create_snapshot_local(io, &snapshot_name).await;
+        let _ = s.send(lvol.create_snapshot_local(&snapshot_name).await);
     });
+    r.await.expect("snapshot sender is gone")
 }
 
-/// Register custom NVMe admin command handler
+/// Register the handler of the snapshot command
 pub fn setup_create_snapshot_hdlr() {
-    unsafe {
-        spdk_nvmf_set_custom_admin_cmd_hdlr(
-            nvme_admin_opc::CREATE_SNAPSHOT,
-            Some(nvmf_create_snapshot_hdlr),
-        );
-    }
+    register_admin_cmd_handler(
+        nvme_admin_opc::CREATE_SNAPSHOT,
+        nvmf_create_snapshot_hdlr,
+    );
 }
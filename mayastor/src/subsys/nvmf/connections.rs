@@ -0,0 +1,341 @@
+//! Hosts connected to the subsystems of the target.
+//!
+//! The controllers of a subsystem belong to the thread of the subsystem, which
+//! is the master core, whereas their queue pairs are spread across the poll
+//! groups of all cores.
+//!
+//! The target neither records when a host connected nor counts the I/O of a
+//! controller, so the completion of every request is intercepted by wrapping
+//! the operations of the transport. A completed Connect command records the
+//! connect time of the controller and the number of I/O queues it created,
+//! and completed reads and writes are counted on the thread of the poll group
+//! they completed on. The counters of each poll group are gathered by
+//! visiting the poll group on its own thread.
+
+use std::{cell::RefCell, collections::HashMap, net::SocketAddr};
+
+use chrono::{DateTime, Utc};
+use once_cell::sync::{Lazy, OnceCell};
+use parking_lot::Mutex;
+
+use spdk_rs::libspdk::{
+    spdk_nvme_transport_id,
+    spdk_nvmf_ctrlr,
+    spdk_nvmf_qpair_get_peer_trid,
+    spdk_nvmf_request,
+    spdk_nvmf_request_get_subsystem,
+    spdk_nvmf_subsystem,
+    spdk_nvmf_transport,
+    spdk_nvmf_transport_ops,
+    SPDK_NVME_OPC_FABRIC,
+    SPDK_NVME_OPC_READ,
+    SPDK_NVME_OPC_WRITE,
+    SPDK_NVMF_FABRIC_COMMAND_CONNECT,
+};
+
+use crate::{
+    bdev::nvmx::utils::nvme_cpl_succeeded,
+    ffihelper::AsStr,
+    subsys::nvmf::{
+        subsystem::NvmfSubsystem,
+        transport::TransportId,
+        NVMF_PGS,
+    },
+};
+
+/// A host connected to a subsystem
+#[derive(Debug, Clone, PartialEq)]
+pub struct NvmfConnection {
+    /// NQN of the host
+    pub host_nqn: String,
+    /// host identifier
+    pub host_id: [u8; 16],
+    /// transport address of the host, none when it cannot be determined
+    pub address: Option<SocketAddr>,
+    /// ID of the controller the host is connected through
+    pub cntlid: u16,
+    /// number of I/O queues the host created
+    pub io_queues: u32,
+    /// time the host connected at
+    pub connected_since: DateTime<Utc>,
+    /// I/O counters of the controller
+    pub stats: NvmfConnectionStats,
+}
+
+/// I/O counters of a controller, counting the commands which completed
+/// successfully
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct NvmfConnectionStats {
+    pub num_read_ops: u64,
+    pub bytes_read: u64,
+    pub num_write_ops: u64,
+    pub bytes_written: u64,
+}
+
+impl NvmfConnectionStats {
+    fn add(&mut self, other: &Self) {
+        self.num_read_ops += other.num_read_ops;
+        self.bytes_read += other.bytes_read;
+        self.num_write_ops += other.num_write_ops;
+        self.bytes_written += other.bytes_written;
+    }
+}
+
+/// a controller as recorded when its admin queue connected
+#[derive(Debug, Clone)]
+struct Controller {
+    subsystem: usize,
+    connected_since: DateTime<Utc>,
+    io_queues: u32,
+}
+
+/// the I/O counters of a controller on the thread of a poll group
+#[derive(Debug, Default)]
+struct GroupStats {
+    /// when the counters were created, counters of a controller which is
+    /// gone are recognised by predating the connect time of the controller
+    /// now at the same address
+    since: DateTime<Utc>,
+    stats: NvmfConnectionStats,
+}
+
+/// controllers of the target by their address, recorded when they connect
+static CONTROLLERS: Lazy<Mutex<HashMap<usize, Controller>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// the request completion operation of the transport, which is wrapped
+static REQ_COMPLETE: OnceCell<
+    unsafe extern "C" fn(req: *mut spdk_nvmf_request) -> i32,
+> = OnceCell::new();
+
+thread_local! {
+    /// I/O counters of the controllers whose requests completed on the poll
+    /// group of this thread
+    static GROUP_STATS: RefCell<HashMap<usize, GroupStats>> =
+        RefCell::new(HashMap::new());
+}
+
+/// Account for the request, which is about to be completed to the host.
+fn account(req: *mut spdk_nvmf_request) {
+    unsafe {
+        let qpair = (*req).qpair;
+        if qpair.is_null() || (*qpair).ctrlr.is_null() {
+            return;
+        }
+        if !nvme_cpl_succeeded(&(*(*req).rsp).nvme_cpl) {
+            return;
+        }
+
+        let ctrlr = (*qpair).ctrlr as usize;
+        let cmd = &(*(*req).cmd).nvme_cmd;
+        match cmd.opc() as u32 {
+            SPDK_NVME_OPC_READ | SPDK_NVME_OPC_WRITE => {
+                let write = cmd.opc() as u32 == SPDK_NVME_OPC_WRITE;
+                let bytes = (*req).length as u64;
+                GROUP_STATS.with(|s| {
+                    let mut s = s.borrow_mut();
+                    let entry = s.entry(ctrlr).or_insert_with(|| GroupStats {
+                        since: Utc::now(),
+                        ..Default::default()
+                    });
+                    if write {
+                        entry.stats.num_write_ops += 1;
+                        entry.stats.bytes_written += bytes;
+                    } else {
+                        entry.stats.num_read_ops += 1;
+                        entry.stats.bytes_read += bytes;
+                    }
+                });
+            }
+            SPDK_NVME_OPC_FABRIC
+                if (*(*req).cmd).nvmf_cmd.fctype as u32
+                    == SPDK_NVMF_FABRIC_COMMAND_CONNECT =>
+            {
+                let mut controllers = CONTROLLERS.lock();
+                if (*qpair).qid == 0 {
+                    let subsystem = spdk_nvmf_request_get_subsystem(req);
+                    controllers.insert(
+                        ctrlr,
+                        Controller {
+                            subsystem: subsystem as usize,
+                            connected_since: Utc::now(),
+                            io_queues: 0,
+                        },
+                    );
+                } else if let Some(c) = controllers.get_mut(&ctrlr) {
+                    c.io_queues += 1;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+extern "C" fn req_complete(req: *mut spdk_nvmf_request) -> i32 {
+    account(req);
+    let complete = REQ_COMPLETE.get().expect("transport is not tracked");
+    unsafe { complete(req) }
+}
+
+/// Track the requests completed through the transport, must be called
+/// before the transport is added to the target.
+pub(crate) fn track_requests(transport: *mut spdk_nvmf_transport) {
+    unsafe {
+        let mut ops: spdk_nvmf_transport_ops = *(*transport).ops;
+        match ops.req_complete {
+            Some(complete) if REQ_COMPLETE.set(complete).is_ok() => {
+                ops.req_complete = Some(req_complete);
+                (*transport).ops = Box::leak(Box::new(ops));
+            }
+            _ => warn!("the requests of the transport cannot be tracked"),
+        }
+    }
+}
+
+/// Forget about all controllers, as the subsystems of the target are
+/// stopped.
+pub(crate) fn clear() {
+    CONTROLLERS.lock().clear();
+}
+
+/// the controllers of the subsystem, must be called on the thread of the
+/// subsystem
+fn controllers(ss: *mut spdk_nvmf_subsystem) -> Vec<*mut spdk_nvmf_ctrlr> {
+    let mut ctrlrs = Vec::new();
+    unsafe {
+        let mut ctrlr = (*ss).ctrlrs.tqh_first;
+        while !ctrlr.is_null() {
+            ctrlrs.push(ctrlr);
+            ctrlr = (*ctrlr).link.tqe_next;
+        }
+    }
+    ctrlrs
+}
+
+/// the I/O counters of the controllers in the poll group of this thread,
+/// along with the time each connected at, the counters of the controllers
+/// which are gone are dropped
+fn group_stats(
+    ctrlrs: &[(usize, DateTime<Utc>)],
+    gone: &[usize],
+) -> Vec<(usize, NvmfConnectionStats)> {
+    GROUP_STATS.with(|s| {
+        let mut s = s.borrow_mut();
+        s.retain(|ctrlr, g| {
+            !gone.contains(ctrlr)
+                && ctrlrs
+                    .iter()
+                    .all(|(c, since)| c != ctrlr || g.since >= *since)
+        });
+        ctrlrs
+            .iter()
+            .filter_map(|(c, _)| s.get(c).map(|g| (*c, g.stats)))
+            .collect()
+    })
+}
+
+/// the I/O counters of the controllers over all poll groups
+async fn stats(
+    ctrlrs: Vec<(usize, DateTime<Utc>)>,
+    gone: Vec<usize>,
+) -> HashMap<usize, NvmfConnectionStats> {
+    let groups = NVMF_PGS.with(|p| p.borrow().clone());
+    let mut stats: HashMap<usize, NvmfConnectionStats> = HashMap::new();
+
+    for pg in groups {
+        let ctrlrs = ctrlrs.clone();
+        let gone = gone.clone();
+        match pg
+            .thread
+            .spawn_local(async move { group_stats(&ctrlrs, &gone) })
+        {
+            Ok(r) => {
+                for (ctrlr, s) in r.await.unwrap_or_default() {
+                    stats.entry(ctrlr).or_default().add(&s);
+                }
+            }
+            Err(e) => {
+                warn!("failed to get the I/O counters of {:?}: {}", pg, e)
+            }
+        }
+    }
+
+    stats
+}
+
+impl NvmfSubsystem {
+    /// Returns the hosts connected to the subsystem.
+    pub async fn connections(&self) -> Vec<NvmfConnection> {
+        let ss = self.0.as_ptr();
+        let ctrlrs = controllers(ss);
+
+        // controllers of the subsystem which are gone are forgotten
+        let mut gone = Vec::new();
+        let known = {
+            let mut controllers = CONTROLLERS.lock();
+            controllers.retain(|ctrlr, c| {
+                let live = c.subsystem != ss as usize
+                    || ctrlrs.iter().any(|live| *live as usize == *ctrlr);
+                if !live {
+                    gone.push(*ctrlr);
+                }
+                live
+            });
+            ctrlrs
+                .iter()
+                .map(|ctrlr| {
+                    let c = controllers.get(&(*ctrlr as usize)).cloned();
+                    c.unwrap_or_else(|| Controller {
+                        subsystem: ss as usize,
+                        connected_since: Utc::now(),
+                        io_queues: 0,
+                    })
+                })
+                .collect::<Vec<_>>()
+        };
+        if ctrlrs.is_empty() && gone.is_empty() {
+            return Vec::new();
+        }
+
+        let stats = stats(
+            ctrlrs
+                .iter()
+                .zip(known.iter())
+                .map(|(ctrlr, c)| (*ctrlr as usize, c.connected_since))
+                .collect(),
+            gone,
+        )
+        .await;
+
+        ctrlrs
+            .into_iter()
+            .zip(known.into_iter())
+            .map(|(ctrlr, c)| unsafe {
+                let mut trid = spdk_nvme_transport_id::default();
+                let address = if !(*ctrlr).admin_qpair.is_null()
+                    && spdk_nvmf_qpair_get_peer_trid(
+                        (*ctrlr).admin_qpair,
+                        &mut trid,
+                    ) == 0
+                {
+                    TransportId(trid).socket_addr()
+                } else {
+                    None
+                };
+
+                NvmfConnection {
+                    host_nqn: (*ctrlr).hostnqn.as_str().to_string(),
+                    host_id: (*ctrlr).hostid.u.raw,
+                    address,
+                    cntlid: (*ctrlr).cntlid,
+                    io_queues: c.io_queues,
+                    connected_since: c.connected_since,
+                    stats: stats
+                        .get(&(ctrlr as usize))
+                        .copied()
+                        .unwrap_or_default(),
+                }
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,558 @@
+//! Discovery controller of the target.
+//!
+//! The discovery log page is generated by mayastor rather than by SPDK, so it
+//! can be extended with referrals to the discovery services of the other
+//! mayastor instances in the cluster, as learned through the registration
+//! subsystem. A host therefore only needs the discovery address of a single
+//! node to find all subsystems which it is allowed to connect to.
+//!
+//! Subsystems are only listed for hosts which are allowed to connect to them,
+//! and a discovery log change notice is sent to connected hosts whenever a
+//! subsystem is started or stopped, its allowed hosts change, or when the
+//! referrals change.
+//!
+//! Discovery controllers are not necessarily polled on the master core, so
+//! the log is built on the master core whenever it changes, and the cached
+//! copy is served to the hosts.
+
+use std::{
+    ffi::CStr,
+    mem::{size_of, zeroed},
+    net::SocketAddr,
+    ptr::copy_nonoverlapping,
+};
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
+use spdk_rs::libspdk::{
+    nvme_cmd_cdw10_get_val,
+    nvme_cmd_cdw11_get_val,
+    spdk_nvmf_discovery_log_page,
+    spdk_nvmf_discovery_log_page_entry,
+    spdk_nvmf_subsystem_get_first,
+    spdk_nvmf_subsystem_get_next,
+    spdk_nvmf_tgt,
+    spdk_nvmf_tgt_get_transport,
+    SPDK_NVME_LOG_DISCOVERY,
+    SPDK_NVME_OPC_GET_LOG_PAGE,
+    SPDK_NVME_SCT_GENERIC,
+    SPDK_NVME_SC_INVALID_FIELD,
+    SPDK_NVMF_DISCOVERY_NQN,
+    SPDK_NVMF_SUBTYPE_DISCOVERY,
+    SPDK_NVMF_SUBTYPE_NVME,
+};
+
+use crate::{
+    core::Mthread,
+    ffihelper::{AsStr, IntoCString},
+    subsys::{
+        nvmf::{
+            register_admin_cmd_handler,
+            subsystem::{NvmfSubsystem, SubType},
+            transport::TransportId,
+            NvmfCmdStatus,
+            NvmfReq,
+            NVMF_TGT,
+        },
+        Config,
+    },
+};
+
+/// referrals to the discovery services of other mayastor instances
+static REFERRALS: Lazy<Mutex<Vec<SocketAddr>>> =
+    Lazy::new(|| Mutex::new(Vec::new()));
+
+/// addresses the discovery service of this instance is reachable on
+static ENDPOINTS: Lazy<Mutex<Vec<SocketAddr>>> =
+    Lazy::new(|| Mutex::new(Vec::new()));
+
+/// addresses of the ports of the target, the port ID of a port is its
+/// position in the list
+static PORTS: Lazy<Mutex<Vec<SocketAddr>>> =
+    Lazy::new(|| Mutex::new(Vec::new()));
+
+/// the discovery log as last built on the master core
+static LOG: Lazy<Mutex<DiscoveryLog>> =
+    Lazy::new(|| Mutex::new(DiscoveryLog::default()));
+
+/// An entry of the discovery log page
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiscoveryEntry {
+    /// whether the entry is a subsystem or a referral
+    pub subtype: SubType,
+    /// NQN of the subsystem, the discovery NQN for referrals
+    pub subnqn: String,
+    /// address of the entry
+    pub address: SocketAddr,
+    /// ID of the port of the target the entry is reachable through, 0 for
+    /// referrals as the ports of other targets are not known
+    pub port_id: u16,
+}
+
+/// An entry of the discovery log page along with the hosts it is listed for
+#[derive(Debug, Clone)]
+struct Record {
+    entry: DiscoveryEntry,
+    /// the entry is listed for any host
+    any_host: bool,
+    /// the hosts the entry is listed for
+    hosts: Vec<String>,
+}
+
+impl Record {
+    fn listed_for(&self, hostnqn: &str) -> bool {
+        self.any_host || self.hosts.iter().any(|h| h == hostnqn)
+    }
+}
+
+/// The discovery log page of the target, for all hosts
+#[derive(Debug, Default)]
+struct DiscoveryLog {
+    /// generation counter, incremented whenever the log changes
+    genctr: u64,
+    /// admin submission queue size of the target
+    asqsz: u16,
+    records: Vec<Record>,
+}
+
+impl DiscoveryLog {
+    /// the entries of the log listed for the host
+    fn entries(&self, hostnqn: &str) -> Vec<DiscoveryEntry> {
+        self.records
+            .iter()
+            .filter(|r| r.listed_for(hostnqn))
+            .map(|r| r.entry.clone())
+            .collect()
+    }
+
+    /// the discovery log page for the host, as it is sent over the wire
+    fn page(&self, hostnqn: &str) -> Vec<u8> {
+        let entries = self.entries(hostnqn);
+
+        let header_len = size_of::<spdk_nvmf_discovery_log_page>();
+        let entry_len = size_of::<spdk_nvmf_discovery_log_page_entry>();
+        let mut page = vec![0u8; header_len + entries.len() * entry_len];
+
+        let mut header: spdk_nvmf_discovery_log_page = unsafe { zeroed() };
+        header.genctr = self.genctr;
+        header.numrec = entries.len() as u64;
+        header.recfmt = 0;
+        unsafe {
+            copy_nonoverlapping(
+                &header as *const _ as *const u8,
+                page.as_mut_ptr(),
+                header_len,
+            );
+        }
+
+        for (i, entry) in entries.iter().enumerate() {
+            let entry = entry.to_log_entry(self.asqsz);
+            unsafe {
+                copy_nonoverlapping(
+                    &entry as *const _ as *const u8,
+                    page.as_mut_ptr().add(header_len + i * entry_len),
+                    entry_len,
+                );
+            }
+        }
+
+        page
+    }
+}
+
+/// Returns the part of the log page requested by a Get Log Page command, none
+/// when the offset is not dword aligned or lies beyond the page.
+fn page_range(page: &[u8], offset: u64, length: u64) -> Option<&[u8]> {
+    if offset % 4 != 0 || offset > page.len() as u64 {
+        return None;
+    }
+    let data = &page[offset as usize ..];
+    Some(&data[.. data.len().min(length as usize)])
+}
+
+impl DiscoveryEntry {
+    fn to_log_entry(&self, asqsz: u16) -> spdk_nvmf_discovery_log_page_entry {
+        let trid =
+            TransportId::with_address(self.address.ip(), self.address.port());
+
+        let mut entry: spdk_nvmf_discovery_log_page_entry = unsafe { zeroed() };
+        entry.trtype = trid.trtype as u8;
+        entry.adrfam = trid.adrfam as u8;
+        entry.subtype = match self.subtype {
+            SubType::Nvme => SPDK_NVMF_SUBTYPE_NVME,
+            SubType::Discovery => SPDK_NVMF_SUBTYPE_DISCOVERY,
+        } as u8;
+        entry.portid = self.port_id;
+        entry.cntlid = 0xffff;
+        entry.asqsz = asqsz;
+        copy_padded(&mut entry.trsvcid, trid.trsvcid.as_str(), b' ');
+        copy_padded(&mut entry.traddr, trid.traddr.as_str(), b' ');
+        copy_padded(&mut entry.subnqn, &self.subnqn, 0);
+        entry
+    }
+}
+
+/// copy the string into the fixed size field, padding the remainder
+fn copy_padded(dst: &mut [u8], src: &str, pad: u8) {
+    let len = src.len().min(dst.len());
+    dst[.. len].copy_from_slice(&src.as_bytes()[.. len]);
+    dst[len ..].iter_mut().for_each(|b| *b = pad);
+}
+
+/// the NQN of the discovery subsystem
+fn discovery_nqn() -> String {
+    CStr::from_bytes_with_nul(SPDK_NVMF_DISCOVERY_NQN)
+        .unwrap()
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Set the addresses the discovery service of this instance is reachable on,
+/// which are left out of the referrals.
+pub(crate) fn set_endpoints(endpoints: Vec<SocketAddr>) {
+    *ENDPOINTS.lock() = endpoints;
+}
+
+/// Set the addresses of the ports of the target, in the order they were
+/// opened.
+pub(crate) fn set_ports(ports: Vec<SocketAddr>) {
+    *PORTS.lock() = ports;
+}
+
+/// Returns the addresses the discovery service of this instance is reachable
+/// on, which are advertised to the control plane.
+pub fn discovery_endpoints() -> Vec<SocketAddr> {
+    ENDPOINTS.lock().clone()
+}
+
+/// Returns the current referrals to other discovery services.
+pub fn discovery_referrals() -> Vec<SocketAddr> {
+    REFERRALS.lock().clone()
+}
+
+/// Replace the referrals to the discovery services of other mayastor
+/// instances, given as address:port. Connected hosts are notified when the
+/// referrals change.
+pub async fn set_discovery_referrals(referrals: &[String]) {
+    let endpoints = discovery_endpoints();
+    let mut addresses = referrals
+        .iter()
+        .filter_map(|r| match r.parse::<SocketAddr>() {
+            Ok(address) => Some(address),
+            Err(_) => {
+                warn!("ignoring invalid discovery referral {}", r);
+                None
+            }
+        })
+        .filter(|a| !endpoints.contains(a))
+        .collect::<Vec<_>>();
+    addresses.sort();
+    addresses.dedup();
+
+    {
+        let mut current = REFERRALS.lock();
+        if *current == addresses {
+            return;
+        }
+        info!("discovery referrals changed to {:?}", addresses);
+        *current = addresses;
+    }
+
+    refresh().await;
+}
+
+/// Rebuild the discovery log on the master core and notify the hosts
+/// connected to the discovery controller that it changed.
+pub(crate) async fn refresh() {
+    if !Config::get().nexus_opts.nvmf_enable {
+        return;
+    }
+
+    match Mthread::get_init().spawn_local(async {
+        NVMF_TGT.with(|t| t.borrow().update_discovery_log())
+    }) {
+        Ok(r) => {
+            if let Err(e) = r.await {
+                error!("failed to update the discovery log: {}", e);
+            }
+        }
+        Err(e) => error!("failed to update the discovery log: {}", e),
+    }
+}
+
+/// Like refresh, for callers which cannot wait for the log to be rebuilt.
+pub(crate) fn schedule_refresh() {
+    if !Config::get().nexus_opts.nvmf_enable {
+        return;
+    }
+
+    if let Err(e) = Mthread::get_init().spawn_local(async {
+        NVMF_TGT.with(|t| t.borrow().update_discovery_log())
+    }) {
+        error!("failed to update the discovery log: {}", e);
+    }
+}
+
+/// Returns the entries of the discovery log page for the given host: the
+/// listeners of all started subsystems the host is allowed to connect to,
+/// followed by the referrals.
+pub fn discovery_entries(hostnqn: &str) -> Vec<DiscoveryEntry> {
+    LOG.lock().entries(hostnqn)
+}
+
+/// Returns the generation counter of the discovery log, which changes
+/// whenever hosts are notified of a change.
+pub fn discovery_generation() -> u64 {
+    LOG.lock().genctr
+}
+
+/// Rebuild the discovery log from the subsystems of the target, must be
+/// called on the master core as it walks the subsystems.
+pub(crate) fn rebuild(tgt: *mut spdk_nvmf_tgt) {
+    let ports = PORTS.lock().clone();
+    let mut records = Vec::new();
+
+    let mut ss = unsafe { spdk_nvmf_subsystem_get_first(tgt) };
+    while !ss.is_null() {
+        let subsystem = NvmfSubsystem::from(ss);
+        ss = unsafe { spdk_nvmf_subsystem_get_next(ss) };
+
+        if subsystem.subtype() != SubType::Nvme || !subsystem.is_started() {
+            continue;
+        }
+
+        let any_host = subsystem.allows_any();
+        let hosts = subsystem.allowed_hosts();
+        for trid in subsystem.listeners_to_vec().unwrap_or_default() {
+            let address = match trid.socket_addr() {
+                Some(address) => address,
+                None => continue,
+            };
+            let port_id = match ports.iter().position(|p| *p == address) {
+                Some(port_id) => port_id as u16,
+                None => {
+                    warn!(
+                        "{} listens on {} which is not a port of the target",
+                        subsystem.get_nqn(),
+                        address
+                    );
+                    continue;
+                }
+            };
+            records.push(Record {
+                entry: DiscoveryEntry {
+                    subtype: SubType::Nvme,
+                    subnqn: subsystem.get_nqn(),
+                    address,
+                    port_id,
+                },
+                any_host,
+                hosts: hosts.clone(),
+            });
+        }
+    }
+
+    let subnqn = discovery_nqn();
+    records.extend(discovery_referrals().into_iter().map(|address| Record {
+        entry: DiscoveryEntry {
+            subtype: SubType::Discovery,
+            subnqn: subnqn.clone(),
+            address,
+            port_id: 0,
+        },
+        any_host: true,
+        hosts: Vec::new(),
+    }));
+
+    let asqsz = unsafe {
+        let transport =
+            spdk_nvmf_tgt_get_transport(tgt, "TCP".into_cstring().as_ptr());
+        if transport.is_null() {
+            0
+        } else {
+            (*transport).opts.max_aq_depth as u16
+        }
+    };
+
+    let mut log = LOG.lock();
+    log.genctr += 1;
+    log.asqsz = asqsz;
+    log.records = records;
+}
+
+/// Handler of the Get Log Page command, which serves the discovery log page
+/// on discovery controllers.
+fn nvmf_discovery_log_hdlr(req: &NvmfReq) -> NvmfCmdStatus {
+    let subsys = req.subsystem();
+    if subsys.is_null()
+        || unsafe { (*subsys).subtype } != SPDK_NVMF_SUBTYPE_DISCOVERY
+    {
+        return NvmfCmdStatus::NotHandled;
+    }
+
+    let cmd = req.cmd();
+    let (cdw10, cdw11, cdw12, cdw13) = unsafe {
+        (
+            nvme_cmd_cdw10_get_val(cmd),
+            nvme_cmd_cdw11_get_val(cmd),
+            cmd.__bindgen_anon_3.cdw12,
+            cmd.cdw13,
+        )
+    };
+    if cdw10 & 0xff != SPDK_NVME_LOG_DISCOVERY {
+        return NvmfCmdStatus::NotHandled;
+    }
+
+    let offset = cdw12 as u64 | (cdw13 as u64) << 32;
+    let numd = ((cdw11 & 0xffff) << 16 | cdw10 >> 16) as u64 + 1;
+    let length = numd * 4;
+
+    let hostnqn = unsafe {
+        let ctrlr = (*(*req.0.as_ptr()).qpair).ctrlr;
+        (*ctrlr).hostnqn.as_str().to_string()
+    };
+    let page = LOG.lock().page(&hostnqn);
+    match page_range(&page, offset, length) {
+        Some(data) => req.copy_from_buf(data),
+        None => {
+            debug!("invalid discovery log page offset {}", offset);
+            req.set_status(SPDK_NVME_SCT_GENERIC, SPDK_NVME_SC_INVALID_FIELD);
+            return NvmfCmdStatus::Complete;
+        }
+    }
+
+    req.set_status(SPDK_NVME_SCT_GENERIC, 0);
+    NvmfCmdStatus::Complete
+}
+
+/// Register the handler serving the discovery log page
+pub fn setup_discovery_log_hdlr() {
+    register_admin_cmd_handler(
+        SPDK_NVME_OPC_GET_LOG_PAGE as u8,
+        nvmf_discovery_log_hdlr,
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use std::mem::size_of;
+
+    use spdk_rs::libspdk::{
+        spdk_nvmf_discovery_log_page,
+        spdk_nvmf_discovery_log_page_entry,
+        SPDK_NVMF_SUBTYPE_DISCOVERY,
+        SPDK_NVMF_SUBTYPE_NVME,
+    };
+
+    use super::{page_range, DiscoveryEntry, DiscoveryLog, Record, SubType};
+
+    fn record(nqn: &str, port_id: u16, hosts: &[&str]) -> Record {
+        Record {
+            entry: DiscoveryEntry {
+                subtype: SubType::Nvme,
+                subnqn: nqn.to_string(),
+                address: format!("10.0.0.{}:8420", port_id + 1)
+                    .parse()
+                    .unwrap(),
+                port_id,
+            },
+            any_host: hosts.is_empty(),
+            hosts: hosts.iter().map(|h| h.to_string()).collect(),
+        }
+    }
+
+    fn log() -> DiscoveryLog {
+        let mut referral =
+            record("nqn.2014-08.org.nvmexpress.discovery", 0, &[]);
+        referral.entry.subtype = SubType::Discovery;
+        DiscoveryLog {
+            genctr: 7,
+            asqsz: 32,
+            records: vec![
+                record("nqn.test:restricted", 1, &["nqn.test:host-a"]),
+                record("nqn.test:open", 0, &[]),
+                referral,
+            ],
+        }
+    }
+
+    fn entry(page: &[u8], i: usize) -> &spdk_nvmf_discovery_log_page_entry {
+        let offset = size_of::<spdk_nvmf_discovery_log_page>()
+            + i * size_of::<spdk_nvmf_discovery_log_page_entry>();
+        unsafe {
+            &*(page.as_ptr().add(offset)
+                as *const spdk_nvmf_discovery_log_page_entry)
+        }
+    }
+
+    fn field(field: &[u8]) -> String {
+        String::from_utf8_lossy(field)
+            .trim_end_matches(|c| c == ' ' || c == '\0')
+            .to_string()
+    }
+
+    #[test]
+    fn discovery_log_page() {
+        let log = log();
+
+        let page = log.page("nqn.test:host-a");
+        let header =
+            unsafe { &*(page.as_ptr() as *const spdk_nvmf_discovery_log_page) };
+        assert_eq!(header.genctr, 7);
+        assert_eq!(header.numrec, 3);
+        assert_eq!(
+            page.len(),
+            size_of::<spdk_nvmf_discovery_log_page>()
+                + 3 * size_of::<spdk_nvmf_discovery_log_page_entry>()
+        );
+
+        let restricted = entry(&page, 0);
+        assert_eq!(field(&restricted.subnqn), "nqn.test:restricted");
+        assert_eq!(field(&restricted.traddr), "10.0.0.2");
+        assert_eq!(field(&restricted.trsvcid), "8420");
+        assert_eq!(restricted.subtype as u32, SPDK_NVMF_SUBTYPE_NVME);
+        assert_eq!({ restricted.portid }, 1);
+        assert_eq!({ restricted.cntlid }, 0xffff);
+        assert_eq!({ restricted.asqsz }, 32);
+
+        let referral = entry(&page, 2);
+        assert_eq!(referral.subtype as u32, SPDK_NVMF_SUBTYPE_DISCOVERY);
+        assert_eq!(
+            field(&referral.subnqn),
+            "nqn.2014-08.org.nvmexpress.discovery"
+        );
+
+        // other hosts only see the open subsystem and the referral
+        let page = log.page("nqn.test:host-b");
+        let header =
+            unsafe { &*(page.as_ptr() as *const spdk_nvmf_discovery_log_page) };
+        assert_eq!(header.numrec, 2);
+        assert_eq!(field(&entry(&page, 0).subnqn), "nqn.test:open");
+    }
+
+    #[test]
+    fn discovery_log_page_range() {
+        let page = log().page("nqn.test:host-a");
+        let header_len = size_of::<spdk_nvmf_discovery_log_page>();
+        let entry_len = size_of::<spdk_nvmf_discovery_log_page_entry>();
+
+        // the header alone, as hosts read it to learn the number of entries
+        let data = page_range(&page, 0, header_len as u64).unwrap();
+        assert_eq!(data, &page[.. header_len]);
+
+        // a single entry in the middle of the page
+        let offset = (header_len + entry_len) as u64;
+        let data = page_range(&page, offset, entry_len as u64).unwrap();
+        assert_eq!(data, &page[offset as usize .. offset as usize + entry_len]);
+
+        // reads past the end are cut short
+        let data = page_range(&page, offset, 1 << 20).unwrap();
+        assert_eq!(data.len(), page.len() - offset as usize);
+        assert!(page_range(&page, page.len() as u64, 4).unwrap().is_empty());
+
+        // offsets must be dword aligned and within the page
+        assert!(page_range(&page, 2, 4).is_none());
+        assert!(page_range(&page, page.len() as u64 + 4, 4).is_none());
+    }
+}
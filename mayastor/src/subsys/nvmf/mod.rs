@@ -13,7 +13,24 @@ use std::cell::RefCell;
 use nix::errno::Errno;
 use snafu::Snafu;
 
-pub use admin_cmd::{create_snapshot, set_snapshot_time, NvmeCpl, NvmfReq};
+pub use admin_cmd::{
+    create_snapshot,
+    register_admin_cmd_handler,
+    set_snapshot_time,
+    AdminCmdHandler,
+    NvmeCpl,
+    NvmfCmdStatus,
+    NvmfReq,
+};
+pub use connections::{NvmfConnection, NvmfConnectionStats};
+pub use discovery::{
+    discovery_endpoints,
+    discovery_entries,
+    discovery_generation,
+    discovery_referrals,
+    set_discovery_referrals,
+    DiscoveryEntry,
+};
 use poll_groups::PollGroup;
 use spdk_rs::libspdk::{
     spdk_subsystem,
@@ -29,6 +46,8 @@ use crate::{
 };
 
 mod admin_cmd;
+mod connections;
+mod discovery;
 mod poll_groups;
 mod subsystem;
 mod target;
@@ -89,8 +108,9 @@ impl Nvmf {
 
         // this code only ever gets run on the first core
 
-        // set up custom NVMe Admin command handler
+        // set up custom NVMe Admin command handlers
         admin_cmd::setup_create_snapshot_hdlr();
+        discovery::setup_discovery_log_hdlr();
 
         if Config::get().nexus_opts.nvmf_enable {
             NVMF_TGT.with(|tgt| tgt.borrow_mut().next_state());
@@ -9,25 +9,32 @@ use futures::channel::oneshot;
 use nix::errno::Errno;
 
 use spdk_rs::libspdk::{
-    nvmf_subsystem_find_listener,
     nvmf_subsystem_set_ana_state,
     nvmf_subsystem_set_cntlid_range,
     spdk_bdev_nvme_opts,
+    spdk_nvmf_host_get_nqn,
     spdk_nvmf_ns_get_bdev,
     spdk_nvmf_ns_opts,
     spdk_nvmf_subsystem,
+    spdk_nvmf_subsystem_add_host,
     spdk_nvmf_subsystem_add_listener,
     spdk_nvmf_subsystem_add_ns_ext,
     spdk_nvmf_subsystem_create,
     spdk_nvmf_subsystem_destroy,
+    spdk_nvmf_subsystem_disconnect_host,
+    spdk_nvmf_subsystem_get_allow_any_host,
     spdk_nvmf_subsystem_get_first,
+    spdk_nvmf_subsystem_get_first_host,
     spdk_nvmf_subsystem_get_first_listener,
     spdk_nvmf_subsystem_get_first_ns,
     spdk_nvmf_subsystem_get_next,
+    spdk_nvmf_subsystem_get_next_host,
     spdk_nvmf_subsystem_get_next_listener,
     spdk_nvmf_subsystem_get_nqn,
+    spdk_nvmf_subsystem_host_allowed,
     spdk_nvmf_subsystem_listener_get_trid,
     spdk_nvmf_subsystem_pause,
+    spdk_nvmf_subsystem_remove_host,
     spdk_nvmf_subsystem_resume,
     spdk_nvmf_subsystem_set_allow_any_host,
     spdk_nvmf_subsystem_set_ana_reporting,
@@ -36,20 +43,31 @@ use spdk_rs::libspdk::{
     spdk_nvmf_subsystem_start,
     spdk_nvmf_subsystem_stop,
     spdk_nvmf_tgt,
+    SPDK_NVME_ANA_CHANGE_STATE,
+    SPDK_NVME_ANA_INACCESSIBLE_STATE,
+    SPDK_NVME_ANA_NON_OPTIMIZED_STATE,
+    SPDK_NVME_ANA_OPTIMIZED_STATE,
+    SPDK_NVMF_SUBSYSTEM_ACTIVATING,
+    SPDK_NVMF_SUBSYSTEM_DEACTIVATING,
+    SPDK_NVMF_SUBSYSTEM_INACTIVE,
     SPDK_NVMF_SUBTYPE_DISCOVERY,
     SPDK_NVMF_SUBTYPE_NVME,
 };
 
 use crate::{
-    core::{Bdev, Reactors, UntypedBdev},
+    bdev::nexus,
+    core::{Bdev, Mthread, NvmfShareProps, Reactors, UntypedBdev},
     ffihelper::{cb_arg, AsStr, FfiResult, IntoCString},
-    subsys::{
-        nvmf::{transport::TransportId, Error, NVMF_TGT},
-        Config,
+    subsys::nvmf::{
+        discovery,
+        target::Network,
+        transport::TransportId,
+        Error,
+        NVMF_TGT,
     },
 };
 
-#[derive(Debug, PartialOrd, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialOrd, PartialEq)]
 pub enum SubType {
     Nvme,
     Discovery,
@@ -106,6 +124,7 @@ impl Debug for NvmfSubsystem {
                     "allow_any_host",
                     &self.0.as_ref().flags.allow_any_host(),
                 )
+                .field("allowed_hosts", &self.allowed_hosts())
                 .field("ana_reporting", &self.0.as_ref().flags.ana_reporting())
                 .field("listeners", &self.listeners_to_vec())
                 .finish()
@@ -122,6 +141,18 @@ impl From<*mut spdk_nvmf_subsystem> for NvmfSubsystem {
 impl NvmfSubsystem {
     /// TODO
     pub fn try_from<T>(bdev: &Bdev<T>) -> Result<Self, Error>
+    where
+        T: spdk_rs::BdevOps,
+    {
+        Self::try_from_props(bdev, &NvmfShareProps::default())
+    }
+
+    /// create a subsystem for the bdev configured with the given share
+    /// properties, the NQN is derived from the bdev name unless set
+    pub fn try_from_props<T>(
+        bdev: &Bdev<T>,
+        props: &NvmfShareProps,
+    ) -> Result<Self, Error>
     where
         T: spdk_rs::BdevOps,
     {
@@ -130,10 +161,16 @@ impl NvmfSubsystem {
                 msg: "already shared".to_string(),
             });
         }
-        let ss = NvmfSubsystem::new(bdev.name())?;
-        ss.set_ana_reporting(true)?;
-        ss.allow_any(true);
-        if let Err(e) = ss.add_namespace(bdev) {
+        let nqn = props.nqn().map_or_else(|| gen_nqn(bdev.name()), Into::into);
+        // ANA group IDs cannot exceed the maximum namespace ID
+        let max_nsid = props.ana_group().unwrap_or(1);
+        let ss = NvmfSubsystem::with_nqn(&nqn, max_nsid)?;
+        let configured =
+            ss.set_ana_reporting(props.ana_reporting()).and_then(|_| {
+                ss.allow_any(props.allow_any_host());
+                ss.add_namespace_ext(bdev, props.ana_group(), props.ptpl_file())
+            });
+        if let Err(e) = configured {
             ss.destroy();
             return Err(e);
         }
@@ -144,22 +181,28 @@ impl NvmfSubsystem {
 impl NvmfSubsystem {
     /// create a new subsystem where the NQN is based on the UUID
     pub fn new(uuid: &str) -> Result<Self, Error> {
-        let nqn = gen_nqn(uuid).into_cstring();
+        Self::with_nqn(&gen_nqn(uuid), 1)
+    }
+
+    /// create a new subsystem with the given NQN, which can hold namespaces
+    /// with IDs up to max_nsid
+    pub fn with_nqn(nqn: &str, max_nsid: u32) -> Result<Self, Error> {
+        let c_nqn = nqn.into_cstring();
         let ss = NVMF_TGT
             .with(|t| {
                 let tgt = t.borrow().tgt.as_ptr();
                 unsafe {
                     spdk_nvmf_subsystem_create(
                         tgt,
-                        nqn.as_ptr(),
+                        c_nqn.as_ptr(),
                         SPDK_NVMF_SUBTYPE_NVME,
-                        1,
+                        max_nsid,
                     )
                 }
             })
             .to_result(|_| Error::Subsystem {
                 source: Errno::EEXIST,
-                nqn: uuid.into(),
+                nqn: nqn.into(),
                 msg: "ss ptr is null".into(),
             })?;
 
@@ -169,7 +212,7 @@ impl NvmfSubsystem {
         unsafe { spdk_nvmf_subsystem_set_sn(ss.as_ptr(), sn.as_ptr()) }
             .to_result(|e| Error::Subsystem {
                 source: Errno::from_i32(e),
-                nqn: uuid.into(),
+                nqn: nqn.into(),
                 msg: "failed to set serial".into(),
             })?;
 
@@ -177,7 +220,7 @@ impl NvmfSubsystem {
         unsafe { spdk_nvmf_subsystem_set_mn(ss.as_ptr(), mn.as_ptr()) }
             .to_result(|e| Error::Subsystem {
                 source: Errno::from_i32(e),
-                nqn: uuid.into(),
+                nqn: nqn.into(),
                 msg: "failed to set model number".into(),
             })?;
 
@@ -191,7 +234,6 @@ impl NvmfSubsystem {
         bdev: &UntypedBdev,
     ) -> Result<Self, Error> {
         let ss = NvmfSubsystem::new(uuid)?;
-        ss.set_ana_reporting(true)?;
         ss.allow_any(true);
         ss.add_namespace(bdev)?;
         Ok(ss)
@@ -199,21 +241,39 @@ impl NvmfSubsystem {
 
     /// add the given bdev to this namespace
     pub fn add_namespace<T>(&self, bdev: &Bdev<T>) -> Result<(), Error>
+    where
+        T: spdk_rs::BdevOps,
+    {
+        self.add_namespace_ext(bdev, None, None)
+    }
+
+    /// add the given bdev to this namespace as part of the given ANA group,
+    /// the group defaults to the namespace ID. Reservations of hosts which
+    /// activate Persist Through Power Loss are kept in the given file, and
+    /// restored from it.
+    pub fn add_namespace_ext<T>(
+        &self,
+        bdev: &Bdev<T>,
+        ana_group: Option<u32>,
+        ptpl_file: Option<&str>,
+    ) -> Result<(), Error>
     where
         T: spdk_rs::BdevOps,
     {
         let opts = spdk_nvmf_ns_opts {
             nguid: *bdev.uuid().as_bytes(),
+            anagrpid: ana_group.unwrap_or(0),
             ..Default::default()
         };
         let bdev_cname = CString::new(bdev.name()).unwrap();
+        let ptpl_cfile = ptpl_file.map(|f| f.into_cstring());
         let ns_id = unsafe {
             spdk_nvmf_subsystem_add_ns_ext(
                 self.0.as_ptr(),
                 bdev_cname.as_ptr(),
                 &opts as *const _,
                 size_of::<spdk_bdev_nvme_opts>() as u64,
-                ptr::null_mut(),
+                ptpl_cfile.as_ref().map_or(ptr::null(), |f| f.as_ptr()),
             )
         };
 
@@ -260,20 +320,129 @@ impl NvmfSubsystem {
         unsafe {
             spdk_nvmf_subsystem_set_allow_any_host(self.0.as_ptr(), enable)
         };
+        self.hosts_changed();
     }
 
-    /// enable Asymmetric Namespace Access (ANA) reporting
-    pub fn set_ana_reporting(&self, enable: bool) -> Result<(), Error> {
-        match std::env::var("NEXUS_NVMF_ANA_ENABLE") {
-            Ok(s) => {
-                if s != "1" {
-                    return Ok(());
-                }
-            }
-            Err(_) => {
-                return Ok(());
+    /// whether any host may connect to the subsystem
+    pub fn allows_any(&self) -> bool {
+        unsafe { spdk_nvmf_subsystem_get_allow_any_host(self.0.as_ptr()) }
+    }
+
+    /// the discovery log page lists started subsystems only for the hosts
+    /// allowed to connect to them
+    fn hosts_changed(&self) {
+        if self.subtype() == SubType::Nvme && self.is_started() {
+            discovery::schedule_refresh();
+        }
+    }
+
+    /// returns the NQNs of the hosts allowed to connect to the subsystem
+    pub fn allowed_hosts(&self) -> Vec<String> {
+        let mut hosts = Vec::new();
+        unsafe {
+            let mut host = spdk_nvmf_subsystem_get_first_host(self.0.as_ptr());
+            while !host.is_null() {
+                hosts.push(spdk_nvmf_host_get_nqn(host).as_str().to_string());
+                host = spdk_nvmf_subsystem_get_next_host(self.0.as_ptr(), host);
             }
         }
+        hosts
+    }
+
+    /// whether the host is allowed to connect to the subsystem
+    pub fn host_allowed(&self, host_nqn: &str) -> bool {
+        let host = host_nqn.into_cstring();
+        unsafe {
+            spdk_nvmf_subsystem_host_allowed(self.0.as_ptr(), host.as_ptr())
+        }
+    }
+
+    /// whether the subsystem has been started, and is thus reachable for
+    /// hosts
+    pub fn is_started(&self) -> bool {
+        !matches!(
+            unsafe { self.0.as_ref().state },
+            SPDK_NVMF_SUBSYSTEM_INACTIVE
+                | SPDK_NVMF_SUBSYSTEM_ACTIVATING
+                | SPDK_NVMF_SUBSYSTEM_DEACTIVATING
+        )
+    }
+
+    /// allow the host to connect to the subsystem
+    pub fn allow_host(&self, host_nqn: &str) -> Result<(), Error> {
+        let host = host_nqn.into_cstring();
+        unsafe { spdk_nvmf_subsystem_add_host(self.0.as_ptr(), host.as_ptr()) }
+            .to_result(|e| Error::Subsystem {
+                source: Errno::from_i32(e.abs()),
+                nqn: self.get_nqn(),
+                msg: format!("failed to allow host {}", host_nqn),
+            })?;
+        self.hosts_changed();
+        Ok(())
+    }
+
+    /// disallow the host to connect to the subsystem, existing connections
+    /// of the host are dropped
+    pub async fn disallow_host(&self, host_nqn: &str) -> Result<(), Error> {
+        extern "C" fn disconnect_cb(arg: *mut c_void, status: i32) {
+            let s = unsafe { Box::from_raw(arg as *mut oneshot::Sender<i32>) };
+            s.send(status).unwrap();
+        }
+
+        let host = host_nqn.into_cstring();
+        unsafe {
+            spdk_nvmf_subsystem_remove_host(self.0.as_ptr(), host.as_ptr())
+        }
+        .to_result(|e| Error::Subsystem {
+            source: Errno::from_i32(e.abs()),
+            nqn: self.get_nqn(),
+            msg: format!("failed to disallow host {}", host_nqn),
+        })?;
+        self.hosts_changed();
+
+        let (s, r) = oneshot::channel::<i32>();
+        unsafe {
+            spdk_nvmf_subsystem_disconnect_host(
+                self.0.as_ptr(),
+                host.as_ptr(),
+                Some(disconnect_cb),
+                cb_arg(s),
+            )
+        }
+        .to_result(|e| Error::Subsystem {
+            source: Errno::from_i32(e.abs()),
+            nqn: self.get_nqn(),
+            msg: format!("failed to disconnect host {}", host_nqn),
+        })?;
+
+        r.await.unwrap().to_result(|e| Error::Subsystem {
+            source: Errno::from_i32(e.abs()),
+            nqn: self.get_nqn(),
+            msg: format!("failed to disconnect host {}", host_nqn),
+        })
+    }
+
+    /// replace the hosts allowed to connect to the subsystem, when the list
+    /// is empty no host can connect unless any host is allowed
+    pub async fn set_allowed_hosts(
+        &self,
+        hosts: &[String],
+    ) -> Result<(), Error> {
+        let current = self.allowed_hosts();
+
+        for host in hosts.iter().filter(|h| !current.contains(h)) {
+            self.allow_host(host)?;
+        }
+
+        for host in current.iter().filter(|h| !hosts.contains(h)) {
+            self.disallow_host(host).await?;
+        }
+
+        Ok(())
+    }
+
+    /// enable Asymmetric Namespace Access (ANA) reporting
+    pub fn set_ana_reporting(&self, enable: bool) -> Result<(), Error> {
         unsafe {
             spdk_nvmf_subsystem_set_ana_reporting(self.0.as_ptr(), enable)
         }
@@ -309,35 +478,48 @@ impl NvmfSubsystem {
         Ok(())
     }
 
-    // we currently allow all listeners to the subsystem
+    /// the network this subsystem is exported on, nexuses are exported to
+    /// hosts while everything else is accessed by nexuses
+    fn network(&self) -> Network {
+        match self.bdev() {
+            Some(bdev) if bdev.driver() == nexus::NEXUS_MODULE_NAME => {
+                Network::Host
+            }
+            _ => Network::Replica,
+        }
+    }
+
+    // we currently allow all listeners of the subsystem's network
     async fn add_listener(&self) -> Result<(), Error> {
         extern "C" fn listen_cb(arg: *mut c_void, status: i32) {
             let s = unsafe { Box::from_raw(arg as *mut oneshot::Sender<i32>) };
             s.send(status).unwrap();
         }
 
-        let cfg = Config::get();
+        let network = self.network();
+        let listeners =
+            NVMF_TGT.with(|t| t.borrow().subsystem_listeners(network));
 
-        // dont yet enable both ports, IOW just add one transportID now
-
-        let trid_replica = TransportId::new(cfg.nexus_opts.nvmf_replica_port);
+        for trid in listeners {
+            let (s, r) = oneshot::channel::<i32>();
+            unsafe {
+                spdk_nvmf_subsystem_add_listener(
+                    self.0.as_ptr(),
+                    trid.as_ptr(),
+                    Some(listen_cb),
+                    cb_arg(s),
+                );
+            }
 
-        let (s, r) = oneshot::channel::<i32>();
-        unsafe {
-            spdk_nvmf_subsystem_add_listener(
-                self.0.as_ptr(),
-                trid_replica.as_ptr(),
-                Some(listen_cb),
-                cb_arg(s),
-            );
+            r.await.expect("listener callback gone").to_result(|e| {
+                Error::Transport {
+                    source: Errno::from_i32(e),
+                    msg: format!("Failed to add listener {}", trid),
+                }
+            })?;
         }
 
-        r.await.expect("listener callback gone").to_result(|e| {
-            Error::Transport {
-                source: Errno::from_i32(e),
-                msg: "Failed to add listener".to_string(),
-            }
-        })
+        Ok(())
     }
 
     /// start the subsystem previously created -- note that we destroy it on
@@ -386,6 +568,7 @@ impl NvmfSubsystem {
         })?;
 
         debug!(?self, "shared");
+        discovery::refresh().await;
         Ok(self.get_nqn())
     }
 
@@ -427,6 +610,7 @@ impl NvmfSubsystem {
         })?;
 
         debug!("stopped {}", self.get_nqn());
+        discovery::refresh().await;
         Ok(())
     }
 
@@ -524,53 +708,125 @@ impl NvmfSubsystem {
         }
     }
 
-    /// get ANA state
-    pub async fn get_ana_state(&self) -> Result<u32, Error> {
-        let cfg = Config::get();
-        let trid_replica = TransportId::new(cfg.nexus_opts.nvmf_replica_port);
-        let listener = unsafe {
-            nvmf_subsystem_find_listener(self.0.as_ptr(), trid_replica.as_ptr())
-        };
+    /// get the ANA state of the given ANA group, the state is set on all
+    /// listeners alike
+    pub async fn get_ana_state(&self, ana_group: u32) -> Result<u32, Error> {
+        let listener =
+            unsafe { spdk_nvmf_subsystem_get_first_listener(self.0.as_ptr()) };
         if listener.is_null() {
-            Err(Error::Listener {
+            return Err(Error::Listener {
                 nqn: self.get_nqn(),
-                trid: trid_replica.to_string(),
-            })
-        } else {
-            Ok(unsafe { *(*listener).ana_state })
+                trid: "any".to_string(),
+            });
         }
+
+        let index = self.ana_group_index(ana_group)?;
+        Ok(unsafe { *(*listener).ana_state.add(index) })
     }
 
-    /// set ANA state: optimized, non_optimized, inaccessible
+    /// index of the given ANA group in the per listener ANA states
+    fn ana_group_index(&self, ana_group: u32) -> Result<usize, Error> {
+        let max_nsid = unsafe { self.0.as_ref().max_nsid };
+        if ana_group == 0 || ana_group > max_nsid {
+            return Err(Error::Subsystem {
+                source: Errno::EINVAL,
+                nqn: self.get_nqn(),
+                msg: format!("invalid ANA group {}", ana_group),
+            });
+        }
+        Ok(ana_group as usize - 1)
+    }
+
+    /// set ANA state: optimized, non_optimized or inaccessible of the given
+    /// ANA group, or of all groups when 0. The state is changed on the thread
+    /// the target runs on, which notifies the connected hosts of the change.
+    /// The listeners pass through the change state first, so hosts that look
+    /// at the ANA log page while they are updated one by one retry their I/O
+    /// instead of failing it on a listener still in the old state.
     /// subsystem must be in paused or inactive state
-    pub async fn set_ana_state(&self, ana_state: u32) -> Result<(), Error> {
+    pub async fn set_ana_state(
+        &self,
+        ana_state: u32,
+        ana_group: u32,
+    ) -> Result<(), Error> {
+        if !matches!(
+            ana_state,
+            SPDK_NVME_ANA_OPTIMIZED_STATE
+                | SPDK_NVME_ANA_NON_OPTIMIZED_STATE
+                | SPDK_NVME_ANA_INACCESSIBLE_STATE
+        ) {
+            return Err(Error::Subsystem {
+                source: Errno::EINVAL,
+                nqn: self.get_nqn(),
+                msg: format!("ANA state {} cannot be set", ana_state),
+            });
+        }
+
+        if !unsafe { self.0.as_ref().flags.ana_reporting() } {
+            return Err(Error::Subsystem {
+                source: Errno::EINVAL,
+                nqn: self.get_nqn(),
+                msg: "ANA reporting is disabled".to_string(),
+            });
+        }
+
+        let ss = *self;
+        Mthread::get_init()
+            .spawn_local(async move {
+                ss.set_listeners_ana_state(
+                    SPDK_NVME_ANA_CHANGE_STATE,
+                    ana_group,
+                )
+                .await?;
+                ss.set_listeners_ana_state(ana_state, ana_group).await
+            })
+            .map_err(|e| Error::Subsystem {
+                source: Errno::EIO,
+                nqn: self.get_nqn(),
+                msg: format!("failed to set_ana_state: {}", e),
+            })?
+            .await
+            .expect("Cancellation is not supported")
+    }
+
+    /// set the ANA state of the group on all listeners of the subsystem
+    async fn set_listeners_ana_state(
+        &self,
+        ana_state: u32,
+        ana_group: u32,
+    ) -> Result<(), Error> {
         extern "C" fn set_ana_state_cb(arg: *mut c_void, status: i32) {
             let s = unsafe { Box::from_raw(arg as *mut oneshot::Sender<i32>) };
             s.send(status).unwrap();
         }
-        let cfg = Config::get();
-        let trid_replica = TransportId::new(cfg.nexus_opts.nvmf_replica_port);
 
-        let (s, r) = oneshot::channel::<i32>();
+        for trid in self.listeners_to_vec().unwrap_or_default() {
+            let (s, r) = oneshot::channel::<i32>();
 
-        unsafe {
-            nvmf_subsystem_set_ana_state(
-                self.0.as_ptr(),
-                trid_replica.as_ptr(),
-                ana_state,
-                0,
-                Some(set_ana_state_cb),
-                cb_arg(s),
-            );
+            unsafe {
+                nvmf_subsystem_set_ana_state(
+                    self.0.as_ptr(),
+                    trid.as_ptr(),
+                    ana_state,
+                    ana_group,
+                    Some(set_ana_state_cb),
+                    cb_arg(s),
+                );
+            }
+
+            r.await
+                .expect("Cancellation is not supported")
+                .to_result(|e| Error::Subsystem {
+                    source: Errno::from_i32(-e),
+                    nqn: self.get_nqn(),
+                    msg: format!(
+                        "failed to set_ana_state of the subsystem on {}",
+                        trid
+                    ),
+                })?;
         }
 
-        r.await
-            .expect("Cancellation is not supported")
-            .to_result(|e| Error::Subsystem {
-                source: Errno::from_i32(-e),
-                nqn: self.get_nqn(),
-                msg: "failed to set_ana_state of the subsystem".to_string(),
-            })
+        Ok(())
     }
 
     /// destroy all subsystems associated with our target, subsystems must be in
@@ -615,10 +871,17 @@ impl NvmfSubsystem {
     /// lookup a subsystem by its UUID
     pub fn nqn_lookup(uuid: &str) -> Option<NvmfSubsystem> {
         let nqn = gen_nqn(uuid);
-        NvmfSubsystem::first()
-            .unwrap()
-            .into_iter()
-            .find(|s| s.get_nqn() == nqn)
+        let subsystems =
+            NvmfSubsystem::first()?.into_iter().collect::<Vec<_>>();
+        if let Some(pos) = subsystems.iter().position(|s| s.get_nqn() == nqn) {
+            return subsystems.into_iter().nth(pos);
+        }
+
+        // a bdev shared under an NQN of its own
+        subsystems.into_iter().find(|s| {
+            s.subtype() == SubType::Nvme
+                && s.bdev().map_or(false, |b| b.name() == uuid)
+        })
     }
 
     /// get the bdev associated with this subsystem -- we implicitly assume the
@@ -633,7 +896,7 @@ impl NvmfSubsystem {
         unsafe { Bdev::checked_from_ptr(spdk_nvmf_ns_get_bdev(ns)) }
     }
 
-    fn listeners_to_vec(&self) -> Option<Vec<TransportId>> {
+    pub(crate) fn listeners_to_vec(&self) -> Option<Vec<TransportId>> {
         unsafe {
             let mut listener =
                 spdk_nvmf_subsystem_get_first_listener(self.0.as_ptr());
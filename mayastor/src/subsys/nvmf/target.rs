@@ -1,12 +1,14 @@
 use std::{
     cell::RefCell,
     ffi::{c_void, CString},
+    net::IpAddr,
     ptr::NonNull,
 };
 
 use nix::errno::Errno;
 
 use spdk_rs::libspdk::{
+    nvmf_update_discovery_log,
     spdk_env_get_core_count,
     spdk_nvmf_listen_opts,
     spdk_nvmf_listen_opts_init,
@@ -26,14 +28,16 @@ use spdk_rs::libspdk::{
 };
 
 use crate::{
-    core::{Cores, Mthread, Reactor, Reactors},
+    core::{Cores, Mthread, Reactors},
     ffihelper::{AsStr, FfiResult},
     subsys::{
         nvmf::{
+            connections,
+            discovery,
             poll_groups::PollGroup,
             subsystem::NvmfSubsystem,
             transport,
-            transport::{get_ipv4_address, TransportId},
+            transport::TransportId,
             Error,
             NVMF_PGS,
         },
@@ -55,6 +59,21 @@ pub struct Target {
     poll_group_count: u16,
     /// The current state of the target
     next_state: TargetState,
+    /// addresses of the network over which nexuses are exported
+    host_addresses: Vec<IpAddr>,
+    /// addresses of the network over which replicas are exported
+    replica_addresses: Vec<IpAddr>,
+    /// the listeners opened by the target
+    listeners: Vec<TransportId>,
+}
+
+/// The network a subsystem is exported on
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Network {
+    /// host facing, used by nexuses
+    Host,
+    /// used for nexus to replica traffic
+    Replica,
 }
 
 impl Default for Target {
@@ -100,6 +119,9 @@ impl Target {
             tgt: NonNull::dangling(),
             poll_group_count: 0,
             next_state: TargetState::Init,
+            host_addresses: Vec::new(),
+            replica_addresses: Vec::new(),
+            listeners: Vec::new(),
         }
     }
 
@@ -219,11 +241,69 @@ impl Target {
         });
     }
 
-    /// Listen for incoming connections by default we only listen on the replica
-    /// port
+    /// Listen for incoming connections on the host and replica networks. The
+    /// nexus port is only opened on the host network, the replica port on
+    /// both as subsystems are exported through it.
     fn listen(&mut self) -> Result<()> {
         let cfg = Config::get();
-        let trid_nexus = TransportId::new(cfg.nexus_opts.nvmf_nexus_port);
+        self.host_addresses =
+            transport::listen_addresses(&cfg.nexus_opts.nvmf_host_addresses)?;
+        self.replica_addresses = transport::listen_addresses(
+            &cfg.nexus_opts.nvmf_replica_addresses,
+        )?;
+
+        for address in self.host_addresses.clone() {
+            let trid = TransportId::with_address(
+                address,
+                cfg.nexus_opts.nvmf_nexus_port,
+            );
+            self.listen_on(trid).map_err(|e| Error::CreateTarget {
+                msg: format!("failed to back target: {}", e),
+            })?;
+        }
+
+        let mut addresses = self.replica_addresses.clone();
+        addresses.extend(
+            self.host_addresses
+                .iter()
+                .filter(|a| !self.replica_addresses.contains(a)),
+        );
+        for address in addresses {
+            let trid = TransportId::with_address(
+                address,
+                cfg.nexus_opts.nvmf_replica_port,
+            );
+            self.listen_on(trid).map_err(|e| Error::CreateTarget {
+                msg: format!("failed to front target: {}", e),
+            })?;
+        }
+
+        discovery::set_ports(
+            self.listeners
+                .iter()
+                .filter_map(|t| t.socket_addr())
+                .collect(),
+        );
+        discovery::set_endpoints(
+            self.subsystem_listeners(Network::Host)
+                .iter()
+                .filter_map(|t| t.socket_addr())
+                .collect(),
+        );
+
+        info!(
+            "nvmf target listening on {:?}",
+            self.listeners
+                .iter()
+                .map(|t| t.to_string())
+                .collect::<Vec<_>>()
+        );
+        self.next_state();
+        Ok(())
+    }
+
+    /// listen on the given transport id
+    fn listen_on(&mut self, trid: TransportId) -> Result<()> {
         let mut opts = spdk_nvmf_listen_opts::default();
         unsafe {
             spdk_nvmf_listen_opts_init(
@@ -231,47 +311,62 @@ impl Target {
                 std::mem::size_of::<spdk_nvmf_listen_opts>() as u64,
             );
         }
-        let rc = unsafe {
+
+        unsafe {
             spdk_nvmf_tgt_listen_ext(
                 self.tgt.as_ptr(),
-                trid_nexus.as_ptr(),
+                trid.as_ptr(),
                 &mut opts,
             )
-        };
-
-        if rc != 0 {
-            return Err(Error::CreateTarget {
-                msg: "failed to back target".into(),
-            });
         }
+        .to_result(|e| Error::Transport {
+            source: Errno::from_i32(e.abs()),
+            msg: format!("failed to listen on {}", trid),
+        })?;
 
-        let trid_replica = TransportId::new(cfg.nexus_opts.nvmf_replica_port);
-        let rc = unsafe {
-            spdk_nvmf_tgt_listen_ext(
-                self.tgt.as_ptr(),
-                trid_replica.as_ptr(),
-                &mut opts,
-            )
+        self.listeners.push(trid);
+        Ok(())
+    }
+
+    /// transport ids of the listeners subsystems exported on the given
+    /// network are reachable through. Subsystems are exported on the replica
+    /// port unless the host network is configured explicitly, in which case
+    /// those exported to hosts use the nexus port.
+    pub(crate) fn subsystem_listeners(
+        &self,
+        network: Network,
+    ) -> Vec<TransportId> {
+        let opts = &Config::get().nexus_opts;
+        let (addresses, port) = match network {
+            Network::Host if !opts.nvmf_host_addresses.is_empty() => {
+                (&self.host_addresses, opts.nvmf_nexus_port)
+            }
+            Network::Host => (&self.host_addresses, opts.nvmf_replica_port),
+            Network::Replica => {
+                (&self.replica_addresses, opts.nvmf_replica_port)
+            }
         };
+        addresses
+            .iter()
+            .map(|a| TransportId::with_address(*a, port))
+            .collect()
+    }
 
-        if rc != 0 {
-            return Err(Error::CreateTarget {
-                msg: "failed to front target".into(),
-            });
+    /// rebuild the discovery log page and notify the hosts connected to the
+    /// discovery controller that it changed
+    pub(crate) fn update_discovery_log(&self) {
+        if self.next_state != TargetState::Running {
+            return;
+        }
+        discovery::rebuild(self.tgt.as_ptr());
+        unsafe {
+            nvmf_update_discovery_log(self.tgt.as_ptr(), std::ptr::null())
         }
-        info!(
-            "nvmf target listening on {}:({},{})",
-            get_ipv4_address().unwrap(),
-            trid_nexus.trsvcid.as_str(),
-            trid_replica.trsvcid.as_str(),
-        );
-        self.next_state();
-        Ok(())
     }
 
     /// enable discovery for the target -- note that the discovery system is not
     /// started
-    fn enable_discovery(&self) {
+    fn enable_discovery(&self) -> NvmfSubsystem {
         debug!("enabling discovery for target");
         let discovery = unsafe {
             NvmfSubsystem::from(spdk_nvmf_subsystem_create(
@@ -294,14 +389,12 @@ impl Target {
         .unwrap();
 
         discovery.allow_any(true);
-
-        Reactor::block_on(async {
-            let _ = discovery.start().await.unwrap();
-        });
+        discovery
     }
 
     /// stop all subsystems on this target we are borrowed here
     fn stop_subsystems(&self) {
+        connections::clear();
         let tgt = self.tgt.as_ptr();
         Reactors::master().send_future(async move {
             NvmfSubsystem::stop_all(tgt).await;
@@ -349,13 +442,25 @@ impl Target {
 
     /// final state for the target during init
     pub fn running(&mut self) {
-        self.enable_discovery();
-        info!(
-            "nvmf target accepting new connections and is ready to roll..{}",
-            '\u{1F483}'
-        );
+        let discovery = self.enable_discovery();
 
-        unsafe { spdk_subsystem_init_next(0) }
+        // the target is borrowed while changing state, but starting the
+        // discovery subsystem looks up the listeners of the target
+        Reactors::master().send_future(async move {
+            match discovery.start().await {
+                Ok(_) => {
+                    info!(
+                        "nvmf target accepting new connections and is ready to roll..{}",
+                        '\u{1F483}'
+                    );
+                    unsafe { spdk_subsystem_init_next(0) }
+                }
+                Err(e) => {
+                    error!("failed to start the discovery subsystem: {}", e);
+                    unsafe { spdk_subsystem_init_next(1) }
+                }
+            }
+        });
     }
 
     ///  shutdown procedure
@@ -367,17 +472,11 @@ impl Target {
             }
         }
 
-        let cfg = Config::get();
-        let trid_nexus = TransportId::new(cfg.nexus_opts.nvmf_nexus_port);
-        let trid_replica = TransportId::new(cfg.nexus_opts.nvmf_replica_port);
-
-        unsafe {
-            spdk_nvmf_tgt_stop_listen(self.tgt.as_ptr(), trid_replica.as_ptr())
-        };
-
-        unsafe {
-            spdk_nvmf_tgt_stop_listen(self.tgt.as_ptr(), trid_nexus.as_ptr())
-        };
+        for trid in self.listeners.drain(..) {
+            unsafe {
+                spdk_nvmf_tgt_stop_listen(self.tgt.as_ptr(), trid.as_ptr())
+            };
+        }
 
         unsafe {
             spdk_nvmf_tgt_destroy(
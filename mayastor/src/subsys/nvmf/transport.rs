@@ -2,13 +2,13 @@ use std::{
     env,
     ffi::CString,
     fmt::{Debug, Display, Formatter},
-    net::Ipv4Addr,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
     ops::{Deref, DerefMut},
     ptr::copy_nonoverlapping,
 };
 
 use futures::channel::oneshot;
-use nix::errno::Errno;
+use nix::{errno::Errno, ifaddrs::getifaddrs, sys::socket::SockAddr};
 use once_cell::sync::Lazy;
 
 use spdk_rs::libspdk::{
@@ -17,6 +17,7 @@ use spdk_rs::libspdk::{
     spdk_nvmf_transport_create,
     SPDK_NVME_TRANSPORT_TCP,
     SPDK_NVMF_ADRFAM_IPV4,
+    SPDK_NVMF_ADRFAM_IPV6,
     SPDK_NVMF_TRSVCID_MAX_LEN,
 };
 
@@ -30,7 +31,7 @@ use crate::{
         IntoCString,
     },
     subsys::{
-        nvmf::{Error, NVMF_TGT},
+        nvmf::{connections, Error, NVMF_TGT},
         Config,
     },
 };
@@ -49,6 +50,7 @@ pub async fn add_tcp_transport() -> Result<(), Error> {
         source: Errno::UnknownErrno,
         msg: "failed to create transport".into(),
     })?;
+    connections::track_requests(transport);
 
     let (s, r) = oneshot::channel::<ErrnoResult<()>>();
     unsafe {
@@ -84,16 +86,18 @@ impl DerefMut for TransportId {
 }
 
 impl TransportId {
-    pub fn new(port: u16) -> Self {
-        let address = get_ipv4_address().unwrap();
-
+    /// transport id of a TCP listener on the given address and port
+    pub fn with_address(address: IpAddr, port: u16) -> Self {
         let mut trid = spdk_nvme_transport_id {
             trtype: SPDK_NVME_TRANSPORT_TCP,
-            adrfam: SPDK_NVMF_ADRFAM_IPV4,
+            adrfam: match address {
+                IpAddr::V4(_) => SPDK_NVMF_ADRFAM_IPV4,
+                IpAddr::V6(_) => SPDK_NVMF_ADRFAM_IPV6,
+            },
             ..Default::default()
         };
 
-        let c_addr = address.into_cstring();
+        let c_addr = address.to_string().into_cstring();
         let port = format!("{}", port);
 
         assert!(port.len() < SPDK_NVMF_TRSVCID_MAX_LEN as usize);
@@ -119,6 +123,14 @@ impl TransportId {
         Self(trid)
     }
 
+    /// the address and port of the transport id, none when the transport
+    /// is not IP based
+    pub fn socket_addr(&self) -> Option<SocketAddr> {
+        let address = self.0.traddr.as_str().parse::<IpAddr>().ok()?;
+        let port = self.0.trsvcid.as_str().parse::<u16>().ok()?;
+        Some(SocketAddr::new(address, port))
+    }
+
     pub fn as_ptr(&self) -> *mut spdk_nvme_transport_id {
         &self.0 as *const _ as *mut spdk_nvme_transport_id
     }
@@ -126,12 +138,21 @@ impl TransportId {
 
 impl Display for TransportId {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "nvmf://{}:{}",
-            self.0.traddr.as_str(),
-            self.0.trsvcid.as_str()
-        )
+        if self.0.adrfam == SPDK_NVMF_ADRFAM_IPV6 {
+            write!(
+                f,
+                "nvmf://[{}]:{}",
+                self.0.traddr.as_str(),
+                self.0.trsvcid.as_str()
+            )
+        } else {
+            write!(
+                f,
+                "nvmf://{}:{}",
+                self.0.traddr.as_str(),
+                self.0.trsvcid.as_str()
+            )
+        }
     }
 }
 
@@ -145,19 +166,69 @@ impl Debug for TransportId {
             .finish()
     }
 }
-pub(crate) fn get_ipv4_address() -> Result<String, Error> {
-    let address = match env::var("MY_POD_IP") {
-        Ok(val) => {
-            if val.parse::<Ipv4Addr>().is_ok() {
-                Ok(val)
-            } else {
-                Err(Error::CreateTarget {
-                    msg: "Invalid IPv4 address".into(),
-                })
+/// returns the address of the pod, which may be either an IPv4 or an IPv6
+/// address, or the loopback address when not running in a pod
+pub(crate) fn get_pod_address() -> Result<IpAddr, Error> {
+    match env::var("MY_POD_IP") {
+        Ok(val) => val.parse::<IpAddr>().map_err(|_| Error::CreateTarget {
+            msg: format!("Invalid IP address {}", val),
+        }),
+        Err(_) => Ok(IpAddr::V4(Ipv4Addr::LOCALHOST)),
+    }
+}
+
+/// resolve the configured listen addresses, an entry is either an address or
+/// the name of a network interface in which case all its addresses are used,
+/// the pod address is used when nothing is configured
+pub(crate) fn listen_addresses(
+    entries: &[String],
+) -> Result<Vec<IpAddr>, Error> {
+    if entries.is_empty() {
+        return Ok(vec![get_pod_address()?]);
+    }
+
+    let mut addresses = Vec::new();
+    for entry in entries {
+        let found = match entry.parse::<IpAddr>() {
+            Ok(address) => vec![address],
+            Err(_) => interface_addresses(entry)?,
+        };
+
+        for address in found {
+            if !addresses.contains(&address) {
+                addresses.push(address);
             }
         }
-        Err(_) => Ok("127.0.0.1".to_owned()),
-    }?;
+    }
+
+    Ok(addresses)
+}
+
+/// returns the addresses of the given network interface, link local IPv6
+/// addresses are skipped as they cannot be used without a scope
+fn interface_addresses(name: &str) -> Result<Vec<IpAddr>, Error> {
+    let addresses = getifaddrs()
+        .map_err(|source| Error::Transport {
+            source,
+            msg: "failed to list the network interfaces".into(),
+        })?
+        .filter(|ifaddr| ifaddr.interface_name == name)
+        .filter_map(|ifaddr| match ifaddr.address {
+            Some(SockAddr::Inet(inet)) => Some(inet.ip().to_std()),
+            _ => None,
+        })
+        .filter(|address| match address {
+            IpAddr::V6(v6) => (v6.segments()[0] & 0xffc0) != 0xfe80,
+            IpAddr::V4(_) => true,
+        })
+        .collect::<Vec<_>>();
+
+    if addresses.is_empty() {
+        return Err(Error::Transport {
+            source: Errno::EADDRNOTAVAIL,
+            msg: format!("no usable address on network interface {}", name),
+        });
+    }
 
-    Ok(address)
+    Ok(addresses)
 }
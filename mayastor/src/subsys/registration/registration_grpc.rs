@@ -10,6 +10,8 @@ use rpc::registration::{
 };
 use std::{env, time::Duration};
 
+use crate::subsys::{discovery_endpoints, set_discovery_referrals};
+
 /// Mayastor sends registration messages in this interval (kind of heart-beat)
 const HB_INTERVAL_SEC: Duration = Duration::from_secs(5);
 /// How long we wait to send a registration message before timing out
@@ -100,7 +102,8 @@ impl Registration {
         self.fini_chan.close();
     }
 
-    /// Register a new node over rpc
+    /// Register a new node over rpc, the control plane replies with the
+    /// discovery services of the other nodes which are used as referrals
     pub async fn register(&mut self) -> Result<(), tonic::Status> {
         match self
             .client
@@ -108,10 +111,20 @@ impl Registration {
                 id: self.config.node.to_string(),
                 grpc_endpoint: self.config.grpc_endpoint.clone(),
                 instance_uuid: None,
+                discovery_endpoints: discovery_endpoints()
+                    .iter()
+                    .map(|e| e.to_string())
+                    .collect(),
             }))
             .await
         {
-            Ok(_) => Ok(()),
+            Ok(response) => {
+                set_discovery_referrals(
+                    &response.into_inner().discovery_referrals,
+                )
+                .await;
+                Ok(())
+            }
             Err(e) => Err(e),
         }
     }
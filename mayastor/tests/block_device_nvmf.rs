@@ -4,7 +4,14 @@ use once_cell::sync::{Lazy, OnceCell};
 
 use common::compose::{Builder, MayastorTest};
 use mayastor::{
-    bdev::{device_create, device_destroy, device_lookup, device_open},
+    bdev::{
+        device_create,
+        device_destroy,
+        device_lookup,
+        device_open,
+        NvmeQpairStats,
+        NVME_CONTROLLERS,
+    },
     core::{
         BlockDevice,
         BlockDeviceHandle,
@@ -12,6 +19,7 @@ use mayastor::{
         IoCompletionStatus,
         MayastorCliArgs,
     },
+    ffihelper::{cb_arg, done_cb},
     subsys::{Config, NvmeBdevOpts},
 };
 use rpc::mayastor::{BdevShareRequest, BdevUri, JsonRpcRequest, Null};
@@ -24,6 +32,7 @@ use std::{
     sync::{
         atomic::{AtomicBool, AtomicPtr, AtomicU64, Ordering},
         Arc,
+        Mutex,
     },
 };
 
@@ -1308,6 +1317,104 @@ async fn nvmf_device_reset() {
     .await;
 }
 
+/// the statistics of the qpairs of the controller, one per core
+async fn qpair_stats(name: &str) -> Vec<NvmeQpairStats> {
+    let (s, r) = futures::channel::oneshot::channel();
+    NVME_CONTROLLERS
+        .lookup_by_name(name)
+        .unwrap()
+        .lock()
+        .get_qpair_stats(|stats, ch| done_cb(ch, stats), cb_arg(s))
+        .unwrap();
+    r.await.unwrap().unwrap()
+}
+
+#[tokio::test]
+async fn nvmf_device_qpair_stats() {
+    let ms = get_ms();
+    let (_test, url) = launch_instance().await;
+    let url2 = url.clone();
+
+    fn reset_callback(
+        _device: &dyn BlockDevice,
+        status: IoCompletionStatus,
+        _ctx: *mut c_void,
+    ) {
+        assert_eq!(status, IoCompletionStatus::Success, "reset() failed");
+    }
+
+    let name = ms
+        .spawn(async move {
+            let name = device_create(&url).await.unwrap();
+            let handle =
+                device_open(&name, false).unwrap().into_handle().unwrap();
+            let alignment = handle.get_device().alignment();
+
+            let buf = create_io_buffer(alignment, 4096, 0xaa);
+            handle.write_at(0, &buf).await.unwrap();
+            let mut buf = create_io_buffer(alignment, 4096, 0);
+            handle.read_at(0, &mut buf).await.unwrap();
+
+            // I/O through the qpair of this core is accounted, along with
+            // its latency
+            let qpairs = qpair_stats(&name).await;
+            assert_eq!(qpairs.len(), 1);
+            let qpair = qpairs[0];
+            assert!(qpair.connected);
+            assert_eq!(qpair.outstanding_io, 0);
+            assert_eq!(qpair.queued_io, 0);
+            assert_eq!(qpair.resets, 0);
+            assert_eq!(qpair.failed_io, 0);
+            assert_eq!(qpair.io_stats.num_read_ops, 1);
+            assert_eq!(qpair.io_stats.num_write_ops, 1);
+            assert!(qpair.io_stats.read_latency_ticks > 0);
+            assert!(qpair.io_stats.write_latency_ticks > 0);
+
+            handle.reset(reset_callback, std::ptr::null_mut()).unwrap();
+            name
+        })
+        .await;
+
+    // The reset is accounted by the controller, and the qpair of this core
+    // reconnected.
+    let mut reconnected = false;
+    for _ in 0 .. 50 {
+        let n = name.clone();
+        let (resets, qpairs) = ms
+            .spawn(async move {
+                let health = NVME_CONTROLLERS
+                    .lookup_by_name(&n)
+                    .unwrap()
+                    .lock()
+                    .get_health_stats();
+                (health.resets, qpair_stats(&n).await)
+            })
+            .await;
+        if resets == 1 && qpairs[0].connected {
+            assert_eq!(qpairs[0].resets, 1);
+            assert_eq!(qpairs[0].outstanding_io, 0);
+            reconnected = true;
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    assert!(reconnected, "the qpair did not reconnect after the reset");
+
+    ms.spawn(async move {
+        let health = NVME_CONTROLLERS
+            .lookup_by_name(&name)
+            .unwrap()
+            .lock()
+            .get_health_stats();
+        assert_eq!(health.failed_resets, 0);
+        assert_eq!(health.timeouts, 0);
+        assert_eq!(health.adminq_failures, 0);
+
+        device_destroy(&url2).await.unwrap();
+    })
+    .await;
+}
+
 async fn wipe_device_blocks(is_unmap: bool) {
     let ms = get_ms();
     let (_test, url) = launch_instance().await;
@@ -1870,3 +1977,145 @@ async fn nvmf_device_hot_remove() {
     })
     .await;
 }
+
+#[tokio::test]
+async fn nvmf_device_compare_and_write() {
+    const OP_OFFSET: u64 = 4 * 1024 * 1024;
+
+    static STATUS: Lazy<Mutex<Vec<IoCompletionStatus>>> =
+        Lazy::new(|| Mutex::new(Vec::new()));
+
+    fn compare_completion_callback(
+        _device: &dyn BlockDevice,
+        status: IoCompletionStatus,
+        _ctx: *mut c_void,
+    ) {
+        STATUS.lock().unwrap().push(status);
+    }
+
+    fn take_status() -> Vec<IoCompletionStatus> {
+        std::mem::take(&mut *STATUS.lock().unwrap())
+    }
+
+    // Placeholder structure to let all the fields outlive API invocations.
+    struct IoCtx {
+        iovs: [IoVec; 2],
+        bufs: [DmaBuf; 2],
+        offset_blocks: u64,
+        handle: Box<dyn BlockDeviceHandle>,
+    }
+
+    let ms = get_ms();
+    let (_test, dev_url) = launch_instance().await;
+    let u = Arc::new(dev_url);
+    let url = u.clone();
+
+    // The data of the device matches the first buffer and not the second,
+    // so of the compares only the first one succeeds, and nothing is written
+    // by the compare-and-write.
+    let ctx = ms
+        .spawn(async move {
+            let name = device_create(&(*url)).await.unwrap();
+            let descr = device_open(&name, false).unwrap();
+            let handle = descr.into_handle().unwrap();
+            let (block_len, alignment) = {
+                let device = handle.get_device();
+                (device.block_len(), device.alignment())
+            };
+
+            let data = create_io_buffer(alignment, block_len, IO_PATTERN);
+            handle.write_at(OP_OFFSET, &data).await.unwrap();
+
+            let mut ctx = IoCtx {
+                iovs: [IoVec::default(), IoVec::default()],
+                bufs: [
+                    data,
+                    create_io_buffer(alignment, block_len, GUARD_PATTERN),
+                ],
+                offset_blocks: OP_OFFSET / block_len,
+                handle,
+            };
+            for i in 0 .. 2 {
+                ctx.iovs[i].iov_base = *ctx.bufs[i];
+                ctx.iovs[i].iov_len = block_len;
+            }
+
+            let same: *mut IoVec = &mut ctx.iovs[0];
+            let other: *mut IoVec = &mut ctx.iovs[1];
+            for iov in &[same, other] {
+                ctx.handle
+                    .comparev_blocks(
+                        *iov,
+                        1,
+                        ctx.offset_blocks,
+                        1,
+                        compare_completion_callback,
+                        std::ptr::null_mut(),
+                    )
+                    .unwrap();
+            }
+            ctx.handle
+                .comparev_and_writev_blocks(
+                    other,
+                    1,
+                    other,
+                    1,
+                    ctx.offset_blocks,
+                    1,
+                    compare_completion_callback,
+                    std::ptr::null_mut(),
+                )
+                .unwrap();
+
+            AtomicPtr::new(Box::into_raw(Box::new(ctx)))
+        })
+        .await;
+
+    tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+
+    let status = take_status();
+    assert_eq!(status.len(), 3, "Not all I/O operations completed");
+    let count = |s| status.iter().filter(|&&c| c == s).count();
+    assert_eq!(count(IoCompletionStatus::Success), 1);
+    assert_eq!(count(IoCompletionStatus::Miscompare), 2);
+
+    // The compare-and-write succeeds once the data matches.
+    let ctx = ms
+        .spawn(async move {
+            let mut ctx = unsafe { Box::<IoCtx>::from_raw(ctx.into_inner()) };
+            let same: *mut IoVec = &mut ctx.iovs[0];
+            let other: *mut IoVec = &mut ctx.iovs[1];
+            ctx.handle
+                .comparev_and_writev_blocks(
+                    same,
+                    1,
+                    other,
+                    1,
+                    ctx.offset_blocks,
+                    1,
+                    compare_completion_callback,
+                    std::ptr::null_mut(),
+                )
+                .unwrap();
+            AtomicPtr::new(Box::into_raw(ctx))
+        })
+        .await;
+
+    tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+    assert_eq!(take_status(), vec![IoCompletionStatus::Success]);
+
+    ms.spawn(async move {
+        let ctx = unsafe { Box::<IoCtx>::from_raw(ctx.into_inner()) };
+        let device = ctx.handle.get_device();
+        let mut buf =
+            DmaBuf::new(device.block_len(), device.alignment()).unwrap();
+        ctx.handle.read_at(OP_OFFSET, &mut buf).await.unwrap();
+        check_buf_pattern(&buf, GUARD_PATTERN);
+    })
+    .await;
+
+    ms.spawn(async move {
+        device_destroy(&u).await.unwrap();
+    })
+    .await;
+}
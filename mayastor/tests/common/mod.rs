@@ -14,10 +14,15 @@ use url::{ParseError, Url};
 use tracing::{error, info, trace};
 
 use mayastor::{
+    bdev::{device_create, device_destroy, device_open},
     core::{MayastorEnvironment, Mthread},
     logger,
     rebuild::{ClientOperations, RebuildJob, RebuildState},
 };
+use spdk_rs::libspdk::{
+    spdk_nvme_registered_ctrlr_extended_data,
+    spdk_nvme_reservation_status_extended_data,
+};
 
 pub mod bdev_io;
 pub mod compose;
@@ -485,3 +490,33 @@ pub fn reactor_run_millis(milliseconds: u64) {
     });
     reactor_poll!(r);
 }
+
+/// connect to the NVMe-oF target at the given uri and return the key of the
+/// reservation holder
+pub async fn reservation_holder(uri: &str) -> Option<u64> {
+    let name = device_create(uri).await.unwrap();
+    let handle = device_open(&name, false).unwrap().into_handle().unwrap();
+
+    let mut buffer = handle.dma_malloc(4096).unwrap();
+    handle.nvme_resv_report(1, &mut buffer).await.unwrap();
+
+    let (stext, sl) = buffer.as_slice().split_at(std::mem::size_of::<
+        spdk_nvme_reservation_status_extended_data,
+    >());
+    let (_, status, _) = unsafe {
+        stext.align_to::<spdk_nvme_reservation_status_extended_data>()
+    };
+    let (_, ctrlrs, _) =
+        unsafe { sl.align_to::<spdk_nvme_registered_ctrlr_extended_data>() };
+
+    let regctl = status[0].data.regctl as usize;
+    let holder = ctrlrs
+        .iter()
+        .take(regctl)
+        .find(|c| c.rcsts.status() == 1)
+        .map(|c| c.rkey);
+
+    drop(handle);
+    device_destroy(uri).await.unwrap();
+    holder
+}
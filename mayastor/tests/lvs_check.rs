@@ -0,0 +1,124 @@
+use common::MayastorTest;
+use mayastor::{
+    core::{MayastorCliArgs, UntypedBdev},
+    lvs::Lvs,
+    nexus_uri::bdev_create,
+    pool::PoolArgs,
+};
+
+pub mod common;
+
+static DISKNAME1: &str = "/tmp/disk1.img";
+
+fn pool_args() -> PoolArgs {
+    PoolArgs {
+        name: "tpool".into(),
+        disks: vec!["aio:///tmp/disk1.img".into()],
+        uuid: None,
+    }
+}
+
+#[tokio::test]
+async fn lvs_check_test() {
+    common::delete_file(&[DISKNAME1.into()]);
+    common::truncate_file(DISKNAME1, 128 * 1024);
+    let args = MayastorCliArgs {
+        reactor_mask: "0x3".into(),
+        ..Default::default()
+    };
+    let ms = MayastorTest::new(args);
+
+    // checking a disk without a pool should fail
+    ms.spawn(async {
+        assert!(Lvs::check(pool_args(), false, &[]).await.is_err());
+    })
+    .await;
+
+    // create a pool with some lvols on it
+    ms.spawn(async {
+        let pool = Lvs::create_or_import(pool_args()).await.unwrap();
+        for i in 0 .. 4 {
+            pool.create_lvol(
+                &format!("vol-{}", i),
+                8 * 1024 * 1024,
+                None,
+                i % 2 == 0,
+            )
+            .await
+            .unwrap();
+        }
+    })
+    .await;
+
+    // an imported pool must not be checked
+    ms.spawn(async {
+        assert!(Lvs::check(pool_args(), false, &[]).await.is_err());
+    })
+    .await;
+
+    // export the pool and check it, the pool should be clean
+    let uuid = ms
+        .spawn(async {
+            let pool = Lvs::lookup("tpool").unwrap();
+            let uuid = pool.uuid();
+            pool.export().await.unwrap();
+
+            let report = Lvs::check(pool_args(), false, &[]).await.unwrap();
+            assert!(report.is_clean());
+            assert_eq!(report.uuid, uuid);
+            assert_eq!(report.lvols.len(), 4);
+            assert_eq!(report.lvols.iter().filter(|l| l.thin).count(), 2);
+            assert!(report.orphaned.is_empty());
+            assert!(!report.repaired);
+            uuid
+        })
+        .await;
+
+    // check with a wrong name or uuid should fail
+    let expected = uuid.clone();
+    ms.spawn(async move {
+        let mut args = pool_args();
+        args.name = "other".into();
+        assert!(Lvs::check(args, false, &[]).await.is_err());
+
+        let mut args = pool_args();
+        args.uuid = Some(uuid::Uuid::new_v4().to_string());
+        assert!(Lvs::check(args, false, &[]).await.is_err());
+
+        let mut args = pool_args();
+        args.uuid = Some(expected);
+        assert!(Lvs::check(args, true, &[]).await.unwrap().is_clean());
+    })
+    .await;
+
+    // a base bdev that exists before the check is left in place, and only
+    // conflicting blobs can be deleted on request
+    let bdev = ms
+        .spawn(async {
+            let bdev = bdev_create("aio:///tmp/disk1.img").await.unwrap();
+
+            let report = Lvs::check(pool_args(), false, &[]).await.unwrap();
+            assert!(report.is_clean());
+            assert!(UntypedBdev::lookup_by_name(&bdev).is_some());
+
+            let blob_id = report.lvols[0].blob_id;
+            assert!(Lvs::check(pool_args(), true, &[blob_id]).await.is_err());
+            assert!(UntypedBdev::lookup_by_name(&bdev).is_some());
+            bdev
+        })
+        .await;
+
+    // the pool must still be importable after the check
+    ms.spawn(async move {
+        let (name, found) = Lvs::probe(&bdev).await.unwrap();
+        assert_eq!(name, "tpool");
+        assert_eq!(found, uuid);
+
+        let pool = Lvs::import("tpool", "aio:///tmp/disk1.img").await.unwrap();
+        assert_eq!(pool.lvols().unwrap().count(), 4);
+        pool.destroy().await.unwrap();
+    })
+    .await;
+
+    common::delete_file(&[DISKNAME1.into()]);
+}
@@ -1,12 +1,19 @@
 use common::MayastorTest;
 use mayastor::{
-    core::{MayastorCliArgs, Protocol, Share, UntypedBdev},
-    lvs::{Lvs, PropName, PropValue},
+    core::{
+        MayastorCliArgs,
+        NvmfShareProps,
+        Protocol,
+        RateLimits,
+        Share,
+        UntypedBdev,
+    },
+    lvs::{LabelSelector, Labels, Lvs, PropName, PropValue},
     nexus_uri::bdev_create,
     pool::PoolArgs,
     subsys::NvmfSubsystem,
 };
-use std::pin::Pin;
+use std::{pin::Pin, str::FromStr};
 
 pub mod common;
 
@@ -160,6 +167,24 @@ async fn lvs_pool_test() {
     })
     .await;
 
+    // thick provisioned lvols have all their clusters allocated, the pool
+    // statistics should account for all of them
+    ms.spawn(async {
+        let pool2 = Lvs::lookup("tpool2").unwrap();
+        let mut allocated = 0;
+        for l in pool2.lvols().unwrap() {
+            let stats = l.stats().await.unwrap();
+            assert_eq!(stats.allocated_bytes(), 8 * 1024 * 1024);
+            assert_eq!(stats.histogram.count(), 0);
+            allocated += stats.allocated_clusters;
+        }
+
+        let stats = pool2.stats().await.unwrap();
+        assert_eq!(stats.num_lvols, 5);
+        assert_eq!(stats.allocated_clusters, allocated);
+    })
+    .await;
+
     // export the first pool and import it again, all replica's
     // should be present, destroy  all of them by name to
     // ensure they are all there
@@ -251,6 +276,168 @@ async fn lvs_pool_test() {
     })
     .await;
 
+    // test setting labels and selecting lvols based on them
+    ms.spawn(async {
+        let pool = Lvs::lookup("tpool").unwrap();
+        let mut lvol = pool
+            .create_lvol("vol-1", 1024 * 1024 * 8, None, false)
+            .await
+            .unwrap();
+
+        // lvols start without labels
+        assert!(lvol.labels().is_empty());
+
+        let labels: Labels = vec![
+            ("volume".to_string(), "vol-uuid".to_string()),
+            ("generation".to_string(), "1".to_string()),
+        ]
+        .into_iter()
+        .collect();
+
+        Pin::new(&mut lvol)
+            .set(PropValue::Labels(labels.clone()))
+            .await
+            .unwrap();
+        assert_eq!(
+            lvol.get(PropName::Labels).await.unwrap(),
+            PropValue::Labels(labels.clone())
+        );
+
+        let selector = |s: &str| LabelSelector::from_str(s).unwrap();
+        assert!(selector("").matches(&lvol.labels()));
+        assert!(selector("volume=vol-uuid").matches(&lvol.labels()));
+        assert!(selector("volume==vol-uuid,generation").matches(&lvol.labels()));
+        assert!(selector("generation!=2,!nexus").matches(&lvol.labels()));
+        assert!(!selector("volume=other").matches(&lvol.labels()));
+        assert!(!selector("nexus").matches(&lvol.labels()));
+        assert!(!selector("!volume").matches(&lvol.labels()));
+        assert!(LabelSelector::from_str("=value").is_err());
+
+        // labels that do not fit in an xattr are refused
+        let huge: Labels =
+            vec![("huge".to_string(), "x".repeat(u16::MAX as usize))]
+                .into_iter()
+                .collect();
+        assert!(Pin::new(&mut lvol)
+            .set(PropValue::Labels(huge))
+            .await
+            .is_err());
+        assert_eq!(lvol.labels(), labels);
+
+        lvol.destroy().await.unwrap();
+    })
+    .await;
+
+    // test setting rate limits, they must be applied and persisted
+    ms.spawn(async {
+        let pool = Lvs::lookup("tpool").unwrap();
+        let mut lvol = pool
+            .create_lvol("vol-1", 1024 * 1024 * 8, None, false)
+            .await
+            .unwrap();
+
+        assert!(lvol.rate_limits().is_unlimited());
+
+        let limits = RateLimits {
+            rw_ios_per_sec: 10000,
+            rw_mbytes_per_sec: 100,
+            ..Default::default()
+        };
+        Pin::new(&mut lvol).set_rate_limits(limits).await.unwrap();
+        assert_eq!(lvol.rate_limits(), limits);
+        assert_eq!(
+            lvol.get(PropName::RateLimits).await.unwrap(),
+            PropValue::RateLimits(limits)
+        );
+
+        Pin::new(&mut lvol)
+            .set_rate_limits(RateLimits::default())
+            .await
+            .unwrap();
+        assert!(lvol.rate_limits().is_unlimited());
+
+        lvol.destroy().await.unwrap();
+    })
+    .await;
+
+    // test the allowed hosts of a share, they must survive a re-share
+    ms.spawn(async {
+        let pool = Lvs::lookup("tpool").unwrap();
+        let mut lvol = pool
+            .create_lvol("vol-1", 1024 * 1024 * 8, None, false)
+            .await
+            .unwrap();
+
+        let host1 = "nqn.2019-05.io.openebs:host1".to_string();
+        let host2 = "nqn.2019-05.io.openebs:host2".to_string();
+
+        let props = NvmfShareProps::new().with_allowed_hosts(vec![&host1]);
+        Pin::new(&mut lvol).share_nvmf(Some(props)).await.unwrap();
+        assert_eq!(lvol.allowed_hosts(), vec![host1.clone()]);
+
+        Pin::new(&mut lvol)
+            .set_allowed_hosts(vec![host1.clone(), host2.clone()])
+            .await
+            .unwrap();
+        assert_eq!(lvol.allowed_hosts().len(), 2);
+
+        Pin::new(&mut lvol).unshare().await.unwrap();
+        assert!(lvol.allowed_hosts().is_empty());
+
+        Pin::new(&mut lvol).share_nvmf(None).await.unwrap();
+        assert_eq!(lvol.allowed_hosts().len(), 2);
+        assert_eq!(
+            lvol.get(PropName::AllowedHosts).await.unwrap(),
+            PropValue::AllowedHosts(vec![host1, host2])
+        );
+
+        lvol.destroy().await.unwrap();
+    })
+    .await;
+
+    // an empty allow-list denies all hosts unless any host is allowed
+    ms.spawn(async {
+        let pool = Lvs::lookup("tpool").unwrap();
+        let mut lvol = pool
+            .create_lvol("vol-1", 1024 * 1024 * 8, None, false)
+            .await
+            .unwrap();
+
+        let host1 = "nqn.2019-05.io.openebs:host1".to_string();
+        let other = "nqn.2019-05.io.openebs:other";
+
+        let props = NvmfShareProps::new().with_allowed_hosts(vec![&host1]);
+        Pin::new(&mut lvol).share_nvmf(Some(props)).await.unwrap();
+        let subsystem = NvmfSubsystem::nqn_lookup(&lvol.name()).unwrap();
+        assert!(subsystem.host_allowed(&host1));
+        assert!(!subsystem.host_allowed(other));
+
+        // removing the last host does not open up the share
+        Pin::new(&mut lvol).set_allowed_hosts(vec![]).await.unwrap();
+        assert!(!subsystem.host_allowed(&host1));
+        assert!(!subsystem.host_allowed(other));
+        assert_eq!(
+            lvol.get(PropName::AllowAnyHost).await.unwrap(),
+            PropValue::AllowAnyHost(false)
+        );
+
+        // nor does sharing it again without any properties
+        Pin::new(&mut lvol).unshare().await.unwrap();
+        Pin::new(&mut lvol).share_nvmf(None).await.unwrap();
+        let subsystem = NvmfSubsystem::nqn_lookup(&lvol.name()).unwrap();
+        assert!(!subsystem.host_allowed(other));
+
+        // any host must be allowed explicitly
+        Pin::new(&mut lvol).unshare().await.unwrap();
+        let props = NvmfShareProps::new().with_allow_any_host(true);
+        Pin::new(&mut lvol).share_nvmf(Some(props)).await.unwrap();
+        let subsystem = NvmfSubsystem::nqn_lookup(&lvol.name()).unwrap();
+        assert!(subsystem.host_allowed(other));
+
+        lvol.destroy().await.unwrap();
+    })
+    .await;
+
     // create 10 shares, 1 unshared lvol and export the pool
     ms.spawn(async {
         let pool = Lvs::lookup("tpool").unwrap();
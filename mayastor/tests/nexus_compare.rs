@@ -0,0 +1,232 @@
+use std::{
+    pin::Pin,
+    sync::{atomic::AtomicPtr, Mutex},
+    time::Duration,
+};
+
+use libc::c_void;
+use once_cell::sync::Lazy;
+
+use common::MayastorTest;
+use mayastor::{
+    bdev::{
+        device_open,
+        nexus::{nexus_create, nexus_lookup_mut, ChildState, NexusStatus},
+    },
+    core::{
+        BlockDevice,
+        BlockDeviceHandle,
+        IoCompletionStatus,
+        MayastorCliArgs,
+        Share,
+        UntypedBdev,
+    },
+    nexus_uri::bdev_create,
+};
+use spdk_rs::{DmaBuf, IoVec};
+
+pub mod common;
+
+const OP_OFFSET: u64 = 4 * 1024 * 1024;
+
+const IO_PATTERN: u8 = 0x77;
+const OTHER_PATTERN: u8 = 0xFF;
+
+static STATUS: Lazy<Mutex<Vec<IoCompletionStatus>>> =
+    Lazy::new(|| Mutex::new(Vec::new()));
+
+fn completion_callback(
+    _device: &dyn BlockDevice,
+    status: IoCompletionStatus,
+    _ctx: *mut c_void,
+) {
+    STATUS.lock().unwrap().push(status);
+}
+
+/// wait for the given number of I/O to complete and return their status
+async fn take_status(
+    ms: &MayastorTest<'_>,
+    count: usize,
+) -> Vec<IoCompletionStatus> {
+    for _ in 0 .. 100 {
+        if ms.spawn(async { STATUS.lock().unwrap().len() }).await >= count {
+            return std::mem::take(&mut *STATUS.lock().unwrap());
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("not all I/O operations completed");
+}
+
+/// Placeholder structure to let all the fields outlive API invocations.
+struct IoCtx {
+    iovs: [IoVec; 2],
+    bufs: [DmaBuf; 2],
+    offset_blocks: u64,
+    handle: Box<dyn BlockDeviceHandle>,
+}
+
+fn io_buffer(alignment: u64, size: u64, pattern: u8) -> DmaBuf {
+    let mut buf = DmaBuf::new(size, alignment).unwrap();
+    buf.fill(pattern);
+    buf
+}
+
+/// the nexus is online, with all its children in service
+async fn check_children_open(ms: &MayastorTest<'_>, name: &'static str) {
+    ms.spawn(async move {
+        let nexus = nexus_lookup_mut(name).unwrap();
+        assert_eq!(nexus.status(), NexusStatus::Online);
+        assert!(nexus.children.iter().all(|c| c.state() == ChildState::Open));
+    })
+    .await;
+}
+
+/// create a nexus over two malloc children, connected over NVMe-oF when
+/// shared
+async fn create_nexus(
+    ms: &MayastorTest<'_>,
+    name: &'static str,
+    children: [&'static str; 2],
+    shared: bool,
+) {
+    ms.spawn(async move {
+        let mut uris = Vec::new();
+        for child in &children {
+            let uri = format!("malloc:///{}?size_mb=64", child);
+            bdev_create(&uri).await.unwrap();
+            if shared {
+                let mut bdev = UntypedBdev::lookup_by_name(child).unwrap();
+                Pin::new(&mut bdev).share_nvmf(None).await.unwrap();
+                uris.push(bdev.share_uri().unwrap());
+            } else {
+                uris.push(uri);
+            }
+        }
+        nexus_create(name, 32 * 1024 * 1024, None, &uris)
+            .await
+            .unwrap();
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn nexus_compare_and_write() {
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+
+    // the children are connected over NVMe-oF, which supports compare
+    create_nexus(&ms, "cmp_nexus", ["cmp0", "cmp1"], true).await;
+    compare_and_write(&ms, "cmp_nexus").await;
+
+    // malloc devices cannot compare, so the nexus reads the data back
+    create_nexus(&ms, "cmp_local_nexus", ["cmp2", "cmp3"], false).await;
+    compare_and_write(&ms, "cmp_local_nexus").await;
+}
+
+async fn compare_and_write(ms: &MayastorTest<'_>, name: &'static str) {
+    // The data of the nexus matches the first buffer and not the second,
+    // so of the compares only the first one succeeds, and nothing is written
+    // by the compare-and-write.
+    let ctx = ms
+        .spawn(async move {
+            let handle =
+                device_open(name, true).unwrap().into_handle().unwrap();
+            let (block_len, alignment) = {
+                let device = handle.get_device();
+                (device.block_len(), device.alignment())
+            };
+
+            let data = io_buffer(alignment, block_len, IO_PATTERN);
+            handle.write_at(OP_OFFSET, &data).await.unwrap();
+
+            let mut ctx = IoCtx {
+                iovs: [IoVec::default(), IoVec::default()],
+                bufs: [data, io_buffer(alignment, block_len, OTHER_PATTERN)],
+                offset_blocks: OP_OFFSET / block_len,
+                handle,
+            };
+            for i in 0 .. 2 {
+                ctx.iovs[i].iov_base = *ctx.bufs[i];
+                ctx.iovs[i].iov_len = block_len;
+            }
+
+            let same: *mut IoVec = &mut ctx.iovs[0];
+            let other: *mut IoVec = &mut ctx.iovs[1];
+            for iov in &[same, other] {
+                ctx.handle
+                    .comparev_blocks(
+                        *iov,
+                        1,
+                        ctx.offset_blocks,
+                        1,
+                        completion_callback,
+                        std::ptr::null_mut(),
+                    )
+                    .unwrap();
+            }
+            ctx.handle
+                .comparev_and_writev_blocks(
+                    other,
+                    1,
+                    other,
+                    1,
+                    ctx.offset_blocks,
+                    1,
+                    completion_callback,
+                    std::ptr::null_mut(),
+                )
+                .unwrap();
+
+            AtomicPtr::new(Box::into_raw(Box::new(ctx)))
+        })
+        .await;
+
+    let status = take_status(ms, 3).await;
+    let count = |s| status.iter().filter(|&&c| c == s).count();
+    assert_eq!(count(IoCompletionStatus::Success), 1);
+    assert_eq!(count(IoCompletionStatus::Miscompare), 2);
+
+    // a miscompare does not take the child out
+    check_children_open(ms, name).await;
+
+    // The compare-and-write succeeds once the data matches, and writes to
+    // all children.
+    let ctx = ms
+        .spawn(async move {
+            let mut ctx = unsafe { Box::<IoCtx>::from_raw(ctx.into_inner()) };
+            let same: *mut IoVec = &mut ctx.iovs[0];
+            let other: *mut IoVec = &mut ctx.iovs[1];
+            ctx.handle
+                .comparev_and_writev_blocks(
+                    same,
+                    1,
+                    other,
+                    1,
+                    ctx.offset_blocks,
+                    1,
+                    completion_callback,
+                    std::ptr::null_mut(),
+                )
+                .unwrap();
+            AtomicPtr::new(Box::into_raw(ctx))
+        })
+        .await;
+    assert_eq!(take_status(ms, 1).await, vec![IoCompletionStatus::Success]);
+    check_children_open(ms, name).await;
+
+    ms.spawn(async move {
+        let ctx = unsafe { Box::<IoCtx>::from_raw(ctx.into_inner()) };
+
+        // reads alternate between the children, which both hold the data
+        let device = ctx.handle.get_device();
+        for _ in 0 .. 2 {
+            let mut buf =
+                DmaBuf::new(device.block_len(), device.alignment()).unwrap();
+            ctx.handle.read_at(OP_OFFSET, &mut buf).await.unwrap();
+            assert!(buf.as_slice().iter().all(|b| *b == OTHER_PATTERN));
+        }
+        drop(ctx);
+
+        nexus_lookup_mut(name).unwrap().destroy().await.unwrap();
+    })
+    .await;
+}
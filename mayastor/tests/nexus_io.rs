@@ -8,9 +8,10 @@ use mayastor::{
         NexusNvmeParams,
         NvmeAnaState,
     },
-    core::{MayastorCliArgs, Protocol},
+    core::{MayastorCliArgs, NvmfShareProps, Protocol, Share},
     lvs::Lvs,
     pool::PoolArgs,
+    subsys::NvmfSubsystem,
 };
 use once_cell::sync::OnceCell;
 use rpc::mayastor::{
@@ -22,7 +23,10 @@ use rpc::mayastor::{
     Null,
     PublishNexusRequest,
 };
-use std::process::{Command, ExitStatus};
+use std::{
+    pin::Pin,
+    process::{Command, ExitStatus},
+};
 
 pub mod common;
 use common::{compose::Builder, MayastorTest};
@@ -513,6 +517,157 @@ async fn nexus_io_resv_acquire() {
     nvme_disconnect_nqn(&rep_nqn);
 }
 
+#[tokio::test]
+/// Create a nexus on each of 2 nodes with the same remote replica as their
+/// child, verifying that the reservations of the child can be reported,
+/// preempted and cleared through the local nexus. Also manage the
+/// reservations of a local replica whose share does not allow this node.
+async fn nexus_io_resv_manage() {
+    std::env::set_var("NEXUS_NVMF_RESV_ENABLE", "1");
+    std::env::set_var("MAYASTOR_NVMF_HOSTID", HOSTID0);
+    let test = Builder::new()
+        .name("nexus_resv_manage_test")
+        .network("10.1.0.0/16")
+        .add_container_bin(
+            "ms2",
+            composer::Binary::from_dbg("mayastor")
+                .with_env("NEXUS_NVMF_RESV_ENABLE", "1")
+                .with_env("MAYASTOR_NVMF_HOSTID", HOSTID1),
+        )
+        .add_container_bin(
+            "ms1",
+            composer::Binary::from_dbg("mayastor")
+                .with_env("NEXUS_NVMF_RESV_ENABLE", "1")
+                .with_env("MAYASTOR_NVMF_HOSTID", HOSTID1),
+        )
+        .with_clean(true)
+        .build()
+        .await
+        .unwrap();
+
+    let mut hdls = test.grpc_handles().await.unwrap();
+
+    hdls[0]
+        .mayastor
+        .create_pool(CreatePoolRequest {
+            name: POOL_NAME.to_string(),
+            disks: vec!["malloc:///disk0?size_mb=64".into()],
+        })
+        .await
+        .unwrap();
+
+    hdls[0]
+        .mayastor
+        .create_replica(CreateReplicaRequest {
+            uuid: UUID.to_string(),
+            pool: POOL_NAME.to_string(),
+            size: 32 * 1024 * 1024,
+            thin: false,
+            share: 1,
+        })
+        .await
+        .unwrap();
+
+    let mayastor = get_ms();
+    let ip0 = hdls[0].endpoint.ip();
+    let child = format!("nvmf://{}:8420/{}:{}", ip0, HOSTNQN, UUID);
+    let resv_key = 0xabcd_ef00_1234_5678;
+    let resv_key2 = 0xfeed_f00d_bead_5678;
+
+    let uri = child.clone();
+    mayastor
+        .spawn(async move {
+            let mut nvme_params = NexusNvmeParams::default();
+            nvme_params.set_resv_key(resv_key);
+            nexus_create_v2(
+                &NXNAME.to_string(),
+                32 * 1024 * 1024,
+                UUID,
+                nvme_params,
+                &[uri],
+                None,
+            )
+            .await
+            .unwrap();
+        })
+        .await;
+
+    hdls[1]
+        .mayastor
+        .create_nexus_v2(CreateNexusV2Request {
+            name: NXNAME.to_string(),
+            uuid: UUID.to_string(),
+            size: 32 * 1024 * 1024,
+            min_cntl_id: 1,
+            max_cntl_id: 0xffef,
+            resv_key: resv_key2,
+            preempt_key: 0,
+            children: [child.clone()].to_vec(),
+            nexus_info_key: "".to_string(),
+        })
+        .await
+        .unwrap();
+
+    mayastor
+        .spawn(async move {
+            let nexus = nexus_lookup_mut(&NXNAME.to_string()).unwrap();
+
+            // the registrations of both nexuses are reported for the child
+            let report = nexus.child_reservations(&child).await.unwrap();
+            assert_eq!(report.registrants.len(), 2);
+            assert_eq!(report.holder().unwrap().key, resv_key);
+
+            // the registration of the second nexus is removed when preempted
+            let report = nexus
+                .preempt_child_reservation(&child, resv_key2)
+                .await
+                .unwrap();
+            assert_eq!(report.registrants.len(), 1);
+            assert_eq!(report.registrants[0].key, resv_key);
+
+            // and none are left once cleared
+            let report = nexus.clear_child_reservations(&child).await.unwrap();
+            assert!(report.registrants.is_empty());
+            assert!(report.holder().is_none());
+
+            nexus.destroy().await.unwrap();
+        })
+        .await;
+
+    mayastor
+        .spawn(async {
+            Lvs::create_or_import(PoolArgs {
+                name: "lpool".to_string(),
+                disks: vec!["malloc:///lresv?size_mb=64".to_string()],
+                uuid: None,
+            })
+            .await
+            .unwrap();
+
+            let mut lvol = Lvs::lookup("lpool")
+                .unwrap()
+                .create_lvol("lresv", 8 * 1024 * 1024, None, false)
+                .await
+                .unwrap();
+
+            let host = format!("nqn.2019-05.io.openebs:uuid:{}", HOSTID0);
+            let props = NvmfShareProps::new()
+                .with_allowed_hosts(vec!["nqn.2019-05.io.openebs:other"]);
+            Pin::new(&mut lvol).share_nvmf(Some(props)).await.unwrap();
+            let subsystem = NvmfSubsystem::nqn_lookup(&lvol.name()).unwrap();
+            assert!(!subsystem.host_allowed(&host));
+
+            // this node is allowed only while the reservations are managed
+            let report = lvol.reservations().await.unwrap();
+            assert!(report.registrants.is_empty());
+            assert!(!subsystem.host_allowed(&host));
+
+            lvol.destroy().await.unwrap();
+            Lvs::lookup("lpool").unwrap().destroy().await.unwrap();
+        })
+        .await;
+}
+
 #[tokio::test]
 /// Create a nexus with a local and a remote replica.
 /// Verify that write-zeroes does actually write zeroes.
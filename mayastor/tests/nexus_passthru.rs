@@ -0,0 +1,66 @@
+use futures::FutureExt;
+
+use common::MayastorTest;
+use mayastor::{
+    bdev::nexus::{
+        nexus_create,
+        nexus_lookup_mut,
+        register_nexus_cmd_handler,
+        NexusCmd,
+        NexusCmdFuture,
+        NexusCmdHandler,
+        NexusCmdKind,
+    },
+    core::{BdevHandle, MayastorCliArgs},
+};
+
+pub mod common;
+
+static NXNAME: &str = "passthru_nexus";
+static VENDOR_OPC: u8 = 0xc2;
+
+fn vendor_cmd(cmd: NexusCmd) -> NexusCmdFuture {
+    async move {
+        assert_eq!(cmd.nexus, NXNAME);
+        assert_eq!(cmd.cmd.opc() as u8, VENDOR_OPC);
+        Ok(())
+    }
+    .boxed_local()
+}
+
+#[tokio::test]
+async fn nexus_passthru_handlers() {
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+
+    ms.spawn(async {
+        register_nexus_cmd_handler(
+            NexusCmdKind::Admin,
+            VENDOR_OPC,
+            NexusCmdHandler {
+                accepts: |_| true,
+                run: vendor_cmd,
+            },
+        );
+
+        nexus_create(
+            NXNAME,
+            32 * 1024 * 1024,
+            None,
+            &["malloc:///passthru0?size_mb=64".to_string()],
+        )
+        .await
+        .unwrap();
+
+        let h = BdevHandle::open(NXNAME, true, false).unwrap();
+        // processed by the registered handler
+        h.nvme_admin_custom(VENDOR_OPC).await.unwrap();
+        // no handler for the opcode
+        h.nvme_admin_custom(VENDOR_OPC + 1)
+            .await
+            .expect_err("unexpectedly succeeded unknown admin command");
+        h.close();
+
+        nexus_lookup_mut(NXNAME).unwrap().destroy().await.unwrap();
+    })
+    .await;
+}
@@ -6,11 +6,12 @@ use once_cell::sync::{Lazy, OnceCell};
 
 use common::compose::{Builder, MayastorTest};
 use mayastor::{
-    bdev::{device_create, device_destroy, device_open},
+    bdev::{device_create, device_destroy, device_open, NVME_CONTROLLERS},
     core::{
         BlockDevice,
         BlockDeviceHandle,
         DeviceTimeoutAction,
+        DeviceTimeoutPolicy,
         IoCompletionStatus,
         MayastorCliArgs,
     },
@@ -27,11 +28,13 @@ static MAYASTOR: Lazy<MayastorTest> =
     Lazy::new(|| MayastorTest::new(MayastorCliArgs::default()));
 
 static CALLBACK_FLAG: AtomicCell<bool> = AtomicCell::new(false);
+static IO_ISSUED: AtomicCell<u64> = AtomicCell::new(0);
+static IO_FAILED: AtomicCell<u64> = AtomicCell::new(0);
 
 const BUF_SIZE: u64 = 32768;
 
 struct IoOpCtx {
-    iov: IoVec,
+    iovs: Vec<IoVec>,
     device_url: String,
     dma_buf: DmaBuf,
     handle: Box<dyn BlockDeviceHandle>,
@@ -49,7 +52,11 @@ fn get_config() -> &'static Config {
     })
 }
 
-async fn test_io_timeout(action_on_timeout: DeviceTimeoutAction) {
+async fn test_io_timeout(
+    action_on_timeout: DeviceTimeoutAction,
+    policy: Option<DeviceTimeoutPolicy>,
+    reads: u64,
+) {
     get_config().apply();
 
     let test = Builder::new()
@@ -113,6 +120,12 @@ async fn test_io_timeout(action_on_timeout: DeviceTimeoutAction) {
                 action_on_timeout,
                 "I/O timeout action mismatches"
             );
+            io_controller.set_timeout_policy(policy).unwrap();
+            assert_eq!(
+                io_controller.get_timeout_policy().unwrap(),
+                policy,
+                "I/O timeout policy mismatches"
+            );
 
             AtomicPtr::new(Box::into_raw(Box::new(IoCtx {
                 handle,
@@ -138,7 +151,10 @@ async fn test_io_timeout(action_on_timeout: DeviceTimeoutAction) {
             IoCompletionStatus::Success,
             "I/O operation completed successfully"
         );
-        assert!(!CALLBACK_FLAG.load(), "Callback called multiple times");
+        assert!(
+            IO_FAILED.fetch_add(1) < IO_ISSUED.load(),
+            "Callback called multiple times"
+        );
 
         // Make sure we have the correct device.
         assert_eq!(
@@ -155,10 +171,12 @@ async fn test_io_timeout(action_on_timeout: DeviceTimeoutAction) {
         };
 
         assert_eq!(s, TEST_CTX_STRING);
-        CALLBACK_FLAG.store(true);
     }
 
-    println!("Issuing I/O operation against disconnected device");
+    println!(
+        "Issuing {} I/O operations against disconnected device",
+        reads
+    );
     let io_ctx = MAYASTOR
         .spawn(async move {
             let ctx = unsafe { Box::from_raw(cptr.into_inner()) };
@@ -169,28 +187,36 @@ async fn test_io_timeout(action_on_timeout: DeviceTimeoutAction) {
             };
 
             let mut io_ctx = IoOpCtx {
-                iov: IoVec::default(),
+                iovs: (0 .. reads).map(|_| IoVec::default()).collect(),
                 device_url: ctx.device_url,
-                dma_buf: DmaBuf::new(BUF_SIZE, alignment).unwrap(),
+                dma_buf: DmaBuf::new(BUF_SIZE * reads, alignment).unwrap(),
                 handle: ctx.handle,
             };
 
-            io_ctx.iov.iov_base = *io_ctx.dma_buf;
-            io_ctx.iov.iov_len = BUF_SIZE;
-
-            CALLBACK_FLAG.store(false);
-
-            io_ctx
-                .handle
-                .readv_blocks(
-                    &mut io_ctx.iov,
-                    1,
-                    (3 * 1024 * 1024) / block_len,
-                    BUF_SIZE / block_len,
-                    read_completion_callback,
-                    TEST_CTX_STRING.as_ptr() as *mut c_void,
-                )
-                .unwrap();
+            IO_ISSUED.store(reads);
+            IO_FAILED.store(0);
+
+            // All reads are in flight when the device stalls, so they time
+            // out together.
+            for (i, iov) in io_ctx.iovs.iter_mut().enumerate() {
+                let offset = i as u64 * BUF_SIZE;
+                iov.iov_base = unsafe {
+                    (*io_ctx.dma_buf as *mut u8).add(offset as usize)
+                } as *mut c_void;
+                iov.iov_len = BUF_SIZE;
+
+                io_ctx
+                    .handle
+                    .readv_blocks(
+                        iov,
+                        1,
+                        (3 * 1024 * 1024 + offset) / block_len,
+                        BUF_SIZE / block_len,
+                        read_completion_callback,
+                        TEST_CTX_STRING.as_ptr() as *mut c_void,
+                    )
+                    .unwrap();
+            }
 
             AtomicPtr::new(Box::into_raw(Box::new(io_ctx)))
         })
@@ -202,9 +228,9 @@ async fn test_io_timeout(action_on_timeout: DeviceTimeoutAction) {
     for i in 1 .. 25 {
         println!("waiting for I/O to be timed out... {}/24", i);
         tokio::time::sleep(std::time::Duration::from_secs(5)).await;
-        // Break the loop if the callback has been called in response to I/O
-        // cancelling.
-        if CALLBACK_FLAG.load() {
+        // Break the loop if the callback has been called for every I/O in
+        // response to I/O cancelling.
+        if IO_FAILED.load() == reads {
             println!("I/O timed out");
             io_timedout = true;
             break;
@@ -215,6 +241,14 @@ async fn test_io_timeout(action_on_timeout: DeviceTimeoutAction) {
         .spawn(async move {
             let ctx = unsafe { Box::from_raw(io_ctx.into_inner()) };
 
+            // The device survived the timeouts, which are accounted.
+            let health = NVME_CONTROLLERS
+                .lookup_by_name(DEVICE_NAME.get().unwrap())
+                .expect("device removed in response to I/O timeout")
+                .lock()
+                .get_health_stats();
+            assert!(health.timeouts > 0, "I/O timeouts not accounted");
+
             device_destroy(&ctx.device_url).await.unwrap();
         })
         .await;
@@ -228,7 +262,28 @@ async fn test_io_timeout(action_on_timeout: DeviceTimeoutAction) {
 
 #[tokio::test]
 async fn io_timeout_reset() {
-    test_io_timeout(DeviceTimeoutAction::Reset).await;
+    test_io_timeout(DeviceTimeoutAction::Reset, None, 1).await;
+}
+
+#[tokio::test]
+async fn io_timeout_qpair_reset() {
+    test_io_timeout(DeviceTimeoutAction::QpairReset, None, 1).await;
+}
+
+#[tokio::test]
+async fn io_timeout_policy() {
+    // The policy takes precedence over the action. Aborting the reads can't
+    // recover the suspended target, so the policy escalates until resetting
+    // the controller fails them. The reads stall together and count once,
+    // counting each of them along with the stalled admin commands would
+    // remove the device instead.
+    let policy = DeviceTimeoutPolicy {
+        abort: 1,
+        qpair_reset: 1,
+        reset: 2,
+        ..Default::default()
+    };
+    test_io_timeout(DeviceTimeoutAction::Ignore, Some(policy), 4).await;
 }
 
 #[tokio::test]
@@ -359,21 +414,21 @@ async fn io_timeout_ignore() {
             };
 
             let mut io_ctx = IoOpCtx {
-                iov: IoVec::default(),
+                iovs: vec![IoVec::default()],
                 device_url: ctx.device_url,
                 dma_buf: DmaBuf::new(BUF_SIZE, alignment).unwrap(),
                 handle: ctx.handle,
             };
 
-            io_ctx.iov.iov_base = *io_ctx.dma_buf;
-            io_ctx.iov.iov_len = BUF_SIZE;
+            io_ctx.iovs[0].iov_base = *io_ctx.dma_buf;
+            io_ctx.iovs[0].iov_len = BUF_SIZE;
 
             CALLBACK_FLAG.store(false);
 
             io_ctx
                 .handle
                 .readv_blocks(
-                    &mut io_ctx.iov,
+                    &mut io_ctx.iovs[0],
                     1,
                     (3 * 1024 * 1024) / block_len,
                     BUF_SIZE / block_len,
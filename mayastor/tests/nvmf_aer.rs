@@ -0,0 +1,235 @@
+use std::{ffi::CString, pin::Pin, time::Duration};
+
+use crossbeam::atomic::AtomicCell;
+use once_cell::sync::Lazy;
+
+use common::MayastorTest;
+use mayastor::{
+    bdev::{
+        device_create,
+        device_destroy,
+        device_lookup,
+        device_open,
+        nexus::{nexus_create, nexus_lookup_mut, NexusAnaParams, NvmeAnaState},
+        NVME_CONTROLLERS,
+    },
+    core::{
+        DeviceEventListener,
+        DeviceEventSink,
+        DeviceEventType,
+        MayastorCliArgs,
+        Protocol,
+        Reactors,
+        Share,
+        UntypedBdev,
+    },
+    nexus_uri::bdev_create,
+};
+use spdk_rs::{libspdk::bdev_aio_rescan, DmaBuf};
+
+pub mod common;
+
+static MAYASTOR: Lazy<MayastorTest> =
+    Lazy::new(|| MayastorTest::new(MayastorCliArgs::default()));
+
+static DISKNAME: &str = "/tmp/aer_resize.img";
+
+const BUF_SIZE: u64 = 32768;
+
+/// set when the device was resized
+static DEVICE_RESIZED: AtomicCell<bool> = AtomicCell::new(false);
+
+/// the outcome of the write issued while the namespace is inaccessible
+static WRITE_RESULT: AtomicCell<Option<bool>> = AtomicCell::new(None);
+
+struct ResizeListener {}
+
+impl DeviceEventListener for ResizeListener {
+    fn handle_device_event(
+        self: Pin<&mut Self>,
+        event: DeviceEventType,
+        device: &str,
+    ) {
+        if event == DeviceEventType::DeviceResized {
+            // the controller must not be locked while listeners are notified
+            assert!(device_lookup(device).is_some());
+            DEVICE_RESIZED.store(true);
+        }
+    }
+}
+
+/// wait for the condition to hold, checking it on the reactor
+async fn wait_for<F>(what: &str, f: F)
+where
+    F: Fn() -> bool + Send + Clone + 'static,
+{
+    for _ in 0 .. 100 {
+        let f = f.clone();
+        if MAYASTOR.spawn(async move { f() }).await {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("timed out waiting for {}", what);
+}
+
+/// the ANA state of the namespace as seen by the host
+fn ana_state(name: &str) -> NvmeAnaState {
+    NVME_CONTROLLERS
+        .lookup_by_name(name)
+        .and_then(|c| c.lock().namespace())
+        .map_or(NvmeAnaState::InvalidState, |ns| ns.ana_state())
+}
+
+#[tokio::test]
+async fn nvmf_aer_resize() {
+    common::truncate_file(DISKNAME, 64 * 1024);
+
+    let uri = MAYASTOR
+        .spawn(async {
+            let name = bdev_create(&format!("aio://{}?blk_size=512", DISKNAME))
+                .await
+                .unwrap();
+            let mut bdev = UntypedBdev::lookup_by_name(&name).unwrap();
+            Pin::new(&mut bdev).share_nvmf(None).await.unwrap();
+            bdev.share_uri().unwrap()
+        })
+        .await;
+
+    let mut listener = Box::pin(ResizeListener {});
+    let sink = DeviceEventSink::new(listener.as_mut());
+    let sink_clone = sink.clone();
+    let u = uri.clone();
+    let name = MAYASTOR
+        .spawn(async move {
+            let name = device_create(&u).await.unwrap();
+            let device = device_open(&name, false).unwrap().get_device();
+            assert_eq!(device.size_in_bytes(), 64 * 1024 * 1024);
+            device.add_event_listener(sink_clone).unwrap();
+            name
+        })
+        .await;
+
+    // growing the backing file resizes the namespace, which the host is
+    // notified of
+    common::truncate_file(DISKNAME, 128 * 1024);
+    MAYASTOR
+        .spawn(async {
+            let cname = CString::new(DISKNAME).unwrap();
+            assert_eq!(unsafe { bdev_aio_rescan(cname.as_ptr()) }, 0);
+        })
+        .await;
+    wait_for("the resize notice", || DEVICE_RESIZED.load()).await;
+
+    MAYASTOR
+        .spawn(async move {
+            let device = device_lookup(&name).unwrap();
+            assert_eq!(device.size_in_bytes(), 128 * 1024 * 1024);
+            device_destroy(&uri).await.unwrap();
+        })
+        .await;
+
+    common::delete_file(&[DISKNAME.to_string()]);
+}
+
+#[tokio::test]
+async fn nvmf_aer_ana_change() {
+    let uri = MAYASTOR
+        .spawn(async {
+            nexus_create(
+                "aer_nexus0",
+                32 * 1024 * 1024,
+                None,
+                &["malloc:///aer0?size_mb=64".to_string()],
+            )
+            .await
+            .unwrap();
+
+            let mut nexus = nexus_lookup_mut("aer_nexus0").unwrap();
+            nexus
+                .as_mut()
+                .set_ana_params(NexusAnaParams {
+                    reporting: true,
+                    group_id: Some(2),
+                    subsystem_nqn: None,
+                })
+                .unwrap();
+            nexus.as_mut().share(Protocol::Nvmf, None).await.unwrap();
+            nexus.get_share_uri().unwrap()
+        })
+        .await;
+
+    let u = uri.clone();
+    let name = MAYASTOR
+        .spawn(async move { device_create(&u).await.unwrap() })
+        .await;
+    let n = name.clone();
+    assert_eq!(
+        MAYASTOR.spawn(async move { ana_state(&n) }).await,
+        NvmeAnaState::OptimizedState
+    );
+
+    // the host learns of the inaccessible namespace through an ANA change
+    // notice
+    MAYASTOR
+        .spawn(async {
+            let nexus = nexus_lookup_mut("aer_nexus0").unwrap();
+            nexus
+                .set_ana_state(NvmeAnaState::InaccessibleState)
+                .await
+                .unwrap();
+        })
+        .await;
+    let n = name.clone();
+    wait_for("the inaccessible state", move || {
+        ana_state(&n) == NvmeAnaState::InaccessibleState
+    })
+    .await;
+
+    // I/O is held back meanwhile, although the controller does not queue
+    // I/O while reconnecting by default
+    let n = name.clone();
+    MAYASTOR
+        .spawn(async move {
+            let handle = device_open(&n, false).unwrap().into_handle().unwrap();
+            let buf =
+                DmaBuf::new(BUF_SIZE, handle.get_device().alignment()).unwrap();
+            Reactors::current().send_future(async move {
+                let result = handle.write_at(0, &buf).await;
+                WRITE_RESULT.store(Some(result.is_ok()));
+            });
+        })
+        .await;
+    for _ in 0 .. 10 {
+        assert_eq!(
+            MAYASTOR.spawn(async { WRITE_RESULT.load() }).await,
+            None,
+            "the write was not held back"
+        );
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    // and completes once the namespace is accessible again
+    MAYASTOR
+        .spawn(async {
+            let nexus = nexus_lookup_mut("aer_nexus0").unwrap();
+            nexus
+                .set_ana_state(NvmeAnaState::OptimizedState)
+                .await
+                .unwrap();
+        })
+        .await;
+    wait_for("the held write", || WRITE_RESULT.load().is_some()).await;
+    assert_eq!(WRITE_RESULT.load(), Some(true));
+
+    MAYASTOR
+        .spawn(async move {
+            device_destroy(&uri).await.unwrap();
+            nexus_lookup_mut("aer_nexus0")
+                .unwrap()
+                .destroy()
+                .await
+                .unwrap();
+        })
+        .await;
+}
@@ -0,0 +1,121 @@
+use common::MayastorTest;
+use mayastor::{
+    bdev::nexus::{
+        nexus_create,
+        nexus_lookup_mut,
+        NexusAnaParams,
+        NvmeAnaState,
+    },
+    core::{MayastorCliArgs, Protocol},
+};
+
+pub mod common;
+
+static SHARED_NQN: &str = "nqn.2019-05.io.openebs:ana-volume";
+
+#[tokio::test]
+async fn nvmf_ana_groups() {
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+
+    // nexus published under a shared NQN in its own ANA group
+    ms.spawn(async {
+        nexus_create(
+            "ana_nexus0",
+            32 * 1024 * 1024,
+            None,
+            &["malloc:///ana0?size_mb=64".to_string()],
+        )
+        .await
+        .unwrap();
+
+        let mut nexus = nexus_lookup_mut("ana_nexus0").unwrap();
+        let params = NexusAnaParams {
+            reporting: true,
+            group_id: Some(2),
+            subsystem_nqn: Some(SHARED_NQN.to_string()),
+        };
+        nexus.as_mut().set_ana_params(params.clone()).unwrap();
+        nexus.as_mut().share(Protocol::Nvmf, None).await.unwrap();
+
+        let uri = nexus.get_share_uri().unwrap();
+        assert!(uri.ends_with(SHARED_NQN));
+
+        // parameters cannot change while published
+        let other = NexusAnaParams {
+            group_id: Some(3),
+            ..params.clone()
+        };
+        assert!(nexus.as_mut().set_ana_params(other).is_err());
+        nexus.as_mut().set_ana_params(params).unwrap();
+
+        assert_eq!(
+            nexus.get_ana_state().await.unwrap(),
+            NvmeAnaState::OptimizedState
+        );
+
+        for state in &[
+            NvmeAnaState::InaccessibleState,
+            NvmeAnaState::NonOptimizedState,
+            NvmeAnaState::OptimizedState,
+        ] {
+            nexus.set_ana_state(*state).await.unwrap();
+            assert_eq!(nexus.get_ana_state().await.unwrap(), *state);
+        }
+
+        // the nexus only passes through the change state and the target does
+        // not support persistent loss, the nexus remains in its current state
+        for state in
+            &[NvmeAnaState::ChangeState, NvmeAnaState::PersistentLossState]
+        {
+            assert!(nexus.set_ana_state(*state).await.is_err());
+            assert_eq!(
+                nexus.get_ana_state().await.unwrap(),
+                NvmeAnaState::OptimizedState
+            );
+        }
+
+        nexus.destroy().await.unwrap();
+    })
+    .await;
+
+    // the ANA state cannot be changed when reporting is disabled
+    ms.spawn(async {
+        nexus_create(
+            "ana_nexus1",
+            32 * 1024 * 1024,
+            None,
+            &["malloc:///ana1?size_mb=64".to_string()],
+        )
+        .await
+        .unwrap();
+
+        let mut nexus = nexus_lookup_mut("ana_nexus1").unwrap();
+        assert!(nexus
+            .as_mut()
+            .set_ana_params(NexusAnaParams {
+                group_id: Some(0),
+                ..Default::default()
+            })
+            .is_err());
+        nexus
+            .as_mut()
+            .set_ana_params(NexusAnaParams {
+                reporting: false,
+                ..Default::default()
+            })
+            .unwrap();
+        nexus.as_mut().share(Protocol::Nvmf, None).await.unwrap();
+
+        assert!(nexus
+            .set_ana_state(NvmeAnaState::ChangeState)
+            .await
+            .is_err());
+        assert!(nexus
+            .set_ana_state(NvmeAnaState::NonOptimizedState)
+            .await
+            .is_err());
+
+        nexus.destroy().await.unwrap();
+    })
+    .await;
+}
@@ -0,0 +1,96 @@
+use std::{pin::Pin, time::Duration};
+
+use chrono::Utc;
+
+use common::MayastorTest;
+use mayastor::{
+    bdev::{device_create, device_destroy, device_open},
+    core::{MayastorCliArgs, Share, UntypedBdev},
+    nexus_uri::bdev_create,
+    subsys::NvmfConnection,
+};
+use spdk_rs::DmaBuf;
+
+pub mod common;
+
+const BUF_SIZE: u64 = 32768;
+
+async fn connections() -> Vec<NvmfConnection> {
+    let bdev = UntypedBdev::lookup_by_name("conn0").unwrap();
+    bdev.nvmf_connections().await
+}
+
+#[tokio::test]
+async fn nvmf_host_connections() {
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+
+    let uri = ms
+        .spawn(async {
+            bdev_create("malloc:///conn0?size_mb=64").await.unwrap();
+            let mut bdev = UntypedBdev::lookup_by_name("conn0").unwrap();
+            Pin::new(&mut bdev).share_nvmf(None).await.unwrap();
+            bdev.share_uri().unwrap()
+        })
+        .await;
+
+    // nobody is connected yet
+    assert!(ms.spawn(connections()).await.is_empty());
+
+    // connect to the share, write twice and read once through it
+    let before = Utc::now();
+    let u = uri.clone();
+    let (connected, after_io) = ms
+        .spawn(async move {
+            let name = device_create(&u).await.unwrap();
+            let handle =
+                device_open(&name, false).unwrap().into_handle().unwrap();
+            let device = handle.get_device();
+            let buf = DmaBuf::new(BUF_SIZE, device.alignment()).unwrap();
+            let mut read_buf =
+                DmaBuf::new(BUF_SIZE, device.alignment()).unwrap();
+            let connected = connections().await;
+
+            handle.write_at(0, &buf).await.unwrap();
+            handle.write_at(BUF_SIZE, &buf).await.unwrap();
+            handle.read_at(0, &mut read_buf).await.unwrap();
+            let after_io = connections().await;
+
+            drop(handle);
+            device_destroy(&u).await.unwrap();
+            (connected, after_io)
+        })
+        .await;
+    let after = Utc::now();
+
+    assert_eq!(connected.len(), 1);
+    let connection = &connected[0];
+    assert!(!connection.host_nqn.is_empty());
+    assert!(connection.address.is_some());
+    assert!(connection.io_queues > 0);
+    assert!(connection.connected_since >= before);
+    assert!(connection.connected_since <= after);
+
+    // only the I/O issued after the first look is counted on top
+    assert_eq!(after_io.len(), 1);
+    let stats = after_io[0].stats;
+    assert_eq!(stats.num_write_ops - connection.stats.num_write_ops, 2);
+    assert_eq!(
+        stats.bytes_written - connection.stats.bytes_written,
+        2 * BUF_SIZE
+    );
+    assert_eq!(stats.num_read_ops - connection.stats.num_read_ops, 1);
+    assert_eq!(stats.bytes_read - connection.stats.bytes_read, BUF_SIZE);
+    assert_eq!(after_io[0].connected_since, connection.connected_since);
+
+    // the host is gone once disconnected, which the target notices
+    // asynchronously
+    let mut disconnected = false;
+    for _ in 0 .. 100 {
+        if ms.spawn(connections()).await.is_empty() {
+            disconnected = true;
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert!(disconnected, "the host is still connected");
+}
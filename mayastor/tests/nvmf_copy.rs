@@ -0,0 +1,73 @@
+use std::pin::Pin;
+
+use common::MayastorTest;
+use mayastor::{
+    bdev::{device_create, device_destroy, device_open},
+    core::{CoreError, MayastorCliArgs, Share, UntypedBdev},
+    nexus_uri::bdev_create,
+};
+use spdk_rs::DmaBuf;
+
+pub mod common;
+
+const BUF_SIZE: u64 = 32768;
+
+async fn share(name: &'static str) -> String {
+    bdev_create(&format!("malloc:///{}?size_mb=64", name))
+        .await
+        .unwrap();
+    let mut bdev = UntypedBdev::lookup_by_name(name).unwrap();
+    Pin::new(&mut bdev).share_nvmf(None).await.unwrap();
+    bdev.share_uri().unwrap()
+}
+
+#[tokio::test]
+async fn nvmf_copy_offload() {
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+
+    ms.spawn(async {
+        let src_uri = share("copy0").await;
+        let dst_uri = share("copy1").await;
+
+        let src_name = device_create(&src_uri).await.unwrap();
+        let dst_name = device_create(&dst_uri).await.unwrap();
+        let src = device_open(&src_name, true).unwrap().into_handle().unwrap();
+        let dst = device_open(&dst_name, true).unwrap().into_handle().unwrap();
+
+        // namespaces of different subsystems cannot copy between each other,
+        // even though both live on the same target
+        assert!(!src.get_device().copy_supported(&*dst.get_device()));
+        assert!(!dst.get_device().copy_supported(&*src.get_device()));
+        assert!(matches!(
+            src.copy_blocks(0, &*dst, 0, 1).await,
+            Err(CoreError::NotSupported { .. })
+        ));
+
+        // blocks are copied within a namespace whose controller supports it
+        if src.get_device().copy_supported(&*src.get_device()) {
+            let block_len = src.get_device().block_len();
+            let num_blocks = BUF_SIZE / block_len;
+            let alignment = src.get_device().alignment();
+
+            let mut buf = src.dma_malloc(BUF_SIZE).unwrap();
+            buf.fill(0x5a);
+            src.write_at(0, &buf).await.unwrap();
+
+            let copied = src
+                .copy_blocks(0, &*src, 2 * num_blocks, num_blocks)
+                .await
+                .unwrap();
+            assert_eq!(copied, num_blocks);
+
+            let mut read_buf = DmaBuf::new(BUF_SIZE, alignment).unwrap();
+            src.read_at(2 * BUF_SIZE, &mut read_buf).await.unwrap();
+            assert!(read_buf.as_slice().iter().all(|b| *b == 0x5a));
+        }
+
+        drop(src);
+        drop(dst);
+        device_destroy(&src_uri).await.unwrap();
+        device_destroy(&dst_uri).await.unwrap();
+    })
+    .await;
+}
@@ -0,0 +1,471 @@
+use std::{
+    ffi::{c_void, CString},
+    mem::{size_of, zeroed},
+    net::SocketAddr,
+    pin::Pin,
+    process::Command,
+    ptr::null_mut,
+};
+
+use crossbeam::atomic::AtomicCell;
+use futures::channel::oneshot;
+use once_cell::sync::Lazy;
+
+use common::MayastorTest;
+use mayastor::{
+    bdev::NVME_CONTROLLERS,
+    core::{poller, MayastorCliArgs, NvmfShareProps, Share, UntypedBdev},
+    nexus_uri::{bdev_create, bdev_destroy},
+    subsys::{
+        discovery_endpoints,
+        discovery_entries,
+        discovery_generation,
+        set_discovery_referrals,
+        DiscoveryEntry,
+        NvmfSubsystem,
+        SubType,
+    },
+};
+use spdk_rs::libspdk::{
+    spdk_nvme_connect_async,
+    spdk_nvme_cpl,
+    spdk_nvme_ctrlr,
+    spdk_nvme_ctrlr_get_default_ctrlr_opts,
+    spdk_nvme_ctrlr_opts,
+    spdk_nvme_ctrlr_process_admin_completions,
+    spdk_nvme_ctrlr_register_aer_callback,
+    spdk_nvme_detach,
+    spdk_nvme_probe_poll_async,
+    spdk_nvme_transport_id,
+    spdk_nvme_transport_id_parse,
+    SPDK_NVME_LOG_DISCOVERY,
+};
+
+pub mod common;
+
+static HOST_A: &str = "nqn.2019-05.io.openebs:host-a";
+static HOST_B: &str = "nqn.2019-05.io.openebs:host-b";
+static REFERRAL: &str = "10.1.0.2:8420";
+static DISCOVERY_NQN: &str = "nqn.2014-08.org.nvmexpress.discovery";
+
+/// discovery log change notices received by the discovery controller the
+/// test connects to
+static LOG_CHANGES: Lazy<AtomicCell<u32>> = Lazy::new(|| AtomicCell::new(0));
+
+/// the NQNs of the subsystems in the discovery log page of the host
+fn subsystems(entries: &[DiscoveryEntry]) -> Vec<String> {
+    entries
+        .iter()
+        .filter(|e| e.subtype == SubType::Nvme)
+        .map(|e| e.subnqn.clone())
+        .collect()
+}
+
+#[tokio::test]
+async fn nvmf_discovery_log() {
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+
+    // one subsystem only host A may connect to, and one open to any host
+    let (restricted, open) = ms
+        .spawn(async {
+            bdev_create("malloc:///disc0?size_mb=64").await.unwrap();
+            bdev_create("malloc:///disc1?size_mb=64").await.unwrap();
+
+            let mut bdev = UntypedBdev::lookup_by_name("disc0").unwrap();
+            let props = NvmfShareProps::new().with_allowed_hosts(vec![HOST_A]);
+            let restricted =
+                Pin::new(&mut bdev).share_nvmf(Some(props)).await.unwrap();
+
+            let mut bdev = UntypedBdev::lookup_by_name("disc1").unwrap();
+            let open = Pin::new(&mut bdev).share_nvmf(None).await.unwrap();
+            (restricted, open)
+        })
+        .await;
+
+    let (host_a, host_b) = ms
+        .spawn(async {
+            (
+                subsystems(&discovery_entries(HOST_A)),
+                subsystems(&discovery_entries(HOST_B)),
+            )
+        })
+        .await;
+    assert!(host_a.contains(&restricted));
+    assert!(host_a.contains(&open));
+    assert!(!host_b.contains(&restricted));
+    assert!(host_b.contains(&open));
+
+    // entries share a port ID when they are reachable through the same port
+    let entries = ms.spawn(async { discovery_entries(HOST_A) }).await;
+    for a in &entries {
+        for b in &entries {
+            assert_eq!(a.address == b.address, a.port_id == b.port_id);
+        }
+    }
+
+    // invalid referrals and the discovery service itself are left out
+    let mut referrals = vec![REFERRAL.to_string(), "invalid".to_string()];
+    referrals.extend(discovery_endpoints().iter().map(|e| e.to_string()));
+    set_discovery_referrals(&referrals).await;
+
+    let entries = ms.spawn(async { discovery_entries(HOST_B) }).await;
+    let referrals = entries
+        .iter()
+        .filter(|e| e.subtype == SubType::Discovery)
+        .map(|e| e.address.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(referrals, vec![REFERRAL.to_string()]);
+
+    // subsystems are no longer listed once unshared
+    let host_a = ms
+        .spawn(async {
+            let mut bdev = UntypedBdev::lookup_by_name("disc1").unwrap();
+            Pin::new(&mut bdev).unshare().await.unwrap();
+            subsystems(&discovery_entries(HOST_A))
+        })
+        .await;
+    assert!(host_a.contains(&restricted));
+    assert!(!host_a.contains(&open));
+
+    set_discovery_referrals(&[]).await;
+}
+
+#[tokio::test]
+async fn nvmf_discovery_uri() {
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+
+    let uri = ms
+        .spawn(async {
+            bdev_create("malloc:///disc2?size_mb=64").await.unwrap();
+            let mut bdev = UntypedBdev::lookup_by_name("disc2").unwrap();
+            Pin::new(&mut bdev).share_nvmf(None).await.unwrap();
+            bdev.share_uri().unwrap()
+        })
+        .await;
+
+    // the subsystem is found through the discovery service of the target
+    let url = url::Url::parse(&uri).unwrap();
+    let nqn = url.path().trim_start_matches('/').to_string();
+    let port = url.port().unwrap_or(8420);
+    let discovery = format!(
+        "nvmf+discovery://{}:{}/?nqn={}",
+        url.host_str().unwrap(),
+        port,
+        nqn
+    );
+
+    let d = discovery.clone();
+    ms.spawn(async move {
+        let name = bdev_create(&d).await.unwrap();
+        let controller = NVME_CONTROLLERS.lookup_by_name(&name).unwrap();
+        let controller = controller.lock();
+        let path = controller.active_path().unwrap();
+        assert_eq!(path.subnqn(), nqn);
+        assert_eq!(path.svcid(), port.to_string());
+        drop(controller);
+
+        bdev_destroy(&d).await.unwrap();
+    })
+    .await;
+
+    // the subsystem must be given, and must be advertised
+    let host = url.host_str().unwrap().to_string();
+    ms.spawn(async move {
+        let missing = format!("nvmf+discovery://{}:{}/", host, port);
+        assert!(bdev_create(&missing).await.is_err());
+        let unknown = format!(
+            "nvmf+discovery://{}:{}/?nqn=nqn.2019-05.io.openebs:unknown",
+            host, port
+        );
+        assert!(bdev_create(&unknown).await.is_err());
+    })
+    .await;
+}
+
+/// the discovery log page read by the kernel initiator as the host
+fn nvme_discover(address: &SocketAddr, hostnqn: &str) -> serde_json::Value {
+    let output = Command::new("nvme")
+        .args(&["discover", "-t", "tcp"])
+        .args(&["-a", &address.ip().to_string()])
+        .args(&["-s", &address.port().to_string()])
+        .args(&["-q", hostnqn])
+        .args(&["-o", "json"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "failed to discover {}: {}",
+        address,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    serde_json::from_slice(&output.stdout).expect("JSON was not well-formatted")
+}
+
+#[tokio::test]
+async fn nvmf_discovery_log_page() {
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+
+    let restricted = ms
+        .spawn(async {
+            bdev_create("malloc:///disc3?size_mb=64").await.unwrap();
+            let mut bdev = UntypedBdev::lookup_by_name("disc3").unwrap();
+            let props = NvmfShareProps::new().with_allowed_hosts(vec![HOST_A]);
+            Pin::new(&mut bdev).share_nvmf(Some(props)).await.unwrap()
+        })
+        .await;
+    set_discovery_referrals(&[REFERRAL.to_string()]).await;
+
+    let (entries, genctr) = ms
+        .spawn(async { (discovery_entries(HOST_A), discovery_generation()) })
+        .await;
+    let endpoint = discovery_endpoints()[0];
+
+    // the page read over the wire matches the log of the target
+    let page = nvme_discover(&endpoint, HOST_A);
+    assert_eq!(page["genctr"], genctr);
+    let records = page["records"].as_array().unwrap();
+    assert_eq!(records.len(), entries.len());
+    for (record, entry) in records.iter().zip(entries.iter()) {
+        assert_eq!(record["subnqn"], entry.subnqn);
+        assert_eq!(record["traddr"], entry.address.ip().to_string());
+        assert_eq!(record["trsvcid"], entry.address.port().to_string());
+        assert_eq!(record["portid"], entry.port_id);
+    }
+    assert!(records.iter().any(|r| r["subnqn"] == restricted));
+    assert!(records.iter().any(|r| r["subnqn"] == DISCOVERY_NQN
+        && r["traddr"] == "10.1.0.2"
+        && r["trsvcid"] == "8420"));
+
+    // other hosts do not see the subsystem
+    let page = nvme_discover(&endpoint, HOST_B);
+    let records = page["records"].as_array().unwrap();
+    assert!(!records.iter().any(|r| r["subnqn"] == restricted));
+
+    set_discovery_referrals(&[]).await;
+    ms.spawn(async {
+        let mut bdev = UntypedBdev::lookup_by_name("disc3").unwrap();
+        Pin::new(&mut bdev).unshare().await.unwrap();
+        bdev_destroy("malloc:///disc3?size_mb=64").await.unwrap();
+    })
+    .await;
+}
+
+/// the options must be the first member as the attach callback is passed a
+/// pointer to them
+#[repr(C)]
+struct ConnectCtx {
+    opts: spdk_nvme_ctrlr_opts,
+    ctrlr: *mut spdk_nvme_ctrlr,
+}
+
+extern "C" fn attach_cb(
+    cb_ctx: *mut c_void,
+    _trid: *const spdk_nvme_transport_id,
+    ctrlr: *mut spdk_nvme_ctrlr,
+    _opts: *const spdk_nvme_ctrlr_opts,
+) {
+    unsafe { (*(cb_ctx as *mut ConnectCtx)).ctrlr = ctrlr };
+}
+
+extern "C" fn aer_cb(_arg: *mut c_void, cpl: *const spdk_nvme_cpl) {
+    let log_page = (unsafe { (*cpl).cdw0 } >> 16) & 0xff;
+    if log_page == SPDK_NVME_LOG_DISCOVERY {
+        LOG_CHANGES.fetch_add(1);
+    }
+}
+
+/// poll on the reactor until the condition holds
+async fn poll_until(mut cond: impl FnMut() -> bool + 'static) {
+    let (s, r) = oneshot::channel::<()>();
+    let mut sender = Some(s);
+    let _poller = poller::Builder::new()
+        .with_interval(1000)
+        .with_poll_fn(move || {
+            if sender.is_some() && cond() {
+                let _ = sender.take().unwrap().send(());
+            }
+            0
+        })
+        .build();
+    r.await.unwrap();
+}
+
+/// connect to the discovery controller of the target as the host
+async fn connect_discovery(
+    address: SocketAddr,
+    hostnqn: &str,
+) -> *mut spdk_nvme_ctrlr {
+    let mut trid: spdk_nvme_transport_id = unsafe { zeroed() };
+    let s = CString::new(format!(
+        "trtype:TCP adrfam:IPv4 traddr:{} trsvcid:{} subnqn:{}",
+        address.ip(),
+        address.port(),
+        DISCOVERY_NQN
+    ))
+    .unwrap();
+    assert_eq!(
+        unsafe { spdk_nvme_transport_id_parse(&mut trid, s.as_ptr()) },
+        0
+    );
+
+    let mut ctx = Box::new(ConnectCtx {
+        opts: unsafe { zeroed() },
+        ctrlr: null_mut(),
+    });
+    unsafe {
+        spdk_nvme_ctrlr_get_default_ctrlr_opts(
+            &mut ctx.opts,
+            size_of::<spdk_nvme_ctrlr_opts>() as u64,
+        )
+    };
+    for (dst, src) in ctx.opts.hostnqn.iter_mut().zip(hostnqn.bytes()) {
+        *dst = src as _;
+    }
+
+    let probe =
+        unsafe { spdk_nvme_connect_async(&trid, &ctx.opts, Some(attach_cb)) };
+    assert!(!probe.is_null());
+    poll_until(move || unsafe {
+        spdk_nvme_probe_poll_async(probe) != -libc::EAGAIN
+    })
+    .await;
+
+    assert!(!ctx.ctrlr.is_null(), "failed to connect to {}", address);
+    ctx.ctrlr
+}
+
+#[tokio::test]
+async fn nvmf_discovery_aen() {
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+    let endpoint = discovery_endpoints()[0];
+
+    ms.spawn(async move {
+        bdev_create("malloc:///disc4?size_mb=64").await.unwrap();
+
+        let ctrlr = connect_discovery(endpoint, HOST_A).await;
+        unsafe {
+            spdk_nvme_ctrlr_register_aer_callback(
+                ctrlr,
+                Some(aer_cb),
+                null_mut(),
+            )
+        };
+        let _adminq = poller::Builder::new()
+            .with_interval(1000)
+            .with_poll_fn(move || unsafe {
+                spdk_nvme_ctrlr_process_admin_completions(ctrlr)
+            })
+            .build();
+
+        // sharing the bdev changes the log, as does unsharing it
+        let mut bdev = UntypedBdev::lookup_by_name("disc4").unwrap();
+        Pin::new(&mut bdev).share_nvmf(None).await.unwrap();
+        poll_until(|| LOG_CHANGES.load() >= 1).await;
+
+        let notified = LOG_CHANGES.load();
+        Pin::new(&mut bdev).unshare().await.unwrap();
+        poll_until(move || LOG_CHANGES.load() > notified).await;
+
+        // and so does allowing a host on a shared subsystem
+        let props = NvmfShareProps::new().with_allowed_hosts(vec![HOST_B]);
+        Pin::new(&mut bdev).share_nvmf(Some(props)).await.unwrap();
+        let notified = LOG_CHANGES.load();
+        let generation = discovery_generation();
+        NvmfSubsystem::nqn_lookup("disc4")
+            .unwrap()
+            .allow_host(HOST_A)
+            .unwrap();
+        poll_until(move || LOG_CHANGES.load() > notified).await;
+        assert!(discovery_generation() > generation);
+        assert!(discovery_entries(HOST_A)
+            .iter()
+            .any(|e| e.subnqn.ends_with(":disc4")));
+
+        unsafe { spdk_nvme_detach(ctrlr) };
+        Pin::new(&mut bdev).unshare().await.unwrap();
+        bdev_destroy("malloc:///disc4?size_mb=64").await.unwrap();
+    })
+    .await;
+}
+
+/// the discovery URI of the subsystem the bdev is shared as
+fn discovery_uri(share_uri: &str) -> String {
+    let url = url::Url::parse(share_uri).unwrap();
+    format!(
+        "nvmf+discovery://{}:{}/?nqn={}",
+        url.host_str().unwrap(),
+        url.port().unwrap_or(8420),
+        url.path().trim_start_matches('/')
+    )
+}
+
+#[tokio::test]
+async fn nvmf_discovery_follow() {
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+
+    // with enough subsystems, the discovery log page is read in several
+    // chunks
+    let uris = ms
+        .spawn(async {
+            let mut uris = Vec::new();
+            for name in &["disc5", "disc6", "disc7", "disc8"] {
+                bdev_create(&format!("malloc:///{}?size_mb=64", name))
+                    .await
+                    .unwrap();
+                let mut bdev = UntypedBdev::lookup_by_name(name).unwrap();
+                Pin::new(&mut bdev).share_nvmf(None).await.unwrap();
+                uris.push(discovery_uri(&bdev.share_uri().unwrap()));
+            }
+            uris
+        })
+        .await;
+
+    let u = uris.clone();
+    let name = ms
+        .spawn(async move {
+            let mut names = Vec::new();
+            for uri in &u {
+                names.push(bdev_create(uri).await.unwrap());
+            }
+            names.pop().unwrap()
+        })
+        .await;
+
+    // the controller reads the paths again when notified that the log
+    // changed, without reconnecting
+    let generation = |name: &str| {
+        NVME_CONTROLLERS
+            .lookup_by_name(name)
+            .and_then(|c| c.lock().discovery_generation())
+            .unwrap_or(0)
+    };
+    let n = name.clone();
+    ms.spawn(async move {
+        let nn = n.clone();
+        poll_until(move || generation(&nn) > 0).await;
+        let read = generation(&n);
+
+        bdev_create("malloc:///disc9?size_mb=64").await.unwrap();
+        let mut bdev = UntypedBdev::lookup_by_name("disc9").unwrap();
+        Pin::new(&mut bdev).share_nvmf(None).await.unwrap();
+        let nn = n.clone();
+        poll_until(move || generation(&nn) > read).await;
+
+        let controller = NVME_CONTROLLERS.lookup_by_name(&n).unwrap();
+        let controller = controller.lock();
+        assert_eq!(controller.get_health_stats().resets, 0);
+        assert!(controller.active_path().is_some());
+        drop(controller);
+
+        Pin::new(&mut bdev).unshare().await.unwrap();
+        bdev_destroy("malloc:///disc9?size_mb=64").await.unwrap();
+    })
+    .await;
+
+    ms.spawn(async move {
+        for uri in &uris {
+            bdev_destroy(uri).await.unwrap();
+        }
+    })
+    .await;
+}
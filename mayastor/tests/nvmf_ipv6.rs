@@ -0,0 +1,65 @@
+use std::pin::Pin;
+
+use common::MayastorTest;
+use mayastor::{
+    core::{MayastorCliArgs, Share, UntypedBdev},
+    grpc,
+    nexus_uri::{bdev_create, bdev_destroy},
+};
+
+pub mod common;
+
+static CONFIG_FILE: &str = "/tmp/nvmf_ipv6.yaml";
+
+static CONFIG: &str = r#"
+nexus_opts:
+  nvmf_host_addresses:
+    - "::1"
+  nvmf_replica_addresses:
+    - "::1"
+"#;
+
+#[test]
+fn grpc_endpoint_ipv6() {
+    assert_eq!(
+        grpc::endpoint("[::1]:10125".into()).to_string(),
+        "[::1]:10125"
+    );
+    assert_eq!(grpc::endpoint("[::1]".into()).to_string(), "[::1]:10124");
+    assert_eq!(grpc::endpoint("::1".into()).to_string(), "[::1]:10124");
+    assert_eq!(
+        grpc::endpoint("10.0.0.1".into()).to_string(),
+        "10.0.0.1:10124"
+    );
+}
+
+#[tokio::test]
+async fn nvmf_ipv6() {
+    std::fs::write(CONFIG_FILE, CONFIG).unwrap();
+    let ms = MayastorTest::new(MayastorCliArgs {
+        mayastor_config: Some(CONFIG_FILE.into()),
+        ..Default::default()
+    });
+
+    let uri = ms
+        .spawn(async {
+            bdev_create("malloc:///ipv6disk?size_mb=64").await.unwrap();
+            let mut bdev = UntypedBdev::lookup_by_name("ipv6disk").unwrap();
+            Pin::new(&mut bdev).share_nvmf(None).await.unwrap();
+
+            let uri = bdev.share_uri().unwrap();
+            assert!(uri.starts_with("nvmf://[::1]:8420/"));
+            uri
+        })
+        .await;
+
+    // connect to the share over IPv6
+    ms.spawn(async move {
+        let name = bdev_create(&uri).await.unwrap();
+        assert!(UntypedBdev::lookup_by_name(&name).is_some());
+        bdev_destroy(&uri).await.unwrap();
+    })
+    .await;
+
+    std::fs::remove_file(CONFIG_FILE).unwrap();
+}
@@ -0,0 +1,72 @@
+use std::pin::Pin;
+
+use common::MayastorTest;
+use mayastor::{
+    bdev::nexus::{nexus_create, nexus_lookup_mut},
+    core::{MayastorCliArgs, Protocol, Share, UntypedBdev},
+    nexus_uri::bdev_create,
+};
+
+pub mod common;
+
+static CONFIG_FILE: &str = "/tmp/nvmf_listen.yaml";
+
+/// nexuses are exported on 127.0.0.1, replicas on two other addresses
+static CONFIG: &str = r#"
+nexus_opts:
+  nvmf_host_addresses:
+    - 127.0.0.1
+  nvmf_replica_addresses:
+    - 127.0.0.2
+    - 127.0.0.3
+"#;
+
+#[tokio::test]
+async fn nvmf_listen_addresses() {
+    std::fs::write(CONFIG_FILE, CONFIG).unwrap();
+    let ms = MayastorTest::new(MayastorCliArgs {
+        mayastor_config: Some(CONFIG_FILE.into()),
+        ..Default::default()
+    });
+
+    // a replica is reachable through every address of the replica network
+    ms.spawn(async {
+        bdev_create("malloc:///listen0?size_mb=64").await.unwrap();
+        let mut bdev = UntypedBdev::lookup_by_name("listen0").unwrap();
+        Pin::new(&mut bdev).share_nvmf(None).await.unwrap();
+
+        let uris = bdev.share_uris();
+        assert_eq!(uris.len(), 2);
+        assert!(uris.iter().any(|u| u.starts_with("nvmf://127.0.0.2:8420/")));
+        assert!(uris.iter().any(|u| u.starts_with("nvmf://127.0.0.3:8420/")));
+        assert_eq!(bdev.share_uri().unwrap(), uris[0]);
+
+        Pin::new(&mut bdev).unshare().await.unwrap();
+        assert_eq!(bdev.share_uris(), vec!["bdev:///listen0".to_string()]);
+    })
+    .await;
+
+    // a nexus is only exported to hosts
+    ms.spawn(async {
+        nexus_create(
+            "listen_nexus",
+            32 * 1024 * 1024,
+            None,
+            &["malloc:///listen1?size_mb=64".to_string()],
+        )
+        .await
+        .unwrap();
+
+        let mut nexus = nexus_lookup_mut("listen_nexus").unwrap();
+        nexus.as_mut().share(Protocol::Nvmf, None).await.unwrap();
+
+        let uris = nexus.get_share_uris();
+        assert_eq!(uris.len(), 1);
+        assert!(uris[0].starts_with("nvmf://127.0.0.1:4421/"));
+
+        nexus.destroy().await.unwrap();
+    })
+    .await;
+
+    std::fs::remove_file(CONFIG_FILE).unwrap();
+}
@@ -0,0 +1,196 @@
+use std::{pin::Pin, time::Duration};
+
+use once_cell::sync::Lazy;
+
+use common::compose::{Builder, MayastorTest};
+use mayastor::{
+    bdev::{device_create, device_destroy, device_open, NVME_CONTROLLERS},
+    core::{MayastorCliArgs, Share, UntypedBdev},
+    nexus_uri::{bdev_create, bdev_destroy},
+    subsys::{Config, NvmeBdevOpts},
+};
+use rpc::mayastor::{BdevShareRequest, BdevUri, Null};
+use spdk_rs::DmaBuf;
+
+pub mod common;
+
+/// a port nothing listens on, so connecting through it is refused
+static UNREACHABLE_PORT: u16 = 4499;
+
+const BUF_SIZE: u64 = 32768;
+
+/// uuid of the namespace exported by both targets
+const DISK_UUID: &str = "4f6d4f4e-8f8a-4e9e-9f5e-1c3b2a6d7e01";
+
+static MAYASTOR: Lazy<MayastorTest> =
+    Lazy::new(|| MayastorTest::new(MayastorCliArgs::default()));
+
+fn get_config() -> &'static Config {
+    Config::get_or_init(|| Config {
+        nvme_bdev_opts: NvmeBdevOpts {
+            keep_alive_timeout_ms: 1_000,
+            transport_retry_count: 1,
+            ..Default::default()
+        },
+        ..Default::default()
+    })
+}
+
+#[tokio::test]
+async fn nvmf_multipath() {
+    get_config().apply();
+    let ms = &*MAYASTOR;
+
+    let uri = ms
+        .spawn(async {
+            bdev_create("malloc:///mpath0?size_mb=64").await.unwrap();
+            let mut bdev = UntypedBdev::lookup_by_name("mpath0").unwrap();
+            Pin::new(&mut bdev).share_nvmf(None).await.unwrap();
+            bdev.share_uri().unwrap()
+        })
+        .await;
+
+    // the primary path is unreachable, so the controller is connected
+    // through the alternate path
+    let url = url::Url::parse(&uri).unwrap();
+    let port = url.port().unwrap_or(8420);
+    let alternate = format!("{}:{}", url.host_str().unwrap(), port);
+    let mut multipath = url.clone();
+    multipath.set_port(Some(UNREACHABLE_PORT)).unwrap();
+    multipath
+        .query_pairs_mut()
+        .append_pair("traddr", &alternate);
+    let multipath = multipath.to_string();
+
+    let u = multipath.clone();
+    ms.spawn(async move {
+        let name = bdev_create(&u).await.unwrap();
+        let controller = NVME_CONTROLLERS.lookup_by_name(&name).unwrap();
+        let controller = controller.lock();
+        assert!(controller.is_multipath());
+        let path = controller.active_path().unwrap();
+        assert_eq!(path.svcid(), port.to_string());
+        drop(controller);
+
+        bdev_destroy(&u).await.unwrap();
+    })
+    .await;
+
+    // an alternate path must be a valid address
+    let mut invalid = url;
+    invalid.query_pairs_mut().append_pair("traddr", "");
+    let invalid = invalid.to_string();
+    ms.spawn(async move {
+        assert!(bdev_create(&invalid).await.is_err());
+    })
+    .await;
+}
+
+/// returns the address of the path the controller of the device is
+/// connected through
+async fn active_traddr(name: String) -> String {
+    let controller = NVME_CONTROLLERS.lookup_by_name(&name).unwrap();
+    let controller = controller.lock();
+    controller.active_path().unwrap().traddr()
+}
+
+#[tokio::test]
+async fn nvmf_multipath_failover() {
+    get_config().apply();
+
+    let test = Builder::new()
+        .name("cargo-test")
+        .network("10.1.0.0/16")
+        .add_container("ms1")
+        .add_container("ms2")
+        .with_clean(true)
+        .build()
+        .await
+        .unwrap();
+
+    // both targets export the same subsystem with the same namespace
+    let mut hdls = test.grpc_handles().await.unwrap();
+    for h in &mut hdls {
+        h.bdev.list(Null {}).await.unwrap();
+        h.bdev
+            .create(BdevUri {
+                uri: format!("malloc:///disk0?size_mb=64&uuid={}", DISK_UUID),
+            })
+            .await
+            .unwrap();
+        h.bdev
+            .share(BdevShareRequest {
+                name: "disk0".into(),
+                proto: "nvmf".into(),
+            })
+            .await
+            .unwrap();
+    }
+
+    let primary = hdls[0].endpoint.ip().to_string();
+    let alternate = hdls[1].endpoint.ip().to_string();
+    let uri = format!(
+        "nvmf://{}:8420/nqn.2019-05.io.openebs:disk0?traddr={}:8420",
+        primary, alternate
+    );
+
+    let u = uri.clone();
+    let name = MAYASTOR
+        .spawn(async move {
+            let name = device_create(&u).await.unwrap();
+            let handle =
+                device_open(&name, false).unwrap().into_handle().unwrap();
+            let buf =
+                DmaBuf::new(BUF_SIZE, handle.get_device().alignment()).unwrap();
+            handle.write_at(0, &buf).await.unwrap();
+            name
+        })
+        .await;
+    assert_eq!(MAYASTOR.spawn(active_traddr(name.clone())).await, primary);
+
+    // the primary target stops responding while I/O is issued, which fails
+    // the controller over to the alternate path once the keep alive times
+    // out, instead of removing the device
+    test.pause("ms1").await.unwrap();
+    let n = name.clone();
+    MAYASTOR
+        .spawn(async move {
+            let handle = device_open(&n, false).unwrap().into_handle().unwrap();
+            let buf =
+                DmaBuf::new(BUF_SIZE, handle.get_device().alignment()).unwrap();
+            // the I/O in flight may be aborted by the failover
+            let _ = handle.write_at(BUF_SIZE, &buf).await;
+        })
+        .await;
+
+    let mut failed_over = false;
+    for _ in 0 .. 100 {
+        if MAYASTOR.spawn(active_traddr(name.clone())).await == alternate {
+            failed_over = true;
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert!(failed_over, "the controller did not fail over");
+
+    // the device is still present and serves I/O through the alternate path
+    let n = name.clone();
+    MAYASTOR
+        .spawn(async move {
+            let handle = device_open(&n, false).unwrap().into_handle().unwrap();
+            let buf =
+                DmaBuf::new(BUF_SIZE, handle.get_device().alignment()).unwrap();
+            handle.write_at(0, &buf).await.unwrap();
+            let mut read_buf =
+                DmaBuf::new(BUF_SIZE, handle.get_device().alignment()).unwrap();
+            handle.read_at(0, &mut read_buf).await.unwrap();
+        })
+        .await;
+
+    test.thaw("ms1").await.unwrap();
+    MAYASTOR
+        .spawn(async move {
+            device_destroy(&uri).await.unwrap();
+        })
+        .await;
+}
@@ -0,0 +1,162 @@
+use std::{pin::Pin, time::Duration};
+
+use crossbeam::atomic::AtomicCell;
+
+use common::MayastorTest;
+use mayastor::{
+    bdev::{device_open, NvmeControllerState, NVME_CONTROLLERS},
+    core::{
+        DeviceEventListener,
+        DeviceEventSink,
+        DeviceEventType,
+        MayastorCliArgs,
+        Reactors,
+        Share,
+        UntypedBdev,
+    },
+    nexus_uri::{bdev_create, bdev_destroy},
+};
+use spdk_rs::DmaBuf;
+
+pub mod common;
+
+const BUF_SIZE: u64 = 32768;
+
+/// set when the device was removed
+static DEVICE_REMOVED: AtomicCell<bool> = AtomicCell::new(false);
+
+/// the outcome of the write issued while the target is gone
+static WRITE_RESULT: AtomicCell<Option<bool>> = AtomicCell::new(None);
+
+struct RemoveListener {}
+
+impl DeviceEventListener for RemoveListener {
+    fn handle_device_event(
+        self: Pin<&mut Self>,
+        event: DeviceEventType,
+        _device: &str,
+    ) {
+        if event == DeviceEventType::DeviceRemoved {
+            DEVICE_REMOVED.store(true);
+        }
+    }
+}
+
+/// the state of the controller, none when it no longer exists
+fn controller_state(name: &str) -> Option<NvmeControllerState> {
+    NVME_CONTROLLERS
+        .lookup_by_name(name)
+        .map(|c| c.lock().get_state())
+}
+
+/// the number of resets of the controller, including reconnect attempts
+fn controller_resets(name: &str) -> u64 {
+    NVME_CONTROLLERS
+        .lookup_by_name(name)
+        .map_or(0, |c| c.lock().get_health_stats().resets)
+}
+
+async fn share(name: &'static str) -> String {
+    let mut bdev = UntypedBdev::lookup_by_name(name).unwrap();
+    Pin::new(&mut bdev).share_nvmf(None).await.unwrap();
+    bdev.share_uri().unwrap()
+}
+
+async fn unshare(name: &'static str) {
+    let mut bdev = UntypedBdev::lookup_by_name(name).unwrap();
+    Pin::new(&mut bdev).unshare().await.unwrap();
+}
+
+/// wait for the condition to hold, checking it on the reactor
+async fn wait_for<F>(ms: &MayastorTest<'_>, what: &str, f: F)
+where
+    F: Fn() -> bool + Send + Clone + 'static,
+{
+    for _ in 0 .. 300 {
+        let f = f.clone();
+        if ms.spawn(async move { f() }).await {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("timed out waiting for {}", what);
+}
+
+#[tokio::test]
+async fn nvmf_reconnect() {
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+
+    let uri = ms
+        .spawn(async {
+            bdev_create("malloc:///reconn0?size_mb=64").await.unwrap();
+            share("reconn0").await
+        })
+        .await;
+
+    // the reconnect timeouts must be valid
+    let invalid = format!("{}?ctrlr_loss_timeout_sec=soon", uri);
+    ms.spawn(async move {
+        assert!(bdev_create(&invalid).await.is_err());
+    })
+    .await;
+
+    // controllers reconnect for 10 secs by default, while I/O is only queued
+    // on request, here for long enough to outlast the attempts after 1, 3 and
+    // 7 secs
+    let child =
+        format!("{}?fast_io_fail_timeout_sec=9&reconnect_delay_sec=1", uri);
+    let c = child.clone();
+    let mut listener = Box::pin(RemoveListener {});
+    let sink = DeviceEventSink::new(listener.as_mut());
+    let sink_clone = sink.clone();
+    let name = ms
+        .spawn(async move {
+            let name = bdev_create(&c).await.unwrap();
+            let descr = device_open(&name, false).unwrap();
+            descr.get_device().add_event_listener(sink_clone).unwrap();
+            name
+        })
+        .await;
+
+    // the controller keeps reconnecting while the target is gone
+    ms.spawn(async { unshare("reconn0").await }).await;
+    let n = name.clone();
+    wait_for(&ms, "the first reconnect attempt", move || {
+        controller_resets(&n) > 0
+    })
+    .await;
+
+    // I/O submitted meanwhile is queued rather than failed
+    let n = name.clone();
+    let resets = ms
+        .spawn(async move {
+            let handle = device_open(&n, false).unwrap().into_handle().unwrap();
+            let buf =
+                DmaBuf::new(BUF_SIZE, handle.get_device().alignment()).unwrap();
+            Reactors::current().send_future(async move {
+                let result = handle.write_at(0, &buf).await;
+                WRITE_RESULT.store(Some(result.is_ok()));
+            });
+            controller_resets(&n)
+        })
+        .await;
+    let n = name.clone();
+    wait_for(&ms, "another reconnect attempt", move || {
+        controller_resets(&n) > resets
+    })
+    .await;
+    assert_eq!(WRITE_RESULT.load(), None, "the write was not queued");
+
+    // and completes once the controller reconnected to the target
+    ms.spawn(async { share("reconn0").await }).await;
+    wait_for(&ms, "the queued write", || WRITE_RESULT.load().is_some()).await;
+    assert_eq!(WRITE_RESULT.load(), Some(true));
+
+    let n = name.clone();
+    let state = ms.spawn(async move { controller_state(&n) }).await;
+    assert_eq!(state, Some(NvmeControllerState::Running));
+    assert!(!DEVICE_REMOVED.load(), "the device was removed");
+
+    ms.spawn(async move { bdev_destroy(&child).await.unwrap() })
+        .await;
+}
@@ -0,0 +1,161 @@
+use common::{reservation_holder, MayastorTest};
+use mayastor::{
+    bdev::{
+        device_create,
+        device_destroy,
+        device_open,
+        nexus::{nexus_create, nexus_lookup_mut},
+    },
+    core::{MayastorCliArgs, Protocol},
+};
+use spdk_rs::{
+    nvme_reservation_acquire_action,
+    nvme_reservation_register_action,
+    nvme_reservation_register_cptpl,
+    nvme_reservation_release_action,
+    nvme_reservation_type,
+};
+
+pub mod common;
+
+static CONFIG_FILE: &str = "/tmp/nvmf_reservation.yaml";
+static PTPL_DIR: &str = "/tmp/nvmf_reservation_ptpl";
+
+static CONFIG: &str = r#"
+nexus_opts:
+  nvmf_ptpl_dir: /tmp/nvmf_reservation_ptpl
+"#;
+
+const NEXUS_NAME: &str = "resv_nexus";
+const HOST_KEY: u64 = 0xabcd_ef00;
+
+#[tokio::test]
+async fn nvmf_host_reservations() {
+    std::fs::write(CONFIG_FILE, CONFIG).unwrap();
+    let _ = std::fs::remove_dir_all(PTPL_DIR);
+    let ms = MayastorTest::new(MayastorCliArgs {
+        mayastor_config: Some(CONFIG_FILE.into()),
+        ..Default::default()
+    });
+
+    let uri = ms
+        .spawn(async {
+            nexus_create(
+                NEXUS_NAME,
+                32 * 1024 * 1024,
+                None,
+                &["malloc:///resv0?size_mb=64".to_string()],
+            )
+            .await
+            .unwrap();
+
+            let mut nexus = nexus_lookup_mut(NEXUS_NAME).unwrap();
+            nexus.as_mut().share(Protocol::Nvmf, None).await.unwrap()
+        })
+        .await;
+
+    // a host registers and takes a reservation without activating PTPL, the
+    // nexus has done so
+    let u = uri.clone();
+    ms.spawn(async move {
+        let name = device_create(&u).await.unwrap();
+        let handle = device_open(&name, false).unwrap().into_handle().unwrap();
+        handle
+            .nvme_resv_register(
+                0,
+                HOST_KEY,
+                nvme_reservation_register_action::REGISTER_KEY,
+                nvme_reservation_register_cptpl::NO_CHANGES,
+            )
+            .await
+            .unwrap();
+        handle
+            .nvme_resv_acquire(
+                HOST_KEY,
+                0,
+                nvme_reservation_acquire_action::ACQUIRE,
+                nvme_reservation_type::WRITE_EXCLUSIVE,
+            )
+            .await
+            .unwrap();
+        drop(handle);
+        device_destroy(&u).await.unwrap();
+    })
+    .await;
+
+    let u = uri.clone();
+    assert_eq!(
+        ms.spawn(async move { reservation_holder(&u).await }).await,
+        Some(HOST_KEY)
+    );
+
+    // the reservation is kept across publishing the nexus again
+    let uri = ms
+        .spawn(async {
+            let mut nexus = nexus_lookup_mut(NEXUS_NAME).unwrap();
+            nexus.as_mut().unshare_nexus().await.unwrap();
+
+            // the target keeps the reservations in the file of the nexus
+            let ptpl_file = std::fs::read_dir(PTPL_DIR)
+                .unwrap()
+                .next()
+                .unwrap()
+                .unwrap();
+            assert!(std::fs::read_to_string(ptpl_file.path())
+                .unwrap()
+                .contains(&HOST_KEY.to_string()));
+
+            nexus.as_mut().share(Protocol::Nvmf, None).await.unwrap()
+        })
+        .await;
+
+    let u = uri.clone();
+    assert_eq!(
+        ms.spawn(async move { reservation_holder(&u).await }).await,
+        Some(HOST_KEY)
+    );
+
+    // releasing the reservation persists as well
+    let u = uri.clone();
+    ms.spawn(async move {
+        let name = device_create(&u).await.unwrap();
+        let handle = device_open(&name, false).unwrap().into_handle().unwrap();
+        handle
+            .nvme_resv_release(
+                HOST_KEY,
+                nvme_reservation_release_action::RELEASE,
+                nvme_reservation_type::WRITE_EXCLUSIVE,
+            )
+            .await
+            .unwrap();
+        drop(handle);
+        device_destroy(&u).await.unwrap();
+    })
+    .await;
+
+    let uri = ms
+        .spawn(async {
+            let mut nexus = nexus_lookup_mut(NEXUS_NAME).unwrap();
+            nexus.as_mut().unshare_nexus().await.unwrap();
+            nexus.as_mut().share(Protocol::Nvmf, None).await.unwrap()
+        })
+        .await;
+
+    let u = uri.clone();
+    assert_eq!(
+        ms.spawn(async move { reservation_holder(&u).await }).await,
+        None
+    );
+
+    ms.spawn(async {
+        nexus_lookup_mut(NEXUS_NAME)
+            .unwrap()
+            .destroy()
+            .await
+            .unwrap();
+    })
+    .await;
+
+    std::fs::remove_file(CONFIG_FILE).unwrap();
+    std::fs::remove_dir_all(PTPL_DIR).unwrap();
+}
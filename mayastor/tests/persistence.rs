@@ -1,5 +1,5 @@
-use crate::common::fio_run_verify;
-use common::compose::Builder;
+use crate::common::{fio_run_verify, reservation_holder};
+use common::compose::{Builder, MayastorTest};
 use composer::{Binary, ComposeTest, ContainerSpec, RpcHandle};
 use etcd_client::Client;
 use rpc::mayastor::{
@@ -20,8 +20,24 @@ use rpc::mayastor::{
     ShareProtocolNexus,
 };
 
-use mayastor::bdev::nexus::{ChildInfo, NexusInfo};
+use mayastor::{
+    bdev::{
+        device_create,
+        device_destroy,
+        device_open,
+        nexus::{ChildInfo, NexusInfo},
+    },
+    core::MayastorCliArgs,
+};
+use spdk_rs::{
+    nvme_reservation_acquire_action,
+    nvme_reservation_register_action,
+    nvme_reservation_register_cptpl,
+    nvme_reservation_type,
+};
 
+use once_cell::sync::OnceCell;
+use serde_json::Value;
 use std::{convert::TryFrom, thread::sleep, time::Duration};
 use url::Url;
 
@@ -31,6 +47,14 @@ static ETCD_ENDPOINT: &str = "0.0.0.0:2379";
 static CHILD1_UUID: &str = "d61b2fdf-1be8-457a-a481-70a42d0a2223";
 static CHILD2_UUID: &str = "094ae8c6-46aa-4139-b4f2-550d39645db3";
 static CHILD3_UUID: &str = "ae09c08f-8909-4024-a9ae-c21a2a0596b9";
+static HOST_KEY: u64 = 0xabcd_ef00;
+
+/// the local mayastor instance acts as the NVMe-oF host of the nexuses
+static MAYASTOR: OnceCell<MayastorTest> = OnceCell::new();
+
+fn get_ms() -> &'static MayastorTest<'static> {
+    MAYASTOR.get_or_init(|| MayastorTest::new(MayastorCliArgs::default()))
+}
 
 /// This test checks that when an unexpected restart occurs, all persisted info
 /// remains unchanged. In particular, the clean shutdown variable must be false.
@@ -332,6 +356,80 @@ async fn persistent_store_connection() {
     assert!(get_nexus(ms1, nexus_uuid).await.is_some());
 }
 
+/// This test checks that the reservations of hosts survive the nexus failing
+/// over to another node. The nexus persists them as soon as they change and
+/// the nexus re-created on the other node restores them before it is
+/// published, so the children need not carry them.
+#[tokio::test]
+async fn persist_reservations_failover() {
+    let test = start_infrastructure("persist_reservations_failover").await;
+    let ms1 = &mut test.grpc_handle("ms1").await.unwrap();
+    let ms2 = &mut test.grpc_handle("ms2").await.unwrap();
+    let ms3 = &mut test.grpc_handle("ms3").await.unwrap();
+
+    let child = create_and_share_bdevs(ms3, CHILD1_UUID).await;
+    let nexus_uuid = "8272e9d3-3738-4e33-b8c3-769d8eed5771";
+    create_nexus(ms1, nexus_uuid, vec![child.clone()]).await;
+    let uri = publish_nexus(ms1, nexus_uuid).await;
+
+    // A host registers and takes a reservation through the nexus.
+    let ms = get_ms();
+    ms.spawn(async move {
+        let name = device_create(&uri).await.unwrap();
+        let handle = device_open(&name, false).unwrap().into_handle().unwrap();
+        handle
+            .nvme_resv_register(
+                0,
+                HOST_KEY,
+                nvme_reservation_register_action::REGISTER_KEY,
+                nvme_reservation_register_cptpl::NO_CHANGES,
+            )
+            .await
+            .unwrap();
+        handle
+            .nvme_resv_acquire(
+                HOST_KEY,
+                0,
+                nvme_reservation_acquire_action::ACQUIRE,
+                nvme_reservation_type::WRITE_EXCLUSIVE,
+            )
+            .await
+            .unwrap();
+        drop(handle);
+        device_destroy(&uri).await.unwrap();
+    })
+    .await;
+
+    // The reservation is persisted without the nexus being unpublished.
+    let key = format!("{}/reservations", nexus_uuid);
+    let mut etcd = Client::connect([ETCD_ENDPOINT], None).await.unwrap();
+    let mut crkey = None;
+    for _ in 0 .. 50 {
+        let response = etcd.get(key.as_str(), None).await.unwrap();
+        if let Some(kv) = response.kvs().first() {
+            let reservations: Value = serde_json::from_slice(kv.value())
+                .expect("Failed to parse the reservations");
+            crkey = reservations["crkey"].as_u64();
+            if crkey == Some(HOST_KEY) {
+                break;
+            }
+        }
+        sleep(Duration::from_millis(100));
+    }
+    assert_eq!(crkey, Some(HOST_KEY));
+
+    // The node of the nexus is lost and the nexus is re-created on another
+    // node, the host finds its reservation there.
+    test.stop("ms1").await.expect("Failed to stop ms1");
+    create_nexus(ms2, nexus_uuid, vec![child]).await;
+    let uri = publish_nexus(ms2, nexus_uuid).await;
+    assert_eq!(
+        ms.spawn(async move { reservation_holder(&uri).await })
+            .await,
+        Some(HOST_KEY)
+    );
+}
+
 /// Start the containers for the tests.
 async fn start_infrastructure(test_name: &str) -> ComposeTest {
     let etcd_endpoint = format!("http://etcd.{}:2379", test_name);
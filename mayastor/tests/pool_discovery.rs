@@ -0,0 +1,89 @@
+use std::process::Command;
+
+use common::MayastorTest;
+use mayastor::{
+    core::MayastorCliArgs,
+    lvs::Lvs,
+    pool::PoolArgs,
+    subsys::{discovered_pools, ImportStatus, PoolConfig},
+};
+
+pub mod common;
+
+static POOL_FILE: &str = "/tmp/discover1.img";
+static DATA_FILE: &str = "/tmp/discover2.img";
+
+/// attach the file to a loop device and return the device path
+fn losetup(file: &str) -> String {
+    let output = Command::new("losetup")
+        .args(&["-f", "--show", file])
+        .output()
+        .expect("failed exec losetup");
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap().trim().to_string()
+}
+
+fn losetup_detach(device: &str) {
+    let output = Command::new("losetup")
+        .args(&["-d", device])
+        .output()
+        .expect("failed exec losetup");
+    assert!(output.status.success());
+}
+
+#[tokio::test]
+async fn pool_discovery() {
+    common::delete_file(&[POOL_FILE.into(), DATA_FILE.into()]);
+    common::truncate_file(POOL_FILE, 64 * 1024);
+    common::dd_random_file(DATA_FILE, 4096, 64 * 1024);
+
+    let pool_dev = losetup(POOL_FILE);
+    let data_dev = losetup(DATA_FILE);
+    let pool_uri = format!("aio://{}", pool_dev);
+
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+
+    // create a pool on one of the devices and export it again
+    let uri = pool_uri.clone();
+    ms.spawn(async move {
+        let pool = Lvs::create_or_import(PoolArgs {
+            name: "dpool".into(),
+            disks: vec![uri],
+            uuid: None,
+        })
+        .await
+        .unwrap();
+        pool.export().await.unwrap();
+    })
+    .await;
+
+    let data = std::fs::read(DATA_FILE).unwrap();
+
+    // the exported pool is found and imported, the device without a pool
+    // is not written to
+    ms.spawn(async move {
+        PoolConfig::discover().await;
+
+        let pools = discovered_pools();
+        let pool = pools.iter().find(|p| p.name == "dpool").unwrap();
+        assert_eq!(pool.status, ImportStatus::Imported);
+        assert_eq!(pool.disks, vec![pool_uri]);
+        assert!(Lvs::lookup("dpool").is_some());
+    })
+    .await;
+
+    assert_eq!(std::fs::read(DATA_FILE).unwrap(), data);
+
+    // an imported pool is not discovered again
+    ms.spawn(async {
+        PoolConfig::discover().await;
+        assert!(discovered_pools().iter().all(|p| p.name != "dpool"));
+
+        Lvs::lookup("dpool").unwrap().destroy().await.unwrap();
+    })
+    .await;
+
+    losetup_detach(&pool_dev);
+    losetup_detach(&data_dev);
+    common::delete_file(&[POOL_FILE.into(), DATA_FILE.into()]);
+}
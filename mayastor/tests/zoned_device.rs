@@ -0,0 +1,242 @@
+use std::ffi::CString;
+
+use futures::channel::oneshot;
+use once_cell::sync::Lazy;
+
+use common::MayastorTest;
+use mayastor::{
+    bdev::{device_create, device_destroy, device_lookup, device_open},
+    core::{CoreError, MayastorCliArgs, ZoneState},
+    ffihelper::{cb_arg, done_errno_cb, ErrnoResult},
+    lvs::Lvs,
+    pool::PoolArgs,
+};
+use spdk_rs::libspdk::{vbdev_zone_block_create, vbdev_zone_block_delete};
+
+pub mod common;
+
+static MAYASTOR: Lazy<MayastorTest> =
+    Lazy::new(|| MayastorTest::new(MayastorCliArgs::default()));
+
+static DISK: &str = "malloc:///zdisk0?size_mb=64";
+static FTL_DISK: &str =
+    "ftl:///ftl0?base=malloc:///zdisk1?size_mb=64&mode=create";
+
+/// the device the zoned device is emulated on, FTL requires 4KiB blocks
+static ZONED_BASE: &str = "malloc:///zdisk2?size_mb=256&blk_size=4096";
+static ZONED_DISK: &str = "zblock0";
+static ZONE_CAPACITY: u64 = 1024;
+static FTL_ZONED_DISK: &str = "ftl:///ftl1?base=bdev:///zblock0";
+static FTL_ZONED_DISK_CREATE: &str =
+    "ftl:///ftl1?base=bdev:///zblock0&mode=create";
+
+/// Creates a zoned device emulated by the zone block vbdev on a malloc
+/// device.
+async fn create_zoned_disk() {
+    let base = device_create(ZONED_BASE).await.unwrap();
+    let cbase = CString::new(base).unwrap();
+    let cname = CString::new(ZONED_DISK).unwrap();
+    let rc = unsafe {
+        vbdev_zone_block_create(
+            cbase.as_ptr(),
+            cname.as_ptr(),
+            ZONE_CAPACITY,
+            1,
+        )
+    };
+    assert_eq!(rc, 0, "failed to create zone block device");
+}
+
+async fn destroy_zoned_disk() {
+    let (s, r) = oneshot::channel::<ErrnoResult<()>>();
+    let cname = CString::new(ZONED_DISK).unwrap();
+    unsafe {
+        vbdev_zone_block_delete(cname.as_ptr(), Some(done_errno_cb), cb_arg(s))
+    };
+    r.await.unwrap().unwrap();
+    device_destroy(ZONED_BASE).await.unwrap();
+}
+
+#[tokio::test]
+async fn zoned_device_not_zoned() {
+    let ms = &*MAYASTOR;
+
+    // zone operations are not supported by devices that are not zoned
+    ms.spawn(async {
+        let name = device_create(DISK).await.unwrap();
+        let handle = device_open(&name, true).unwrap().into_handle().unwrap();
+        assert!(handle.get_device().zone_geometry().is_none());
+
+        let buf = handle.dma_malloc(4096).unwrap();
+        assert!(matches!(
+            handle.zone_report(0, 1).await,
+            Err(CoreError::NotSupported { .. })
+        ));
+        assert!(matches!(
+            handle.zone_append(0, &buf).await,
+            Err(CoreError::NotSupported { .. })
+        ));
+        assert!(matches!(
+            handle.zone_reset(0, true).await,
+            Err(CoreError::NotSupported { .. })
+        ));
+
+        drop(handle);
+        device_destroy(DISK).await.unwrap();
+    })
+    .await;
+
+    // a translation layer can not be built on a device that is not zoned,
+    // and the base device it created is destroyed again
+    ms.spawn(async {
+        device_create(FTL_DISK)
+            .await
+            .expect_err("created ftl device");
+        assert!(device_lookup("ftl0").is_none());
+        assert!(device_lookup("zdisk1").is_none());
+
+        Lvs::create_or_import(PoolArgs {
+            name: "zpool".into(),
+            disks: vec![FTL_DISK.into()],
+            uuid: None,
+        })
+        .await
+        .expect_err("created pool on ftl device");
+        assert_eq!(Lvs::iter().count(), 0);
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn zoned_device_zone_ops() {
+    MAYASTOR
+        .spawn(async {
+            create_zoned_disk().await;
+
+            let handle = device_open(ZONED_DISK, true)
+                .unwrap()
+                .into_handle()
+                .unwrap();
+            let device = handle.get_device();
+            let geometry = device.zone_geometry().expect("device not zoned");
+            assert!(geometry.zone_size >= ZONE_CAPACITY);
+            assert_eq!(
+                geometry.num_zones,
+                device.num_blocks() / geometry.zone_size
+            );
+
+            // all zones are empty to begin with
+            let zones =
+                handle.zone_report(0, geometry.num_zones).await.unwrap();
+            assert_eq!(zones.len() as u64, geometry.num_zones);
+            for (i, zone) in zones.iter().enumerate() {
+                assert_eq!(zone.zone_id, i as u64 * geometry.zone_size);
+                assert_eq!(zone.write_pointer, zone.zone_id);
+                assert_eq!(zone.capacity, ZONE_CAPACITY);
+                assert_eq!(zone.state, ZoneState::Empty);
+            }
+
+            // the report does not run past the last zone
+            let last = (geometry.num_zones - 1) * geometry.zone_size;
+            assert_eq!(handle.zone_report(last, 8).await.unwrap().len(), 1);
+
+            // appends go to the write pointer of the zone, and advance it
+            let zone_id = geometry.zone_size;
+            let block_len = device.block_len();
+            let buf = handle.dma_malloc(block_len).unwrap();
+            assert_eq!(
+                handle.zone_append(zone_id, &buf).await.unwrap(),
+                zone_id
+            );
+            assert_eq!(
+                handle.zone_append(zone_id, &buf).await.unwrap(),
+                zone_id + 1
+            );
+
+            let zone = handle.zone_report(zone_id, 1).await.unwrap()[0];
+            assert_eq!(zone.write_pointer, zone_id + 2);
+            assert_ne!(zone.state, ZoneState::Empty);
+
+            // appends must be whole blocks
+            let buf = handle.dma_malloc(block_len + 512).unwrap();
+            assert!(matches!(
+                handle.zone_append(zone_id, &buf).await,
+                Err(CoreError::ZoneAppendDispatch { .. })
+            ));
+
+            // resetting a zone empties it
+            handle.zone_reset(zone_id, false).await.unwrap();
+            let zone = handle.zone_report(zone_id, 1).await.unwrap()[0];
+            assert_eq!(zone.write_pointer, zone_id);
+            assert_eq!(zone.state, ZoneState::Empty);
+
+            // resetting all zones empties every zone written to
+            let buf = handle.dma_malloc(block_len).unwrap();
+            handle.zone_append(0, &buf).await.unwrap();
+            handle.zone_append(last, &buf).await.unwrap();
+            handle.zone_reset(0, true).await.unwrap();
+            let zones =
+                handle.zone_report(0, geometry.num_zones).await.unwrap();
+            assert!(zones.iter().all(|zone| zone.state == ZoneState::Empty));
+
+            drop(handle);
+            destroy_zoned_disk().await;
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn zoned_device_ftl() {
+    MAYASTOR
+        .spawn(async {
+            create_zoned_disk().await;
+
+            // the zoned device is only formatted when asked for, not when
+            // the uuid of the translation layer to restore is missing
+            device_create(FTL_ZONED_DISK)
+                .await
+                .expect_err("created ftl device without a mode");
+            assert!(device_lookup("ftl1").is_none());
+
+            // a new translation layer is a regular block device, aliased by
+            // its URI with the UUID it was created with
+            let name = device_create(FTL_ZONED_DISK_CREATE).await.unwrap();
+            assert_eq!(name, "ftl1");
+            let device = device_lookup(&name).unwrap();
+            assert!(device.zone_geometry().is_none());
+            let uuid = device.uuid();
+
+            let handle =
+                device_open(&name, true).unwrap().into_handle().unwrap();
+            let mut buf = handle.dma_malloc(4096).unwrap();
+            buf.fill(0xa5);
+            handle.write_at(0, &buf).await.unwrap();
+            drop(handle);
+
+            // the zoned device existed before the translation layer, so it
+            // outlives the layer
+            device_destroy(FTL_ZONED_DISK_CREATE).await.unwrap();
+            assert!(device_lookup(&name).is_none());
+            assert!(device_lookup(ZONED_DISK).is_some());
+
+            // the translation layer is restored by its UUID, along with the
+            // data it holds
+            let uri =
+                format!("{}&uuid={}", FTL_ZONED_DISK, uuid.to_hyphenated());
+            assert_eq!(device_create(&uri).await.unwrap(), name);
+            assert_eq!(device_lookup(&name).unwrap().uuid(), uuid);
+
+            let handle =
+                device_open(&name, false).unwrap().into_handle().unwrap();
+            let mut read = handle.dma_malloc(4096).unwrap();
+            handle.read_at(0, &mut read).await.unwrap();
+            assert_eq!(read.as_slice(), buf.as_slice());
+            drop(handle);
+
+            device_destroy(&uri).await.unwrap();
+            assert!(device_lookup(ZONED_DISK).is_some());
+
+            destroy_zoned_disk().await;
+        })
+        .await;
+}
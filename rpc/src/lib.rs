@@ -66,23 +66,46 @@ pub mod mayastor {
                 DestroyPoolRequest,
                 ExportPoolRequest,
                 ImportPoolRequest,
+                IoStats,
+                LatencyStats,
                 ListPoolOptions,
                 ListPoolsResponse,
                 Pool,
+                PoolImportState,
+                PoolImportStatus,
                 PoolState,
+                PoolStats,
                 PoolType,
+                StatPoolsResponse,
             };
         }
 
         pub mod replica {
             pub use super::pb::{
+                replica_rpc_client::ReplicaRpcClient,
                 replica_rpc_server::{ReplicaRpc, ReplicaRpcServer},
+                AddReplicaAllowedHostRequest,
+                ClearReplicaReservationsRequest,
                 CreateReplicaRequest,
                 DestroyReplicaRequest,
+                GetReplicaConnectionsRequest,
+                GetReplicaReservationsRequest,
+                IoStats,
+                LatencyStats,
                 ListReplicaOptions,
                 ListReplicasResponse,
+                NvmfConnection,
+                NvmfConnectionStats,
+                NvmfConnections,
+                PreemptReplicaReservationRequest,
+                Registrant,
+                RemoveReplicaAllowedHostRequest,
                 Replica,
+                ReplicaRateLimits,
+                ReplicaStats,
+                ReservationReport,
                 ShareReplicaRequest,
+                StatReplicasResponse,
                 UnshareReplicaRequest,
             };
         }
@@ -99,8 +122,10 @@ pub mod mayastor {
                 MayastorFeatures,
                 MayastorInfoResponse,
                 NvmeController,
+                NvmeControllerHealthStats,
                 NvmeControllerIoStats,
                 NvmeControllerState,
+                NvmeQpairStats,
                 ResourceUsage,
                 StatNvmeControllerRequest,
                 StatNvmeControllerResponse,
@@ -109,34 +134,47 @@ pub mod mayastor {
 
         pub mod nexus {
             pub use super::pb::{
+                nexus_rpc_client::NexusRpcClient,
                 nexus_rpc_server::{NexusRpc, NexusRpcServer},
                 AddChildNexusRequest,
                 AddChildNexusResponse,
+                AddNexusAllowedHostRequest,
                 Child,
                 ChildOperationRequest,
                 ChildOperationResponse,
                 ChildState,
+                ClearChildReservationsRequest,
                 CreateNexusRequest,
                 CreateNexusResponse,
                 DestroyNexusRequest,
                 FaultNexusChildRequest,
+                GetChildReservationsRequest,
+                GetNexusConnectionsRequest,
                 GetNvmeAnaStateRequest,
                 GetNvmeAnaStateResponse,
                 ListNexusOptions,
                 ListNexusResponse,
                 Nexus,
+                NexusAnaConfig,
                 NexusState,
                 NvmeAnaState,
+                NvmfConnection,
+                NvmfConnectionStats,
+                NvmfConnections,
                 PauseRebuildRequest,
                 PauseRebuildResponse,
+                PreemptChildReservationRequest,
                 PublishNexusRequest,
                 PublishNexusResponse,
                 RebuildStateRequest,
                 RebuildStateResponse,
                 RebuildStatsRequest,
                 RebuildStatsResponse,
+                Registrant,
                 RemoveChildNexusRequest,
                 RemoveChildNexusResponse,
+                RemoveNexusAllowedHostRequest,
+                ReservationReport,
                 ResumeRebuildRequest,
                 ResumeRebuildResponse,
                 SetNvmeAnaStateRequest,